@@ -0,0 +1,4 @@
+// Re-embed the SQL migrations when one is added or edited
+fn main() {
+    println!("cargo:rerun-if-changed=../migrations");
+}
@@ -0,0 +1,7 @@
+//! Types and storage shared by the td01 aggregator and the td02 binaries: the canonical
+//! `StockPrice`, its WebSocket form `PriceUpdate`, and the `PriceStore` backends.
+
+mod price;
+pub mod store;
+
+pub use price::{PriceUpdate, StockPrice};
@@ -0,0 +1,86 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A quote as fetched, stored and read back; the one price type of the workspace.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StockPrice {
+    pub symbol: String,
+    pub price: f64,
+    pub source: String,
+    /// Quote time as reported by the provider, or fetch time when it doesn't say.
+    pub timestamp: DateTime<Utc>,
+    /// Session open. This and `high`, `low`, `prev_close` are only set by providers that
+    /// send them with the quote.
+    pub open: Option<f64>,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub prev_close: Option<f64>,
+    /// Currency the provider quoted in, when `price` was converted from it; `None` means
+    /// the quote was already in the base currency.
+    pub currency: Option<String>,
+    /// The price as quoted, before conversion.
+    pub raw_price: Option<f64>,
+    /// The provider has been sending this exact quote for several cycles: likely a delayed
+    /// feed or a closed market rather than a price that holds still.
+    pub stale: bool,
+    /// Several standard deviations away from the recent mean of this symbol and source.
+    pub anomaly: bool,
+}
+
+/// What WebSocket clients receive for each new price.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceUpdate {
+    pub symbol: String,
+    pub price: f64,
+    pub source: String,
+    /// RFC 3339 on the wire.
+    pub timestamp: DateTime<Utc>,
+    /// Day range and previous close; `null` when the source doesn't report them.
+    pub open: Option<f64>,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub prev_close: Option<f64>,
+    /// Best bid and ask around `price`, and the volume traded in this tick; `null` for
+    /// stored quotes, which don't keep them.
+    pub bid: Option<f64>,
+    pub ask: Option<f64>,
+    pub volume: Option<u64>,
+    /// The provider keeps sending this same quote (delayed feed, closed market).
+    pub stale: bool,
+    /// Read back from the database for a `replay`, not live; left out of the frame when
+    /// false.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replayed: bool,
+    /// Kind of asset (`stock`, `crypto`...) when the feed reads several price tables; left
+    /// out of the frame otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset_class: Option<String>,
+    /// When the server queued the frame for this client, on the server's clock: receive
+    /// time minus `sent_at` is the delivery delay, where minus `timestamp` is the age of the
+    /// price. Left out of the frame until then.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+impl From<StockPrice> for PriceUpdate {
+    fn from(price: StockPrice) -> Self {
+        Self {
+            symbol: price.symbol,
+            price: price.price,
+            source: price.source,
+            timestamp: price.timestamp,
+            open: price.open,
+            high: price.high,
+            low: price.low,
+            prev_close: price.prev_close,
+            bid: None,
+            ask: None,
+            volume: None,
+            stale: price.stale,
+            replayed: false,
+            asset_class: None,
+            sent_at: None,
+        }
+    }
+}
@@ -0,0 +1,432 @@
+//! Price storage. Postgres when `DATABASE_URL` points at one, a local SQLite file otherwise,
+//! both behind `PriceStore` so callers don't care which.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::stream::BoxStream;
+use sqlx::FromRow;
+use tracing::warn;
+
+pub mod notify;
+mod postgres;
+mod sqlite;
+pub mod table;
+
+pub use postgres::PostgresStore;
+pub use sqlite::SqliteStore;
+pub use table::PriceTable;
+
+pub use crate::StockPrice;
+
+/// Used when neither `--db` nor `DATABASE_URL` is given.
+pub const DEFAULT_SQLITE_URL: &str = "sqlite://prices.db";
+
+/// The `stock_prices` columns, as both backends select them.
+#[derive(FromRow)]
+struct PriceRow {
+    symbol: String,
+    price: f64,
+    source: String,
+    timestamp: DateTime<Utc>,
+    open: Option<f64>,
+    high: Option<f64>,
+    low: Option<f64>,
+    prev_close: Option<f64>,
+    currency: Option<String>,
+    raw_price: Option<f64>,
+    stale: bool,
+    anomaly: bool,
+}
+
+impl From<PriceRow> for StockPrice {
+    fn from(row: PriceRow) -> Self {
+        Self {
+            symbol: row.symbol,
+            price: row.price,
+            source: row.source,
+            timestamp: row.timestamp,
+            open: row.open,
+            high: row.high,
+            low: row.low,
+            prev_close: row.prev_close,
+            currency: row.currency,
+            raw_price: row.raw_price,
+            stale: row.stale,
+            anomaly: row.anomaly,
+        }
+    }
+}
+
+/// One moving-average value, stamped with the price that produced it.
+#[derive(Debug, Clone)]
+pub struct Indicator {
+    pub symbol: String,
+    pub source: String,
+    /// `"sma"` or `"ema"`.
+    pub kind: &'static str,
+    pub period: u32,
+    pub timestamp: DateTime<Utc>,
+    pub value: f64,
+}
+
+/// One OHLC bar in `stock_candles`.
+#[derive(Debug, Clone)]
+pub struct Candle {
+    pub symbol: String,
+    pub source: String,
+    /// Width label: `"1m"`, `"5m"`, `"1h"`, `"1d"`.
+    pub interval: &'static str,
+    pub bucket_start: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Prices rolled into the bar; 1 for a bar taken as is from a provider.
+    pub samples: u32,
+}
+
+/// One request to a provider, as recorded in `fetch_stats`.
+#[derive(Debug, Clone)]
+pub struct FetchStat {
+    pub symbol: String,
+    pub source: String,
+    pub timestamp: DateTime<Utc>,
+    pub ok: bool,
+    /// Retries included.
+    pub latency_ms: u32,
+    /// Kind of error of a failed attempt, e.g. `"http"` or `"rate_limited"`.
+    pub error_kind: Option<&'static str>,
+}
+
+/// Value of the configured holdings at the end of a cycle, as stored in
+/// `portfolio_snapshots`.
+#[derive(Debug, Clone)]
+pub struct PortfolioSnapshot {
+    pub timestamp: DateTime<Utc>,
+    pub total_value: f64,
+    pub total_cost: f64,
+    pub pnl: f64,
+    /// Positions valued at a stale or missing price.
+    pub stale_positions: u32,
+    /// Per-position detail, stored as is.
+    pub positions: serde_json::Value,
+}
+
+/// How one source did over a range of `fetch_stats`.
+#[derive(Debug, Clone)]
+pub struct SourceStats {
+    pub source: String,
+    pub attempts: i64,
+    pub successes: i64,
+    /// Nearest-rank 95th percentile, failed attempts included.
+    pub p95_latency_ms: Option<i64>,
+}
+
+/// Filters for `PriceStore::stream` and `source_stats`; `None` leaves that side open.
+#[derive(Debug, Clone, Default)]
+pub struct PriceQuery {
+    pub symbol: Option<String>,
+    pub source: Option<String>,
+    /// Inclusive.
+    pub since: Option<DateTime<Utc>>,
+    /// Exclusive.
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Where a page of `PriceStore::page_after` starts: rows strictly after this one, in
+/// (timestamp, symbol, source) order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageKey {
+    pub timestamp: DateTime<Utc>,
+    pub symbol: String,
+    pub source: String,
+}
+
+impl PageKey {
+    /// Before every row at `timestamp` or later.
+    pub fn since(timestamp: DateTime<Utc>) -> Self {
+        Self {
+            timestamp,
+            symbol: String::new(),
+            source: String::new(),
+        }
+    }
+
+    /// Right after `price`, for the page that follows it.
+    pub fn after(price: &StockPrice) -> Self {
+        Self {
+            timestamp: price.timestamp,
+            symbol: price.symbol.clone(),
+            source: price.source.clone(),
+        }
+    }
+}
+
+/// Why a price could not be written or read.
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("cannot save {symbol} from {provider}: {error}")]
+    Insert {
+        symbol: String,
+        provider: String,
+        #[source]
+        error: sqlx::Error,
+    },
+    #[error("cannot save batch of {rows} prices: {error}")]
+    Batch {
+        rows: usize,
+        #[source]
+        error: sqlx::Error,
+    },
+    #[error("database error: {0}")]
+    Query(#[from] sqlx::Error),
+    #[error("migration failed: {0}")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+    #[error("unsupported database URL '{0}' (expected postgres:// or sqlite://)")]
+    UnsupportedUrl(String),
+}
+
+impl StorageError {
+    /// The database could not be reached, as opposed to refusing the statement: worth
+    /// trying the same write again later.
+    pub fn is_unreachable(&self) -> bool {
+        match self {
+            StorageError::Insert { error, .. }
+            | StorageError::Batch { error, .. }
+            | StorageError::Query(error) => is_connection_error(error),
+            StorageError::Migrate(_) | StorageError::UnsupportedUrl(_) => false,
+        }
+    }
+}
+
+/// Connection-level failure, including a Postgres still starting up (`57P03`).
+fn is_connection_error(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::Io(_) => true,
+        sqlx::Error::Database(e) => e.code().as_deref() == Some("57P03"),
+        _ => false,
+    }
+}
+
+/// Connection pool settings, the same for every binary. Defaults, then `with_env`.
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    pub max_connections: u32,
+    /// How long a query waits for a free connection before failing.
+    pub acquire_timeout: Duration,
+    /// Postgres `statement_timeout`; `None` keeps the server's. Ignored by SQLite.
+    pub statement_timeout: Option<Duration>,
+    /// Tries at startup while the database is unreachable, e.g. a container still booting.
+    pub connect_attempts: u32,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            max_connections: 5,
+            acquire_timeout: Duration::from_secs(30),
+            statement_timeout: None,
+            connect_attempts: 8,
+        }
+    }
+}
+
+impl PoolOptions {
+    /// Overrides from `DB_MAX_CONNECTIONS`, `DB_ACQUIRE_TIMEOUT`, `DB_STATEMENT_TIMEOUT`
+    /// and `DB_CONNECT_ATTEMPTS`; durations as `30s`, `500ms`...
+    pub fn with_env(mut self) -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let count = |name: &str, raw: String| {
+            raw.trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| format!("{name}: expected a positive number, got '{raw}'"))
+        };
+        let duration = |name: &str, raw: String| {
+            humantime::parse_duration(raw.trim())
+                .ok()
+                .filter(|d| !d.is_zero())
+                .ok_or_else(|| format!("{name}: expected a duration such as 30s, got '{raw}'"))
+        };
+        if let Some(raw) = var("DB_MAX_CONNECTIONS") {
+            self.max_connections = count("DB_MAX_CONNECTIONS", raw)?;
+        }
+        if let Some(raw) = var("DB_ACQUIRE_TIMEOUT") {
+            self.acquire_timeout = duration("DB_ACQUIRE_TIMEOUT", raw)?;
+        }
+        if let Some(raw) = var("DB_STATEMENT_TIMEOUT") {
+            self.statement_timeout = Some(duration("DB_STATEMENT_TIMEOUT", raw)?);
+        }
+        if let Some(raw) = var("DB_CONNECT_ATTEMPTS") {
+            self.connect_attempts = count("DB_CONNECT_ATTEMPTS", raw)?;
+        }
+        Ok(self)
+    }
+}
+
+/// First wait between two connection attempts, doubled each time up to the maximum.
+const CONNECT_BASE_DELAY: Duration = Duration::from_secs(1);
+const CONNECT_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Runs `connect`, a single connection rather than a pool (which would retry on its own for
+/// the whole acquire timeout), until it succeeds, fails for a reason other than an
+/// unreachable database, or `attempts` tries are used up. Each failed try is logged.
+async fn connect_with_retry<F, Fut>(attempts: u32, mut connect: F) -> Result<(), sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), sqlx::Error>>,
+{
+    let mut delay = CONNECT_BASE_DELAY;
+    let mut attempt = 1;
+    loop {
+        match connect().await {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < attempts && is_connection_error(&e) => {
+                warn!(
+                    attempt,
+                    attempts,
+                    retry_in = %humantime::format_duration(delay),
+                    error = %e,
+                    "Database unreachable, retrying"
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(CONNECT_MAX_DELAY);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[async_trait]
+pub trait PriceStore: Send + Sync {
+    /// `"postgres"` or `"sqlite"`, for logs.
+    fn backend(&self) -> &'static str;
+
+    /// `false` when the row was already there (same symbol, source and timestamp). On
+    /// Postgres, new rows are also announced on the `stock_prices` channel (see `notify`).
+    async fn save(&self, price: &StockPrice) -> Result<bool, StorageError>;
+
+    /// All rows in one statement inside a transaction; returns the rows written, duplicates
+    /// excluded. Either every row is written or none.
+    async fn save_batch(&self, prices: &[StockPrice]) -> Result<u64, StorageError>;
+
+    /// Most recent price for every (symbol, source) pair.
+    async fn latest_per_symbol_source(&self) -> Result<Vec<StockPrice>, StorageError>;
+
+    /// Most recent price for every (symbol, source) pair of `table`, which must be valid.
+    async fn latest_in(&self, table: &PriceTable) -> Result<Vec<StockPrice>, StorageError>;
+
+    /// Prices of `table` stamped `since` or later, oldest first.
+    async fn since_in(
+        &self,
+        table: &PriceTable,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<StockPrice>, StorageError>;
+
+    /// Prices for `symbol` since `since`, oldest first.
+    async fn history(
+        &self,
+        symbol: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<StockPrice>, StorageError>;
+
+    /// Matching prices oldest first, read row by row instead of collected.
+    fn stream<'a>(
+        &'a self,
+        query: &'a PriceQuery,
+    ) -> BoxStream<'a, Result<StockPrice, StorageError>>;
+
+    /// Up to `limit` prices of `symbols` (all of them when empty) after `after`, oldest
+    /// first; the last row gives the key of the next page. For long ranges read a page at
+    /// a time, served by the timestamp index.
+    async fn page_after(
+        &self,
+        symbols: &[String],
+        after: &PageKey,
+        limit: u32,
+    ) -> Result<Vec<StockPrice>, StorageError>;
+
+    /// Up to `limit` prices of `symbol` (from `source` only, if given) strictly before
+    /// `before`, oldest first.
+    async fn recent(
+        &self,
+        symbol: &str,
+        source: Option<&str>,
+        before: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<Vec<StockPrice>, StorageError>;
+
+    /// Writes to `stock_indicators`; values already stored are left alone.
+    async fn save_indicators(&self, rows: &[Indicator]) -> Result<u64, StorageError>;
+
+    /// Writes bars fetched from a provider to `stock_candles`; bars already stored are left
+    /// alone. Returns the rows written.
+    async fn save_candles(&self, candles: &[Candle]) -> Result<u64, StorageError>;
+
+    /// Appends to `fetch_stats` in one statement.
+    async fn save_fetch_stats(&self, stats: &[FetchStat]) -> Result<u64, StorageError>;
+
+    /// Appends one row to `portfolio_snapshots`.
+    async fn save_portfolio_snapshot(
+        &self,
+        snapshot: &PortfolioSnapshot,
+    ) -> Result<(), StorageError>;
+
+    /// Records the end-of-day report of `day`; `false` when that day was already reported.
+    async fn save_daily_report(
+        &self,
+        day: NaiveDate,
+        generated_at: DateTime<Utc>,
+        report: &serde_json::Value,
+    ) -> Result<bool, StorageError>;
+
+    /// Most recent day in `daily_reports`.
+    async fn last_daily_report(&self) -> Result<Option<NaiveDate>, StorageError>;
+
+    /// `fetch_stats` per source, over the rows matching `query`, sorted by source.
+    async fn source_stats(&self, query: &PriceQuery) -> Result<Vec<SourceStats>, StorageError>;
+
+    /// Deletes at most `limit` rows older than `cutoff`; returns how many went.
+    async fn prune_before(&self, cutoff: DateTime<Utc>, limit: u32) -> Result<u64, StorageError>;
+
+    /// Rolls prices up into `stock_candles` buckets of `secs` seconds labelled `label`.
+    /// Only closed buckets are written, from the last one stored for the same symbol and
+    /// source onwards.
+    async fn roll_up_candles(&self, label: &str, secs: u32) -> Result<u64, StorageError>;
+
+    /// Cheap round trip (`SELECT 1`) to check the database is reachable.
+    async fn ping(&self) -> Result<(), StorageError>;
+
+    async fn close(&self);
+}
+
+/// `explicit` (from `--db` or the config), else `DATABASE_URL`, else the SQLite fallback.
+pub fn database_url(explicit: Option<String>) -> String {
+    explicit
+        .or_else(|| std::env::var("DATABASE_URL").ok())
+        .unwrap_or_else(|| DEFAULT_SQLITE_URL.to_string())
+}
+
+/// Opens the store matching the URL scheme and, unless `migrate` is false, brings its
+/// schema up to date.
+pub async fn connect(
+    url: &str,
+    options: &PoolOptions,
+    migrate: bool,
+) -> Result<Arc<dyn PriceStore>, StorageError> {
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        Ok(Arc::new(
+            PostgresStore::connect(url, options, migrate).await?,
+        ))
+    } else if url.starts_with("sqlite:") {
+        Ok(Arc::new(SqliteStore::connect(url, options, migrate).await?))
+    } else {
+        Err(StorageError::UnsupportedUrl(url.to_string()))
+    }
+}
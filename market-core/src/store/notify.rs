@@ -0,0 +1,176 @@
+//! `NOTIFY stock_prices` payloads: sent by `PostgresStore` with every insert, read by the
+//! dashboard through `PriceListener`. Both sides go through these types so the format can't
+//! drift apart.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+
+use super::{StockPrice, StorageError};
+
+pub const CHANNEL: &str = "stock_prices";
+
+/// Bumped on any incompatible change; listeners should ignore versions they don't know.
+pub const VERSION: u32 = 1;
+
+/// Postgres rejects payloads of 8000 bytes or more.
+const MAX_PAYLOAD: usize = 7999;
+
+/// Past this many notifications for one batch, listeners are only told which symbols
+/// changed and read the prices themselves.
+const MAX_PRICE_PAYLOADS: usize = 4;
+
+/// `{"v":1,"kind":"prices","prices":[...]}` or `{"v":1,"kind":"changed","symbols":[...]}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub v: u32,
+    #[serde(flatten)]
+    pub body: Body,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Body {
+    /// The rows just written.
+    Prices { prices: Vec<PriceNote> },
+    /// Too many rows to list: these symbols have new prices.
+    Changed { symbols: Vec<String> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceNote {
+    pub symbol: String,
+    pub price: f64,
+    pub source: String,
+    /// RFC 3339.
+    pub timestamp: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub high: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev_close: Option<f64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub anomaly: bool,
+}
+
+impl From<&StockPrice> for PriceNote {
+    fn from(price: &StockPrice) -> Self {
+        Self {
+            symbol: price.symbol.clone(),
+            price: price.price,
+            source: price.source.clone(),
+            timestamp: price.timestamp,
+            open: price.open,
+            high: price.high,
+            low: price.low,
+            prev_close: price.prev_close,
+            stale: price.stale,
+            anomaly: price.anomaly,
+        }
+    }
+}
+
+impl From<PriceNote> for StockPrice {
+    fn from(note: PriceNote) -> Self {
+        Self {
+            symbol: note.symbol,
+            price: note.price,
+            source: note.source,
+            timestamp: note.timestamp,
+            open: note.open,
+            high: note.high,
+            low: note.low,
+            prev_close: note.prev_close,
+            stale: note.stale,
+            anomaly: note.anomaly,
+            ..Default::default()
+        }
+    }
+}
+
+/// Payloads announcing `prices`, each under the NOTIFY size limit. Large batches are split,
+/// and very large ones reduced to a `changed` list of their symbols.
+pub fn payloads(prices: &[StockPrice]) -> Vec<String> {
+    let notes: Vec<PriceNote> = prices.iter().map(PriceNote::from).collect();
+    let payloads = pack(notes, |prices| Body::Prices { prices });
+    if payloads.len() <= MAX_PRICE_PAYLOADS {
+        return payloads;
+    }
+    let mut symbols: Vec<String> = prices.iter().map(|p| p.symbol.clone()).collect();
+    symbols.sort();
+    symbols.dedup();
+    pack(symbols, |symbols| Body::Changed { symbols })
+}
+
+/// Splits `items` into as few payloads as fit under `MAX_PAYLOAD`.
+fn pack<T: Serialize>(items: Vec<T>, body: impl Fn(Vec<T>) -> Body) -> Vec<String> {
+    let encode = |items| {
+        serde_json::to_string(&Notification {
+            v: VERSION,
+            body: body(items),
+        })
+        .expect("notification payloads always serialize")
+    };
+    let overhead = encode(Vec::new()).len();
+
+    let mut payloads = Vec::new();
+    let mut group = Vec::new();
+    let mut size = overhead;
+    for item in items {
+        // Item plus its separating comma
+        let len = serde_json::to_string(&item).map_or(0, |s| s.len()) + 1;
+        if !group.is_empty() && size + len > MAX_PAYLOAD {
+            payloads.push(encode(std::mem::take(&mut group)));
+            size = overhead;
+        }
+        size += len;
+        group.push(item);
+    }
+    if !group.is_empty() {
+        payloads.push(encode(group));
+    }
+    payloads
+}
+
+/// What `PriceListener::recv` got.
+#[derive(Debug)]
+pub enum Event {
+    Notification(Notification),
+    /// A payload that is not a notification of this version.
+    Invalid(String),
+    /// The connection dropped; anything notified until the next `recv` reconnects is lost.
+    Lost,
+}
+
+/// `LISTEN stock_prices` on a connection of its own.
+pub struct PriceListener {
+    inner: PgListener,
+}
+
+impl PriceListener {
+    pub async fn connect(url: &str) -> Result<Self, StorageError> {
+        let mut inner = PgListener::connect(url).await?;
+        inner.listen(CHANNEL).await?;
+        Ok(Self { inner })
+    }
+
+    pub async fn recv(&mut self) -> Result<Event, StorageError> {
+        let Some(notification) = self.inner.try_recv().await? else {
+            return Ok(Event::Lost);
+        };
+        Ok(parse(notification.payload()))
+    }
+}
+
+fn parse(payload: &str) -> Event {
+    match serde_json::from_str::<Notification>(payload) {
+        Ok(notification) if notification.v == VERSION => Event::Notification(notification),
+        Ok(notification) => Event::Invalid(format!("unknown version {}", notification.v)),
+        Err(e) => Event::Invalid(e.to_string()),
+    }
+}
@@ -0,0 +1,621 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::stream::{BoxStream, StreamExt};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Connection, PgConnection, PgPool};
+
+use super::table::{quoted, TableRow};
+use super::{
+    connect_with_retry, notify, Candle, FetchStat, Indicator, PageKey, PoolOptions,
+    PortfolioSnapshot, PriceQuery, PriceRow, PriceStore, PriceTable, SourceStats, StockPrice,
+    StorageError,
+};
+
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    pub async fn connect(
+        url: &str,
+        options: &PoolOptions,
+        migrate: bool,
+    ) -> Result<Self, StorageError> {
+        let pool = pg_pool(url, options).await?;
+        if migrate {
+            sqlx::migrate!("../migrations/postgres").run(&pool).await?;
+        }
+        Ok(Self { pool })
+    }
+}
+
+/// Postgres pool with `options` applied, waiting for the server if it isn't up yet.
+async fn pg_pool(url: &str, options: &PoolOptions) -> Result<PgPool, StorageError> {
+    let mut connect_options: PgConnectOptions = url.parse()?;
+    if let Some(timeout) = options.statement_timeout {
+        connect_options =
+            connect_options.options([("statement_timeout", timeout.as_millis().to_string())]);
+    }
+    connect_with_retry(options.connect_attempts, || async {
+        PgConnection::connect_with(&connect_options)
+            .await?
+            .close()
+            .await
+    })
+    .await?;
+    let pool = PgPoolOptions::new()
+        .max_connections(options.max_connections)
+        .acquire_timeout(options.acquire_timeout)
+        .connect_with(connect_options)
+        .await?;
+    Ok(pool)
+}
+
+/// `NOTIFY`s listeners of `prices`; delivered when the transaction commits, so they only
+/// ever hear about rows that were really written.
+async fn announce(conn: &mut PgConnection, prices: &[StockPrice]) -> Result<(), sqlx::Error> {
+    for payload in notify::payloads(prices) {
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(notify::CHANNEL)
+            .bind(payload)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl PriceStore for PostgresStore {
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn save(&self, price: &StockPrice) -> Result<bool, StorageError> {
+        let insert = |error| StorageError::Insert {
+            symbol: price.symbol.clone(),
+            provider: price.source.clone(),
+            error,
+        };
+        let mut tx = self.pool.begin().await.map_err(insert)?;
+        let result = sqlx::query(
+            r#"
+            INSERT INTO stock_prices
+                (symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            ON CONFLICT (symbol, source, timestamp) DO NOTHING
+            "#,
+        )
+        .bind(&price.symbol)
+        .bind(price.price)
+        .bind(&price.source)
+        .bind(price.timestamp)
+        .bind(price.open)
+        .bind(price.high)
+        .bind(price.low)
+        .bind(price.prev_close)
+        .bind(&price.currency)
+        .bind(price.raw_price)
+        .bind(price.stale)
+        .bind(price.anomaly)
+        .execute(&mut *tx)
+        .await
+        .map_err(insert)?;
+        let inserted = result.rows_affected() > 0;
+        if inserted {
+            announce(&mut tx, std::slice::from_ref(price))
+                .await
+                .map_err(insert)?;
+        }
+        tx.commit().await.map_err(insert)?;
+        Ok(inserted)
+    }
+
+    /// A single `UNNEST` insert, announcing the rows actually written.
+    async fn save_batch(&self, prices: &[StockPrice]) -> Result<u64, StorageError> {
+        let mut symbols = Vec::with_capacity(prices.len());
+        let mut values = Vec::with_capacity(prices.len());
+        let mut sources = Vec::with_capacity(prices.len());
+        let mut timestamps = Vec::with_capacity(prices.len());
+        let mut opens = Vec::with_capacity(prices.len());
+        let mut highs = Vec::with_capacity(prices.len());
+        let mut lows = Vec::with_capacity(prices.len());
+        let mut prev_closes = Vec::with_capacity(prices.len());
+        let mut currencies = Vec::with_capacity(prices.len());
+        let mut raw_prices = Vec::with_capacity(prices.len());
+        let mut stales = Vec::with_capacity(prices.len());
+        let mut anomalies = Vec::with_capacity(prices.len());
+        for price in prices {
+            symbols.push(price.symbol.clone());
+            values.push(price.price);
+            sources.push(price.source.clone());
+            timestamps.push(price.timestamp);
+            opens.push(price.open);
+            highs.push(price.high);
+            lows.push(price.low);
+            prev_closes.push(price.prev_close);
+            currencies.push(price.currency.clone());
+            raw_prices.push(price.raw_price);
+            stales.push(price.stale);
+            anomalies.push(price.anomaly);
+        }
+
+        let batch = |error| StorageError::Batch {
+            rows: prices.len(),
+            error,
+        };
+        let mut tx = self.pool.begin().await.map_err(batch)?;
+        let inserted: Vec<(String, String, DateTime<Utc>)> = sqlx::query_as(
+            r#"
+            INSERT INTO stock_prices
+                (symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly)
+            SELECT * FROM UNNEST(
+                $1::varchar[], $2::float8[], $3::varchar[], $4::timestamptz[],
+                $5::float8[], $6::float8[], $7::float8[], $8::float8[],
+                $9::varchar[], $10::float8[], $11::bool[], $12::bool[]
+            )
+            ON CONFLICT (symbol, source, timestamp) DO NOTHING
+            RETURNING symbol, source, timestamp
+            "#,
+        )
+        .bind(&symbols)
+        .bind(&values)
+        .bind(&sources)
+        .bind(&timestamps)
+        .bind(&opens)
+        .bind(&highs)
+        .bind(&lows)
+        .bind(&prev_closes)
+        .bind(&currencies)
+        .bind(&raw_prices)
+        .bind(&stales)
+        .bind(&anomalies)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(batch)?;
+        // Postgres keeps microseconds, the prices in hand may have nanoseconds
+        let keys: HashSet<(&str, &str, i64)> = inserted
+            .iter()
+            .map(|(symbol, source, timestamp)| {
+                (
+                    symbol.as_str(),
+                    source.as_str(),
+                    timestamp.timestamp_micros(),
+                )
+            })
+            .collect();
+        let written: Vec<StockPrice> = prices
+            .iter()
+            .filter(|p| {
+                keys.contains(&(
+                    p.symbol.as_str(),
+                    p.source.as_str(),
+                    p.timestamp.timestamp_micros(),
+                ))
+            })
+            .cloned()
+            .collect();
+        announce(&mut tx, &written).await.map_err(batch)?;
+        tx.commit().await.map_err(batch)?;
+        Ok(inserted.len() as u64)
+    }
+
+    async fn latest_per_symbol_source(&self) -> Result<Vec<StockPrice>, StorageError> {
+        let rows: Vec<PriceRow> = sqlx::query_as(
+            r#"
+            SELECT DISTINCT ON (symbol, source)
+                symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly
+            FROM stock_prices
+            ORDER BY symbol, source, timestamp DESC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(StockPrice::from).collect())
+    }
+
+    async fn latest_in(&self, table: &PriceTable) -> Result<Vec<StockPrice>, StorageError> {
+        let columns = &table.columns;
+        let (symbol, source) = (quoted(&columns.symbol), quoted(&columns.source));
+        let sql = format!(
+            "SELECT DISTINCT ON ({symbol}, {source}) {} FROM {} ORDER BY {symbol}, {source}, {} DESC",
+            table.select_list(),
+            quoted(&table.name),
+            quoted(&columns.timestamp),
+        );
+        let rows: Vec<TableRow> = sqlx::query_as(&sql).fetch_all(&self.pool).await?;
+        Ok(rows.into_iter().map(StockPrice::from).collect())
+    }
+
+    async fn since_in(
+        &self,
+        table: &PriceTable,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<StockPrice>, StorageError> {
+        let timestamp = quoted(&table.columns.timestamp);
+        let sql = format!(
+            "SELECT {} FROM {} WHERE {timestamp} >= $1 ORDER BY {timestamp}",
+            table.select_list(),
+            quoted(&table.name),
+        );
+        let rows: Vec<TableRow> = sqlx::query_as(&sql)
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(StockPrice::from).collect())
+    }
+
+    async fn history(
+        &self,
+        symbol: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<StockPrice>, StorageError> {
+        let rows: Vec<PriceRow> = sqlx::query_as(
+            r#"
+            SELECT symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly
+            FROM stock_prices
+            WHERE symbol = $1 AND timestamp >= $2
+            ORDER BY timestamp
+            "#,
+        )
+        .bind(symbol)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(StockPrice::from).collect())
+    }
+
+    fn stream<'a>(
+        &'a self,
+        query: &'a PriceQuery,
+    ) -> BoxStream<'a, Result<StockPrice, StorageError>> {
+        sqlx::query_as::<_, PriceRow>(
+            r#"
+            SELECT symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly
+            FROM stock_prices
+            WHERE ($1::varchar IS NULL OR symbol = $1)
+              AND ($2::varchar IS NULL OR source = $2)
+              AND ($3::timestamptz IS NULL OR timestamp >= $3)
+              AND ($4::timestamptz IS NULL OR timestamp < $4)
+            ORDER BY timestamp, symbol, source
+            "#,
+        )
+        .bind(&query.symbol)
+        .bind(&query.source)
+        .bind(query.since)
+        .bind(query.until)
+        .fetch(&self.pool)
+        .map(|row| row.map(StockPrice::from).map_err(StorageError::from))
+        .boxed()
+    }
+
+    async fn page_after(
+        &self,
+        symbols: &[String],
+        after: &PageKey,
+        limit: u32,
+    ) -> Result<Vec<StockPrice>, StorageError> {
+        let rows: Vec<PriceRow> = sqlx::query_as(
+            r#"
+            SELECT symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly
+            FROM stock_prices
+            WHERE (cardinality($1::varchar[]) = 0 OR symbol = ANY($1))
+              AND (timestamp, symbol, source) > ($2, $3, $4)
+            ORDER BY timestamp, symbol, source
+            LIMIT $5
+            "#,
+        )
+        .bind(symbols)
+        .bind(after.timestamp)
+        .bind(&after.symbol)
+        .bind(&after.source)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(StockPrice::from).collect())
+    }
+
+    async fn recent(
+        &self,
+        symbol: &str,
+        source: Option<&str>,
+        before: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<Vec<StockPrice>, StorageError> {
+        let rows: Vec<PriceRow> = sqlx::query_as(
+            r#"
+            SELECT symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly
+            FROM stock_prices
+            WHERE symbol = $1 AND ($2::varchar IS NULL OR source = $2) AND timestamp < $3
+            ORDER BY timestamp DESC
+            LIMIT $4
+            "#,
+        )
+        .bind(symbol)
+        .bind(source)
+        .bind(before)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().rev().map(StockPrice::from).collect())
+    }
+
+    async fn save_indicators(&self, rows: &[Indicator]) -> Result<u64, StorageError> {
+        let mut symbols = Vec::with_capacity(rows.len());
+        let mut sources = Vec::with_capacity(rows.len());
+        let mut kinds = Vec::with_capacity(rows.len());
+        let mut periods = Vec::with_capacity(rows.len());
+        let mut timestamps = Vec::with_capacity(rows.len());
+        let mut values = Vec::with_capacity(rows.len());
+        for row in rows {
+            symbols.push(row.symbol.clone());
+            sources.push(row.source.clone());
+            kinds.push(row.kind.to_string());
+            periods.push(row.period as i32);
+            timestamps.push(row.timestamp);
+            values.push(row.value);
+        }
+        let written = sqlx::query(
+            r#"
+            INSERT INTO stock_indicators (symbol, source, kind, period, timestamp, value)
+            SELECT * FROM UNNEST(
+                $1::varchar[], $2::varchar[], $3::varchar[], $4::int4[], $5::timestamptz[], $6::float8[]
+            )
+            ON CONFLICT (symbol, source, kind, period, timestamp) DO NOTHING
+            "#,
+        )
+        .bind(&symbols)
+        .bind(&sources)
+        .bind(&kinds)
+        .bind(&periods)
+        .bind(&timestamps)
+        .bind(&values)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(written)
+    }
+
+    async fn save_candles(&self, candles: &[Candle]) -> Result<u64, StorageError> {
+        let mut symbols = Vec::with_capacity(candles.len());
+        let mut sources = Vec::with_capacity(candles.len());
+        let mut intervals = Vec::with_capacity(candles.len());
+        let mut starts = Vec::with_capacity(candles.len());
+        let mut opens = Vec::with_capacity(candles.len());
+        let mut highs = Vec::with_capacity(candles.len());
+        let mut lows = Vec::with_capacity(candles.len());
+        let mut closes = Vec::with_capacity(candles.len());
+        let mut samples = Vec::with_capacity(candles.len());
+        for candle in candles {
+            symbols.push(candle.symbol.clone());
+            sources.push(candle.source.clone());
+            intervals.push(candle.interval.to_string());
+            starts.push(candle.bucket_start);
+            opens.push(candle.open);
+            highs.push(candle.high);
+            lows.push(candle.low);
+            closes.push(candle.close);
+            samples.push(candle.samples as i32);
+        }
+        let written = sqlx::query(
+            r#"
+            INSERT INTO stock_candles
+                (symbol, source, interval, bucket_start, open, high, low, close, samples)
+            SELECT * FROM UNNEST(
+                $1::varchar[], $2::varchar[], $3::varchar[], $4::timestamptz[],
+                $5::float8[], $6::float8[], $7::float8[], $8::float8[], $9::int4[]
+            )
+            ON CONFLICT (symbol, source, interval, bucket_start) DO NOTHING
+            "#,
+        )
+        .bind(&symbols)
+        .bind(&sources)
+        .bind(&intervals)
+        .bind(&starts)
+        .bind(&opens)
+        .bind(&highs)
+        .bind(&lows)
+        .bind(&closes)
+        .bind(&samples)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(written)
+    }
+
+    async fn save_fetch_stats(&self, stats: &[FetchStat]) -> Result<u64, StorageError> {
+        let mut symbols = Vec::with_capacity(stats.len());
+        let mut sources = Vec::with_capacity(stats.len());
+        let mut timestamps = Vec::with_capacity(stats.len());
+        let mut oks = Vec::with_capacity(stats.len());
+        let mut latencies = Vec::with_capacity(stats.len());
+        let mut kinds = Vec::with_capacity(stats.len());
+        for stat in stats {
+            symbols.push(stat.symbol.clone());
+            sources.push(stat.source.clone());
+            timestamps.push(stat.timestamp);
+            oks.push(stat.ok);
+            latencies.push(i32::try_from(stat.latency_ms).unwrap_or(i32::MAX));
+            kinds.push(stat.error_kind.map(str::to_string));
+        }
+        let written = sqlx::query(
+            r#"
+            INSERT INTO fetch_stats (symbol, source, ts, ok, latency_ms, error_kind)
+            SELECT * FROM UNNEST(
+                $1::varchar[], $2::varchar[], $3::timestamptz[], $4::bool[], $5::int4[], $6::varchar[]
+            )
+            "#,
+        )
+        .bind(&symbols)
+        .bind(&sources)
+        .bind(&timestamps)
+        .bind(&oks)
+        .bind(&latencies)
+        .bind(&kinds)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(written)
+    }
+
+    async fn save_portfolio_snapshot(
+        &self,
+        snapshot: &PortfolioSnapshot,
+    ) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            INSERT INTO portfolio_snapshots
+                (ts, total_value, total_cost, pnl, stale_positions, positions)
+            VALUES ($1, $2, $3, $4, $5, $6::text::jsonb)
+            "#,
+        )
+        .bind(snapshot.timestamp)
+        .bind(snapshot.total_value)
+        .bind(snapshot.total_cost)
+        .bind(snapshot.pnl)
+        .bind(i32::try_from(snapshot.stale_positions).unwrap_or(i32::MAX))
+        .bind(snapshot.positions.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn save_daily_report(
+        &self,
+        day: NaiveDate,
+        generated_at: DateTime<Utc>,
+        report: &serde_json::Value,
+    ) -> Result<bool, StorageError> {
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO daily_reports (day, generated_at, report)
+            VALUES ($1, $2, $3::text::jsonb)
+            ON CONFLICT (day) DO NOTHING
+            "#,
+        )
+        .bind(day)
+        .bind(generated_at)
+        .bind(report.to_string())
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(inserted > 0)
+    }
+
+    async fn last_daily_report(&self) -> Result<Option<NaiveDate>, StorageError> {
+        let day = sqlx::query_scalar("SELECT MAX(day) FROM daily_reports")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(day)
+    }
+
+    async fn source_stats(&self, query: &PriceQuery) -> Result<Vec<SourceStats>, StorageError> {
+        let rows: Vec<(String, i64, i64, Option<i64>)> = sqlx::query_as(
+            r#"
+            SELECT
+                source,
+                COUNT(*),
+                COUNT(*) FILTER (WHERE ok),
+                (percentile_disc(0.95) WITHIN GROUP (ORDER BY latency_ms))::int8 AS p95_latency_ms
+            FROM fetch_stats
+            WHERE ($1::varchar IS NULL OR symbol = $1)
+              AND ($2::varchar IS NULL OR source = $2)
+              AND ($3::timestamptz IS NULL OR ts >= $3)
+              AND ($4::timestamptz IS NULL OR ts < $4)
+            GROUP BY source
+            ORDER BY source
+            "#,
+        )
+        .bind(&query.symbol)
+        .bind(&query.source)
+        .bind(query.since)
+        .bind(query.until)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(
+                |(source, attempts, successes, p95_latency_ms)| SourceStats {
+                    source,
+                    attempts,
+                    successes,
+                    p95_latency_ms,
+                },
+            )
+            .collect())
+    }
+
+    async fn prune_before(&self, cutoff: DateTime<Utc>, limit: u32) -> Result<u64, StorageError> {
+        let deleted = sqlx::query(
+            r#"
+            DELETE FROM stock_prices
+            WHERE id IN (
+                SELECT id FROM stock_prices
+                WHERE timestamp < $1
+                LIMIT $2
+            )
+            "#,
+        )
+        .bind(cutoff)
+        .bind(i64::from(limit))
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(deleted)
+    }
+
+    /// The last stored bucket of each series is recomputed on every run, which the upsert
+    /// makes harmless.
+    async fn roll_up_candles(&self, label: &str, secs: u32) -> Result<u64, StorageError> {
+        let written = sqlx::query(
+            r#"
+            INSERT INTO stock_candles
+                (symbol, source, interval, bucket_start, open, high, low, close, samples)
+            SELECT
+                symbol,
+                source,
+                $1,
+                bucket,
+                (array_agg(price ORDER BY timestamp))[1],
+                MAX(price),
+                MIN(price),
+                (array_agg(price ORDER BY timestamp DESC))[1],
+                COUNT(*)::INTEGER
+            FROM (
+                SELECT symbol, source, price, timestamp,
+                    date_bin(make_interval(secs => $2), timestamp, TIMESTAMPTZ '1970-01-01') AS bucket
+                FROM stock_prices sp
+                WHERE timestamp >= COALESCE(
+                    (SELECT MAX(bucket_start) FROM stock_candles c
+                     WHERE c.symbol = sp.symbol AND c.source = sp.source AND c.interval = $1),
+                    '-infinity'
+                )
+            ) p
+            WHERE bucket + make_interval(secs => $2) <= NOW()
+            GROUP BY symbol, source, bucket
+            ON CONFLICT (symbol, source, interval, bucket_start) DO UPDATE SET
+                open = EXCLUDED.open,
+                high = EXCLUDED.high,
+                low = EXCLUDED.low,
+                close = EXCLUDED.close,
+                samples = EXCLUDED.samples
+            "#,
+        )
+        .bind(label)
+        .bind(f64::from(secs))
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(written)
+    }
+
+    async fn ping(&self) -> Result<(), StorageError> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+}
@@ -0,0 +1,529 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use futures::stream::{BoxStream, StreamExt};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Connection, QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+
+use super::table::{quoted, TableRow};
+use super::{
+    connect_with_retry, Candle, FetchStat, Indicator, PageKey, PoolOptions, PortfolioSnapshot,
+    PriceQuery, PriceRow, PriceStore, PriceTable, SourceStats, StockPrice, StorageError,
+};
+
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// Creates the database file if it doesn't exist yet.
+    pub async fn connect(
+        url: &str,
+        options: &PoolOptions,
+        migrate: bool,
+    ) -> Result<Self, StorageError> {
+        let connect_options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        connect_with_retry(options.connect_attempts, || async {
+            SqliteConnection::connect_with(&connect_options)
+                .await?
+                .close()
+                .await
+        })
+        .await?;
+        let pool = SqlitePoolOptions::new()
+            .max_connections(options.max_connections)
+            .acquire_timeout(options.acquire_timeout)
+            .connect_with(connect_options)
+            .await?;
+        if migrate {
+            sqlx::migrate!("../migrations/sqlite").run(&pool).await?;
+        }
+        Ok(Self { pool })
+    }
+}
+
+/// Rows per multi-row `VALUES` insert, well under SQLite's bound-parameter cap.
+const ROWS_PER_STATEMENT: usize = 1000;
+
+/// Fixed-width RFC 3339, so comparing the stored text compares the instants.
+fn ts(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+#[async_trait]
+impl PriceStore for SqliteStore {
+    fn backend(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn save(&self, price: &StockPrice) -> Result<bool, StorageError> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO stock_prices
+                (symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+            ON CONFLICT (symbol, source, timestamp) DO NOTHING
+            "#,
+        )
+        .bind(&price.symbol)
+        .bind(price.price)
+        .bind(&price.source)
+        .bind(ts(price.timestamp))
+        .bind(price.open)
+        .bind(price.high)
+        .bind(price.low)
+        .bind(price.prev_close)
+        .bind(&price.currency)
+        .bind(price.raw_price)
+        .bind(price.stale)
+        .bind(price.anomaly)
+        .execute(&self.pool)
+        .await
+        .map_err(|error| StorageError::Insert {
+            symbol: price.symbol.clone(),
+            provider: price.source.clone(),
+            error,
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Multi-row `VALUES` inserts of `ROWS_PER_STATEMENT` rows, all in one transaction.
+    async fn save_batch(&self, prices: &[StockPrice]) -> Result<u64, StorageError> {
+        let batch = |error| StorageError::Batch {
+            rows: prices.len(),
+            error,
+        };
+
+        let mut tx = self.pool.begin().await.map_err(batch)?;
+        let mut written = 0;
+        for chunk in prices.chunks(ROWS_PER_STATEMENT) {
+            let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+                "INSERT INTO stock_prices \
+                 (symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly) ",
+            );
+            query.push_values(chunk, |mut row, price| {
+                row.push_bind(&price.symbol)
+                    .push_bind(price.price)
+                    .push_bind(&price.source)
+                    .push_bind(ts(price.timestamp))
+                    .push_bind(price.open)
+                    .push_bind(price.high)
+                    .push_bind(price.low)
+                    .push_bind(price.prev_close)
+                    .push_bind(&price.currency)
+                    .push_bind(price.raw_price)
+                    .push_bind(price.stale)
+                    .push_bind(price.anomaly);
+            });
+            query.push(" ON CONFLICT (symbol, source, timestamp) DO NOTHING");
+            written += query
+                .build()
+                .execute(&mut *tx)
+                .await
+                .map_err(batch)?
+                .rows_affected();
+        }
+        tx.commit().await.map_err(batch)?;
+        Ok(written)
+    }
+
+    async fn latest_per_symbol_source(&self) -> Result<Vec<StockPrice>, StorageError> {
+        let rows: Vec<PriceRow> = sqlx::query_as(
+            r#"
+            SELECT symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly
+            FROM (
+                SELECT symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly,
+                    ROW_NUMBER() OVER (PARTITION BY symbol, source ORDER BY timestamp DESC) AS rn
+                FROM stock_prices
+            )
+            WHERE rn = 1
+            ORDER BY symbol, source
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(StockPrice::from).collect())
+    }
+
+    async fn latest_in(&self, table: &PriceTable) -> Result<Vec<StockPrice>, StorageError> {
+        let columns = &table.columns;
+        let sql = format!(
+            "SELECT * FROM (
+                SELECT {}, ROW_NUMBER() OVER (PARTITION BY {symbol}, {source} ORDER BY {timestamp} DESC) AS rn
+                FROM {}
+            )
+            WHERE rn = 1
+            ORDER BY symbol, source",
+            table.select_list(),
+            quoted(&table.name),
+            symbol = quoted(&columns.symbol),
+            source = quoted(&columns.source),
+            timestamp = quoted(&columns.timestamp),
+        );
+        let rows: Vec<TableRow> = sqlx::query_as(&sql).fetch_all(&self.pool).await?;
+        Ok(rows.into_iter().map(StockPrice::from).collect())
+    }
+
+    async fn since_in(
+        &self,
+        table: &PriceTable,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<StockPrice>, StorageError> {
+        let timestamp = quoted(&table.columns.timestamp);
+        let sql = format!(
+            "SELECT {} FROM {} WHERE {timestamp} >= ?1 ORDER BY {timestamp}",
+            table.select_list(),
+            quoted(&table.name),
+        );
+        let rows: Vec<TableRow> = sqlx::query_as(&sql)
+            .bind(ts(since))
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(StockPrice::from).collect())
+    }
+
+    async fn history(
+        &self,
+        symbol: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<StockPrice>, StorageError> {
+        let rows: Vec<PriceRow> = sqlx::query_as(
+            r#"
+            SELECT symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly
+            FROM stock_prices
+            WHERE symbol = ?1 AND timestamp >= ?2
+            ORDER BY timestamp
+            "#,
+        )
+        .bind(symbol)
+        .bind(ts(since))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(StockPrice::from).collect())
+    }
+
+    fn stream<'a>(
+        &'a self,
+        query: &'a PriceQuery,
+    ) -> BoxStream<'a, Result<StockPrice, StorageError>> {
+        sqlx::query_as::<_, PriceRow>(
+            r#"
+            SELECT symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly
+            FROM stock_prices
+            WHERE (?1 IS NULL OR symbol = ?1)
+              AND (?2 IS NULL OR source = ?2)
+              AND (?3 IS NULL OR timestamp >= ?3)
+              AND (?4 IS NULL OR timestamp < ?4)
+            ORDER BY timestamp, symbol, source
+            "#,
+        )
+        .bind(&query.symbol)
+        .bind(&query.source)
+        .bind(query.since.map(ts))
+        .bind(query.until.map(ts))
+        .fetch(&self.pool)
+        .map(|row| row.map(StockPrice::from).map_err(StorageError::from))
+        .boxed()
+    }
+
+    async fn page_after(
+        &self,
+        symbols: &[String],
+        after: &PageKey,
+        limit: u32,
+    ) -> Result<Vec<StockPrice>, StorageError> {
+        let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "SELECT symbol, price, source, timestamp, open, high, low, prev_close, currency, \
+             raw_price, stale, anomaly FROM stock_prices WHERE (timestamp, symbol, source) > (",
+        );
+        query
+            .push_bind(ts(after.timestamp))
+            .push(", ")
+            .push_bind(&after.symbol)
+            .push(", ")
+            .push_bind(&after.source)
+            .push(")");
+        if !symbols.is_empty() {
+            query.push(" AND symbol IN (");
+            let mut list = query.separated(", ");
+            for symbol in symbols {
+                list.push_bind(symbol);
+            }
+            query.push(")");
+        }
+        query
+            .push(" ORDER BY timestamp, symbol, source LIMIT ")
+            .push_bind(i64::from(limit));
+        let rows: Vec<PriceRow> = query.build_query_as().fetch_all(&self.pool).await?;
+        Ok(rows.into_iter().map(StockPrice::from).collect())
+    }
+
+    async fn recent(
+        &self,
+        symbol: &str,
+        source: Option<&str>,
+        before: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<Vec<StockPrice>, StorageError> {
+        let rows: Vec<PriceRow> = sqlx::query_as(
+            r#"
+            SELECT symbol, price, source, timestamp, open, high, low, prev_close, currency, raw_price, stale, anomaly
+            FROM stock_prices
+            WHERE symbol = ?1 AND (?2 IS NULL OR source = ?2) AND timestamp < ?3
+            ORDER BY timestamp DESC
+            LIMIT ?4
+            "#,
+        )
+        .bind(symbol)
+        .bind(source)
+        .bind(ts(before))
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().rev().map(StockPrice::from).collect())
+    }
+
+    async fn save_indicators(&self, rows: &[Indicator]) -> Result<u64, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let mut written = 0;
+        for chunk in rows.chunks(ROWS_PER_STATEMENT) {
+            let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+                "INSERT INTO stock_indicators (symbol, source, kind, period, timestamp, value) ",
+            );
+            query.push_values(chunk, |mut row, indicator| {
+                row.push_bind(&indicator.symbol)
+                    .push_bind(&indicator.source)
+                    .push_bind(indicator.kind)
+                    .push_bind(i64::from(indicator.period))
+                    .push_bind(ts(indicator.timestamp))
+                    .push_bind(indicator.value);
+            });
+            query.push(" ON CONFLICT (symbol, source, kind, period, timestamp) DO NOTHING");
+            written += query.build().execute(&mut *tx).await?.rows_affected();
+        }
+        tx.commit().await?;
+        Ok(written)
+    }
+
+    async fn save_candles(&self, candles: &[Candle]) -> Result<u64, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let mut written = 0;
+        for chunk in candles.chunks(ROWS_PER_STATEMENT) {
+            let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+                "INSERT INTO stock_candles \
+                 (symbol, source, interval, bucket_start, open, high, low, close, samples) ",
+            );
+            query.push_values(chunk, |mut row, candle| {
+                row.push_bind(&candle.symbol)
+                    .push_bind(&candle.source)
+                    .push_bind(candle.interval)
+                    .push_bind(ts(candle.bucket_start))
+                    .push_bind(candle.open)
+                    .push_bind(candle.high)
+                    .push_bind(candle.low)
+                    .push_bind(candle.close)
+                    .push_bind(i64::from(candle.samples));
+            });
+            query.push(" ON CONFLICT (symbol, source, interval, bucket_start) DO NOTHING");
+            written += query.build().execute(&mut *tx).await?.rows_affected();
+        }
+        tx.commit().await?;
+        Ok(written)
+    }
+
+    async fn save_fetch_stats(&self, stats: &[FetchStat]) -> Result<u64, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let mut written = 0;
+        for chunk in stats.chunks(ROWS_PER_STATEMENT) {
+            let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+                "INSERT INTO fetch_stats (symbol, source, ts, ok, latency_ms, error_kind) ",
+            );
+            query.push_values(chunk, |mut row, stat| {
+                row.push_bind(&stat.symbol)
+                    .push_bind(&stat.source)
+                    .push_bind(ts(stat.timestamp))
+                    .push_bind(stat.ok)
+                    .push_bind(stat.latency_ms)
+                    .push_bind(stat.error_kind);
+            });
+            written += query.build().execute(&mut *tx).await?.rows_affected();
+        }
+        tx.commit().await?;
+        Ok(written)
+    }
+
+    async fn save_portfolio_snapshot(
+        &self,
+        snapshot: &PortfolioSnapshot,
+    ) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            INSERT INTO portfolio_snapshots
+                (ts, total_value, total_cost, pnl, stale_positions, positions)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            "#,
+        )
+        .bind(ts(snapshot.timestamp))
+        .bind(snapshot.total_value)
+        .bind(snapshot.total_cost)
+        .bind(snapshot.pnl)
+        .bind(snapshot.stale_positions)
+        .bind(snapshot.positions.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn save_daily_report(
+        &self,
+        day: NaiveDate,
+        generated_at: DateTime<Utc>,
+        report: &serde_json::Value,
+    ) -> Result<bool, StorageError> {
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO daily_reports (day, generated_at, report)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT (day) DO NOTHING
+            "#,
+        )
+        .bind(day.to_string())
+        .bind(ts(generated_at))
+        .bind(report.to_string())
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(inserted > 0)
+    }
+
+    async fn last_daily_report(&self) -> Result<Option<NaiveDate>, StorageError> {
+        let day: Option<String> = sqlx::query_scalar("SELECT MAX(day) FROM daily_reports")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(day.and_then(|day| day.parse().ok()))
+    }
+
+    /// No `percentile_disc` in SQLite: the p95 is the smallest latency ranked at or past
+    /// 95 % of the source's attempts.
+    async fn source_stats(&self, query: &PriceQuery) -> Result<Vec<SourceStats>, StorageError> {
+        let rows: Vec<(String, i64, i64, Option<i64>)> = sqlx::query_as(
+            r#"
+            WITH ranked AS (
+                SELECT source, ok, latency_ms,
+                    ROW_NUMBER() OVER (PARTITION BY source ORDER BY latency_ms) AS rank,
+                    COUNT(*) OVER (PARTITION BY source) AS attempts
+                FROM fetch_stats
+                WHERE (?1 IS NULL OR symbol = ?1)
+                  AND (?2 IS NULL OR source = ?2)
+                  AND (?3 IS NULL OR ts >= ?3)
+                  AND (?4 IS NULL OR ts < ?4)
+            )
+            SELECT source, COUNT(*), SUM(ok),
+                MIN(CASE WHEN rank * 100 >= attempts * 95 THEN latency_ms END)
+            FROM ranked
+            GROUP BY source
+            ORDER BY source
+            "#,
+        )
+        .bind(&query.symbol)
+        .bind(&query.source)
+        .bind(query.since.map(ts))
+        .bind(query.until.map(ts))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(
+                |(source, attempts, successes, p95_latency_ms)| SourceStats {
+                    source,
+                    attempts,
+                    successes,
+                    p95_latency_ms,
+                },
+            )
+            .collect())
+    }
+
+    async fn prune_before(&self, cutoff: DateTime<Utc>, limit: u32) -> Result<u64, StorageError> {
+        let deleted = sqlx::query(
+            r#"
+            DELETE FROM stock_prices
+            WHERE id IN (
+                SELECT id FROM stock_prices
+                WHERE timestamp < ?1
+                LIMIT ?2
+            )
+            "#,
+        )
+        .bind(ts(cutoff))
+        .bind(i64::from(limit))
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(deleted)
+    }
+
+    /// Same roll-up as the Postgres one, without `date_bin` or `array_agg`: buckets are
+    /// computed on epoch seconds and open/close looked up by the first/last timestamp.
+    async fn roll_up_candles(&self, label: &str, secs: u32) -> Result<u64, StorageError> {
+        let written = sqlx::query(
+            r#"
+            WITH b AS (
+                SELECT symbol, source, price, timestamp,
+                    (CAST(strftime('%s', timestamp) AS INTEGER) / ?2) * ?2 AS bucket
+                FROM stock_prices sp
+                WHERE timestamp >= COALESCE(
+                    (SELECT MAX(bucket_start) FROM stock_candles c
+                     WHERE c.symbol = sp.symbol AND c.source = sp.source AND c.interval = ?1),
+                    ''
+                )
+            ),
+            agg AS (
+                SELECT symbol, source, bucket,
+                    MAX(price) AS high, MIN(price) AS low, COUNT(*) AS samples,
+                    MIN(timestamp) AS first_ts, MAX(timestamp) AS last_ts
+                FROM b
+                GROUP BY symbol, source, bucket
+            )
+            INSERT INTO stock_candles
+                (symbol, source, interval, bucket_start, open, high, low, close, samples)
+            SELECT
+                a.symbol,
+                a.source,
+                ?1,
+                strftime('%Y-%m-%dT%H:%M:%S.000000Z', a.bucket, 'unixepoch'),
+                (SELECT price FROM b WHERE b.symbol = a.symbol AND b.source = a.source
+                    AND b.timestamp = a.first_ts),
+                a.high,
+                a.low,
+                (SELECT price FROM b WHERE b.symbol = a.symbol AND b.source = a.source
+                    AND b.timestamp = a.last_ts),
+                a.samples
+            FROM agg a
+            WHERE a.bucket + ?2 <= CAST(strftime('%s', 'now') AS INTEGER)
+            ON CONFLICT (symbol, source, interval, bucket_start) DO UPDATE SET
+                open = excluded.open,
+                high = excluded.high,
+                low = excluded.low,
+                close = excluded.close,
+                samples = excluded.samples
+            "#,
+        )
+        .bind(label)
+        .bind(i64::from(secs))
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(written)
+    }
+
+    async fn ping(&self) -> Result<(), StorageError> {
+        sqlx::query("SELECT 1").fetch_one(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+}
@@ -0,0 +1,174 @@
+//! Price tables besides `stock_prices`, such as a `crypto_prices` fed by another writer:
+//! `PriceStore::latest_in` and `PriceStore::since_in` read any table described by a
+//! `PriceTable`, with its own column names where they differ. Table and column names can't
+//! be bound as parameters, so they are checked against `[a-z_][a-z0-9_]*` (63 characters at
+//! most) by `validate` and quoted before they go into the SQL.
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::FromRow;
+
+use crate::StockPrice;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PriceTable {
+    pub name: String,
+    /// Tag of the prices read from this table, such as `stock` or `crypto`.
+    pub asset_class: String,
+    #[serde(default)]
+    pub columns: Columns,
+}
+
+/// Column names of a price table. The day range and `stale` are only read when named.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Columns {
+    pub symbol: String,
+    pub price: String,
+    pub source: String,
+    pub timestamp: String,
+    pub open: Option<String>,
+    pub high: Option<String>,
+    pub low: Option<String>,
+    pub prev_close: Option<String>,
+    pub stale: Option<String>,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Self {
+            symbol: "symbol".to_string(),
+            price: "price".to_string(),
+            source: "source".to_string(),
+            timestamp: "timestamp".to_string(),
+            open: None,
+            high: None,
+            low: None,
+            prev_close: None,
+            stale: None,
+        }
+    }
+}
+
+impl PriceTable {
+    /// `stock_prices`, with every column it has.
+    pub fn stock_prices() -> Self {
+        let named = |column: &str| Some(column.to_string());
+        Self {
+            name: "stock_prices".to_string(),
+            asset_class: "stock".to_string(),
+            columns: Columns {
+                open: named("open"),
+                high: named("high"),
+                low: named("low"),
+                prev_close: named("prev_close"),
+                stale: named("stale"),
+                ..Columns::default()
+            },
+        }
+    }
+
+    /// Every name safe to put in SQL, and an asset class.
+    pub fn validate(&self) -> Result<(), String> {
+        let columns = &self.columns;
+        let names = [
+            Some(&self.name),
+            Some(&columns.symbol),
+            Some(&columns.price),
+            Some(&columns.source),
+            Some(&columns.timestamp),
+            columns.open.as_ref(),
+            columns.high.as_ref(),
+            columns.low.as_ref(),
+            columns.prev_close.as_ref(),
+            columns.stale.as_ref(),
+        ];
+        if let Some(name) = names
+            .into_iter()
+            .flatten()
+            .find(|name| !is_identifier(name))
+        {
+            return Err(format!(
+                "table {}: '{name}' is not a valid name (lowercase letters, digits and _)",
+                self.name
+            ));
+        }
+        if self.asset_class.trim().is_empty() {
+            return Err(format!("table {}: asset_class is empty", self.name));
+        }
+        Ok(())
+    }
+
+    /// The columns of `TableRow`, `NULL` or false for those the table doesn't name.
+    pub(super) fn select_list(&self) -> String {
+        let columns = &self.columns;
+        let optional = |column: &Option<String>, name: &str| match column {
+            Some(column) => format!("CAST({} AS DOUBLE PRECISION) AS {name}", quoted(column)),
+            None => format!("CAST(NULL AS DOUBLE PRECISION) AS {name}"),
+        };
+        [
+            format!("{} AS symbol", quoted(&columns.symbol)),
+            format!(
+                "CAST({} AS DOUBLE PRECISION) AS price",
+                quoted(&columns.price)
+            ),
+            format!("{} AS source", quoted(&columns.source)),
+            format!("{} AS timestamp", quoted(&columns.timestamp)),
+            optional(&columns.open, "open"),
+            optional(&columns.high, "high"),
+            optional(&columns.low, "low"),
+            optional(&columns.prev_close, "prev_close"),
+            match &columns.stale {
+                Some(column) => format!("{} AS stale", quoted(column)),
+                None => "FALSE AS stale".to_string(),
+            },
+        ]
+        .join(", ")
+    }
+}
+
+/// Lowercase identifier Postgres and SQLite both take as is.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_lowercase() || first == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && name.len() <= 63
+}
+
+/// For names already validated, so there is no quote to escape.
+pub(super) fn quoted(name: &str) -> String {
+    format!("\"{name}\"")
+}
+
+#[derive(FromRow)]
+pub(super) struct TableRow {
+    symbol: String,
+    price: f64,
+    source: String,
+    timestamp: DateTime<Utc>,
+    open: Option<f64>,
+    high: Option<f64>,
+    low: Option<f64>,
+    prev_close: Option<f64>,
+    stale: bool,
+}
+
+impl From<TableRow> for StockPrice {
+    fn from(row: TableRow) -> Self {
+        Self {
+            symbol: row.symbol,
+            price: row.price,
+            source: row.source,
+            timestamp: row.timestamp,
+            open: row.open,
+            high: row.high,
+            low: row.low,
+            prev_close: row.prev_close,
+            stale: row.stale,
+            ..Default::default()
+        }
+    }
+}
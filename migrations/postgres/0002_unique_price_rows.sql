@@ -0,0 +1,10 @@
+-- Same quote stored twice (provider repeating its last timestamp): keep the oldest row
+DELETE FROM stock_prices a
+USING stock_prices b
+WHERE a.id > b.id
+  AND a.symbol = b.symbol
+  AND a.source = b.source
+  AND a.timestamp = b.timestamp;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_symbol_source_timestamp
+    ON stock_prices(symbol, source, timestamp);
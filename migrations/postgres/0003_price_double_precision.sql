@@ -0,0 +1,2 @@
+-- REAL rounded prices to ~7 significant digits; existing values are widened as-is
+ALTER TABLE stock_prices ALTER COLUMN price TYPE DOUBLE PRECISION;
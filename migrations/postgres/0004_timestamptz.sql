@@ -0,0 +1,5 @@
+-- Epoch seconds -> TIMESTAMPTZ. Existing rows convert exactly; both indexes on
+-- (symbol, ..., timestamp) are rebuilt with the column and keep their ordering.
+-- Readers that still want epoch seconds can select EXTRACT(EPOCH FROM timestamp)::BIGINT.
+ALTER TABLE stock_prices
+    ALTER COLUMN timestamp TYPE TIMESTAMPTZ USING to_timestamp(timestamp);
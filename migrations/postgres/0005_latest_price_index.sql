@@ -0,0 +1,3 @@
+-- Matches the dashboard's DISTINCT ON (symbol, source) ... ORDER BY symbol, source, timestamp DESC
+CREATE INDEX IF NOT EXISTS idx_symbol_source_timestamp_desc
+    ON stock_prices(symbol, source, timestamp DESC);
@@ -0,0 +1,16 @@
+-- OHLC candles rolled up from stock_prices. Only closed buckets are written, and
+-- re-aggregating a bucket overwrites it, so the roll-up can safely re-run.
+CREATE TABLE IF NOT EXISTS stock_candles (
+    symbol VARCHAR(10) NOT NULL,
+    source VARCHAR(50) NOT NULL,
+    interval VARCHAR(8) NOT NULL,
+    bucket_start TIMESTAMPTZ NOT NULL,
+    open DOUBLE PRECISION NOT NULL,
+    high DOUBLE PRECISION NOT NULL,
+    low DOUBLE PRECISION NOT NULL,
+    close DOUBLE PRECISION NOT NULL,
+    samples INTEGER NOT NULL,
+    PRIMARY KEY (symbol, source, interval, bucket_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_candles_interval_bucket ON stock_candles(interval, bucket_start DESC);
@@ -0,0 +1,11 @@
+-- Moving averages computed by the aggregator after each cycle, one row per
+-- (symbol, source, kind, period) at the timestamp of the price that produced it.
+CREATE TABLE IF NOT EXISTS stock_indicators (
+    symbol VARCHAR(10) NOT NULL,
+    source VARCHAR(50) NOT NULL,
+    kind VARCHAR(8) NOT NULL,
+    period INTEGER NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    value DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (symbol, source, kind, period, timestamp)
+);
@@ -0,0 +1,7 @@
+-- Day range and previous close as reported with the quote; NULL for providers that
+-- only give a last price, and for rows written before this migration.
+ALTER TABLE stock_prices
+    ADD COLUMN IF NOT EXISTS open DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS high DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS low DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS prev_close DOUBLE PRECISION;
@@ -0,0 +1,5 @@
+-- Quotes converted to the base currency keep what the provider actually sent;
+-- NULL when the price was already in the base currency.
+ALTER TABLE stock_prices
+    ADD COLUMN IF NOT EXISTS currency VARCHAR(8),
+    ADD COLUMN IF NOT EXISTS raw_price DOUBLE PRECISION;
@@ -0,0 +1,4 @@
+-- Set on quotes the provider kept repeating unchanged cycle after cycle (delayed feed,
+-- closed market), so readers can tell a flat line from a frozen one.
+ALTER TABLE stock_prices
+    ADD COLUMN IF NOT EXISTS stale BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,14 @@
+-- One row per request the aggregator sent to a provider, written once per cycle, to answer
+-- "how reliable has this source been for this symbol" after the fact.
+CREATE TABLE IF NOT EXISTS fetch_stats (
+    id BIGSERIAL PRIMARY KEY,
+    symbol VARCHAR(10) NOT NULL,
+    source VARCHAR(50) NOT NULL,
+    ts TIMESTAMPTZ NOT NULL,
+    ok BOOLEAN NOT NULL,
+    latency_ms INTEGER NOT NULL,
+    -- FetchError kind (`http`, `rate_limited`, `rejected`...) of a failed attempt
+    error_kind VARCHAR(32)
+);
+
+CREATE INDEX IF NOT EXISTS idx_fetch_stats_source_ts ON fetch_stats(source, ts);
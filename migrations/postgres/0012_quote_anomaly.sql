@@ -0,0 +1,4 @@
+-- Set on prices far (in standard deviations) from the recent mean of their symbol and
+-- source: news or a glitch, worth a second look either way.
+ALTER TABLE stock_prices
+    ADD COLUMN IF NOT EXISTS anomaly BOOLEAN NOT NULL DEFAULT FALSE;
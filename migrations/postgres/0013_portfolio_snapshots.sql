@@ -0,0 +1,14 @@
+-- Value of the configured holdings after each fetch cycle. `positions` holds the per-position
+-- detail (quantity, price used, P&L, whether that price was stale) as written by exo4.
+CREATE TABLE IF NOT EXISTS portfolio_snapshots (
+    id BIGSERIAL PRIMARY KEY,
+    ts TIMESTAMPTZ NOT NULL,
+    total_value DOUBLE PRECISION NOT NULL,
+    total_cost DOUBLE PRECISION NOT NULL,
+    pnl DOUBLE PRECISION NOT NULL,
+    -- Positions valued at a stale or missing price
+    stale_positions INTEGER NOT NULL,
+    positions JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_portfolio_snapshots_ts ON portfolio_snapshots(ts);
@@ -0,0 +1,7 @@
+-- End-of-day reports already produced, one per local day, so a restart doesn't report the
+-- same day twice. `report` holds the per-symbol figures as sent.
+CREATE TABLE IF NOT EXISTS daily_reports (
+    day DATE PRIMARY KEY,
+    generated_at TIMESTAMPTZ NOT NULL,
+    report JSONB NOT NULL
+);
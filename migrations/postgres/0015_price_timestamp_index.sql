@@ -0,0 +1,2 @@
+-- Matches the dashboard's incremental poll, WHERE timestamp >= $1 ORDER BY timestamp
+CREATE INDEX IF NOT EXISTS idx_price_timestamp ON stock_prices(timestamp);
@@ -0,0 +1,3 @@
+-- The unique (symbol, source, timestamp) index of 0002 already serves the dashboard's
+-- DISTINCT ON ... ORDER BY symbol, source, timestamp DESC by scanning it backwards
+DROP INDEX IF EXISTS idx_symbol_source_timestamp_desc;
@@ -0,0 +1,6 @@
+-- Ten characters didn't fit longer tickers and pairs such as BRK.B-USD or
+-- BTC-USDT-PERP; the new limit only bumps the type modifier, nothing is rewritten
+ALTER TABLE stock_prices ALTER COLUMN symbol TYPE VARCHAR(32);
+ALTER TABLE stock_candles ALTER COLUMN symbol TYPE VARCHAR(32);
+ALTER TABLE stock_indicators ALTER COLUMN symbol TYPE VARCHAR(32);
+ALTER TABLE fetch_stats ALTER COLUMN symbol TYPE VARCHAR(32);
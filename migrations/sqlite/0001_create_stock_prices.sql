@@ -0,0 +1,29 @@
+-- Same schema as migrations/postgres, for the local SQLite fallback.
+-- Timestamps are stored as fixed-width RFC 3339 UTC text, so text order is time order.
+CREATE TABLE IF NOT EXISTS stock_prices (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    symbol TEXT NOT NULL,
+    price REAL NOT NULL,
+    source TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_symbol_timestamp ON stock_prices(symbol, timestamp DESC);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_symbol_source_timestamp
+    ON stock_prices(symbol, source, timestamp);
+
+CREATE TABLE IF NOT EXISTS stock_candles (
+    symbol TEXT NOT NULL,
+    source TEXT NOT NULL,
+    interval TEXT NOT NULL,
+    bucket_start TEXT NOT NULL,
+    open REAL NOT NULL,
+    high REAL NOT NULL,
+    low REAL NOT NULL,
+    close REAL NOT NULL,
+    samples INTEGER NOT NULL,
+    PRIMARY KEY (symbol, source, interval, bucket_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_candles_interval_bucket ON stock_candles(interval, bucket_start DESC);
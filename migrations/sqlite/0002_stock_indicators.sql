@@ -0,0 +1,10 @@
+-- Same as migrations/postgres/0007_stock_indicators.sql.
+CREATE TABLE IF NOT EXISTS stock_indicators (
+    symbol TEXT NOT NULL,
+    source TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    period INTEGER NOT NULL,
+    timestamp TEXT NOT NULL,
+    value REAL NOT NULL,
+    PRIMARY KEY (symbol, source, kind, period, timestamp)
+);
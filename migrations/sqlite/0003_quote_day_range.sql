@@ -0,0 +1,5 @@
+-- Same as migrations/postgres/0008_quote_day_range.sql.
+ALTER TABLE stock_prices ADD COLUMN open REAL;
+ALTER TABLE stock_prices ADD COLUMN high REAL;
+ALTER TABLE stock_prices ADD COLUMN low REAL;
+ALTER TABLE stock_prices ADD COLUMN prev_close REAL;
@@ -0,0 +1,3 @@
+-- Same as migrations/postgres/0009_quote_currency.sql.
+ALTER TABLE stock_prices ADD COLUMN currency TEXT;
+ALTER TABLE stock_prices ADD COLUMN raw_price REAL;
@@ -0,0 +1,2 @@
+-- Same as migrations/postgres/0010_quote_stale.sql.
+ALTER TABLE stock_prices ADD COLUMN stale BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,12 @@
+-- Same as migrations/postgres/0011_fetch_stats.sql.
+CREATE TABLE IF NOT EXISTS fetch_stats (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    symbol TEXT NOT NULL,
+    source TEXT NOT NULL,
+    ts TEXT NOT NULL,
+    ok BOOLEAN NOT NULL,
+    latency_ms INTEGER NOT NULL,
+    error_kind TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_fetch_stats_source_ts ON fetch_stats(source, ts);
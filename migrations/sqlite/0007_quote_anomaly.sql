@@ -0,0 +1,2 @@
+-- Same as migrations/postgres/0012_quote_anomaly.sql.
+ALTER TABLE stock_prices ADD COLUMN anomaly BOOLEAN NOT NULL DEFAULT FALSE;
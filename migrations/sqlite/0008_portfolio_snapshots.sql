@@ -0,0 +1,12 @@
+-- Same as migrations/postgres/0013_portfolio_snapshots.sql, `positions` as JSON text.
+CREATE TABLE IF NOT EXISTS portfolio_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    ts TEXT NOT NULL,
+    total_value REAL NOT NULL,
+    total_cost REAL NOT NULL,
+    pnl REAL NOT NULL,
+    stale_positions INTEGER NOT NULL,
+    positions TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_portfolio_snapshots_ts ON portfolio_snapshots(ts);
@@ -0,0 +1,7 @@
+-- Same as migrations/postgres/0014_daily_reports.sql, `day` as YYYY-MM-DD and `report` as
+-- JSON text.
+CREATE TABLE IF NOT EXISTS daily_reports (
+    day TEXT PRIMARY KEY,
+    generated_at TEXT NOT NULL,
+    report TEXT NOT NULL
+);
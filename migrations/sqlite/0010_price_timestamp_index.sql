@@ -0,0 +1,2 @@
+-- Same as migrations/postgres/0015_price_timestamp_index.sql.
+CREATE INDEX IF NOT EXISTS idx_price_timestamp ON stock_prices(timestamp);
@@ -5,11 +5,12 @@
 
 ---*/
 use dotenv::dotenv;
-use reqwest;
+use market_core::store::{self, PoolOptions};
+use market_core::StockPrice;
+use reqwest::StatusCode;
 use serde::Deserialize;
-use sqlx::postgres::PgPoolOptions;
-use sqlx::PgPool;
 use std::env;
+use std::time::Duration;
 
 #[derive(Deserialize, Debug)]
 struct GlobalQuote {
@@ -19,8 +20,6 @@ struct GlobalQuote {
 
 #[derive(Deserialize, Debug)]
 struct Quote {
-    #[serde(rename = "01. symbol")]
-    symbol: String,
     #[serde(rename = "05. price")]
     price: String,
 }
@@ -38,47 +37,89 @@ struct FinnhubQuote {
     c: f64, // current price
 }
 
-#[derive(Debug, Clone)]
-struct StockPrice {
-    symbol: String,
-    price: f64,
-    source: String,
-    timestamp: i64,
+#[derive(Debug, thiserror::Error)]
+enum FetchError {
+    #[error("{provider}: rate limited{}", retry_hint(.retry_after))]
+    RateLimited {
+        provider: &'static str,
+        retry_after: Option<Duration>,
+    },
+    #[error("{provider}: authentication failed: {message}")]
+    Auth {
+        provider: &'static str,
+        message: String,
+    },
+    #[error("{provider}: unknown symbol {symbol}")]
+    UnknownSymbol {
+        provider: &'static str,
+        symbol: String,
+    },
+    #[error("{provider}: API error for {symbol}: {message}")]
+    Api {
+        provider: &'static str,
+        symbol: String,
+        message: String,
+    },
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("invalid response: {0}")]
+    Parse(String),
 }
 
-async fn save_price(pool: &PgPool, price: &StockPrice) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO stock_prices (symbol, price, source, timestamp)
-        VALUES ($1, $2, $3, $4)
-        "#,
-        price.symbol,
-        price.price as f32,
-        price.source,
-        price.timestamp
-    )
-    .execute(pool)
-    .await?;
+fn retry_hint(retry_after: &Option<Duration>) -> String {
+    retry_after
+        .map(|after| format!(", retry after {}s", after.as_secs()))
+        .unwrap_or_default()
+}
 
-    Ok(())
+impl From<serde_json::Error> for FetchError {
+    fn from(e: serde_json::Error) -> Self {
+        FetchError::Parse(e.to_string())
+    }
+}
+
+impl From<std::num::ParseFloatError> for FetchError {
+    fn from(e: std::num::ParseFloatError) -> Self {
+        FetchError::Parse(e.to_string())
+    }
 }
 
-async fn fetch_alpha_vantage(symbol: &str) -> Result<StockPrice, Box<dyn std::error::Error>> {
-    let api_key = env::var("ALPHA_VANTAGE_API_KEY")?;
+fn api_key(provider: &'static str, var: &str) -> Result<String, FetchError> {
+    env::var(var).map_err(|_| FetchError::Auth {
+        provider,
+        message: format!("{var} is not set"),
+    })
+}
+
+async fn fetch_alpha_vantage(symbol: &str) -> Result<StockPrice, FetchError> {
+    let api_key = api_key("alpha_vantage", "ALPHA_VANTAGE_API_KEY")?;
     let url = format!(
         "https://www.alphavantage.co/query?function=GLOBAL_QUOTE&symbol={}&apikey={}",
         symbol, api_key
     );
 
-    let text = reqwest::get(&url).await?.text().await?;
+    let text = reqwest::get(&url).await?.error_for_status()?.text().await?;
 
     // Check for rate limit or error message
     if let Ok(error) = serde_json::from_str::<AlphaVantageError>(&text) {
-        if let Some(info) = error.information {
-            return Err(format!("Rate limit: {}", info).into());
+        if error.information.is_some() {
+            return Err(FetchError::RateLimited {
+                provider: "alpha_vantage",
+                retry_after: None,
+            });
         }
-        if let Some(msg) = error.error_message {
-            return Err(format!("API error: {}", msg).into());
+        if let Some(message) = error.error_message {
+            if message.contains("apikey") {
+                return Err(FetchError::Auth {
+                    provider: "alpha_vantage",
+                    message,
+                });
+            }
+            return Err(FetchError::Api {
+                provider: "alpha_vantage",
+                symbol: symbol.to_string(),
+                message,
+            });
         }
     }
 
@@ -89,24 +130,50 @@ async fn fetch_alpha_vantage(symbol: &str) -> Result<StockPrice, Box<dyn std::er
         symbol: symbol.to_string(),
         price,
         source: "alpha_vantage".to_string(),
-        timestamp: chrono::Utc::now().timestamp(),
+        timestamp: chrono::Utc::now(),
+        ..Default::default()
     })
 }
 
-async fn fetch_finnhub(symbol: &str) -> Result<StockPrice, Box<dyn std::error::Error>> {
-    let api_key = env::var("FINNHUB_API_KEY")?;
+async fn fetch_finnhub(symbol: &str) -> Result<StockPrice, FetchError> {
+    let api_key = api_key("finnhub", "FINNHUB_API_KEY")?;
     let url = format!(
         "https://finnhub.io/api/v1/quote?symbol={}&token={}",
         symbol, api_key
     );
 
-    let resp = reqwest::get(&url).await?.json::<FinnhubQuote>().await?;
+    let resp = reqwest::get(&url).await?;
+    match resp.status() {
+        StatusCode::TOO_MANY_REQUESTS => {
+            return Err(FetchError::RateLimited {
+                provider: "finnhub",
+                retry_after: None,
+            });
+        }
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            return Err(FetchError::Auth {
+                provider: "finnhub",
+                message: resp.status().to_string(),
+            });
+        }
+        _ => {}
+    }
+    let resp = resp.error_for_status()?.json::<FinnhubQuote>().await?;
+
+    // Finnhub answers unknown symbols with an all-zero quote
+    if resp.c == 0.0 {
+        return Err(FetchError::UnknownSymbol {
+            provider: "finnhub",
+            symbol: symbol.to_string(),
+        });
+    }
 
     Ok(StockPrice {
         symbol: symbol.to_string(),
         price: resp.c,
         source: "finnhub".to_string(),
-        timestamp: chrono::Utc::now().timestamp(),
+        timestamp: chrono::Utc::now(),
+        ..Default::default()
     })
 }
 
@@ -114,15 +181,21 @@ async fn fetch_finnhub(symbol: &str) -> Result<StockPrice, Box<dyn std::error::E
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Load environment variables
     dotenv().ok();
+    // Only for the connection retries logged by the shared store
+    tracing_subscriber::fmt().with_target(false).init();
 
     println!("Stock Price Aggregator with PostgreSQL\n");
 
     // Connect to database
     let database_url = env::var("DATABASE_URL")?;
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await?;
+    if !database_url.starts_with("postgres") {
+        return Err("exo3 needs a PostgreSQL DATABASE_URL".into());
+    }
+    // Schema lives in migrations/ at the workspace root; --skip-migrations when the
+    // database user has no DDL rights. Waits for the database if it isn't up yet, pool
+    // settings from DB_* variables
+    let migrate = !std::env::args().any(|arg| arg == "--skip-migrations");
+    let store = store::connect(&database_url, &PoolOptions::default().with_env()?, migrate).await?;
 
     println!("Connected to database\n");
 
@@ -139,7 +212,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         // Save Alpha Vantage result
         match alpha_result {
-            Ok(price) => match save_price(&pool, &price).await {
+            Ok(price) => match store.save(&price).await {
                 Ok(_) => println!("  [OK] Saved Alpha Vantage: ${:.2}", price.price),
                 Err(e) => println!("  [ERROR] Failed to save Alpha Vantage: {}", e),
             },
@@ -148,7 +221,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         // Save Finnhub result
         match finnhub_result {
-            Ok(price) => match save_price(&pool, &price).await {
+            Ok(price) => match store.save(&price).await {
                 Ok(_) => println!("  [OK] Saved Finnhub: ${:.2}", price.price),
                 Err(e) => println!("  [ERROR] Failed to save Finnhub: {}", e),
             },
@@ -159,23 +232,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // Query and display saved data
-    println!("\nRecent stock prices from database:\n");
-
-    let rows = sqlx::query!(
-        r#"
-        SELECT symbol, price, source, timestamp
-        FROM stock_prices
-        ORDER BY id DESC
-        LIMIT 10
-        "#
-    )
-    .fetch_all(&pool)
-    .await?;
+    println!("\nLatest stock prices from database:\n");
+
+    let rows = store.latest_per_symbol_source().await?;
 
     for row in rows {
-        let dt = chrono::DateTime::from_timestamp(row.timestamp, 0)
-            .unwrap()
-            .format("%Y-%m-%d %H:%M:%S");
+        let dt = row.timestamp.format("%Y-%m-%d %H:%M:%S");
         println!(
             "  {} | ${:>8.2} | {:>15} | {}",
             row.symbol, row.price, row.source, dt
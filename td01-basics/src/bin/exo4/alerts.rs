@@ -0,0 +1,104 @@
+//! Price-move alerts: a symbol moving more than its threshold within the alert window.
+
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use crate::config::AlertsConfig;
+use crate::sources::StockPrice;
+use crate::webhook;
+
+/// `(quote time, price)`, oldest first.
+type Samples = VecDeque<(DateTime<Utc>, f64)>;
+
+/// Recent quotes per (symbol, source), trimmed to the alert window. Sources are
+/// kept apart so two providers disagreeing doesn't look like a move.
+#[derive(Default)]
+pub struct MoveAlerts {
+    windows: HashMap<(String, String), Samples>,
+    last_alert: HashMap<String, DateTime<Utc>>,
+}
+
+impl MoveAlerts {
+    pub fn check(&mut self, cfg: &AlertsConfig, prices: &[StockPrice]) {
+        let window = chrono::Duration::from_std(cfg.window).unwrap_or(chrono::Duration::MAX);
+        let cooldown = chrono::Duration::from_std(cfg.cooldown).unwrap_or(chrono::Duration::MAX);
+
+        for price in prices {
+            let Some(threshold_pct) = cfg.thresholds.get(&price.symbol).copied().or(cfg.move_pct)
+            else {
+                continue;
+            };
+            let samples = self
+                .windows
+                .entry((price.symbol.clone(), price.source.clone()))
+                .or_default();
+            // Providers repeat their last quote when nothing traded; that is no new sample.
+            if samples.back().is_some_and(|(at, _)| *at >= price.timestamp) {
+                continue;
+            }
+            while samples
+                .front()
+                .is_some_and(|(at, _)| price.timestamp - *at > window)
+            {
+                samples.pop_front();
+            }
+            samples.push_back((price.timestamp, price.price));
+
+            let (since, reference) = samples[0];
+            if samples.len() < 2 || reference <= 0.0 {
+                continue;
+            }
+            let change_pct = (price.price - reference) / reference * 100.0;
+            if change_pct.abs() < threshold_pct {
+                continue;
+            }
+
+            let now = Utc::now();
+            if let Some(last) = self.last_alert.get(&price.symbol) {
+                if now - *last < cooldown {
+                    debug!(symbol = %price.symbol, change_pct, "Move alert suppressed by cooldown");
+                    continue;
+                }
+            }
+            self.last_alert.insert(price.symbol.clone(), now);
+
+            warn!(
+                symbol = %price.symbol,
+                source = %price.source,
+                old_price = reference,
+                new_price = price.price,
+                change_pct,
+                since = %since,
+                "Price moved past alert threshold"
+            );
+            if let Some(url) = &cfg.webhook {
+                webhook::post(
+                    url,
+                    "price_move",
+                    json!({
+                        "kind": "price_move",
+                        "text": format!(
+                            "{} {:+.2}% ({} -> {}) on {} since {}",
+                            price.symbol,
+                            change_pct,
+                            reference,
+                            price.price,
+                            price.source,
+                            since.to_rfc3339()
+                        ),
+                        "symbol": price.symbol,
+                        "source": price.source,
+                        "old_price": reference,
+                        "new_price": price.price,
+                        "change_pct": change_pct,
+                        "since": since,
+                        "timestamp": price.timestamp,
+                    }),
+                );
+            }
+        }
+    }
+}
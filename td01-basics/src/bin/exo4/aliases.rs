@@ -0,0 +1,71 @@
+//! Per-provider tickers: the same instrument is `BRK.B` on one API and `BRK-B` on another.
+//! The wrapped source only ever sees its own ticker; what comes out of it carries the
+//! canonical symbol again, so storage, alerts and the dashboard never see aliases.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::sources::{AssetClass, FetchError, PriceSource, StockPrice};
+
+pub struct Aliased {
+    inner: Box<dyn PriceSource>,
+    /// Canonical symbol -> provider ticker; symbols not listed are sent as they are.
+    tickers: HashMap<String, String>,
+}
+
+impl Aliased {
+    pub fn new(inner: Box<dyn PriceSource>, tickers: HashMap<String, String>) -> Self {
+        Self { inner, tickers }
+    }
+
+    fn ticker<'a>(&'a self, symbol: &'a str) -> &'a str {
+        self.tickers
+            .get(symbol)
+            .map(String::as_str)
+            .unwrap_or(symbol)
+    }
+}
+
+fn canonical(
+    mut result: Result<StockPrice, FetchError>,
+    symbol: &str,
+) -> Result<StockPrice, FetchError> {
+    if let Ok(price) = &mut result {
+        price.symbol = symbol.to_string();
+    }
+    result
+}
+
+#[async_trait]
+impl PriceSource for Aliased {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn asset_classes(&self) -> &'static [AssetClass] {
+        self.inner.asset_classes()
+    }
+
+    fn has_batch_endpoint(&self) -> bool {
+        self.inner.has_batch_endpoint()
+    }
+
+    fn is_available(&self) -> bool {
+        self.inner.is_available()
+    }
+
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        canonical(self.inner.fetch(self.ticker(symbol)).await, symbol)
+    }
+
+    async fn fetch_many(&self, symbols: &[&str]) -> Vec<Result<StockPrice, FetchError>> {
+        let tickers: Vec<&str> = symbols.iter().map(|symbol| self.ticker(symbol)).collect();
+        let results = self.inner.fetch_many(&tickers).await;
+        results
+            .into_iter()
+            .zip(symbols)
+            .map(|(result, symbol)| canonical(result, symbol))
+            .collect()
+    }
+}
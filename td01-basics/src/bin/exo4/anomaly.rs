@@ -0,0 +1,140 @@
+//! Prices far from what their source has been quoting lately: real news or a data glitch.
+//! Unlike `validate` nothing is rejected; the price is stored with `anomaly` set.
+
+use std::collections::{HashMap, VecDeque};
+
+use market_core::store::PriceStore;
+use serde_json::json;
+use tracing::warn;
+
+use crate::config::AnomalyConfig;
+use crate::cycle::FetchOutcome;
+use crate::sources::StockPrice;
+use crate::webhook;
+
+/// Recent prices per (symbol, source), oldest first. A pair seen for the first time is
+/// warmed up from `stock_prices`, so a restart doesn't go blind for a whole window.
+#[derive(Default)]
+pub struct Anomalies {
+    windows: HashMap<(String, &'static str), VecDeque<f64>>,
+}
+
+impl Anomalies {
+    /// Sets `anomaly` on prices whose z-score against their window passes the threshold.
+    /// Stale quotes are left out: a repeated price says nothing new.
+    pub async fn check(
+        &mut self,
+        store: &dyn PriceStore,
+        cfg: &AnomalyConfig,
+        webhook_url: Option<&str>,
+        outcomes: &mut [FetchOutcome],
+    ) {
+        if !cfg.enabled {
+            return;
+        }
+        for outcome in outcomes {
+            let Ok(price) = &mut outcome.result else {
+                continue;
+            };
+            if price.stale {
+                continue;
+            }
+            let key = (price.symbol.clone(), outcome.source);
+            if !self.windows.contains_key(&key) {
+                let window = warm_up(store, cfg, price).await;
+                self.windows.insert(key.clone(), window);
+            }
+            let Some(window) = self.windows.get_mut(&key) else {
+                continue;
+            };
+
+            if let Some((mean, std_dev)) = stats(window, cfg.min_samples) {
+                let z_score = (price.price - mean) / std_dev;
+                if z_score.abs() > cfg.z_threshold {
+                    price.anomaly = true;
+                    report(price, mean, std_dev, z_score, cfg, webhook_url);
+                }
+            }
+            window.push_back(price.price);
+            while window.len() > cfg.window {
+                window.pop_front();
+            }
+        }
+    }
+}
+
+/// Mean and sample standard deviation, once the window has warmed up and isn't flat.
+fn stats(window: &VecDeque<f64>, min_samples: usize) -> Option<(f64, f64)> {
+    if window.len() < min_samples.max(2) {
+        return None;
+    }
+    let n = window.len() as f64;
+    let mean = window.iter().sum::<f64>() / n;
+    let variance = window.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    let std_dev = variance.sqrt();
+    (std_dev > 0.0).then_some((mean, std_dev))
+}
+
+fn report(
+    price: &StockPrice,
+    mean: f64,
+    std_dev: f64,
+    z_score: f64,
+    cfg: &AnomalyConfig,
+    webhook_url: Option<&str>,
+) {
+    warn!(
+        symbol = %price.symbol,
+        source = %price.source,
+        price = price.price,
+        mean,
+        std_dev,
+        z_score,
+        threshold = cfg.z_threshold,
+        "Price anomaly"
+    );
+    let Some(url) = webhook_url.filter(|_| cfg.webhook) else {
+        return;
+    };
+    webhook::post(
+        url,
+        "anomaly",
+        json!({
+            "kind": "anomaly",
+            "text": format!(
+                "{} at {} on {} is {:+.1} standard deviations from its recent mean {:.4}",
+                price.symbol, price.price, price.source, z_score, mean
+            ),
+            "symbol": price.symbol,
+            "source": price.source,
+            "price": price.price,
+            "mean": mean,
+            "std_dev": std_dev,
+            "z_score": z_score,
+            "timestamp": price.timestamp,
+        }),
+    );
+}
+
+async fn warm_up(store: &dyn PriceStore, cfg: &AnomalyConfig, price: &StockPrice) -> VecDeque<f64> {
+    let limit = u32::try_from(cfg.window).unwrap_or(u32::MAX);
+    match store
+        .recent(&price.symbol, Some(&price.source), price.timestamp, limit)
+        .await
+    {
+        Ok(history) => history
+            .iter()
+            .filter(|old| !old.stale && old.price > 0.0)
+            .map(|old| old.price)
+            .collect(),
+        Err(e) => {
+            warn!(
+                symbol = %price.symbol,
+                source = %price.source,
+                error = %e,
+                "Cannot load history for anomaly detection, starting empty"
+            );
+            VecDeque::new()
+        }
+    }
+}
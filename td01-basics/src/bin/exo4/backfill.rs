@@ -0,0 +1,188 @@
+//! `exo4 backfill`: history written straight to `stock_candles`, so a newly added symbol
+//! doesn't start from nothing. Daily bars from Alpha Vantage's `TIME_SERIES_DAILY`, or
+//! intraday ones from Finnhub's `/stock/candle`.
+
+use chrono::{DateTime, Days, Months, NaiveDate, NaiveTime, Utc};
+use clap::{Args, ValueEnum};
+use market_core::store::{PriceStore, StorageError};
+use tracing::{error, info};
+
+use crate::config::Config;
+use crate::ratelimit::{self, Limits};
+use crate::sources::{
+    self, AlphaVantageSource, AssetClass, FetchError, FinnhubSource, Http, PriceSource, Resolution,
+};
+
+#[derive(Args, Debug)]
+pub struct BackfillArgs {
+    /// Symbols to backfill, comma separated (default: the configured equities)
+    #[arg(long, value_delimiter = ',')]
+    symbols: Option<Vec<String>>,
+
+    #[arg(long, value_enum, default_value_t = Provider::AlphaVantage)]
+    source: Provider,
+
+    /// Finnhub bar width: 1, 5, 15, 30 or 60 minutes, D or W [default: D]
+    #[arg(long)]
+    resolution: Option<Resolution>,
+
+    /// First day to store (YYYY-MM-DD) [default: a year ago]
+    #[arg(long)]
+    from: Option<NaiveDate>,
+
+    /// Last day to store (YYYY-MM-DD) [default: today]
+    #[arg(long)]
+    to: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Provider {
+    /// Daily bars, stored as `alpha_vantage_daily`
+    #[value(name = "alpha_vantage")]
+    AlphaVantage,
+    /// Bars of `--resolution`, stored as `finnhub_candles`
+    Finnhub,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum BackfillError {
+    #[error(transparent)]
+    Fetch(#[from] FetchError),
+    #[error(transparent)]
+    Store(#[from] StorageError),
+}
+
+/// Rows written and rows already there, for one symbol.
+#[derive(Default)]
+struct Counts {
+    inserted: u64,
+    skipped: u64,
+}
+
+/// Fetches and stores every symbol, reporting inserted vs already-stored bars for each.
+/// Symbols that fail are logged and the others still run; the error counts them.
+pub async fn run(
+    store: &dyn PriceStore,
+    cfg: &Config,
+    args: &BackfillArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let today = Utc::now().date_naive();
+    let to = args.to.unwrap_or(today);
+    let from = args.from.unwrap_or(today - Months::new(12));
+    if from > to {
+        return Err(format!("--from {from} is after --to {to}").into());
+    }
+    if args.resolution.is_some() && !matches!(args.source, Provider::Finnhub) {
+        return Err("--resolution only applies to --source finnhub".into());
+    }
+    let symbols: Vec<String> = match &args.symbols {
+        Some(symbols) => symbols.iter().map(|s| s.trim().to_uppercase()).collect(),
+        None => cfg
+            .symbols
+            .iter()
+            .filter(|s| AssetClass::of(s) == AssetClass::Equity)
+            .cloned()
+            .collect(),
+    };
+
+    let name = match args.source {
+        Provider::AlphaVantage => "alpha_vantage",
+        Provider::Finnhub => "finnhub",
+    };
+    let source_cfg = cfg.sources.get(name).cloned().unwrap_or_default();
+    let limits = sources::limits(name, &source_cfg)?;
+    let api_key = sources::api_key(name, &source_cfg)?;
+    let http = Http::new(&source_cfg);
+    let alpha_vantage = AlphaVantageSource::new(api_key.clone(), http.clone());
+    let finnhub = FinnhubSource::new(api_key, http);
+    let resolution = args.resolution.unwrap_or(Resolution::Day);
+
+    let mut failed = 0;
+    for (i, symbol) in symbols.iter().enumerate() {
+        let result = match args.source {
+            Provider::AlphaVantage => daily(store, &alpha_vantage, limits, symbol, from, to).await,
+            Provider::Finnhub => {
+                let start = from.and_time(NaiveTime::MIN).and_utc();
+                let end = (to + Days::new(1)).and_time(NaiveTime::MIN).and_utc();
+                intraday(store, &finnhub, limits, symbol, resolution, start, end).await
+            }
+        };
+        match result {
+            Ok(counts) => println!(
+                "{symbol}: {} inserted, {} skipped ({from} to {to})",
+                counts.inserted, counts.skipped
+            ),
+            Err(BackfillError::Fetch(e @ FetchError::BudgetExhausted { .. })) => {
+                error!(symbol = %symbol, error = %e, "Stopping backfill");
+                failed += symbols.len() - i;
+                break;
+            }
+            Err(e) => {
+                failed += 1;
+                error!(symbol = %symbol, error = %e, "Backfill failed");
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("backfill failed for {failed} of {} symbols", symbols.len()).into());
+    }
+    Ok(())
+}
+
+/// `TIME_SERIES_DAILY` returns the whole history at once, so this is a single request.
+async fn daily(
+    store: &dyn PriceStore,
+    provider: &AlphaVantageSource,
+    limits: Limits,
+    symbol: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Counts, BackfillError> {
+    ratelimit::acquire_waiting(provider.name(), limits).await?;
+    let candles = provider.daily(symbol, from, to).await?;
+    let inserted = store.save_candles(&candles).await?;
+    let skipped = candles.len() as u64 - inserted;
+    info!(symbol, inserted, skipped, "Backfilled daily candles");
+    Ok(Counts { inserted, skipped })
+}
+
+/// One request per `Resolution::max_span`, each chunk stored as soon as it arrives so an
+/// interrupted run keeps what it got; re-running skips what is already there.
+async fn intraday(
+    store: &dyn PriceStore,
+    provider: &FinnhubSource,
+    limits: Limits,
+    symbol: &str,
+    resolution: Resolution,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Counts, BackfillError> {
+    let span = resolution.max_span();
+    let chunks = ((to - from).num_seconds() + span.num_seconds() - 1) / span.num_seconds();
+    let mut counts = Counts::default();
+    let mut start = from;
+    let mut chunk = 0;
+    while start < to {
+        let end = (start + span).min(to);
+        chunk += 1;
+        ratelimit::acquire_waiting(provider.name(), limits).await?;
+        let candles = provider.candles(symbol, resolution, start, end).await?;
+        let inserted = store.save_candles(&candles).await?;
+        let skipped = candles.len() as u64 - inserted;
+        info!(
+            symbol,
+            chunk,
+            chunks,
+            from = %start.date_naive(),
+            to = %end.date_naive(),
+            inserted,
+            skipped,
+            "Backfilled candle chunk"
+        );
+        counts.inserted += inserted;
+        counts.skipped += skipped;
+        start = end;
+    }
+    Ok(counts)
+}
@@ -0,0 +1,165 @@
+//! Circuit breaker around a `PriceSource`: after too many consecutive failures the source
+//! is skipped for a cooldown, then a single probe decides whether it comes back.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use tracing::info;
+
+use crate::config::BreakerConfig;
+use crate::metrics::metrics;
+use crate::sources::{AssetClass, FetchError, PriceSource, StockPrice};
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// Cooldown is over and one probe request is in flight.
+    HalfOpen,
+}
+
+pub struct Breaker {
+    inner: Box<dyn PriceSource>,
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+impl Breaker {
+    pub fn new(inner: Box<dyn PriceSource>, cfg: BreakerConfig) -> Self {
+        Self {
+            inner,
+            failure_threshold: cfg.failure_threshold.max(1),
+            cooldown: cfg.cooldown,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether a request may go out now. Moves an expired `Open` to `HalfOpen`, letting
+    /// exactly one caller through as the probe.
+    fn admit(&self) -> Result<(), FetchError> {
+        let mut state = self.state();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if Instant::now() >= until => {
+                *state = State::HalfOpen;
+                info!(
+                    source = self.name(),
+                    "Circuit half-open, sending a probe request"
+                );
+                Ok(())
+            }
+            State::Open { .. } | State::HalfOpen => Err(FetchError::CircuitOpen {
+                provider: self.name(),
+            }),
+        }
+    }
+
+    /// `None` when the request was skipped further down: the probe slot is handed back.
+    fn record(&self, success: Option<bool>) {
+        let mut state = self.state();
+        let Some(success) = success else {
+            if let State::HalfOpen = *state {
+                *state = State::Open {
+                    until: Instant::now(),
+                };
+            }
+            return;
+        };
+        *state = match (*state, success) {
+            (State::HalfOpen, true) => {
+                info!(source = self.name(), "Circuit closed, probe succeeded");
+                State::Closed { failures: 0 }
+            }
+            (State::HalfOpen, false) => {
+                info!(
+                    source = self.name(),
+                    cooldown_secs = self.cooldown.as_secs(),
+                    "Circuit re-opened, probe failed"
+                );
+                State::Open {
+                    until: Instant::now() + self.cooldown,
+                }
+            }
+            (State::Closed { .. }, true) => State::Closed { failures: 0 },
+            (State::Closed { failures }, false) if failures + 1 >= self.failure_threshold => {
+                info!(
+                    source = self.name(),
+                    failures = failures + 1,
+                    cooldown_secs = self.cooldown.as_secs(),
+                    "Circuit opened"
+                );
+                State::Open {
+                    until: Instant::now() + self.cooldown,
+                }
+            }
+            (State::Closed { failures }, false) => State::Closed {
+                failures: failures + 1,
+            },
+            // A request admitted before the circuit opened; its result changes nothing.
+            (open @ State::Open { .. }, _) => open,
+        };
+        metrics().set_circuit_open(self.name(), !matches!(*state, State::Closed { .. }));
+    }
+}
+
+/// Whether a result says anything about the provider's health. An unknown symbol is a
+/// healthy answer; a skipped request says nothing either way.
+fn health<T>(result: &Result<T, FetchError>) -> Option<bool> {
+    match result {
+        Ok(_) | Err(FetchError::UnknownSymbol { .. }) => Some(true),
+        Err(e) if e.is_skipped() => None,
+        Err(_) => Some(false),
+    }
+}
+
+#[async_trait]
+impl PriceSource for Breaker {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn asset_classes(&self) -> &'static [AssetClass] {
+        self.inner.asset_classes()
+    }
+
+    fn has_batch_endpoint(&self) -> bool {
+        self.inner.has_batch_endpoint()
+    }
+
+    /// Only a closed circuit counts; an open or probing one is still considered broken.
+    fn is_available(&self) -> bool {
+        matches!(*self.state(), State::Closed { .. }) && self.inner.is_available()
+    }
+
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        self.admit()?;
+        let result = self.inner.fetch(symbol).await;
+        self.record(health(&result));
+        result
+    }
+
+    /// One batch counts as one request: healthy if any symbol got a healthy answer.
+    async fn fetch_many(&self, symbols: &[&str]) -> Vec<Result<StockPrice, FetchError>> {
+        if self.admit().is_err() {
+            let provider = self.name();
+            return symbols
+                .iter()
+                .map(|_| Err(FetchError::CircuitOpen { provider }))
+                .collect();
+        }
+        let results = self.inner.fetch_many(symbols).await;
+        let verdicts: Vec<bool> = results.iter().filter_map(health).collect();
+        self.record((!verdicts.is_empty()).then(|| verdicts.contains(&true)));
+        results
+    }
+}
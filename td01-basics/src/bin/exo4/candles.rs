@@ -0,0 +1,41 @@
+//! Rolls `stock_prices` up into OHLC candles in `stock_candles`.
+
+use std::sync::Arc;
+
+use market_core::store::{PriceStore, StorageError};
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{error, info, instrument};
+
+use crate::config::CandlesConfig;
+
+/// Candle widths we maintain: stored label and width in seconds.
+const WIDTHS: &[(&str, u32)] = &[("1m", 60), ("5m", 300), ("1h", 3600)];
+
+/// Runs forever; does nothing when candles are disabled.
+pub async fn run(store: Arc<dyn PriceStore>, cfg: CandlesConfig) {
+    if !cfg.enabled {
+        return;
+    }
+    let mut ticker = interval(cfg.every);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        for (label, secs) in WIDTHS {
+            if let Err(e) = aggregate(store.as_ref(), label, *secs).await {
+                error!(interval = label, error = %e, "Candle aggregation failed");
+            }
+        }
+    }
+}
+
+/// Aggregates every closed bucket from the last stored candle of this width onwards. The
+/// bucket still in progress is left for a later run.
+#[instrument(skip(store))]
+async fn aggregate(store: &dyn PriceStore, label: &str, secs: u32) -> Result<u64, StorageError> {
+    let written = store.roll_up_candles(label, secs).await?;
+
+    if written > 0 {
+        info!(written, "Candles updated");
+    }
+    Ok(written)
+}
@@ -0,0 +1,175 @@
+//! The fetch half of a cycle: who gets asked for what, and how many requests run at once.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use futures::stream::{self, StreamExt};
+use market_core::store::FetchStat;
+use tokio::time::{sleep_until, Instant};
+use tracing::{debug, info};
+
+use crate::config::{Strategy, StrategyConfig};
+use crate::sources::{FetchError, PriceSource, StockPrice};
+
+/// One provider's answer for one symbol.
+pub struct FetchOutcome {
+    pub source: &'static str,
+    pub symbol: String,
+    pub result: Result<StockPrice, FetchError>,
+    /// Time the request took, retries included; for a batch, the whole batch.
+    pub elapsed: Duration,
+}
+
+impl FetchOutcome {
+    /// The `fetch_stats` row for this attempt; `None` when no request went out.
+    pub fn stat(&self, at: DateTime<Utc>) -> Option<FetchStat> {
+        if self.result.as_ref().is_err_and(FetchError::is_skipped) {
+            return None;
+        }
+        Some(FetchStat {
+            symbol: self.symbol.clone(),
+            source: self.source.to_string(),
+            timestamp: at,
+            ok: self.result.is_ok(),
+            latency_ms: u32::try_from(self.elapsed.as_millis()).unwrap_or(u32::MAX),
+            error_kind: self.result.as_ref().err().map(FetchError::reason),
+        })
+    }
+}
+
+/// Symbols on the `all` strategy: batch-capable sources get a single `fetch_many` call with
+/// all of them, the other sources are queried in parallel per symbol. Symbols on another
+/// strategy go through the sources one at a time, in priority order (see `by_priority`).
+/// At most `concurrency` symbols are in flight. Failures stay per (source, symbol).
+///
+/// With `spread`, per-symbol fetches are paced instead of sent in a burst: symbol `i` of
+/// `n` starts `i * spread / n` after the call. Batch requests go out at once either way.
+pub async fn fetch_all(
+    sources: &[Box<dyn PriceSource>],
+    symbols: &[String],
+    concurrency: usize,
+    spread: Option<Duration>,
+    strategy: &StrategyConfig,
+) -> Vec<FetchOutcome> {
+    let started = Instant::now();
+    let spacing = spread.map(|spread| spread / symbols.len().max(1) as u32);
+
+    let (batched, single): (Vec<&dyn PriceSource>, Vec<&dyn PriceSource>) = sources
+        .iter()
+        .map(|s| s.as_ref())
+        .partition(|s| s.has_batch_endpoint());
+
+    let batch_fetches = join_all(batched.into_iter().map(|source| async move {
+        let wanted: Vec<&str> = symbols
+            .iter()
+            .map(String::as_str)
+            .filter(|symbol| strategy.for_symbol(symbol) == Strategy::All)
+            .filter(|symbol| source.supports(symbol))
+            .collect();
+        if wanted.is_empty() {
+            return Vec::new();
+        }
+        let started = Instant::now();
+        let results = source.fetch_many(&wanted).await;
+        let elapsed = started.elapsed();
+        wanted
+            .into_iter()
+            .zip(results)
+            .map(|(symbol, result)| FetchOutcome {
+                source: source.name(),
+                symbol: symbol.to_string(),
+                result,
+                elapsed,
+            })
+            .collect::<Vec<_>>()
+    }));
+
+    let single = &single;
+    let per_symbol_fetches = stream::iter(symbols.iter().enumerate())
+        .map(|(i, symbol)| async move {
+            if let Some(spacing) = spacing {
+                sleep_until(started + spacing * i as u32).await;
+            }
+            match strategy.for_symbol(symbol) {
+                Strategy::All => {
+                    join_all(
+                        single
+                            .iter()
+                            .filter(|source| source.supports(symbol))
+                            .map(|source| fetch_one(*source, symbol)),
+                    )
+                    .await
+                }
+                other => by_priority(sources, symbol, other, strategy.max_age).await,
+            }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect::<Vec<_>>();
+
+    let (batch_outcomes, symbol_outcomes) = tokio::join!(batch_fetches, per_symbol_fetches);
+    batch_outcomes
+        .into_iter()
+        .chain(symbol_outcomes)
+        .flatten()
+        .collect()
+}
+
+async fn fetch_one(source: &dyn PriceSource, symbol: &str) -> FetchOutcome {
+    let started = Instant::now();
+    let result = source.fetch(symbol).await;
+    let elapsed = started.elapsed();
+    FetchOutcome {
+        source: source.name(),
+        symbol: symbol.to_string(),
+        result,
+        elapsed,
+    }
+}
+
+/// Asks the sources one at a time, in priority order, until one gives a usable quote.
+/// Failed attempts are returned too so they still show up in logs and metrics. Under
+/// `primary-with-fallback` a stale primary quote is only kept when no fallback answers.
+async fn by_priority(
+    sources: &[Box<dyn PriceSource>],
+    symbol: &str,
+    strategy: Strategy,
+    max_age: Duration,
+) -> Vec<FetchOutcome> {
+    let mut outcomes = Vec::new();
+    let mut stale_primary = None;
+    let candidates = sources.iter().filter(|source| source.supports(symbol));
+    for (rank, source) in candidates.enumerate() {
+        let outcome = fetch_one(source.as_ref(), symbol).await;
+        match &outcome.result {
+            Ok(price)
+                if strategy == Strategy::PrimaryWithFallback
+                    && rank == 0
+                    && (Utc::now() - price.timestamp).to_std().unwrap_or_default() > max_age =>
+            {
+                debug!(
+                    symbol,
+                    source = outcome.source,
+                    quoted_at = %price.timestamp,
+                    "Primary quote is stale, trying fallbacks"
+                );
+                stale_primary = Some(outcome);
+            }
+            Ok(_) => {
+                if rank > 0 {
+                    info!(
+                        symbol,
+                        source = outcome.source,
+                        strategy = strategy.name(),
+                        "Price taken from a fallback source"
+                    );
+                }
+                outcomes.push(outcome);
+                return outcomes;
+            }
+            Err(_) => outcomes.push(outcome),
+        }
+    }
+    outcomes.extend(stale_primary);
+    outcomes
+}
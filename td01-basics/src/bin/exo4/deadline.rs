@@ -0,0 +1,88 @@
+//! Per-attempt deadline around any `PriceSource`, so one slow provider can't eat the cycle.
+//! Sits under the rate limiter (waiting for a token doesn't count) and under `Retrying`,
+//! which treats `FetchError::Timeout` as transient. Being the layer closest to the request,
+//! it is also where request latency is measured.
+
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use tracing::{debug, field, instrument, Span};
+
+use crate::latency;
+use crate::metrics::metrics;
+use crate::sources::{AssetClass, FetchError, PriceSource, StockPrice};
+
+pub struct Deadline {
+    inner: Box<dyn PriceSource>,
+    limit: Duration,
+}
+
+impl Deadline {
+    pub fn new(inner: Box<dyn PriceSource>, limit: Duration) -> Self {
+        Self { inner, limit }
+    }
+
+    fn measured(&self, started: Instant) {
+        let elapsed = started.elapsed();
+        Span::current().record("latency_ms", elapsed.as_millis() as u64);
+        latency::record(self.name(), elapsed);
+    }
+
+    fn timed_out(&self, symbols: &[&str]) -> FetchError {
+        metrics().fetch_timed_out(self.name());
+        debug!(
+            source = self.name(),
+            symbols = %symbols.join(","),
+            limit_ms = self.limit.as_millis() as u64,
+            "Fetch timed out"
+        );
+        FetchError::Timeout {
+            provider: self.name(),
+            after: self.limit,
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSource for Deadline {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn asset_classes(&self) -> &'static [AssetClass] {
+        self.inner.asset_classes()
+    }
+
+    fn has_batch_endpoint(&self) -> bool {
+        self.inner.has_batch_endpoint()
+    }
+
+    fn is_available(&self) -> bool {
+        self.inner.is_available()
+    }
+
+    #[instrument(name = "request", skip(self), fields(source = self.name(), latency_ms = field::Empty))]
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        let started = Instant::now();
+        let result = tokio::time::timeout(self.limit, self.inner.fetch(symbol))
+            .await
+            .unwrap_or_else(|_| Err(self.timed_out(&[symbol])));
+        self.measured(started);
+        result
+    }
+
+    /// A batch shares one deadline; when it passes, every symbol of the batch times out.
+    #[instrument(name = "request", skip_all, fields(source = self.name(), symbols = symbols.len(), latency_ms = field::Empty))]
+    async fn fetch_many(&self, symbols: &[&str]) -> Vec<Result<StockPrice, FetchError>> {
+        let started = Instant::now();
+        let results = match tokio::time::timeout(self.limit, self.inner.fetch_many(symbols)).await {
+            Ok(results) => results,
+            Err(_) => {
+                let err = self.timed_out(symbols);
+                symbols.iter().map(|_| Err(err.clone())).collect()
+            }
+        };
+        self.measured(started);
+        results
+    }
+}
@@ -0,0 +1,97 @@
+//! Cross-source check: the point of asking several providers is noticing when they disagree.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::Utc;
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+use crate::config::DivergenceConfig;
+use crate::sources::StockPrice;
+use crate::webhook;
+
+/// Latest quote per symbol and source, kept across cycles so sources fetched at different
+/// rates can still be compared.
+#[derive(Default)]
+pub struct Divergence {
+    latest: HashMap<String, BTreeMap<String, StockPrice>>,
+    /// Symbols currently over the threshold; alerts fire only when a symbol enters this set.
+    diverging: HashSet<String>,
+}
+
+impl Divergence {
+    pub fn check(&mut self, cfg: &DivergenceConfig, prices: &[StockPrice]) {
+        let Some(threshold_pct) = cfg.threshold_pct else {
+            return;
+        };
+        for price in prices {
+            self.latest
+                .entry(price.symbol.clone())
+                .or_default()
+                .insert(price.source.clone(), price.clone());
+        }
+
+        let now = Utc::now();
+        let freshness = chrono::Duration::from_std(cfg.freshness).unwrap_or(chrono::Duration::MAX);
+        for (symbol, by_source) in &self.latest {
+            let fresh: Vec<&StockPrice> = by_source
+                .values()
+                .filter(|p| now - p.timestamp <= freshness && p.price > 0.0)
+                .collect();
+            if fresh.len() < 2 {
+                self.diverging.remove(symbol);
+                continue;
+            }
+            let (Some(low), Some(high)) = (
+                fresh.iter().min_by(|a, b| a.price.total_cmp(&b.price)),
+                fresh.iter().max_by(|a, b| a.price.total_cmp(&b.price)),
+            ) else {
+                continue;
+            };
+
+            let spread_pct = (high.price - low.price) / ((high.price + low.price) / 2.0) * 100.0;
+            if spread_pct <= threshold_pct {
+                if self.diverging.remove(symbol) {
+                    info!(symbol = %symbol, spread_pct, "Sources agree again");
+                }
+                continue;
+            }
+            if !self.diverging.insert(symbol.clone()) {
+                debug!(symbol = %symbol, spread_pct, "Sources still diverging");
+                continue;
+            }
+
+            warn!(
+                symbol = %symbol,
+                spread_pct,
+                threshold_pct,
+                high_source = %high.source,
+                high_price = high.price,
+                high_at = %high.timestamp,
+                low_source = %low.source,
+                low_price = low.price,
+                low_at = %low.timestamp,
+                "Sources disagree"
+            );
+            if let Some(url) = &cfg.webhook {
+                let quote = |p: &StockPrice| json!({ "source": p.source, "price": p.price, "timestamp": p.timestamp });
+                webhook::post(
+                    url,
+                    "divergence",
+                    json!({
+                        "kind": "divergence",
+                        "text": format!(
+                            "{symbol}: {} says {} but {} says {} ({spread_pct:.2}% apart)",
+                            high.source, high.price, low.source, low.price
+                        ),
+                        "symbol": symbol,
+                        "spread_pct": spread_pct,
+                        "threshold_pct": threshold_pct,
+                        "high": quote(high),
+                        "low": quote(low),
+                    }),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! `--dry-run`: a store that logs what would be written and keeps nothing, so a full cycle
+//! runs without a database.
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::stream::{self, BoxStream, StreamExt};
+use market_core::store::{
+    Candle, FetchStat, Indicator, PageKey, PortfolioSnapshot, PriceQuery, PriceStore, PriceTable,
+    SourceStats, StockPrice, StorageError,
+};
+use tracing::info;
+
+pub struct DryRunStore;
+
+fn log_price(price: &StockPrice) {
+    info!(
+        symbol = %price.symbol,
+        price = price.price,
+        source = %price.source,
+        timestamp = %price.timestamp,
+        "Dry run, not saved"
+    );
+}
+
+#[async_trait]
+impl PriceStore for DryRunStore {
+    fn backend(&self) -> &'static str {
+        "dry-run"
+    }
+
+    async fn save(&self, price: &StockPrice) -> Result<bool, StorageError> {
+        log_price(price);
+        Ok(false)
+    }
+
+    async fn save_batch(&self, prices: &[StockPrice]) -> Result<u64, StorageError> {
+        prices.iter().for_each(log_price);
+        Ok(0)
+    }
+
+    async fn latest_per_symbol_source(&self) -> Result<Vec<StockPrice>, StorageError> {
+        Ok(Vec::new())
+    }
+
+    async fn latest_in(&self, _table: &PriceTable) -> Result<Vec<StockPrice>, StorageError> {
+        Ok(Vec::new())
+    }
+
+    async fn since_in(
+        &self,
+        _table: &PriceTable,
+        _since: DateTime<Utc>,
+    ) -> Result<Vec<StockPrice>, StorageError> {
+        Ok(Vec::new())
+    }
+
+    async fn history(
+        &self,
+        _symbol: &str,
+        _since: DateTime<Utc>,
+    ) -> Result<Vec<StockPrice>, StorageError> {
+        Ok(Vec::new())
+    }
+
+    fn stream<'a>(
+        &'a self,
+        _query: &'a PriceQuery,
+    ) -> BoxStream<'a, Result<StockPrice, StorageError>> {
+        stream::empty().boxed()
+    }
+
+    async fn page_after(
+        &self,
+        _symbols: &[String],
+        _after: &PageKey,
+        _limit: u32,
+    ) -> Result<Vec<StockPrice>, StorageError> {
+        Ok(Vec::new())
+    }
+
+    async fn recent(
+        &self,
+        _symbol: &str,
+        _source: Option<&str>,
+        _before: DateTime<Utc>,
+        _limit: u32,
+    ) -> Result<Vec<StockPrice>, StorageError> {
+        Ok(Vec::new())
+    }
+
+    async fn save_indicators(&self, _rows: &[Indicator]) -> Result<u64, StorageError> {
+        Ok(0)
+    }
+
+    async fn save_candles(&self, _candles: &[Candle]) -> Result<u64, StorageError> {
+        Ok(0)
+    }
+
+    async fn save_fetch_stats(&self, _stats: &[FetchStat]) -> Result<u64, StorageError> {
+        Ok(0)
+    }
+
+    async fn save_portfolio_snapshot(
+        &self,
+        _snapshot: &PortfolioSnapshot,
+    ) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    async fn save_daily_report(
+        &self,
+        _day: NaiveDate,
+        _generated_at: DateTime<Utc>,
+        _report: &serde_json::Value,
+    ) -> Result<bool, StorageError> {
+        Ok(true)
+    }
+
+    async fn last_daily_report(&self) -> Result<Option<NaiveDate>, StorageError> {
+        Ok(None)
+    }
+
+    async fn source_stats(&self, _query: &PriceQuery) -> Result<Vec<SourceStats>, StorageError> {
+        Ok(Vec::new())
+    }
+
+    async fn prune_before(&self, _cutoff: DateTime<Utc>, _limit: u32) -> Result<u64, StorageError> {
+        Ok(0)
+    }
+
+    async fn roll_up_candles(&self, _label: &str, _secs: u32) -> Result<u64, StorageError> {
+        Ok(0)
+    }
+
+    async fn ping(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    async fn close(&self) {}
+}
@@ -0,0 +1,130 @@
+//! `exo4 export`: stored prices to CSV or NDJSON, for pandas and friends.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use clap::{Args, ValueEnum};
+use futures::StreamExt;
+use market_core::store::{PriceQuery, PriceStore};
+use serde_json::json;
+use tracing::info;
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// Only this symbol
+    #[arg(long)]
+    symbol: Option<String>,
+
+    /// Only this source (e.g. finnhub)
+    #[arg(long)]
+    source: Option<String>,
+
+    /// First day (YYYY-MM-DD) or instant (RFC 3339) to include
+    #[arg(long, value_parser = parse_since)]
+    since: Option<DateTime<Utc>>,
+
+    /// Last day to include (YYYY-MM-DD, whole day) or instant to stop before (RFC 3339)
+    #[arg(long, value_parser = parse_until)]
+    until: Option<DateTime<Utc>>,
+
+    /// Output file (default: stdout)
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    /// One JSON object per line
+    Json,
+}
+
+enum Sink {
+    Csv(Box<csv::Writer<Box<dyn Write>>>),
+    Json(BufWriter<Box<dyn Write>>),
+}
+
+fn parse_instant(raw: &str, day_offset: u64) -> Result<DateTime<Utc>, String> {
+    if let Ok(day) = NaiveDate::parse_from_str(raw, "%Y-%m-%d") {
+        return day
+            .checked_add_days(chrono::Days::new(day_offset))
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|t| t.and_utc())
+            .ok_or_else(|| format!("date out of range: {raw}"));
+    }
+    DateTime::parse_from_rfc3339(raw)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| format!("expected YYYY-MM-DD or an RFC 3339 timestamp, got '{raw}'"))
+}
+
+pub fn parse_since(raw: &str) -> Result<DateTime<Utc>, String> {
+    parse_instant(raw, 0)
+}
+
+/// A bare date means "up to the end of that day".
+pub fn parse_until(raw: &str) -> Result<DateTime<Utc>, String> {
+    parse_instant(raw, 1)
+}
+
+/// Streams the matching rows to the output; returns how many were written.
+pub async fn run(
+    store: &dyn PriceStore,
+    args: &ExportArgs,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let query = PriceQuery {
+        symbol: args.symbol.as_deref().map(str::to_uppercase),
+        source: args.source.clone(),
+        since: args.since,
+        until: args.until,
+    };
+    let out: Box<dyn Write> = match &args.out {
+        Some(path) => Box::new(File::create(path).map_err(|e| format!("{}: {e}", path.display()))?),
+        None => Box::new(io::stdout().lock()),
+    };
+    // Both writers buffer, so rows go out as they arrive and memory stays flat.
+    let mut sink = match args.format {
+        Format::Csv => {
+            let mut w = csv::Writer::from_writer(out);
+            w.write_record(["timestamp", "symbol", "source", "price"])?;
+            Sink::Csv(Box::new(w))
+        }
+        Format::Json => Sink::Json(BufWriter::new(out)),
+    };
+
+    let mut rows = store.stream(&query);
+    let mut written = 0;
+    while let Some(price) = rows.next().await {
+        let price = price?;
+        let timestamp = price.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true);
+        match &mut sink {
+            Sink::Csv(w) => w.write_record([
+                timestamp.as_str(),
+                &price.symbol,
+                &price.source,
+                &price.price.to_string(),
+            ])?,
+            Sink::Json(w) => {
+                let line = json!({
+                    "timestamp": timestamp,
+                    "symbol": price.symbol,
+                    "source": price.source,
+                    "price": price.price,
+                });
+                writeln!(w, "{line}")?;
+            }
+        }
+        written += 1;
+    }
+    match &mut sink {
+        Sink::Csv(w) => w.flush()?,
+        Sink::Json(w) => w.flush()?,
+    }
+
+    info!(rows = written, "Export complete");
+    Ok(written)
+}
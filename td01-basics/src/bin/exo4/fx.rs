@@ -0,0 +1,208 @@
+//! Conversion of quotes into the base currency before they are stored, so cross-source
+//! checks and anything adding prices up compare like with like.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::config::{CurrencyConfig, SourceConfig};
+use crate::cycle::FetchOutcome;
+use crate::sources::{FetchError, Http, StockPrice};
+
+const PROVIDER: &str = "open_er_api";
+
+/// `/v6/latest/{base}` answer: how much of each currency one unit of `base` buys.
+#[derive(Deserialize, Debug)]
+struct LatestRates {
+    result: String,
+    #[serde(default)]
+    rates: HashMap<String, f64>,
+    #[serde(rename = "error-type")]
+    error_type: Option<String>,
+}
+
+/// Splits a configured quote currency into the ISO 4217 code rates are looked up with and
+/// the value of one quoted unit in it. London quotes most equities in pence (`GBp`, also
+/// written `GBX`); reading those as pounds is the classic factor-of-100 mistake.
+pub fn unit(code: &str) -> Result<(String, f64), String> {
+    match code {
+        "GBp" | "GBX" => Ok(("GBP".to_string(), 0.01)),
+        _ if code.len() == 3 && code.bytes().all(|b| b.is_ascii_uppercase()) => {
+            Ok((code.to_string(), 1.0))
+        }
+        _ => Err(format!(
+            "unknown currency '{code}' (expected an ISO 4217 code such as EUR, or GBp for pence)"
+        )),
+    }
+}
+
+struct Rates {
+    base: String,
+    fetched: Instant,
+    per_base: HashMap<String, f64>,
+}
+
+/// Exchange rates from open.er-api.com (free, no key), fetched at most once per
+/// `rates_ttl`.
+pub struct Fx {
+    http: Http,
+    cached: Mutex<Option<Rates>>,
+}
+
+impl Fx {
+    pub fn new() -> Self {
+        Self {
+            http: Http::new(&SourceConfig::default()),
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Converts the prices of symbols with a configured currency. A price that can't be
+    /// converted becomes a failed outcome rather than being stored in the wrong currency.
+    pub async fn convert_all(&self, cfg: &CurrencyConfig, outcomes: &mut [FetchOutcome]) {
+        for outcome in outcomes {
+            let Some(code) = cfg.symbols.get(&outcome.symbol) else {
+                continue;
+            };
+            if outcome.result.is_err() || *code == cfg.base {
+                continue;
+            }
+            match self.factor(cfg, code).await {
+                Ok(factor) => {
+                    if let Ok(price) = &mut outcome.result {
+                        apply(price, code, factor);
+                        debug!(symbol = %price.symbol, currency = %code, factor, "Converted price");
+                    }
+                }
+                Err(e) => outcome.result = Err(e),
+            }
+        }
+    }
+
+    /// What one unit quoted in `code` is worth in the base currency.
+    async fn factor(&self, cfg: &CurrencyConfig, code: &str) -> Result<f64, FetchError> {
+        let (iso, per_unit) = unit(code).map_err(FetchError::Parse)?;
+        if iso == cfg.base {
+            return Ok(per_unit);
+        }
+        Ok(per_unit / self.rate(cfg, &iso).await?)
+    }
+
+    /// Units of `iso` per unit of the base currency. When a refresh fails, the previous
+    /// rates are kept rather than dropping every converted price.
+    async fn rate(&self, cfg: &CurrencyConfig, iso: &str) -> Result<f64, FetchError> {
+        let mut cached = self.cached.lock().await;
+        let fresh = cached
+            .as_ref()
+            .is_some_and(|r| r.base == cfg.base && r.fetched.elapsed() < cfg.rates_ttl);
+        if !fresh {
+            match self.fetch(&cfg.base).await {
+                Ok(per_base) => {
+                    *cached = Some(Rates {
+                        base: cfg.base.clone(),
+                        fetched: Instant::now(),
+                        per_base,
+                    })
+                }
+                Err(e) => match cached.as_ref() {
+                    Some(stale) if stale.base == cfg.base => {
+                        warn!(
+                            error = %e,
+                            age_secs = stale.fetched.elapsed().as_secs(),
+                            "Exchange rate refresh failed, using previous rates"
+                        );
+                    }
+                    _ => return Err(e),
+                },
+            }
+        }
+        cached
+            .as_ref()
+            .and_then(|r| r.per_base.get(iso))
+            .copied()
+            .filter(|rate| rate.is_finite() && *rate > 0.0)
+            .ok_or_else(|| FetchError::Api {
+                provider: PROVIDER,
+                message: format!("no {iso} rate against {}", cfg.base),
+            })
+    }
+
+    async fn fetch(&self, base: &str) -> Result<HashMap<String, f64>, FetchError> {
+        let url = format!("https://open.er-api.com/v6/latest/{base}");
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
+        let latest: LatestRates = resp.json().await?;
+        if latest.result != "success" {
+            return Err(FetchError::Api {
+                provider: PROVIDER,
+                message: latest.error_type.unwrap_or(latest.result),
+            });
+        }
+        debug!(
+            base,
+            currencies = latest.rates.len(),
+            "Fetched exchange rates"
+        );
+        Ok(latest.rates)
+    }
+}
+
+/// Keeps the quoted price and currency, and scales every price field by `factor`.
+fn apply(price: &mut StockPrice, code: &str, factor: f64) {
+    price.raw_price = Some(price.price);
+    price.currency = Some(code.to_string());
+    price.price *= factor;
+    for value in [
+        &mut price.open,
+        &mut price.high,
+        &mut price.low,
+        &mut price.prev_close,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *value *= factor;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pence_are_hundredths_of_a_pound() {
+        assert_eq!(unit("GBp").unwrap(), ("GBP".to_string(), 0.01));
+        assert_eq!(unit("GBX").unwrap(), ("GBP".to_string(), 0.01));
+        assert_eq!(unit("GBP").unwrap(), ("GBP".to_string(), 1.0));
+        assert_eq!(unit("EUR").unwrap(), ("EUR".to_string(), 1.0));
+        for code in ["gbp", "EURO", "", "E1R"] {
+            assert!(unit(code).is_err(), "{code}");
+        }
+    }
+
+    #[test]
+    fn apply_scales_every_price_field() {
+        let mut price = StockPrice {
+            symbol: "VOD.L".to_string(),
+            price: 7250.0,
+            source: "yahoo".to_string(),
+            open: Some(7200.0),
+            high: Some(7300.0),
+            low: None,
+            prev_close: Some(7100.0),
+            ..Default::default()
+        };
+        apply(&mut price, "GBp", 0.01);
+
+        assert_eq!(price.price, 72.5);
+        assert_eq!(price.open, Some(72.0));
+        assert_eq!(price.high, Some(73.0));
+        assert_eq!(price.low, None);
+        assert_eq!(price.prev_close, Some(71.0));
+        // As quoted, before conversion
+        assert_eq!(price.raw_price, Some(7250.0));
+        assert_eq!(price.currency.as_deref(), Some("GBp"));
+    }
+}
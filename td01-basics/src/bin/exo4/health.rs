@@ -0,0 +1,130 @@
+//! `/healthz` and `/readyz` probes, served on the metrics listener.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use market_core::store::PriceStore;
+use serde_json::{json, Value};
+use tokio::time::{timeout, Instant};
+use tracing::warn;
+
+use crate::config::HealthConfig;
+use crate::sources::PriceSource;
+
+/// A `SELECT 1` slower than this counts as a failure.
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct Health {
+    store: Arc<dyn PriceStore>,
+    db_grace: Duration,
+    started: Instant,
+    /// Set on the first failed ping, cleared by the next successful one.
+    db_failing_since: Mutex<Option<Instant>>,
+    /// Source name -> circuit closed, as of the last cycle or reload.
+    providers: Mutex<BTreeMap<&'static str, bool>>,
+}
+
+impl Health {
+    pub fn new(store: Arc<dyn PriceStore>, cfg: &HealthConfig) -> Self {
+        Self {
+            store,
+            db_grace: cfg.db_grace,
+            started: Instant::now(),
+            db_failing_since: Mutex::new(None),
+            providers: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Breakers only change state during a cycle, so calling this after each cycle and each
+    /// reload keeps readiness current.
+    pub fn update_providers(&self, sources: &[Box<dyn PriceSource>]) {
+        let snapshot = sources
+            .iter()
+            .map(|s| (s.name(), s.is_available()))
+            .collect();
+        *self.providers.lock().unwrap_or_else(|e| e.into_inner()) = snapshot;
+    }
+
+    /// Pings the database. Returns whether it is usable and how long it has been failing;
+    /// a failure younger than `db_grace` still counts as usable.
+    async fn check_db(&self) -> (bool, Value) {
+        let error = match timeout(PING_TIMEOUT, self.store.ping()).await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(_) => Some(format!("no answer within {}s", PING_TIMEOUT.as_secs())),
+        };
+        let mut failing_since = self
+            .db_failing_since
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        match error {
+            None => {
+                *failing_since = None;
+                (true, json!({ "ok": true, "backend": self.store.backend() }))
+            }
+            Some(error) => {
+                let failing_for = failing_since.get_or_insert_with(Instant::now).elapsed();
+                warn!(error = %error, failing_secs = failing_for.as_secs(), "Readiness ping failed");
+                (
+                    failing_for < self.db_grace,
+                    json!({
+                        "ok": false,
+                        "backend": self.store.backend(),
+                        "error": error,
+                        "failing_secs": failing_for.as_secs(),
+                    }),
+                )
+            }
+        }
+    }
+}
+
+pub fn router(health: Arc<Health>) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(health)
+}
+
+/// Answering at all means the runtime is scheduling tasks.
+async fn healthz(State(health): State<Arc<Health>>) -> Json<Value> {
+    Json(json!({
+        "status": "ok",
+        "uptime_secs": health.started.elapsed().as_secs(),
+    }))
+}
+
+/// Ready when the database answers (or has been failing for less than the grace period)
+/// and at least one provider's circuit is closed.
+async fn readyz(State(health): State<Arc<Health>>) -> (StatusCode, Json<Value>) {
+    let (db_ok, database) = health.check_db().await;
+    let providers = health
+        .providers
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    let providers_ok = providers.values().any(|&available| available);
+
+    let ready = db_ok && providers_ok;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "database": database,
+            "providers": providers
+                .iter()
+                .map(|(name, &available)| (*name, if available { "closed" } else { "open" }))
+                .collect::<BTreeMap<_, _>>(),
+        })),
+    )
+}
@@ -0,0 +1,178 @@
+//! Simple and exponential moving averages per (symbol, source), updated one sample at a time.
+//! State lives in memory; a series seen for the first time is warmed up from `stock_prices`.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use market_core::store::{Indicator, PriceStore};
+use tracing::{info, warn};
+
+use crate::config::IndicatorsConfig;
+use crate::sources::StockPrice;
+
+/// Stored rows replayed per period of the longest average, so the EMAs have settled.
+const WARMUP_FACTOR: u32 = 4;
+
+#[derive(Default)]
+struct Series {
+    /// The last `longest period` prices, oldest first.
+    prices: VecDeque<f64>,
+    last_at: Option<DateTime<Utc>>,
+    ema: BTreeMap<u32, f64>,
+    /// Whether the shortest SMA was above the longest one at the previous sample.
+    short_above: Option<bool>,
+}
+
+/// What one sample produced: the averages, and a crossover if the short SMA changed sides.
+struct Update {
+    values: Vec<(&'static str, u32, f64)>,
+    crossed: Option<bool>,
+}
+
+impl Series {
+    /// `None` when the sample is not newer than the last one (a repeated quote).
+    fn push(&mut self, at: DateTime<Utc>, price: f64, periods: &[u32]) -> Option<Update> {
+        if self.last_at.is_some_and(|last| at <= last) {
+            return None;
+        }
+        self.last_at = Some(at);
+        let longest = *periods.last()? as usize;
+        self.prices.push_back(price);
+        while self.prices.len() > longest {
+            self.prices.pop_front();
+        }
+
+        let mut values = Vec::new();
+        let mut smas = BTreeMap::new();
+        for &period in periods {
+            let n = period as usize;
+            if self.prices.len() < n {
+                continue;
+            }
+            let sma = self.prices.iter().rev().take(n).sum::<f64>() / n as f64;
+            smas.insert(period, sma);
+            values.push(("sma", period, sma));
+
+            // Seeded with the SMA the first time the window is full.
+            let alpha = 2.0 / (f64::from(period) + 1.0);
+            let ema = self
+                .ema
+                .entry(period)
+                .and_modify(|ema| *ema = alpha * price + (1.0 - alpha) * *ema)
+                .or_insert(sma);
+            values.push(("ema", period, *ema));
+        }
+
+        let mut crossed = None;
+        if let (Some(short), Some(long)) =
+            (smas.get(&periods[0]), smas.get(&periods[periods.len() - 1]))
+        {
+            if periods.len() > 1 && short != long {
+                let above = short > long;
+                if self.short_above.is_some_and(|was| was != above) {
+                    crossed = Some(above);
+                }
+                self.short_above = Some(above);
+            }
+        }
+        Some(Update { values, crossed })
+    }
+}
+
+pub struct Indicators {
+    enabled: bool,
+    /// Sorted, without duplicates.
+    periods: Vec<u32>,
+    series: HashMap<(String, String), Series>,
+}
+
+impl Indicators {
+    pub fn new(cfg: &IndicatorsConfig) -> Self {
+        let mut periods = cfg.periods.clone();
+        periods.sort_unstable();
+        periods.dedup();
+        Self {
+            enabled: cfg.enabled && !periods.is_empty(),
+            periods,
+            series: HashMap::new(),
+        }
+    }
+
+    /// Feeds this cycle's prices and returns the resulting averages, to be written.
+    pub async fn update(
+        &mut self,
+        store: &dyn PriceStore,
+        prices: &[StockPrice],
+    ) -> Vec<Indicator> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        let mut rows = Vec::new();
+        for price in prices {
+            let key = (price.symbol.clone(), price.source.clone());
+            if !self.series.contains_key(&key) {
+                let series = self.warm_up(store, price).await;
+                self.series.insert(key.clone(), series);
+            }
+            let Some(series) = self.series.get_mut(&key) else {
+                continue;
+            };
+            let Some(update) = series.push(price.timestamp, price.price, &self.periods) else {
+                continue;
+            };
+
+            if let Some(bullish) = update.crossed {
+                info!(
+                    symbol = %price.symbol,
+                    source = %price.source,
+                    short = self.periods[0],
+                    long = self.periods[self.periods.len() - 1],
+                    direction = if bullish { "up" } else { "down" },
+                    price = price.price,
+                    "Moving-average crossover"
+                );
+            }
+            rows.extend(
+                update
+                    .values
+                    .into_iter()
+                    .map(|(kind, period, value)| Indicator {
+                        symbol: price.symbol.clone(),
+                        source: price.source.clone(),
+                        kind,
+                        period,
+                        timestamp: price.timestamp,
+                        value,
+                    }),
+            );
+        }
+
+        rows
+    }
+
+    /// Replays the stored history before `price` so the averages pick up where they left off.
+    async fn warm_up(&self, store: &dyn PriceStore, price: &StockPrice) -> Series {
+        let mut series = Series::default();
+        let Some(&longest) = self.periods.last() else {
+            return series;
+        };
+        let limit = longest.saturating_mul(WARMUP_FACTOR);
+        match store
+            .recent(&price.symbol, Some(&price.source), price.timestamp, limit)
+            .await
+        {
+            Ok(history) => {
+                for old in history {
+                    series.push(old.timestamp, old.price, &self.periods);
+                }
+            }
+            Err(e) => warn!(
+                symbol = %price.symbol,
+                source = %price.source,
+                error = %e,
+                "Cannot load history for moving averages, starting empty"
+            ),
+        }
+        series
+    }
+}
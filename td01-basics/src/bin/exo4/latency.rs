@@ -0,0 +1,51 @@
+//! Wall-clock time of each provider request, one attempt at a time: rate-limit waits and
+//! retry delays are not in it. Recorded by `Deadline`, summed up once per cycle.
+
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::metrics::metrics;
+
+/// This cycle's request times per source.
+static REQUESTS: LazyLock<Mutex<BTreeMap<&'static str, Vec<Duration>>>> =
+    LazyLock::new(Default::default);
+
+pub fn record(source: &'static str, elapsed: Duration) {
+    metrics().observe_latency(source, elapsed);
+    REQUESTS
+        .lock()
+        .unwrap()
+        .entry(source)
+        .or_default()
+        .push(elapsed);
+}
+
+/// Logs the p95 request time per source since the last call, e.g.
+/// `finnhub p95 310ms (12), alpha_vantage p95 1.8s (3)`.
+pub fn log_summary() {
+    let requests = std::mem::take(&mut *REQUESTS.lock().unwrap());
+    if requests.is_empty() {
+        return;
+    }
+    let summary: Vec<String> = requests
+        .into_iter()
+        .map(|(source, mut times)| {
+            times.sort();
+            // Nearest rank: the smallest time at least 95% of the requests didn't exceed
+            let rank = (times.len() * 95).div_ceil(100).max(1);
+            format!("{source} p95 {} ({})", format(times[rank - 1]), times.len())
+        })
+        .collect();
+    info!(latency = %summary.join(", "), "Provider request latency");
+}
+
+fn format(elapsed: Duration) -> String {
+    if elapsed < Duration::from_secs(1) {
+        format!("{}ms", elapsed.as_millis())
+    } else {
+        format!("{:.1}s", elapsed.as_secs_f64())
+    }
+}
@@ -0,0 +1,47 @@
+//! Log output: human-readable by default, JSON lines for log pipelines (Loki and the like).
+
+use clap::ValueEnum;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    Pretty,
+    /// One JSON object per line; event fields are top-level keys, the enclosing span's
+    /// fields sit under `span`
+    Json,
+}
+
+/// `level` is an `EnvFilter` directive (`debug`, `info,exo4::sources=debug`); without it
+/// `RUST_LOG` is used, else `info`. Logs go to stderr when stdout carries a subcommand's
+/// output.
+pub fn init(format: LogFormat, level: Option<&str>, to_stderr: bool) -> Result<(), String> {
+    let filter = match level {
+        Some(level) => {
+            EnvFilter::try_new(level).map_err(|e| format!("invalid --log-level '{level}': {e}"))?
+        }
+        None => EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .from_env_lossy(),
+    };
+    let writer = if to_stderr {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer);
+    match format {
+        LogFormat::Pretty => builder.with_target(false).with_thread_ids(false).init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .init(),
+    }
+    Ok(())
+}
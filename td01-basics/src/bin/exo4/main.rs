@@ -0,0 +1,535 @@
+/* Part 4
+  * Use `tokio::time::interval` for periodic tasks (fetch every minute)
+  * Use `tokio::select!` for handling multiple concurrent operations
+  * Implement graceful shutdown with `tokio::signal::ctrl_c()`
+  * Add structured logging with `tracing`
+
+---*/
+use std::sync::Arc;
+
+use clap::{Parser, Subcommand};
+use market_core::store::{self, PriceStore};
+use tokio::sync::watch;
+use tokio::time::{interval, timeout, Duration, Instant, MissedTickBehavior};
+use tracing::{debug, error, info, instrument, warn};
+
+mod alerts;
+mod aliases;
+mod anomaly;
+mod backfill;
+mod breaker;
+mod candles;
+mod config;
+mod cycle;
+mod deadline;
+mod divergence;
+mod dry_run;
+mod export;
+mod fx;
+mod health;
+mod indicators;
+mod latency;
+mod logging;
+mod market_hours;
+mod metrics;
+mod portfolio;
+mod publish;
+mod ratelimit;
+mod reload;
+mod report;
+mod retention;
+mod retry;
+mod show;
+mod shutdown;
+mod sources;
+mod spread;
+mod staleness;
+mod stats;
+mod storage;
+mod validate;
+mod webhook;
+mod writer;
+
+use alerts::MoveAlerts;
+use anomaly::Anomalies;
+use config::StaleAction;
+use divergence::Divergence;
+use fx::Fx;
+use health::Health;
+use indicators::Indicators;
+use market_hours::MarketHours;
+use portfolio::Portfolio;
+use publish::Publisher;
+use reload::{ReloadSignal, Tracked};
+use shutdown::ShutdownSignal;
+use sources::FetchError;
+use staleness::Staleness;
+use validate::Validator;
+use writer::Writer;
+
+#[derive(Parser, Debug)]
+#[command(
+    about = "Stock price aggregator: fetches quotes periodically and stores them in Postgres or SQLite"
+)]
+struct Cli {
+    /// Symbols to track, comma separated [default: AAPL,GOOGL,MSFT,BTC-USD,ETH-USD]
+    #[arg(long, value_delimiter = ',')]
+    symbols: Option<Vec<String>>,
+
+    /// Time between fetch cycles (e.g. 30s, 5m) [default: 60s]
+    #[arg(long, value_parser = humantime::parse_duration)]
+    interval: Option<Duration>,
+
+    /// Sources to fetch from, comma separated (default: every source with credentials set)
+    #[arg(long, value_delimiter = ',')]
+    sources: Option<Vec<String>>,
+
+    /// Fetch only from the mock source: random-walk prices, no network or API key needed
+    #[arg(long, conflicts_with = "sources")]
+    mock: bool,
+
+    /// Seed of the mock source, for the same prices on every run (see [mock] in the config)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Maximum number of symbols fetched at the same time [default: 4]
+    #[arg(long)]
+    concurrency: Option<usize>,
+
+    /// Run a single fetch cycle and exit
+    #[arg(long)]
+    once: bool,
+
+    /// Fetch and log the prices that would be saved, without touching (or needing) a database
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Fetch every symbol at the tick instead of spreading them over the interval
+    #[arg(long)]
+    no_stagger: bool,
+
+    /// Cancel a fetch cycle still running after this long (e.g. 2m) [default: no limit]
+    #[arg(long, value_parser = humantime::parse_duration)]
+    max_cycle_duration: Option<Duration>,
+
+    /// On Ctrl+C or SIGTERM, how long the cycle in progress may take to finish [default: 30s]
+    #[arg(long, value_parser = humantime::parse_duration)]
+    shutdown_grace: Option<Duration>,
+
+    /// Config file (default: aggregator.toml if present)
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Print the effective configuration (secrets redacted) and exit
+    #[arg(long)]
+    print_config: bool,
+
+    /// Database URL, postgres://... or sqlite://... (default: DATABASE_URL, else sqlite://prices.db)
+    #[arg(long)]
+    db: Option<String>,
+
+    /// Delete stored prices older than this many days (default: keep everything)
+    #[arg(long)]
+    retention_days: Option<u32>,
+
+    /// Warn when two sources disagree on a symbol by more than this many percent (e.g. 0.5)
+    #[arg(long)]
+    divergence_threshold: Option<f64>,
+
+    /// POST price-move alerts (see [alerts] in the config) to this URL
+    #[arg(long)]
+    alert_webhook: Option<String>,
+
+    /// Serve Prometheus metrics on this port at /metrics (0 disables)
+    #[arg(long)]
+    metrics_port: Option<u16>,
+
+    /// Don't apply pending database migrations at startup (for users without DDL rights)
+    #[arg(long)]
+    skip_migrations: bool,
+
+    #[arg(long, value_enum, env = "LOG_FORMAT", default_value_t = logging::LogFormat::Pretty)]
+    log_format: logging::LogFormat,
+
+    /// Log filter, e.g. debug or info,exo4::sources=debug [default: RUST_LOG, else info]
+    #[arg(long)]
+    log_level: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write stored prices as CSV (or NDJSON) instead of fetching
+    Export(export::ExportArgs),
+    /// Print the latest stored price per source (or recent history) for a symbol
+    Show(show::ShowArgs),
+    /// Store a year (or --from/--to) of Alpha Vantage daily candles for new symbols
+    Backfill(backfill::BackfillArgs),
+    /// Print success rate and p95 latency per source over the last week (or --since/--until)
+    Stats(stats::StatsArgs),
+}
+
+/// State carried from one cycle to the next by the checks on each cycle's prices.
+struct Watchers {
+    validator: Validator,
+    staleness: Staleness,
+    anomalies: Anomalies,
+    divergence: Divergence,
+    move_alerts: MoveAlerts,
+    indicators: Indicators,
+    portfolio: Portfolio,
+    market_hours: MarketHours,
+}
+
+#[instrument(skip_all, fields(symbols = tracked.symbols.len()))]
+async fn fetch_and_save_all(
+    store: &dyn PriceStore,
+    tracked: &Tracked,
+    spread: Option<Duration>,
+    watchers: &mut Watchers,
+    fx: &Fx,
+    writer: &Writer,
+    publisher: &Publisher,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (symbols, market_closed) = watchers
+        .market_hours
+        .due(&tracked.market_hours, &tracked.symbols);
+    match spread {
+        Some(spread) => info!(
+            spacing_ms = (spread / symbols.len().max(1) as u32).as_millis() as u64,
+            "Starting staggered fetch cycle"
+        ),
+        None => info!("Starting fetch cycle"),
+    }
+    let started = Instant::now();
+    let started_at = chrono::Utc::now();
+
+    let mut outcomes = cycle::fetch_all(
+        &tracked.sources,
+        &symbols,
+        tracked.concurrency,
+        spread,
+        &tracked.strategy,
+    )
+    .await;
+    fx.convert_all(&tracked.currency, &mut outcomes).await;
+    watchers
+        .validator
+        .check(store, &tracked.validation, &mut outcomes)
+        .await;
+    watchers.staleness.check(&tracked.staleness, &mut outcomes);
+    watchers
+        .anomalies
+        .check(
+            store,
+            &tracked.anomaly,
+            tracked.alerts.webhook.as_deref(),
+            &mut outcomes,
+        )
+        .await;
+
+    let mut prices = Vec::new();
+    let mut stats = Vec::new();
+    let (mut failed, mut skipped) = (0, 0);
+    for outcome in outcomes {
+        metrics::metrics().record_fetch(outcome.source, &outcome.symbol, &outcome.result);
+        stats.extend(outcome.stat(started_at));
+        match outcome.result {
+            Ok(price) => prices.push(price),
+            Err(e @ FetchError::BudgetExhausted { .. }) => {
+                skipped += 1;
+                info!(symbol = %outcome.symbol, source = outcome.source, reason = %e, "Rate-budget exhausted, skipped fetch");
+            }
+            Err(FetchError::CircuitOpen { .. }) => {
+                skipped += 1;
+                debug!(symbol = %outcome.symbol, source = outcome.source, "Circuit open, skipped fetch");
+            }
+            // Already logged with the quote by the validator
+            Err(FetchError::Rejected { .. }) => failed += 1,
+            Err(e) => {
+                failed += 1;
+                warn!(symbol = %outcome.symbol, source = outcome.source, error = %e, "Failed to fetch price");
+            }
+        }
+    }
+    let succeeded = prices.len();
+    let stale = prices.iter().filter(|price| price.stale).count();
+    if tracked.staleness.action == StaleAction::Skip {
+        prices.retain(|price| !price.stale);
+    }
+
+    writer.send(&prices);
+    publisher.publish(&prices);
+    latency::log_summary();
+    spread::log_summary(&prices, tracked.divergence.freshness);
+    watchers.divergence.check(&tracked.divergence, &prices);
+    watchers.move_alerts.check(&tracked.alerts, &prices);
+    let indicators = watchers.indicators.update(store, &prices).await;
+    let portfolio = watchers
+        .portfolio
+        .value(
+            store,
+            &tracked.portfolio,
+            &prices,
+            tracked.divergence.freshness,
+        )
+        .await;
+    writer.send_derived(stats, indicators, portfolio.map(|v| v.snapshot()));
+    // A cycle with every market closed has nothing to fetch, which is not a failure
+    if succeeded > 0 || symbols.is_empty() {
+        metrics::metrics().cycle_succeeded();
+    }
+
+    info!(
+        succeeded,
+        failed,
+        skipped,
+        market_closed,
+        stale,
+        queued = prices.len(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "Completed fetch cycle"
+    );
+    Ok(())
+}
+
+/// Outcome of a cycle run under the optional `max_cycle_duration` timeout.
+fn log_cycle_result(
+    result: Result<Result<(), Box<dyn std::error::Error>>, tokio::time::error::Elapsed>,
+    max_cycle_duration: Option<Duration>,
+) {
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => error!(error = %e, "Error during fetch cycle"),
+        Err(_) => {
+            metrics::metrics().cycle_timed_out();
+            warn!(
+                max_cycle_duration = %humantime::format_duration(max_cycle_duration.unwrap_or_default()),
+                "Fetch cycle took too long, cancelled"
+            );
+        }
+    }
+}
+
+async fn open_store(
+    cfg: &config::Config,
+    cli: &Cli,
+) -> Result<Arc<dyn PriceStore>, store::StorageError> {
+    let database_url = store::database_url(cfg.database.url.clone());
+    store::connect(&database_url, &cfg.database.pool(), !cli.skip_migrations).await
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
+    // Load environment variables from .env file in current directory or parent
+    dotenv::from_filename(".env")
+        .ok()
+        .or_else(|| dotenv::from_filename("td01-basics/.env").ok());
+
+    // Setup tracing; on stderr for subcommands, whose output goes to stdout
+    logging::init(
+        cli.log_format,
+        cli.log_level.as_deref(),
+        cli.command.is_some(),
+    )?;
+
+    // Configuration (validated before touching the database)
+    let cfg = config::merge_config(config::load_config(cli.config.as_deref())?, &cli);
+    if cli.print_config {
+        print!("{}", config::redacted_toml(&cfg));
+        return Ok(());
+    }
+
+    match &cli.command {
+        Some(Command::Export(args)) => {
+            let store = open_store(&cfg, &cli).await?;
+            let result = export::run(store.as_ref(), args).await;
+            store.close().await;
+            return result.map(|_| ());
+        }
+        Some(Command::Show(args)) => {
+            let store = open_store(&cfg, &cli).await?;
+            let result = show::run(store.as_ref(), args).await;
+            store.close().await;
+            if !result? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Stats(args)) => {
+            let store = open_store(&cfg, &cli).await?;
+            let result = stats::run(store.as_ref(), args).await;
+            store.close().await;
+            if !result? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Backfill(args)) => {
+            let store = open_store(&cfg, &cli).await?;
+            let result = backfill::run(store.as_ref(), &cfg, args).await;
+            store.close().await;
+            return result;
+        }
+        None => {}
+    }
+
+    info!("Starting stock price aggregator");
+
+    let mut tracked = Tracked::from_config(&cfg, &cli)?;
+    tracked.log_strategy();
+
+    let store: Arc<dyn PriceStore> = if cli.dry_run {
+        info!("Dry run: prices are logged, nothing is written");
+        Arc::new(dry_run::DryRunStore)
+    } else {
+        let store = open_store(&cfg, &cli).await?;
+        info!(backend = store.backend(), "Connected to database");
+        store
+    };
+
+    let mut watchers = Watchers {
+        validator: Validator::default(),
+        staleness: Staleness::default(),
+        anomalies: Anomalies::default(),
+        divergence: Divergence::default(),
+        move_alerts: MoveAlerts::default(),
+        indicators: Indicators::new(&cfg.indicators),
+        portfolio: Portfolio::default(),
+        market_hours: MarketHours::default(),
+    };
+    let fx = Fx::new();
+    let writer = Writer::spawn(store.clone(), cfg.writer.clone());
+    let publisher = if cli.dry_run {
+        Publisher::default()
+    } else {
+        Publisher::from_env()?
+    };
+    if cli.once {
+        if let Err(e) = fetch_and_save_all(
+            store.as_ref(),
+            &tracked,
+            None,
+            &mut watchers,
+            &fx,
+            &writer,
+            &publisher,
+        )
+        .await
+        {
+            error!(error = %e, "Error during fetch cycle");
+        }
+    } else {
+        // Create interval for periodic fetching
+        let mut fetch_interval = interval(cfg.interval);
+        // After an overrunning cycle, wait for the next aligned tick rather than catching up
+        fetch_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut reload_signal = ReloadSignal::new()?;
+        let retention = tokio::spawn(retention::run(store.clone(), cfg.retention.clone()));
+        let candles = tokio::spawn(candles::run(store.clone(), cfg.candles.clone()));
+        let (report_symbols, symbols) = watch::channel(tracked.symbols.clone());
+        let report = tokio::spawn(report::run(store.clone(), cfg.report.clone(), symbols));
+        let health = Arc::new(Health::new(store.clone(), &cfg.health));
+        health.update_providers(&tracked.sources);
+        let http_server = match cfg.metrics.port.filter(|&port| port != 0) {
+            Some(port) => {
+                let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+                info!(port, "Serving /metrics, /healthz and /readyz");
+                let app = metrics::router()
+                    .merge(health::router(health.clone()))
+                    .merge(portfolio::router());
+                Some(tokio::spawn(async move {
+                    if let Err(e) = axum::serve(listener, app).await {
+                        error!(error = %e, "HTTP listener stopped");
+                    }
+                }))
+            }
+            None => None,
+        };
+
+        info!(
+            interval = %humantime::format_duration(cfg.interval),
+            "Starting periodic fetch loop. Press Ctrl+C (or send SIGTERM) to stop, send SIGHUP to reload the config."
+        );
+
+        let mut shutdown = ShutdownSignal::new()?;
+
+        // Main loop
+        loop {
+            tokio::select! {
+                _ = fetch_interval.tick() => {
+                    // Leave a quarter of the interval for the last fetches to finish before the next tick
+                    let spread = tracked.stagger.then_some(cfg.interval * 3 / 4);
+                    let started = Instant::now();
+                    let cycle = fetch_and_save_all(store.as_ref(), &tracked, spread, &mut watchers, &fx, &writer, &publisher);
+                    let cycle = async {
+                        match cfg.max_cycle_duration {
+                            Some(max) => timeout(max, cycle).await,
+                            None => Ok(cycle.await),
+                        }
+                    };
+                    tokio::pin!(cycle);
+                    tokio::select! {
+                        result = &mut cycle => log_cycle_result(result, cfg.max_cycle_duration),
+                        signal = shutdown.recv() => {
+                            info!(signal, "Shutdown signal received, no new cycle will start");
+                            if let Some(result) = shutdown::drain(cycle, cfg.shutdown_grace, &mut shutdown).await {
+                                log_cycle_result(result, cfg.max_cycle_duration);
+                            }
+                            break;
+                        }
+                    }
+                    health.update_providers(&tracked.sources);
+
+                    let took = started.elapsed();
+                    if took > cfg.interval {
+                        let skipped = (took.as_nanos() / cfg.interval.as_nanos()) as u64;
+                        metrics::metrics().ticks_skipped(skipped);
+                        warn!(
+                            previous_cycle_ms = took.as_millis() as u64,
+                            skipped,
+                            "Fetch cycle overran the interval, skipping ticks"
+                        );
+                    }
+                }
+                // Cycles run inside the tick arm, so a reload always lands between two cycles
+                _ = reload_signal.recv() => {
+                    match Tracked::reload(&cli) {
+                        Ok(new) => {
+                            reload::log_reload(&tracked, &new);
+                            tracked = new;
+                            health.update_providers(&tracked.sources);
+                            report_symbols.send_replace(tracked.symbols.clone());
+                        }
+                        Err(e) => error!(error = %e, "Config reload rejected, keeping the current one"),
+                    }
+                }
+                signal = shutdown.recv() => {
+                    info!(signal, "Shutdown signal received");
+                    break;
+                }
+            }
+        }
+        info!("Stopping background tasks");
+        retention.abort();
+        candles.abort();
+        report.abort();
+        if let Some(server) = http_server {
+            server.abort();
+        }
+    }
+
+    // Graceful shutdown
+    info!("Writing buffered prices...");
+    writer.close(cfg.shutdown_grace).await;
+    publisher.close().await;
+    info!("Closing database connections...");
+    store.close().await;
+    info!("Shutdown complete");
+
+    Ok(())
+}
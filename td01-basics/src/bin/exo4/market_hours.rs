@@ -0,0 +1,91 @@
+//! Exchange calendar gate: equities are left out of the cycle while their market is closed,
+//! so weekends and nights don't spend the request budget on the same closing quote.
+
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{DateTime, Datelike, Utc, Weekday};
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::config::{Market, MarketHoursConfig};
+use crate::sources::AssetClass;
+
+/// Which symbols were closed last cycle, and when each was last let through off hours.
+#[derive(Default)]
+pub struct MarketHours {
+    closed: BTreeSet<String>,
+    off_hours_fetch: HashMap<String, Instant>,
+}
+
+impl MarketHours {
+    /// The symbols to fetch this cycle, and how many were left out as "market closed".
+    /// With `off_hours_interval`, a closed symbol still goes through once per interval.
+    pub fn due(&mut self, cfg: &MarketHoursConfig, symbols: &[String]) -> (Vec<String>, usize) {
+        if !cfg.enabled {
+            self.closed.clear();
+            return (symbols.to_vec(), 0);
+        }
+        let now = Utc::now();
+        let closed: BTreeSet<String> = symbols
+            .iter()
+            .filter(|symbol| AssetClass::of(symbol) == AssetClass::Equity)
+            .filter(|symbol| !is_open(&cfg.market(symbol), now))
+            .cloned()
+            .collect();
+        self.closed.retain(|symbol| symbols.contains(symbol));
+        self.log_changes(&closed);
+        self.off_hours_fetch
+            .retain(|symbol, _| closed.contains(symbol));
+
+        let mut skipped = 0;
+        let due = symbols
+            .iter()
+            .filter(|symbol| {
+                if !closed.contains(*symbol) {
+                    return true;
+                }
+                let off_hours_due = cfg.off_hours_interval.is_some_and(|every| {
+                    self.off_hours_fetch
+                        .get(*symbol)
+                        .is_none_or(|last| last.elapsed() >= every)
+                });
+                if off_hours_due {
+                    self.off_hours_fetch
+                        .insert(symbol.to_string(), Instant::now());
+                } else {
+                    skipped += 1;
+                }
+                off_hours_due
+            })
+            .cloned()
+            .collect();
+        self.closed = closed;
+        (due, skipped)
+    }
+
+    fn log_changes(&self, closed: &BTreeSet<String>) {
+        let opened: Vec<&str> = self.closed.difference(closed).map(String::as_str).collect();
+        let paused: Vec<&str> = closed
+            .difference(&self.closed)
+            .map(String::as_str)
+            .collect();
+        if !opened.is_empty() {
+            info!(symbols = %opened.join(","), "Market open, resuming fetches");
+        }
+        if !paused.is_empty() {
+            info!(symbols = %paused.join(","), "Market closed, pausing fetches");
+        }
+    }
+}
+
+/// Regular session: Monday to Friday between `open` and `close` local time, holidays excluded.
+pub fn is_open(market: &Market, now: DateTime<Utc>) -> bool {
+    let local = now.with_timezone(&market.timezone);
+    if matches!(local.weekday(), Weekday::Sat | Weekday::Sun)
+        || market.holidays.contains(&local.date_naive())
+    {
+        return false;
+    }
+    let time = local.time();
+    market.open <= time && time < market.close
+}
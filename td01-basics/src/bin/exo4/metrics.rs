@@ -0,0 +1,238 @@
+//! Prometheus metrics. Every handle is registered here; the rest of the aggregator only
+//! bumps them through `metrics()`. Served on `/metrics` next to the health probes.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use axum::routing::get;
+use axum::Router;
+use chrono::Utc;
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use tracing::error;
+
+use crate::sources::{FetchError, StockPrice};
+
+pub struct Metrics {
+    registry: Registry,
+    fetch_success: IntCounterVec,
+    fetch_failure: IntCounterVec,
+    rows_inserted: IntCounter,
+    fetch_latency: HistogramVec,
+    circuit_open: IntGaugeVec,
+    since_last_success: Gauge,
+    ticks_skipped: IntCounter,
+    cycle_timeouts: IntCounter,
+    fetch_timeouts: IntCounterVec,
+    writer_buffered: IntGauge,
+    writer_dropped: IntCounter,
+    redis_dropped: IntCounterVec,
+    /// Unix seconds of the last cycle that fetched at least one price; process start until then.
+    last_success: AtomicI64,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let fetch_success = IntCounterVec::new(
+            Opts::new("fetch_success_total", "Prices fetched successfully"),
+            &["source", "symbol"],
+        )
+        .expect("valid metric");
+        let fetch_failure = IntCounterVec::new(
+            Opts::new(
+                "fetch_failure_total",
+                "Failed or skipped fetches, by error kind",
+            ),
+            &["source", "reason"],
+        )
+        .expect("valid metric");
+        let rows_inserted =
+            IntCounter::new("rows_inserted_total", "Price rows written to the database")
+                .expect("valid metric");
+        let fetch_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "fetch_duration_seconds",
+                "Time of one provider request, without rate-limit waits or retry delays",
+            )
+            .buckets(vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]),
+            &["source"],
+        )
+        .expect("valid metric");
+        let circuit_open = IntGaugeVec::new(
+            Opts::new(
+                "circuit_open",
+                "1 while the source's circuit breaker is open or probing",
+            ),
+            &["source"],
+        )
+        .expect("valid metric");
+        let since_last_success = Gauge::new(
+            "seconds_since_last_successful_cycle",
+            "Seconds since a fetch cycle last got at least one price",
+        )
+        .expect("valid metric");
+        let ticks_skipped = IntCounter::new(
+            "skipped_ticks_total",
+            "Interval ticks dropped because the previous cycle was still running",
+        )
+        .expect("valid metric");
+        let cycle_timeouts = IntCounter::new(
+            "cycle_timeouts_total",
+            "Fetch cycles cancelled after max_cycle_duration",
+        )
+        .expect("valid metric");
+        let fetch_timeouts = IntCounterVec::new(
+            Opts::new(
+                "fetch_timeouts_total",
+                "Fetch attempts abandoned after the source's fetch_timeout, retries included",
+            ),
+            &["source"],
+        )
+        .expect("valid metric");
+        let writer_buffered = IntGauge::new(
+            "writer_buffered_prices",
+            "Prices waiting to be written to the database",
+        )
+        .expect("valid metric");
+        let writer_dropped = IntCounter::new(
+            "writer_dropped_total",
+            "Prices dropped unwritten because the writer buffer was full",
+        )
+        .expect("valid metric");
+        let redis_dropped = IntCounterVec::new(
+            Opts::new(
+                "redis_dropped_total",
+                "Price updates not published to Redis, by reason",
+            ),
+            &["reason"],
+        )
+        .expect("valid metric");
+
+        let registry = Registry::new();
+        for collector in [
+            Box::new(fetch_success.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(fetch_failure.clone()),
+            Box::new(rows_inserted.clone()),
+            Box::new(fetch_latency.clone()),
+            Box::new(circuit_open.clone()),
+            Box::new(since_last_success.clone()),
+            Box::new(ticks_skipped.clone()),
+            Box::new(cycle_timeouts.clone()),
+            Box::new(fetch_timeouts.clone()),
+            Box::new(writer_buffered.clone()),
+            Box::new(writer_dropped.clone()),
+            Box::new(redis_dropped.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("metric registered once");
+        }
+
+        Self {
+            registry,
+            fetch_success,
+            fetch_failure,
+            rows_inserted,
+            fetch_latency,
+            circuit_open,
+            since_last_success,
+            ticks_skipped,
+            cycle_timeouts,
+            fetch_timeouts,
+            writer_buffered,
+            writer_dropped,
+            redis_dropped,
+            last_success: AtomicI64::new(Utc::now().timestamp()),
+        }
+    }
+
+    pub fn record_fetch(
+        &self,
+        source: &str,
+        symbol: &str,
+        result: &Result<StockPrice, FetchError>,
+    ) {
+        match result {
+            Ok(_) => self
+                .fetch_success
+                .with_label_values(&[source, symbol])
+                .inc(),
+            Err(e) => self
+                .fetch_failure
+                .with_label_values(&[source, e.reason()])
+                .inc(),
+        }
+    }
+
+    pub fn observe_latency(&self, source: &str, elapsed: Duration) {
+        self.fetch_latency
+            .with_label_values(&[source])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn rows_inserted(&self, rows: u64) {
+        self.rows_inserted.inc_by(rows);
+    }
+
+    pub fn set_circuit_open(&self, source: &str, open: bool) {
+        self.circuit_open
+            .with_label_values(&[source])
+            .set(i64::from(open));
+    }
+
+    pub fn cycle_succeeded(&self) {
+        self.last_success
+            .store(Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    pub fn ticks_skipped(&self, ticks: u64) {
+        self.ticks_skipped.inc_by(ticks);
+    }
+
+    pub fn cycle_timed_out(&self) {
+        self.cycle_timeouts.inc();
+    }
+
+    pub fn fetch_timed_out(&self, source: &str) {
+        self.fetch_timeouts.with_label_values(&[source]).inc();
+    }
+
+    pub fn set_writer_buffered(&self, prices: usize) {
+        self.writer_buffered.set(prices as i64);
+    }
+
+    pub fn writer_dropped(&self, prices: u64) {
+        self.writer_dropped.inc_by(prices);
+    }
+
+    #[cfg_attr(not(feature = "redis"), allow(dead_code))]
+    pub fn redis_dropped(&self, reason: &str, events: u64) {
+        self.redis_dropped
+            .with_label_values(&[reason])
+            .inc_by(events);
+    }
+
+    fn render(&self) -> String {
+        let since = Utc::now().timestamp() - self.last_success.load(Ordering::Relaxed);
+        self.since_last_success.set(since as f64);
+
+        let mut buf = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buf) {
+            error!(error = %e, "Cannot encode metrics");
+        }
+        String::from_utf8(buf).unwrap_or_default()
+    }
+}
+
+pub fn router() -> Router {
+    Router::new().route("/metrics", get(|| async { metrics().render() }))
+}
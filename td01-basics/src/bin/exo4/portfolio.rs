@@ -0,0 +1,200 @@
+//! Value and P&L of the `[portfolio]` holdings after each cycle. The latest valuation is
+//! served as JSON on `/portfolio` next to the metrics.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{LazyLock, RwLock};
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use market_core::store::{PortfolioSnapshot, PriceStore};
+use serde::Serialize;
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::config::PortfolioConfig;
+use crate::sources::StockPrice;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Position {
+    pub symbol: String,
+    pub quantity: f64,
+    pub cost_basis: f64,
+    /// `None` when no price was ever seen for the symbol.
+    pub price: Option<f64>,
+    pub source: Option<String>,
+    pub price_time: Option<DateTime<Utc>>,
+    pub value: Option<f64>,
+    pub pnl: Option<f64>,
+    pub pnl_pct: Option<f64>,
+    /// Valued at a price older than the freshness window, flagged stale, or not at all.
+    pub stale: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Valuation {
+    pub timestamp: DateTime<Utc>,
+    /// Positions with a price only.
+    pub total_value: f64,
+    pub total_cost: f64,
+    pub pnl: f64,
+    pub pnl_pct: Option<f64>,
+    pub stale_positions: u32,
+    pub positions: Vec<Position>,
+}
+
+impl Valuation {
+    pub fn snapshot(&self) -> PortfolioSnapshot {
+        PortfolioSnapshot {
+            timestamp: self.timestamp,
+            total_value: self.total_value,
+            total_cost: self.total_cost,
+            pnl: self.pnl,
+            stale_positions: self.stale_positions,
+            positions: serde_json::to_value(&self.positions).unwrap_or_default(),
+        }
+    }
+}
+
+static LATEST: LazyLock<RwLock<Option<Valuation>>> = LazyLock::new(Default::default);
+
+/// Best price seen so far per symbol, kept across cycles.
+#[derive(Default)]
+pub struct Portfolio {
+    last: HashMap<String, StockPrice>,
+    /// Symbols already looked up in the store, found or not.
+    loaded: HashSet<String>,
+}
+
+impl Portfolio {
+    /// Values the holdings with this cycle's prices, falling back to the last known price
+    /// (from earlier cycles or the store). `None` without holdings.
+    pub async fn value(
+        &mut self,
+        store: &dyn PriceStore,
+        cfg: &PortfolioConfig,
+        prices: &[StockPrice],
+        freshness: Duration,
+    ) -> Option<Valuation> {
+        if cfg.holdings.is_empty() {
+            return None;
+        }
+        let now = Utc::now();
+        let freshness = chrono::Duration::from_std(freshness).unwrap_or(chrono::Duration::MAX);
+        let is_fresh = |price: &StockPrice| !price.stale && now - price.timestamp <= freshness;
+
+        for price in prices {
+            let better = match self.last.get(&price.symbol) {
+                None => true,
+                // Fresh over stale, then the most recent
+                Some(last) => (is_fresh(price), price.timestamp) > (is_fresh(last), last.timestamp),
+            };
+            if better {
+                self.last.insert(price.symbol.clone(), price.clone());
+            }
+        }
+        for holding in &cfg.holdings {
+            if self.last.contains_key(&holding.symbol)
+                || !self.loaded.insert(holding.symbol.clone())
+            {
+                continue;
+            }
+            match store.recent(&holding.symbol, None, now, 1).await {
+                Ok(mut found) => {
+                    if let Some(price) = found.pop() {
+                        self.last.insert(holding.symbol.clone(), price);
+                    }
+                }
+                Err(e) => warn!(
+                    symbol = %holding.symbol,
+                    error = %e,
+                    "Cannot load last price for the portfolio"
+                ),
+            }
+        }
+
+        let positions: Vec<Position> = cfg
+            .holdings
+            .iter()
+            .map(|holding| {
+                let price = self.last.get(&holding.symbol);
+                let value = price.map(|p| p.price * holding.quantity);
+                let cost = holding.cost_basis * holding.quantity;
+                Position {
+                    symbol: holding.symbol.clone(),
+                    quantity: holding.quantity,
+                    cost_basis: holding.cost_basis,
+                    price: price.map(|p| p.price),
+                    source: price.map(|p| p.source.clone()),
+                    price_time: price.map(|p| p.timestamp),
+                    value,
+                    pnl: value.map(|v| v - cost),
+                    pnl_pct: value
+                        .filter(|_| cost != 0.0)
+                        .map(|v| (v - cost) / cost * 100.0),
+                    stale: !price.is_some_and(is_fresh),
+                }
+            })
+            .collect();
+
+        let priced = positions.iter().filter(|p| p.value.is_some());
+        let total_value: f64 = priced.clone().filter_map(|p| p.value).sum();
+        let total_cost: f64 = priced.map(|p| p.cost_basis * p.quantity).sum();
+        let pnl = total_value - total_cost;
+        let valuation = Valuation {
+            timestamp: now,
+            total_value,
+            total_cost,
+            pnl,
+            pnl_pct: (total_cost != 0.0).then(|| pnl / total_cost * 100.0),
+            stale_positions: positions.iter().filter(|p| p.stale).count() as u32,
+            positions,
+        };
+        log(&valuation);
+        *LATEST.write().unwrap() = Some(valuation.clone());
+        Some(valuation)
+    }
+}
+
+fn log(valuation: &Valuation) {
+    let positions: Vec<String> = valuation
+        .positions
+        .iter()
+        .map(|p| match (p.price, p.pnl) {
+            (Some(price), Some(pnl)) => format!(
+                "{} {} x {price:.2} ({pnl:+.2}){}",
+                p.symbol,
+                p.quantity,
+                if p.stale { " stale" } else { "" }
+            ),
+            _ => format!("{} {} x ? (no price)", p.symbol, p.quantity),
+        })
+        .collect();
+    info!(
+        total_value = valuation.total_value,
+        total_cost = valuation.total_cost,
+        pnl = valuation.pnl,
+        pnl_pct = valuation.pnl_pct,
+        stale = valuation.stale_positions,
+        positions = %positions.join(", "),
+        "Portfolio valuation"
+    );
+}
+
+/// `GET /portfolio`: the latest valuation, 404 until the first cycle with holdings.
+pub fn router() -> Router {
+    Router::new().route("/portfolio", get(latest))
+}
+
+async fn latest() -> impl IntoResponse {
+    match LATEST.read().unwrap().clone() {
+        Some(valuation) => (StatusCode::OK, Json(json!(valuation))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "no portfolio valuation yet" })),
+        ),
+    }
+}
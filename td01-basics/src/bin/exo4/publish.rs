@@ -0,0 +1,144 @@
+//! Redis pub/sub of saved prices, for consumers that don't read the database: each price
+//! goes out as JSON on `prices.{symbol}`, in the same shape as the `NOTIFY` price notes.
+//! Enabled by `REDIS_URL` in builds with the `redis` feature.
+//!
+//! Publishing never holds up a cycle: prices wait in a bounded queue drained by a background
+//! task, and while Redis is away they are dropped and counted rather than piling up.
+
+#[cfg(feature = "redis")]
+use market_core::store::notify::PriceNote;
+use market_core::StockPrice;
+#[cfg(feature = "redis")]
+use tokio::sync::mpsc;
+#[cfg(feature = "redis")]
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+#[cfg(feature = "redis")]
+use crate::metrics::metrics;
+
+/// Prices waiting to be published; a little over a few cycles of a large watchlist.
+#[cfg(feature = "redis")]
+const QUEUE_CAPACITY: usize = 1024;
+
+#[cfg(feature = "redis")]
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[cfg(feature = "redis")]
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Does nothing unless `REDIS_URL` was set at startup.
+#[derive(Default)]
+pub struct Publisher {
+    #[cfg(feature = "redis")]
+    worker: Option<(mpsc::Sender<PriceNote>, JoinHandle<()>)>,
+}
+
+impl Publisher {
+    #[cfg(feature = "redis")]
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let Ok(url) = std::env::var("REDIS_URL") else {
+            return Ok(Self::default());
+        };
+        let client = redis::Client::open(url).map_err(|e| format!("invalid REDIS_URL: {e}"))?;
+        let (queue, rx) = mpsc::channel(QUEUE_CAPACITY);
+        let task = tokio::spawn(run(client, rx));
+        tracing::info!("Publishing prices to Redis");
+        Ok(Self {
+            worker: Some((queue, task)),
+        })
+    }
+
+    #[cfg(not(feature = "redis"))]
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        if std::env::var_os("REDIS_URL").is_some() {
+            warn!(
+                "REDIS_URL is set but exo4 was built without the `redis` feature, not publishing"
+            );
+        }
+        Ok(Self::default())
+    }
+
+    /// Queues `prices` for publication without waiting; what doesn't fit is dropped.
+    #[cfg(feature = "redis")]
+    pub fn publish(&self, prices: &[StockPrice]) {
+        let Some((queue, _)) = &self.worker else {
+            return;
+        };
+        let mut dropped = 0;
+        for price in prices {
+            if queue.try_send(PriceNote::from(price)).is_err() {
+                dropped += 1;
+            }
+        }
+        if dropped > 0 {
+            metrics().redis_dropped("queue_full", dropped);
+            warn!(dropped, "Redis publish queue full, dropped price updates");
+        }
+    }
+
+    #[cfg(not(feature = "redis"))]
+    pub fn publish(&self, _prices: &[StockPrice]) {}
+
+    /// Gives the queue up to a few seconds to drain, so a `--once` run publishes what it saved.
+    pub async fn close(self) {
+        #[cfg(feature = "redis")]
+        if let Some((queue, mut task)) = self.worker {
+            drop(queue);
+            if tokio::time::timeout(TIMEOUT, &mut task).await.is_err() {
+                warn!("Redis publish queue not drained in time, dropping the rest");
+                task.abort();
+            }
+        }
+    }
+}
+
+/// Owns the connection. `ConnectionManager` multiplexes commands over one connection and
+/// reconnects on its own after a failure; only the first connection is retried here.
+#[cfg(feature = "redis")]
+async fn run(client: redis::Client, mut queue: mpsc::Receiver<PriceNote>) {
+    use redis::aio::ConnectionManagerConfig;
+    use redis::AsyncCommands;
+    use tracing::{debug, info};
+
+    let config = ConnectionManagerConfig::new()
+        .set_connection_timeout(TIMEOUT)
+        .set_response_timeout(TIMEOUT);
+    let mut conn = loop {
+        match client
+            .get_connection_manager_with_config(config.clone())
+            .await
+        {
+            Ok(conn) => break conn,
+            Err(e) => {
+                warn!(error = %e, retry_in_secs = RECONNECT_DELAY.as_secs(), "Cannot connect to Redis");
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    };
+    info!("Connected to Redis");
+
+    // Warn once per outage rather than once per price
+    let mut failing = false;
+    while let Some(note) = queue.recv().await {
+        let channel = format!("prices.{}", note.symbol);
+        let payload = serde_json::to_string(&note).expect("price notes always serialize");
+        match conn.publish::<_, _, ()>(&channel, payload).await {
+            Ok(()) => {
+                if failing {
+                    info!("Redis publishing resumed");
+                    failing = false;
+                }
+            }
+            Err(e) => {
+                metrics().redis_dropped("error", 1);
+                if !failing {
+                    warn!(error = %e, "Redis publish failed, dropping price updates until it recovers");
+                    failing = true;
+                } else {
+                    debug!(error = %e, channel, "Redis publish failed");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Per-provider request budget: a token bucket for the per-minute limit and a counter for
+//! the daily one. When either runs out the request is not sent at all.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Days, NaiveTime, Utc};
+
+use crate::sources::{AssetClass, FetchError, PriceSource, StockPrice};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub per_minute: Option<u32>,
+    pub per_day: Option<u32>,
+    /// UTC time of day at which the daily counter starts over.
+    pub daily_reset: NaiveTime,
+}
+
+impl Limits {
+    pub fn is_unlimited(&self) -> bool {
+        self.per_minute.is_none() && self.per_day.is_none()
+    }
+}
+
+struct Budget {
+    limits: Limits,
+    tokens: f64,
+    refilled_at: Instant,
+    used_today: u32,
+    /// Start of the current daily window.
+    day_started: DateTime<Utc>,
+}
+
+impl Budget {
+    fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            tokens: limits.per_minute.unwrap_or_default() as f64,
+            refilled_at: Instant::now(),
+            used_today: 0,
+            day_started: window_start(Utc::now(), limits.daily_reset),
+        }
+    }
+
+    fn try_acquire(&mut self) -> Result<(), &'static str> {
+        let now = Utc::now();
+        let day_started = window_start(now, self.limits.daily_reset);
+        if day_started != self.day_started {
+            self.day_started = day_started;
+            self.used_today = 0;
+        }
+        if let Some(per_minute) = self.limits.per_minute {
+            let capacity = per_minute as f64;
+            let elapsed = self.refilled_at.elapsed().as_secs_f64();
+            self.tokens = (self.tokens + elapsed * capacity / 60.0).min(capacity);
+            self.refilled_at = Instant::now();
+        }
+
+        if self
+            .limits
+            .per_day
+            .is_some_and(|max| self.used_today >= max)
+        {
+            return Err("daily");
+        }
+        if self.limits.per_minute.is_some() {
+            if self.tokens < 1.0 {
+                return Err("per-minute");
+            }
+            self.tokens -= 1.0;
+        }
+        self.used_today += 1;
+        Ok(())
+    }
+}
+
+/// Most recent `reset` time of day (UTC) at or before `now`.
+fn window_start(now: DateTime<Utc>, reset: NaiveTime) -> DateTime<Utc> {
+    let today = now.date_naive().and_time(reset).and_utc();
+    if today <= now {
+        today
+    } else {
+        today - Days::new(1)
+    }
+}
+
+type SharedBudget = Arc<Mutex<Budget>>;
+
+/// Budgets live for the whole process so a config reload doesn't hand out a fresh quota.
+fn budget_for(source: &'static str, limits: Limits) -> SharedBudget {
+    static BUDGETS: OnceLock<Mutex<HashMap<&'static str, SharedBudget>>> = OnceLock::new();
+    let mut budgets = BUDGETS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let budget = budgets
+        .entry(source)
+        .or_insert_with(|| Arc::new(Mutex::new(Budget::new(limits))))
+        .clone();
+    {
+        let mut b = budget.lock().unwrap_or_else(|e| e.into_inner());
+        if b.limits != limits {
+            b.limits = limits;
+            if let Some(per_minute) = limits.per_minute {
+                b.tokens = b.tokens.min(per_minute as f64);
+            }
+        }
+    }
+    budget
+}
+
+/// For one-off jobs that would rather wait than skip: takes a request from `source`'s
+/// budget, sleeping while the per-minute bucket is empty. A used-up daily budget is
+/// still an error.
+pub async fn acquire_waiting(source: &'static str, limits: Limits) -> Result<(), FetchError> {
+    if limits.is_unlimited() {
+        return Ok(());
+    }
+    let budget = budget_for(source, limits);
+    loop {
+        let acquired = budget
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .try_acquire();
+        match (acquired, limits.per_minute) {
+            (Ok(()), _) => return Ok(()),
+            (Err("per-minute"), Some(per_minute)) => {
+                tokio::time::sleep(Duration::from_secs_f64(60.0 / per_minute.max(1) as f64)).await
+            }
+            (Err(window), _) => {
+                return Err(FetchError::BudgetExhausted {
+                    provider: source,
+                    window,
+                })
+            }
+        }
+    }
+}
+
+pub struct RateLimited {
+    inner: Box<dyn PriceSource>,
+    budget: SharedBudget,
+}
+
+impl RateLimited {
+    pub fn new(inner: Box<dyn PriceSource>, limits: Limits) -> Self {
+        let budget = budget_for(inner.name(), limits);
+        Self { inner, budget }
+    }
+
+    /// Takes one request from the budget, or names the window that is exhausted.
+    fn acquire(&self) -> Result<(), &'static str> {
+        self.budget
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .try_acquire()
+    }
+
+    fn exhausted(&self, window: &'static str) -> FetchError {
+        FetchError::BudgetExhausted {
+            provider: self.name(),
+            window,
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSource for RateLimited {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn asset_classes(&self) -> &'static [AssetClass] {
+        self.inner.asset_classes()
+    }
+
+    fn has_batch_endpoint(&self) -> bool {
+        self.inner.has_batch_endpoint()
+    }
+
+    fn is_available(&self) -> bool {
+        self.inner.is_available()
+    }
+
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        self.acquire().map_err(|window| self.exhausted(window))?;
+        self.inner.fetch(symbol).await
+    }
+
+    /// A batch request costs one token; otherwise every symbol is its own request.
+    async fn fetch_many(&self, symbols: &[&str]) -> Vec<Result<StockPrice, FetchError>> {
+        if !self.has_batch_endpoint() {
+            let mut results = Vec::with_capacity(symbols.len());
+            for symbol in symbols {
+                results.push(self.fetch(symbol).await);
+            }
+            return results;
+        }
+        match self.acquire() {
+            Ok(()) => self.inner.fetch_many(symbols).await,
+            Err(window) => symbols
+                .iter()
+                .map(|_| Err(self.exhausted(window)))
+                .collect(),
+        }
+    }
+}
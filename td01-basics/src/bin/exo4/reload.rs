@@ -0,0 +1,411 @@
+//! Symbol/source list that can be swapped at runtime on SIGHUP.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::config::{
+    self, AlertsConfig, AnomalyConfig, Config, CurrencyConfig, DivergenceConfig, Holding,
+    MarketHoursConfig, PortfolioConfig, StalenessConfig, StrategyConfig, ValidationConfig,
+};
+use crate::fx;
+use crate::sources::{self, AssetClass, PriceSource, KNOWN_SOURCES};
+use crate::Cli;
+
+/// What the fetch loop works on. Rebuilt from scratch on reload, applied between cycles.
+pub struct Tracked {
+    pub symbols: Vec<String>,
+    /// In priority order.
+    pub sources: Vec<Box<dyn PriceSource>>,
+    pub concurrency: usize,
+    pub stagger: bool,
+    pub strategy: StrategyConfig,
+    pub divergence: DivergenceConfig,
+    pub alerts: AlertsConfig,
+    pub currency: CurrencyConfig,
+    pub validation: ValidationConfig,
+    pub staleness: StalenessConfig,
+    pub anomaly: AnomalyConfig,
+    pub portfolio: PortfolioConfig,
+    pub market_hours: MarketHoursConfig,
+}
+
+impl Tracked {
+    /// Validates the config; nothing is returned unless it is fully usable.
+    pub fn from_config(cfg: &Config, cli: &Cli) -> Result<Self, String> {
+        let symbols: Vec<String> = cfg
+            .symbols
+            .iter()
+            .map(|s| s.trim().to_uppercase())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if symbols.is_empty() {
+            return Err("symbols must list at least one symbol".into());
+        }
+        if cfg.interval.is_zero() {
+            return Err("interval must be greater than zero".into());
+        }
+        if cfg.max_cycle_duration.is_some_and(|max| max.is_zero()) {
+            return Err("max_cycle_duration must be greater than zero".into());
+        }
+        if cfg.concurrency == 0 {
+            return Err("concurrency must be greater than zero".into());
+        }
+        let database = &cfg.database;
+        if database.max_connections == 0
+            || database.connect_attempts == 0
+            || database.acquire_timeout.is_zero()
+            || database.statement_timeout.is_some_and(|t| t.is_zero())
+        {
+            return Err(
+                "database max_connections, connect_attempts and timeouts must be greater than zero"
+                    .into(),
+            );
+        }
+        if cfg.retention.interval.is_zero() || cfg.retention.batch_size == 0 {
+            return Err("retention interval and batch_size must be greater than zero".into());
+        }
+        if cfg.candles.enabled && cfg.candles.every.is_zero() {
+            return Err("candles.every must be greater than zero".into());
+        }
+        if cfg
+            .divergence
+            .threshold_pct
+            .is_some_and(|pct| !pct.is_finite() || pct <= 0.0)
+        {
+            return Err("divergence threshold must be a positive percentage".into());
+        }
+        if cfg.divergence.freshness.is_zero() {
+            return Err("divergence.freshness must be greater than zero".into());
+        }
+        if cfg.indicators.enabled
+            && (cfg.indicators.periods.is_empty() || cfg.indicators.periods.contains(&0))
+        {
+            return Err("indicators.periods must list periods greater than zero".into());
+        }
+        let alerts = &cfg.alerts;
+        if alerts
+            .move_pct
+            .iter()
+            .chain(alerts.thresholds.values())
+            .any(|pct| !pct.is_finite() || *pct <= 0.0)
+        {
+            return Err("alert thresholds must be positive percentages".into());
+        }
+        if alerts.window.is_zero() {
+            return Err("alerts.window must be greater than zero".into());
+        }
+        if cfg.sources.values().any(|s| {
+            [s.timeout, s.fetch_timeout]
+                .iter()
+                .flatten()
+                .any(Duration::is_zero)
+        }) {
+            return Err("source timeouts must be greater than zero".into());
+        }
+        if let Some(unknown) = cfg
+            .strategy
+            .priority
+            .iter()
+            .find(|name| !KNOWN_SOURCES.contains(&name.as_str()))
+        {
+            return Err(format!("unknown source '{unknown}' in strategy.priority"));
+        }
+        let (_, per_unit) =
+            fx::unit(&cfg.currency.base).map_err(|e| format!("currency.base: {e}"))?;
+        if per_unit != 1.0 {
+            return Err("currency.base must be a whole currency, not pence".into());
+        }
+        if let Some(e) = cfg
+            .currency
+            .symbols
+            .values()
+            .find_map(|code| fx::unit(code).err())
+        {
+            return Err(format!("currency.symbols: {e}"));
+        }
+        if !cfg.validation.max_factor.is_finite() || cfg.validation.max_factor <= 1.0 {
+            return Err("validation.max_factor must be greater than 1".into());
+        }
+        if cfg.validation.window == 0 {
+            return Err("validation.window must be greater than zero".into());
+        }
+        if cfg.staleness.after == 0 {
+            return Err("staleness.after must be greater than zero".into());
+        }
+        let holdings: Vec<Holding> = cfg
+            .portfolio
+            .holdings
+            .iter()
+            .map(|h| Holding {
+                symbol: h.symbol.trim().to_uppercase(),
+                ..h.clone()
+            })
+            .collect();
+        if let Some(bad) = holdings.iter().find(|h| {
+            h.symbol.is_empty()
+                || !h.quantity.is_finite()
+                || !h.cost_basis.is_finite()
+                || h.cost_basis < 0.0
+        }) {
+            return Err(format!(
+                "portfolio holding '{}' needs a symbol, a finite quantity and a non-negative cost_basis",
+                bad.symbol
+            ));
+        }
+        let untracked: Vec<&str> = holdings
+            .iter()
+            .map(|h| h.symbol.as_str())
+            .filter(|symbol| !symbols.iter().any(|s| s == symbol))
+            .collect();
+        if !untracked.is_empty() {
+            warn!(
+                symbols = %untracked.join(","),
+                "Portfolio holds symbols that are not fetched, they are valued at their last stored price"
+            );
+        }
+        let writer = &cfg.writer;
+        if writer.batch_size == 0 || writer.buffer == 0 || writer.flush_interval.is_zero() {
+            return Err(
+                "writer batch_size, buffer and flush_interval must be greater than zero".into(),
+            );
+        }
+        let anomaly = &cfg.anomaly;
+        if !anomaly.z_threshold.is_finite() || anomaly.z_threshold <= 0.0 {
+            return Err("anomaly.z_threshold must be greater than zero".into());
+        }
+        if anomaly.min_samples < 2 || anomaly.min_samples > anomaly.window {
+            return Err("anomaly.min_samples must be at least 2 and at most anomaly.window".into());
+        }
+        let market_hours = &cfg.market_hours;
+        if market_hours.open >= market_hours.close {
+            return Err("market_hours.open must be before market_hours.close".into());
+        }
+        if let Some(symbol) = market_hours
+            .symbols
+            .iter()
+            .find_map(|(symbol, market)| (market.open >= market.close).then_some(symbol))
+        {
+            return Err(format!(
+                "market_hours.symbols.\"{symbol}\": open must be before close"
+            ));
+        }
+        if market_hours
+            .off_hours_interval
+            .is_some_and(|every| every.is_zero())
+        {
+            return Err("market_hours.off_hours_interval must be greater than zero".into());
+        }
+        let aliases: BTreeMap<String, BTreeMap<String, String>> = cfg
+            .aliases
+            .iter()
+            .map(|(symbol, by_source)| (symbol.trim().to_uppercase(), by_source.clone()))
+            .collect();
+        if let Some((symbol, unknown)) = aliases.iter().find_map(|(symbol, by_source)| {
+            by_source
+                .keys()
+                .find(|name| !KNOWN_SOURCES.contains(&name.as_str()))
+                .map(|name| (symbol, name))
+        }) {
+            return Err(format!(
+                "unknown source '{unknown}' in aliases.\"{symbol}\""
+            ));
+        }
+        let names = if cli.mock {
+            Some(vec!["mock".to_string()])
+        } else {
+            cli.sources.clone()
+        };
+        if !cfg.mock.volatility.is_finite() || cfg.mock.volatility < 0.0 {
+            return Err("mock.volatility must be zero or a positive fraction".into());
+        }
+        let mut sources = sources::build(names.as_deref(), cfg, &aliases)?;
+        if sources.is_empty() {
+            return Err("no source to fetch from (all disabled or missing their API key)".into());
+        }
+        warn_unsupported(&symbols, &sources, &aliases);
+        let priority = &cfg.strategy.priority;
+        sources.sort_by_key(|s| {
+            priority
+                .iter()
+                .position(|name| name == s.name())
+                .unwrap_or(priority.len())
+        });
+        Ok(Self {
+            symbols,
+            sources,
+            concurrency: cfg.concurrency,
+            stagger: cfg.stagger,
+            strategy: StrategyConfig {
+                symbols: cfg
+                    .strategy
+                    .symbols
+                    .iter()
+                    .map(|(symbol, strategy)| (symbol.trim().to_uppercase(), *strategy))
+                    .collect(),
+                ..cfg.strategy.clone()
+            },
+            divergence: cfg.divergence.clone(),
+            alerts: AlertsConfig {
+                thresholds: alerts
+                    .thresholds
+                    .iter()
+                    .map(|(symbol, pct)| (symbol.trim().to_uppercase(), *pct))
+                    .collect(),
+                ..alerts.clone()
+            },
+            currency: CurrencyConfig {
+                symbols: cfg
+                    .currency
+                    .symbols
+                    .iter()
+                    .map(|(symbol, code)| (symbol.trim().to_uppercase(), code.clone()))
+                    .collect(),
+                ..cfg.currency.clone()
+            },
+            validation: cfg.validation.clone(),
+            staleness: cfg.staleness.clone(),
+            anomaly: cfg.anomaly.clone(),
+            portfolio: PortfolioConfig { holdings },
+            market_hours: MarketHoursConfig {
+                symbols: market_hours
+                    .symbols
+                    .iter()
+                    .map(|(symbol, market)| (symbol.trim().to_uppercase(), market.clone()))
+                    .collect(),
+                ..market_hours.clone()
+            },
+        })
+    }
+
+    /// Re-reads the config file. On error the caller keeps running with the current list.
+    pub fn reload(cli: &Cli) -> Result<Self, String> {
+        let cfg = config::merge_config(config::load_config(cli.config.as_deref())?, cli);
+        Self::from_config(&cfg, cli)
+    }
+
+    pub fn log_strategy(&self) {
+        let overrides: Vec<String> = self
+            .strategy
+            .symbols
+            .iter()
+            .map(|(symbol, strategy)| format!("{symbol}={}", strategy.name()))
+            .collect();
+        info!(
+            strategy = self.strategy.default.name(),
+            overrides = %overrides.join(","),
+            priority = %self.source_names().join(","),
+            "Fetch strategy"
+        );
+    }
+
+    fn source_names(&self) -> Vec<String> {
+        self.sources.iter().map(|s| s.name().to_string()).collect()
+    }
+
+    /// `symbols: +NVDA, -GOOGL` style summary of what changed, `None` if nothing did.
+    pub fn describe_changes(&self, new: &Tracked) -> Option<String> {
+        let parts: Vec<String> = [
+            ("symbols", diff(&self.symbols, &new.symbols)),
+            ("sources", diff(&self.source_names(), &new.source_names())),
+        ]
+        .into_iter()
+        .filter(|(_, changes)| !changes.is_empty())
+        .map(|(what, changes)| format!("{what}: {}", changes.join(", ")))
+        .collect();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("; "))
+        }
+    }
+}
+
+/// Flags tickers a source is known to reject, so a missing alias shows up at startup rather
+/// than as a failed fetch every cycle.
+fn warn_unsupported(
+    symbols: &[String],
+    sources: &[Box<dyn PriceSource>],
+    aliases: &BTreeMap<String, BTreeMap<String, String>>,
+) {
+    for symbol in aliases.keys().filter(|symbol| !symbols.contains(symbol)) {
+        warn!(symbol = %symbol, "Alias for a symbol that is not tracked");
+    }
+    for symbol in symbols {
+        if !sources.iter().any(|source| source.supports(symbol)) {
+            warn!(
+                symbol = %symbol,
+                asset_class = ?AssetClass::of(symbol),
+                "No enabled source handles this kind of symbol, it will not be fetched"
+            );
+        }
+        for source in sources.iter().filter(|source| source.supports(symbol)) {
+            let ticker = aliases
+                .get(symbol)
+                .and_then(|by_source| by_source.get(source.name()))
+                .unwrap_or(symbol);
+            if let Some(reason) = sources::known_unsupported(source.name(), ticker) {
+                warn!(
+                    symbol = %symbol,
+                    source = source.name(),
+                    ticker = %ticker,
+                    reason,
+                    "Source is known not to support this ticker, add an alias under [aliases]"
+                );
+            }
+        }
+    }
+}
+
+fn diff(old: &[String], new: &[String]) -> Vec<String> {
+    let old: BTreeSet<&String> = old.iter().collect();
+    let new: BTreeSet<&String> = new.iter().collect();
+    new.difference(&old)
+        .map(|s| format!("+{s}"))
+        .chain(old.difference(&new).map(|s| format!("-{s}")))
+        .collect()
+}
+
+/// SIGHUP on unix; never fires elsewhere.
+pub struct ReloadSignal {
+    #[cfg(unix)]
+    inner: tokio::signal::unix::Signal,
+}
+
+impl ReloadSignal {
+    pub fn new() -> std::io::Result<Self> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            Ok(Self {
+                inner: signal(SignalKind::hangup())?,
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            Ok(Self {})
+        }
+    }
+
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        {
+            self.inner.recv().await;
+        }
+        #[cfg(not(unix))]
+        {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+pub fn log_reload(old: &Tracked, new: &Tracked) {
+    match old.describe_changes(new) {
+        Some(changes) => info!(changes = %changes, "Config reloaded"),
+        None => info!("Config reloaded, no change"),
+    }
+    if old.strategy != new.strategy || old.source_names() != new.source_names() {
+        new.log_strategy();
+    }
+}
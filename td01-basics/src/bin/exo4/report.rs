@@ -0,0 +1,297 @@
+//! End-of-day report: once a day at `[report] at`, per symbol the open and close (first and
+//! last stored price of the local day), high, low, change against the previous close and the
+//! share of fetch attempts each source answered. Reported days are recorded in
+//! `daily_reports`, so a restart doesn't report the same day twice.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use futures::StreamExt;
+use market_core::store::{PriceQuery, PriceStore, StorageError};
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::watch;
+use tokio::time::{sleep, Duration};
+use tracing::{error, info, warn};
+
+use crate::config::ReportConfig;
+use crate::webhook;
+
+/// Wait before trying a failed report again.
+const RETRY_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Default, Serialize)]
+pub struct SymbolDay {
+    pub symbol: String,
+    /// `None` when nothing was stored for the symbol that day.
+    pub open: Option<f64>,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub close: Option<f64>,
+    /// Last price stored before the day started.
+    pub prev_close: Option<f64>,
+    pub change_pct: Option<f64>,
+    pub samples: u64,
+    /// Successful fetch attempts per source, in percent.
+    pub availability: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DailyReport {
+    pub day: NaiveDate,
+    pub timezone: String,
+    pub generated_at: DateTime<Utc>,
+    pub symbols: Vec<SymbolDay>,
+}
+
+/// Runs forever; does nothing when the report is disabled. Only the most recent due day is
+/// caught up after downtime, and on a first start only today's. Each report covers the
+/// symbols tracked when it is built, so a SIGHUP reload applies to the next one.
+pub async fn run(
+    store: Arc<dyn PriceStore>,
+    cfg: ReportConfig,
+    symbols: watch::Receiver<Vec<String>>,
+) {
+    if !cfg.enabled {
+        return;
+    }
+    let mut last = match store.last_daily_report().await {
+        Ok(last) => last,
+        Err(e) => {
+            warn!(error = %e, "Cannot read the last reported day, assuming none");
+            None
+        }
+    };
+    info!(
+        at = %cfg.at.format("%H:%M"),
+        timezone = %cfg.timezone,
+        last = ?last,
+        "Daily report enabled"
+    );
+
+    loop {
+        let now = Utc::now();
+        let today = now.with_timezone(&cfg.timezone).date_naive();
+        let due = if now >= local(cfg.timezone, today, cfg.at) {
+            today
+        } else {
+            today - Days::new(1)
+        };
+        let pending = match last {
+            Some(last) => last < due,
+            None => due == today,
+        };
+        if pending {
+            let symbols = symbols.borrow().clone();
+            match report(store.as_ref(), &cfg, &symbols, due).await {
+                Ok(()) => last = Some(due),
+                Err(e) => {
+                    error!(day = %due, error = %e, "Daily report failed");
+                    sleep(RETRY_DELAY).await;
+                    continue;
+                }
+            }
+        }
+
+        let mut next = local(cfg.timezone, today, cfg.at);
+        if next <= now {
+            next = local(cfg.timezone, today + Days::new(1), cfg.at);
+        }
+        sleep((next - Utc::now()).to_std().unwrap_or_default()).await;
+    }
+}
+
+/// Builds the report of `day`, claims the day in `daily_reports` and sends it out. A day
+/// already claimed, e.g. by another instance, is not sent again.
+async fn report(
+    store: &dyn PriceStore,
+    cfg: &ReportConfig,
+    symbols: &[String],
+    day: NaiveDate,
+) -> Result<(), StorageError> {
+    let report = build(store, cfg.timezone, symbols, day).await?;
+    let body = json!(report);
+    if !store
+        .save_daily_report(day, report.generated_at, &body)
+        .await?
+    {
+        info!(%day, "Day already reported, skipping");
+        return Ok(());
+    }
+
+    let table = render(&report);
+    let with_data = report.symbols.iter().filter(|s| s.samples > 0).count();
+    if with_data == 0 {
+        warn!(%day, "No prices stored for the day, reporting it empty");
+    }
+    info!(%day, symbols = report.symbols.len(), with_data, "Daily report");
+    println!("{table}");
+
+    if let Some(dir) = &cfg.dir {
+        let path = dir.join(format!("report-{day}.txt"));
+        let written = match tokio::fs::create_dir_all(dir).await {
+            Ok(()) => tokio::fs::write(&path, format!("{table}\n")).await,
+            Err(e) => Err(e),
+        };
+        match written {
+            Ok(()) => info!(path = %path.display(), "Daily report written"),
+            Err(e) => warn!(path = %path.display(), error = %e, "Cannot write the daily report"),
+        }
+    }
+    if let Some(url) = &cfg.webhook {
+        let mut payload = body;
+        payload["kind"] = json!("daily_report");
+        payload["text"] = json!(format!("```\n{table}\n```"));
+        webhook::post(url, "daily_report", payload);
+    }
+    Ok(())
+}
+
+async fn build(
+    store: &dyn PriceStore,
+    tz: Tz,
+    symbols: &[String],
+    day: NaiveDate,
+) -> Result<DailyReport, StorageError> {
+    let start = local(tz, day, NaiveTime::MIN);
+    let end = local(tz, day + Days::new(1), NaiveTime::MIN);
+    let mut days: BTreeMap<String, SymbolDay> = symbols
+        .iter()
+        .map(|symbol| {
+            let day = SymbolDay {
+                symbol: symbol.clone(),
+                ..Default::default()
+            };
+            (symbol.clone(), day)
+        })
+        .collect();
+
+    // Oldest first, so the first price seen is the open and the last one the close
+    let query = PriceQuery {
+        since: Some(start),
+        until: Some(end),
+        ..Default::default()
+    };
+    let mut prices = store.stream(&query);
+    while let Some(price) = prices.next().await {
+        let price = price?;
+        let entry = days
+            .entry(price.symbol.clone())
+            .or_insert_with(|| SymbolDay {
+                symbol: price.symbol.clone(),
+                ..Default::default()
+            });
+        entry.open.get_or_insert(price.price);
+        entry.close = Some(price.price);
+        entry.high = Some(entry.high.map_or(price.price, |high| high.max(price.price)));
+        entry.low = Some(entry.low.map_or(price.price, |low| low.min(price.price)));
+        entry.samples += 1;
+    }
+    drop(prices);
+
+    for entry in days.values_mut() {
+        entry.prev_close = store
+            .recent(&entry.symbol, None, start, 1)
+            .await?
+            .pop()
+            .map(|p| p.price);
+        entry.change_pct = match (entry.close, entry.prev_close) {
+            (Some(close), Some(prev)) if prev != 0.0 => Some((close - prev) / prev * 100.0),
+            _ => None,
+        };
+        let query = PriceQuery {
+            symbol: Some(entry.symbol.clone()),
+            since: Some(start),
+            until: Some(end),
+            ..Default::default()
+        };
+        entry.availability = store
+            .source_stats(&query)
+            .await?
+            .into_iter()
+            .map(|s| {
+                let pct = s.successes as f64 / s.attempts.max(1) as f64 * 100.0;
+                (s.source, pct)
+            })
+            .collect();
+    }
+
+    Ok(DailyReport {
+        day,
+        timezone: tz.to_string(),
+        generated_at: Utc::now(),
+        symbols: days.into_values().collect(),
+    })
+}
+
+/// `time` on `day` in `tz`. Ambiguous times (clocks going back) take the first occurrence;
+/// times skipped by clocks going forward move an hour later.
+fn local(tz: Tz, day: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+    let naive = day.and_time(time);
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| {
+            tz.from_local_datetime(&(naive + chrono::Duration::hours(1)))
+                .earliest()
+        })
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(|| naive.and_utc())
+}
+
+fn render(report: &DailyReport) -> String {
+    let price = |p: Option<f64>| p.map_or_else(|| "-".to_string(), |p| format!("{p:.2}"));
+    let cells: Vec<[String; 7]> = report
+        .symbols
+        .iter()
+        .map(|s| {
+            let sources = if s.availability.is_empty() {
+                "-".to_string()
+            } else {
+                s.availability
+                    .iter()
+                    .map(|(source, pct)| format!("{source} {pct:.0}%"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            [
+                s.symbol.clone(),
+                price(s.open),
+                price(s.high),
+                price(s.low),
+                price(s.close),
+                s.change_pct
+                    .map_or_else(|| "-".to_string(), |pct| format!("{pct:+.2}%")),
+                sources,
+            ]
+        })
+        .collect();
+    let header = [
+        "SYMBOL", "OPEN", "HIGH", "LOW", "CLOSE", "CHANGE", "SOURCES",
+    ];
+    let mut widths = header.map(str::len);
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut lines = vec![format!("Daily report {} ({})", report.day, report.timezone)];
+    for row in std::iter::once(header.map(String::from)).chain(cells) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(i, (cell, w))| match i {
+                0 | 6 => format!("{cell:<w$}"),
+                _ => format!("{cell:>w$}"),
+            })
+            .collect();
+        lines.push(line.join("  ").trim_end().to_string());
+    }
+    if report.symbols.iter().all(|s| s.samples == 0) {
+        lines.push("No prices stored for the day.".to_string());
+    }
+    lines.join("\n")
+}
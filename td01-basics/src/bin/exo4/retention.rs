@@ -0,0 +1,52 @@
+//! Background pruning of old `stock_prices` rows.
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use market_core::store::{PriceStore, StorageError};
+use tokio::time::{interval, sleep, Duration, MissedTickBehavior};
+use tracing::{error, info, instrument};
+
+use crate::config::RetentionConfig;
+
+/// Pause between two delete batches, so other writers get the table back in between.
+const BATCH_PAUSE: Duration = Duration::from_millis(200);
+
+/// Runs forever; does nothing when no retention period is configured.
+pub async fn run(store: Arc<dyn PriceStore>, cfg: RetentionConfig) {
+    let Some(days) = cfg.days else {
+        return;
+    };
+    info!(
+        days,
+        every = %humantime::format_duration(cfg.interval),
+        "Retention enabled"
+    );
+
+    let mut ticker = interval(cfg.interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        if let Err(e) = prune(store.as_ref(), days, cfg.batch_size).await {
+            error!(error = %e, "Retention pass failed");
+        }
+    }
+}
+
+/// Deletes rows older than `days` days, `batch_size` rows per statement.
+#[instrument(skip(store))]
+async fn prune(store: &dyn PriceStore, days: u32, batch_size: u32) -> Result<u64, StorageError> {
+    let cutoff = Utc::now() - chrono::Duration::days(days.into());
+    let mut removed = 0;
+    loop {
+        let deleted = store.prune_before(cutoff, batch_size).await?;
+        removed += deleted;
+        if deleted < u64::from(batch_size) {
+            break;
+        }
+        sleep(BATCH_PAUSE).await;
+    }
+
+    info!(removed, %cutoff, "Retention pass complete");
+    Ok(removed)
+}
@@ -0,0 +1,328 @@
+//! Retry with exponential backoff around any `PriceSource`. Only transient errors are
+//! retried; an unknown symbol or a bad key fails on the first attempt.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rand::Rng;
+use tracing::{field, instrument, warn, Span};
+
+use crate::config::RetryConfig;
+use crate::sources::{AssetClass, FetchError, PriceSource, StockPrice};
+
+pub struct Retrying {
+    inner: Box<dyn PriceSource>,
+    policy: RetryConfig,
+}
+
+impl Retrying {
+    pub fn new(inner: Box<dyn PriceSource>, policy: RetryConfig) -> Self {
+        Self { inner, policy }
+    }
+
+    /// Delay before attempt `attempt + 1`, or `None` when we should give up instead.
+    /// A provider's `Retry-After` wins over our own backoff, unless it exceeds `max_delay`.
+    fn delay_after(&self, attempt: u32, err: &FetchError) -> Option<Duration> {
+        if attempt >= self.policy.max_attempts || !err.is_transient() {
+            return None;
+        }
+        if let FetchError::RateLimited {
+            retry_after: Some(after),
+            ..
+        } = err
+        {
+            return (*after <= self.policy.max_delay).then_some(*after);
+        }
+        Some(self.backoff(attempt))
+    }
+
+    /// `base_delay * 2^(attempt - 1)` capped at `max_delay`, with equal jitter so that
+    /// sources failing together don't all come back at the same instant.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .policy
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.policy.max_delay);
+        let half = exp / 2;
+        let jitter_ms = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+        half + Duration::from_millis(jitter_ms)
+    }
+}
+
+fn outcome<T>(result: &Result<T, FetchError>) -> &'static str {
+    match result {
+        Ok(_) => "ok",
+        Err(e) if e.is_transient() => "gave_up",
+        Err(_) => "failed",
+    }
+}
+
+#[async_trait]
+impl PriceSource for Retrying {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn asset_classes(&self) -> &'static [AssetClass] {
+        self.inner.asset_classes()
+    }
+
+    fn has_batch_endpoint(&self) -> bool {
+        self.inner.has_batch_endpoint()
+    }
+
+    fn is_available(&self) -> bool {
+        self.inner.is_available()
+    }
+
+    #[instrument(skip(self), fields(source = self.name(), attempts = field::Empty, outcome = field::Empty))]
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        let mut attempt = 1;
+        let result = loop {
+            let result = self.inner.fetch(symbol).await;
+            let delay = match &result {
+                Err(e) => self.delay_after(attempt, e),
+                Ok(_) => None,
+            };
+            let Some(delay) = delay else {
+                break result;
+            };
+            if let Err(e) = &result {
+                warn!(attempt, delay_ms = delay.as_millis() as u64, error = %e, "Retrying fetch");
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        };
+
+        let span = Span::current();
+        span.record("attempts", attempt);
+        span.record("outcome", outcome(&result));
+        result
+    }
+
+    /// Only the symbols that failed transiently are asked for again, still in one call.
+    #[instrument(skip(self), fields(source = self.name(), attempts = field::Empty, outcome = field::Empty))]
+    async fn fetch_many(&self, symbols: &[&str]) -> Vec<Result<StockPrice, FetchError>> {
+        let mut results = self.inner.fetch_many(symbols).await;
+        let mut attempt = 1;
+        loop {
+            let pending: Vec<(usize, Duration)> = results
+                .iter()
+                .enumerate()
+                .filter_map(|(i, r)| {
+                    let delay = self.delay_after(attempt, r.as_ref().err()?)?;
+                    Some((i, delay))
+                })
+                .collect();
+            let Some(delay) = pending.iter().map(|(_, d)| *d).max() else {
+                break;
+            };
+            warn!(
+                attempt,
+                delay_ms = delay.as_millis() as u64,
+                failed = pending.len(),
+                "Retrying batch fetch"
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+
+            let retry: Vec<&str> = pending.iter().map(|(i, _)| symbols[*i]).collect();
+            let retried = self.inner.fetch_many(&retry).await;
+            for ((i, _), result) in pending.into_iter().zip(retried) {
+                results[i] = result;
+            }
+        }
+
+        let span = Span::current();
+        span.record("attempts", attempt);
+        let worst = results
+            .iter()
+            .map(outcome)
+            .find(|o| *o != "ok")
+            .unwrap_or("ok");
+        span.record("outcome", worst);
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// Answers each symbol with its scripted errors first, then a price. Remembers the
+    /// symbols of every call, a batch being one call.
+    #[derive(Default)]
+    struct Flaky {
+        errors: Mutex<HashMap<String, VecDeque<FetchError>>>,
+        calls: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl Flaky {
+        fn failing(symbol: &str, errors: impl IntoIterator<Item = FetchError>) -> Self {
+            let flaky = Self::default();
+            flaky.fail(symbol, errors);
+            flaky
+        }
+
+        fn fail(&self, symbol: &str, errors: impl IntoIterator<Item = FetchError>) {
+            self.errors
+                .lock()
+                .unwrap()
+                .insert(symbol.to_string(), errors.into_iter().collect());
+        }
+
+        fn answer(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+            let next = self
+                .errors
+                .lock()
+                .unwrap()
+                .get_mut(symbol)
+                .and_then(VecDeque::pop_front);
+            match next {
+                Some(e) => Err(e),
+                None => Ok(StockPrice {
+                    symbol: symbol.to_string(),
+                    price: 100.0,
+                    source: "flaky".to_string(),
+                    ..Default::default()
+                }),
+            }
+        }
+    }
+
+    /// The source as `Retrying` owns it, with a view on its calls.
+    struct Shared(Arc<Flaky>);
+
+    #[async_trait]
+    impl PriceSource for Shared {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn has_batch_endpoint(&self) -> bool {
+            true
+        }
+
+        async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+            self.0.calls.lock().unwrap().push(vec![symbol.to_string()]);
+            self.0.answer(symbol)
+        }
+
+        async fn fetch_many(&self, symbols: &[&str]) -> Vec<Result<StockPrice, FetchError>> {
+            let batch = symbols.iter().map(|s| s.to_string()).collect();
+            self.0.calls.lock().unwrap().push(batch);
+            symbols.iter().map(|symbol| self.0.answer(symbol)).collect()
+        }
+    }
+
+    fn retrying(flaky: Flaky, max_attempts: u32) -> (Retrying, Arc<Flaky>) {
+        let flaky = Arc::new(flaky);
+        let policy = RetryConfig {
+            max_attempts,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(4),
+        };
+        (
+            Retrying::new(Box::new(Shared(flaky.clone())), policy),
+            flaky,
+        )
+    }
+
+    /// Transient, without a Retry-After: our own backoff applies.
+    fn rate_limited() -> FetchError {
+        FetchError::RateLimited {
+            provider: "flaky",
+            retry_after: None,
+        }
+    }
+
+    fn unknown(symbol: &str) -> FetchError {
+        FetchError::UnknownSymbol {
+            provider: "flaky",
+            symbol: symbol.to_string(),
+        }
+    }
+
+    fn calls(flaky: &Flaky) -> Vec<Vec<String>> {
+        flaky.calls.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn retries_until_it_works() {
+        let (source, flaky) = retrying(Flaky::failing("AAPL", [rate_limited(), rate_limited()]), 3);
+        let price = source.fetch("AAPL").await.unwrap();
+        assert_eq!(price.symbol, "AAPL");
+        assert_eq!(calls(&flaky).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let (source, flaky) = retrying(Flaky::failing("AAPL", [rate_limited(), rate_limited()]), 2);
+        assert!(matches!(
+            source.fetch("AAPL").await,
+            Err(FetchError::RateLimited { .. })
+        ));
+        assert_eq!(calls(&flaky).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn permanent_errors_fail_at_once() {
+        let auth = FetchError::Auth {
+            provider: "flaky",
+            message: "invalid key".to_string(),
+        };
+        let unparsable = FetchError::Parse("no price in the answer".to_string());
+        for error in [unknown("NOPE"), auth, unparsable] {
+            let message = error.to_string();
+            let (source, flaky) = retrying(Flaky::failing("NOPE", [error]), 3);
+            let result = source.fetch("NOPE").await;
+            assert_eq!(result.unwrap_err().to_string(), message);
+            assert_eq!(calls(&flaky).len(), 1, "{message}");
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_after_past_max_delay_is_not_waited_for() {
+        let rate_limited = |after| FetchError::RateLimited {
+            provider: "flaky",
+            retry_after: Some(after),
+        };
+
+        let (source, flaky) = retrying(
+            Flaky::failing("AAPL", [rate_limited(Duration::from_secs(60))]),
+            3,
+        );
+        assert!(matches!(
+            source.fetch("AAPL").await,
+            Err(FetchError::RateLimited { .. })
+        ));
+        assert_eq!(calls(&flaky).len(), 1);
+
+        let (source, flaky) = retrying(
+            Flaky::failing("AAPL", [rate_limited(Duration::from_millis(2))]),
+            3,
+        );
+        assert!(source.fetch("AAPL").await.is_ok());
+        assert_eq!(calls(&flaky).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn batch_asks_again_only_for_the_failed_symbols() {
+        let flaky = Flaky::failing("MSFT", [rate_limited(), rate_limited()]);
+        flaky.fail("NOPE", [unknown("NOPE")]);
+        let (source, flaky) = retrying(flaky, 3);
+
+        let results = source.fetch_many(&["AAPL", "MSFT", "NOPE"]).await;
+        assert!(results[0].is_ok());
+        assert!(results[1].is_ok());
+        assert!(matches!(results[2], Err(FetchError::UnknownSymbol { .. })));
+        assert_eq!(
+            calls(&flaky),
+            [vec!["AAPL", "MSFT", "NOPE"], vec!["MSFT"], vec!["MSFT"]]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! `exo4 show`: latest stored price per source, or recent history, for one symbol.
+
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::{Args, ValueEnum};
+use market_core::store::PriceStore;
+use market_core::StockPrice;
+use serde_json::json;
+
+#[derive(Args, Debug)]
+pub struct ShowArgs {
+    #[arg(long)]
+    symbol: String,
+
+    /// Show the last N rows (all sources) instead of the latest row per source
+    #[arg(long)]
+    last: Option<u32>,
+
+    #[arg(long, value_enum, default_value_t = Output::Table)]
+    output: Output,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Output {
+    Table,
+    Json,
+}
+
+/// Prints the rows; `Ok(false)` when there were none, so the caller can exit non-zero.
+pub async fn run(
+    store: &dyn PriceStore,
+    args: &ShowArgs,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let symbol = args.symbol.trim().to_uppercase();
+    let mut rows = match args.last {
+        Some(n) => {
+            store
+                .recent(&symbol, None, Utc::now() + chrono::Duration::days(1), n)
+                .await?
+        }
+        None => store
+            .latest_per_symbol_source()
+            .await?
+            .into_iter()
+            .filter(|p| p.symbol == symbol)
+            .collect(),
+    };
+    if rows.is_empty() {
+        eprintln!("no rows found for {symbol}");
+        return Ok(false);
+    }
+    // Newest first reads best for both views.
+    rows.sort_by_key(|p| std::cmp::Reverse(p.timestamp));
+
+    let now = Utc::now();
+    match args.output {
+        Output::Json => {
+            let out: Vec<_> = rows
+                .iter()
+                .map(|p| {
+                    json!({
+                        "symbol": p.symbol,
+                        "source": p.source,
+                        "price": p.price,
+                        "timestamp": p.timestamp,
+                        "age_secs": age(now, p.timestamp).as_secs(),
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&out)?);
+        }
+        Output::Table => print_table(now, &rows),
+    }
+    Ok(true)
+}
+
+fn age(now: DateTime<Utc>, at: DateTime<Utc>) -> Duration {
+    (now - at).to_std().unwrap_or_default()
+}
+
+fn print_table(now: DateTime<Utc>, rows: &[StockPrice]) {
+    let cells: Vec<[String; 4]> = rows
+        .iter()
+        .map(|p| {
+            [
+                p.source.clone(),
+                format!("{:.4}", p.price),
+                p.timestamp.to_rfc3339_opts(SecondsFormat::Secs, true),
+                humantime::format_duration(Duration::from_secs(age(now, p.timestamp).as_secs()))
+                    .to_string(),
+            ]
+        })
+        .collect();
+    let header = ["SOURCE", "PRICE", "TIMESTAMP", "AGE"];
+    let mut widths = header.map(str::len);
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    println!(
+        "{:<w0$}  {:>w1$}  {:<w2$}  {}",
+        header[0],
+        header[1],
+        header[2],
+        header[3],
+        w0 = widths[0],
+        w1 = widths[1],
+        w2 = widths[2]
+    );
+    for [source, price, timestamp, age] in &cells {
+        println!(
+            "{source:<w0$}  {price:>w1$}  {timestamp:<w2$}  {age}",
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2]
+        );
+    }
+}
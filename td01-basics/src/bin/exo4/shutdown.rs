@@ -0,0 +1,89 @@
+//! Graceful shutdown: Ctrl+C and SIGTERM (what systemd and Kubernetes send) both stop new
+//! cycles and give the one in flight some time to finish and save its prices.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use tokio::time::{interval_at, sleep_until, Instant};
+use tracing::{info, warn};
+
+/// How often a drain that is taking a while says so.
+const PROGRESS_EVERY: Duration = Duration::from_secs(5);
+
+/// SIGINT or SIGTERM on unix, Ctrl+C elsewhere. Signals are buffered from creation on, so
+/// one arriving while nothing is waiting for it is not lost.
+pub struct ShutdownSignal {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+}
+
+impl ShutdownSignal {
+    pub fn new() -> std::io::Result<Self> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            Ok(Self {
+                interrupt: signal(SignalKind::interrupt())?,
+                terminate: signal(SignalKind::terminate())?,
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            Ok(Self {})
+        }
+    }
+
+    /// Waits for the next signal and returns its name, for logs.
+    pub async fn recv(&mut self) -> &'static str {
+        #[cfg(unix)]
+        {
+            tokio::select! {
+                _ = self.interrupt.recv() => "SIGINT",
+                _ = self.terminate.recv() => "SIGTERM",
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = tokio::signal::ctrl_c().await;
+            "Ctrl+C"
+        }
+    }
+}
+
+/// Lets `cycle` run for up to `grace`, logging every few seconds while it does. Returns its
+/// output, or `None` when the grace period ran out or a second signal asked to stop now.
+pub async fn drain<F: Future>(
+    mut cycle: Pin<&mut F>,
+    grace: Duration,
+    signal: &mut ShutdownSignal,
+) -> Option<F::Output> {
+    info!(
+        grace = %humantime::format_duration(grace),
+        "Waiting for the current cycle to finish"
+    );
+    let started = Instant::now();
+    let deadline = started + grace;
+    let mut progress = interval_at(started + PROGRESS_EVERY, PROGRESS_EVERY);
+    loop {
+        tokio::select! {
+            output = &mut cycle => {
+                info!(waited_ms = started.elapsed().as_millis() as u64, "Current cycle finished");
+                return Some(output);
+            }
+            _ = sleep_until(deadline) => {
+                warn!("Shutdown grace period elapsed, abandoning the current cycle");
+                return None;
+            }
+            _ = progress.tick() => {
+                info!(waited_secs = started.elapsed().as_secs(), "Still draining the current cycle");
+            }
+            signal = signal.recv() => {
+                warn!(signal, "Second shutdown signal, abandoning the current cycle");
+                return None;
+            }
+        }
+    }
+}
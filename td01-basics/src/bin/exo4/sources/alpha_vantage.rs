@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use market_core::store::Candle;
+use serde::Deserialize;
+use tracing::instrument;
+
+use super::{check_status, forex_pair, AssetClass, FetchError, Http, PriceSource, StockPrice};
+
+#[derive(Deserialize, Debug)]
+struct GlobalQuote {
+    #[serde(rename = "Global Quote")]
+    quote: Quote,
+}
+
+#[derive(Deserialize, Debug)]
+struct Quote {
+    /// Missing when the symbol is unknown: the whole object is then `{}`.
+    #[serde(rename = "05. price")]
+    price: Option<String>,
+    #[serde(rename = "02. open")]
+    open: Option<String>,
+    #[serde(rename = "03. high")]
+    high: Option<String>,
+    #[serde(rename = "04. low")]
+    low: Option<String>,
+    #[serde(rename = "08. previous close")]
+    prev_close: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ExchangeRate {
+    #[serde(rename = "Realtime Currency Exchange Rate")]
+    rate: Rate,
+}
+
+#[derive(Deserialize, Debug)]
+struct Rate {
+    #[serde(rename = "5. Exchange Rate")]
+    rate: String,
+    /// `2024-05-03 16:22:01`, in UTC.
+    #[serde(rename = "6. Last Refreshed")]
+    last_refreshed: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct DailySeries {
+    #[serde(rename = "Time Series (Daily)")]
+    days: BTreeMap<NaiveDate, DailyBar>,
+}
+
+#[derive(Deserialize, Debug)]
+struct DailyBar {
+    #[serde(rename = "1. open")]
+    open: String,
+    #[serde(rename = "2. high")]
+    high: String,
+    #[serde(rename = "3. low")]
+    low: String,
+    #[serde(rename = "4. close")]
+    close: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct AlphaVantageError {
+    #[serde(rename = "Information")]
+    information: Option<String>,
+    #[serde(rename = "Error Message")]
+    error_message: Option<String>,
+}
+
+pub struct AlphaVantageSource {
+    api_key: String,
+    http: Http,
+}
+
+impl AlphaVantageSource {
+    /// Stored in the `source` column of backfilled daily candles.
+    pub const DAILY_SOURCE: &'static str = "alpha_vantage_daily";
+
+    pub fn new(api_key: String, http: Http) -> Self {
+        Self { api_key, http }
+    }
+
+    /// Sends the query and returns the body, after turning Alpha Vantage's in-band error
+    /// messages into `FetchError`s.
+    async fn query(&self, params: &str) -> Result<String, FetchError> {
+        let url = format!(
+            "https://www.alphavantage.co/query?{}&apikey={}",
+            params, self.api_key
+        );
+
+        let resp = check_status(self.name(), self.http.get(&url).send().await?)?;
+        let text = resp.error_for_status()?.text().await?;
+
+        // Check for rate limit or error message
+        if let Ok(error) = serde_json::from_str::<AlphaVantageError>(&text) {
+            // "Information" is how the free tier says the minute or daily quota is used up
+            if error.information.is_some() {
+                return Err(FetchError::RateLimited {
+                    provider: self.name(),
+                    retry_after: None,
+                });
+            }
+            if let Some(message) = error.error_message {
+                if message.contains("apikey") {
+                    return Err(FetchError::Auth {
+                        provider: self.name(),
+                        message,
+                    });
+                }
+                return Err(FetchError::Api {
+                    provider: self.name(),
+                    message,
+                });
+            }
+        }
+        Ok(text)
+    }
+
+    /// Full `TIME_SERIES_DAILY` history as `1d` candles stamped at midnight UTC, keeping
+    /// the days in `from..=to`.
+    #[instrument(skip(self))]
+    pub async fn daily(
+        &self,
+        symbol: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Candle>, FetchError> {
+        let text = self
+            .query(&format!(
+                "function=TIME_SERIES_DAILY&symbol={symbol}&outputsize=full"
+            ))
+            .await?;
+        let series: DailySeries = serde_json::from_str(&text)?;
+
+        series
+            .days
+            .range(from..=to)
+            .map(|(day, bar)| {
+                Ok(Candle {
+                    symbol: symbol.to_string(),
+                    source: Self::DAILY_SOURCE.to_string(),
+                    interval: "1d",
+                    bucket_start: day.and_time(NaiveTime::MIN).and_utc(),
+                    open: bar.open.parse()?,
+                    high: bar.high.parse()?,
+                    low: bar.low.parse()?,
+                    close: bar.close.parse()?,
+                    samples: 1,
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl PriceSource for AlphaVantageSource {
+    fn name(&self) -> &'static str {
+        "alpha_vantage"
+    }
+
+    #[instrument(skip(self))]
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        let text = self
+            .query(&format!("function=GLOBAL_QUOTE&symbol={symbol}"))
+            .await?;
+
+        let resp: GlobalQuote = serde_json::from_str(&text)?;
+        let quote = resp.quote;
+        let optional = |v: Option<String>| v.map(|v| v.parse::<f64>()).transpose();
+        let Some(price) = optional(quote.price)? else {
+            return Err(FetchError::UnknownSymbol {
+                provider: self.name(),
+                symbol: symbol.to_string(),
+            });
+        };
+
+        Ok(StockPrice {
+            symbol: symbol.to_string(),
+            price,
+            source: self.name().to_string(),
+            timestamp: chrono::Utc::now(),
+            open: optional(quote.open)?,
+            high: optional(quote.high)?,
+            low: optional(quote.low)?,
+            prev_close: optional(quote.prev_close)?,
+            ..Default::default()
+        })
+    }
+}
+
+/// Currency pairs through `CURRENCY_EXCHANGE_RATE`. Same key, and same daily quota, as
+/// the equity source.
+pub struct AlphaVantageFxSource {
+    inner: AlphaVantageSource,
+}
+
+impl AlphaVantageFxSource {
+    pub fn new(api_key: String, http: Http) -> Self {
+        Self {
+            inner: AlphaVantageSource::new(api_key, http),
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSource for AlphaVantageFxSource {
+    fn name(&self) -> &'static str {
+        "alpha_vantage_fx"
+    }
+
+    fn asset_classes(&self) -> &'static [AssetClass] {
+        &[AssetClass::Forex]
+    }
+
+    #[instrument(skip(self))]
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        let Some((from, to)) = forex_pair(symbol) else {
+            return Err(FetchError::UnknownSymbol {
+                provider: self.name(),
+                symbol: symbol.to_string(),
+            });
+        };
+        let text = self
+            .inner
+            .query(&format!(
+                "function=CURRENCY_EXCHANGE_RATE&from_currency={from}&to_currency={to}"
+            ))
+            .await?;
+
+        let resp: ExchangeRate = serde_json::from_str(&text)?;
+        let timestamp = resp
+            .rate
+            .last_refreshed
+            .and_then(|raw| NaiveDateTime::parse_from_str(&raw, "%Y-%m-%d %H:%M:%S").ok())
+            .map(|t| t.and_utc())
+            .unwrap_or_else(Utc::now);
+
+        Ok(StockPrice {
+            symbol: symbol.to_string(),
+            price: resp.rate.rate.parse()?,
+            source: self.name().to_string(),
+            timestamp,
+            ..Default::default()
+        })
+    }
+}
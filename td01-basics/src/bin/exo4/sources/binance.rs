@@ -0,0 +1,136 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::{instrument, warn};
+
+use super::{check_status, AssetClass, FetchError, Http, PriceSource, StockPrice};
+
+/// Pause used when a 418/429 comes without a Retry-After header.
+const DEFAULT_PAUSE: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Debug)]
+struct TickerPrice {
+    price: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct BinanceError {
+    code: i64,
+    msg: String,
+}
+
+/// Public ticker endpoint, no API key needed for reads.
+pub struct BinanceSource {
+    /// Set after a 418 (IP ban) or 429; requests are not sent until it expires.
+    paused_until: Mutex<Option<Instant>>,
+    http: Http,
+}
+
+impl BinanceSource {
+    pub fn new(http: Http) -> Self {
+        Self {
+            paused_until: Mutex::new(None),
+            http,
+        }
+    }
+
+    fn rate_limited(&self, retry_after: Option<Duration>) -> FetchError {
+        FetchError::RateLimited {
+            provider: self.name(),
+            retry_after,
+        }
+    }
+}
+
+/// `BTC-USD` -> `BTCUSDT`: Binance has no USD spot books, USDT is the closest quote.
+fn binance_symbol(symbol: &str) -> Option<String> {
+    let (base, quote) = symbol.split_once('-')?;
+    let quote = match quote {
+        "USD" => "USDT",
+        other => other,
+    };
+    Some(format!("{}{}", base, quote).to_uppercase())
+}
+
+fn parse_price(raw: &str) -> Result<f64, FetchError> {
+    let price: f64 = raw.parse()?;
+    if !price.is_finite() || price <= 0.0 {
+        return Err(FetchError::Parse(format!("invalid price {raw:?}")));
+    }
+    Ok(price)
+}
+
+#[async_trait]
+impl PriceSource for BinanceSource {
+    fn name(&self) -> &'static str {
+        "binance"
+    }
+
+    fn asset_classes(&self) -> &'static [AssetClass] {
+        &[AssetClass::Crypto]
+    }
+
+    #[instrument(skip(self))]
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        if let Some(until) = *self.paused_until.lock().unwrap() {
+            let now = Instant::now();
+            if now < until {
+                return Err(self.rate_limited(Some(until - now)));
+            }
+        }
+
+        let pair = binance_symbol(symbol).ok_or_else(|| FetchError::UnknownSymbol {
+            provider: self.name(),
+            symbol: symbol.to_string(),
+        })?;
+        let url = format!(
+            "https://api.binance.com/api/v3/ticker/price?symbol={}",
+            pair
+        );
+
+        let resp = check_status(self.name(), self.http.get(&url).send().await?)?;
+        match resp.status() {
+            StatusCode::IM_A_TEAPOT | StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = resp
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .map(Duration::from_secs);
+                let pause = retry_after.unwrap_or(DEFAULT_PAUSE);
+                warn!(status = %resp.status(), pause_secs = pause.as_secs(), "Binance rate limit hit, pausing source");
+                *self.paused_until.lock().unwrap() = Some(Instant::now() + pause);
+                return Err(self.rate_limited(Some(pause)));
+            }
+            StatusCode::BAD_REQUEST => {
+                let error: BinanceError = resp.json().await?;
+                // -1121: Invalid symbol
+                if error.code == -1121 {
+                    return Err(FetchError::UnknownSymbol {
+                        provider: self.name(),
+                        symbol: symbol.to_string(),
+                    });
+                }
+                return Err(FetchError::Api {
+                    provider: self.name(),
+                    message: format!("{}: {}", error.code, error.msg),
+                });
+            }
+            _ => {}
+        }
+
+        let ticker: TickerPrice = resp.error_for_status()?.json().await?;
+
+        Ok(StockPrice {
+            symbol: symbol.to_string(),
+            price: parse_price(&ticker.price)?,
+            source: self.name().to_string(),
+            timestamp: chrono::Utc::now(),
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::env;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::instrument;
+
+use super::{check_status, AssetClass, FetchError, Http, PriceSource, StockPrice};
+
+/// Ticker -> CoinGecko coin id for the coins we care about.
+const BUILTIN_IDS: &[(&str, &str)] = &[
+    ("BTC", "bitcoin"),
+    ("ETH", "ethereum"),
+    ("SOL", "solana"),
+    ("XRP", "ripple"),
+    ("ADA", "cardano"),
+    ("DOGE", "dogecoin"),
+    ("LTC", "litecoin"),
+];
+
+#[derive(Deserialize, Debug)]
+struct CoinPrice {
+    #[serde(flatten)]
+    prices: HashMap<String, f64>,
+    last_updated_at: Option<i64>,
+}
+
+/// Simple price endpoint, no API key needed.
+pub struct CoinGeckoSource {
+    ids: HashMap<String, String>,
+    http: Http,
+}
+
+impl CoinGeckoSource {
+    /// Built-in ids, extended by `COINGECKO_IDS` (`PEPE=pepe,ARB=arbitrum`).
+    pub fn from_env(http: Http) -> Self {
+        let mut ids: HashMap<String, String> = BUILTIN_IDS
+            .iter()
+            .map(|(ticker, id)| (ticker.to_string(), id.to_string()))
+            .collect();
+        if let Ok(raw) = env::var("COINGECKO_IDS") {
+            for (ticker, id) in raw.split(',').filter_map(|pair| pair.split_once('=')) {
+                ids.insert(ticker.trim().to_uppercase(), id.trim().to_string());
+            }
+        }
+        Self { ids, http }
+    }
+
+    /// `BTC-USD` -> (`bitcoin`, `usd`)
+    fn coin_and_currency(&self, symbol: &str) -> Option<(&str, String)> {
+        let (base, quote) = symbol.split_once('-')?;
+        let id = self.ids.get(&base.to_uppercase())?;
+        let currency = match quote.to_uppercase().as_str() {
+            "USDT" | "USDC" => "usd".to_string(),
+            other => other.to_lowercase(),
+        };
+        Some((id.as_str(), currency))
+    }
+
+    fn unknown(&self, symbol: &str) -> FetchError {
+        FetchError::UnknownSymbol {
+            provider: self.name(),
+            symbol: symbol.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSource for CoinGeckoSource {
+    fn name(&self) -> &'static str {
+        "coingecko"
+    }
+
+    fn asset_classes(&self) -> &'static [AssetClass] {
+        &[AssetClass::Crypto]
+    }
+
+    fn has_batch_endpoint(&self) -> bool {
+        true
+    }
+
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        self.fetch_many(&[symbol])
+            .await
+            .pop()
+            .unwrap_or_else(|| Err(self.unknown(symbol)))
+    }
+
+    /// All coins go in a single `ids=bitcoin,ethereum&vs_currencies=usd` request.
+    #[instrument(skip(self))]
+    async fn fetch_many(&self, symbols: &[&str]) -> Vec<Result<StockPrice, FetchError>> {
+        let resolved: Vec<Option<(&str, String)>> = symbols
+            .iter()
+            .map(|symbol| self.coin_and_currency(symbol))
+            .collect();
+
+        let mut ids: Vec<&str> = resolved.iter().flatten().map(|(id, _)| *id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        let mut currencies: Vec<&str> = resolved
+            .iter()
+            .flatten()
+            .map(|(_, cur)| cur.as_str())
+            .collect();
+        currencies.sort_unstable();
+        currencies.dedup();
+
+        let mut body: HashMap<String, CoinPrice> = HashMap::new();
+        if !ids.is_empty() {
+            let url = format!(
+                "https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies={}&include_last_updated_at=true",
+                ids.join(","),
+                currencies.join(",")
+            );
+            let response = match self.http.get(&url).send().await.map_err(FetchError::from) {
+                Ok(response) => check_status(self.name(), response),
+                Err(e) => Err(e),
+            };
+            let response = match response {
+                Ok(response) => response,
+                Err(e) => return vec![Err(e); symbols.len()],
+            };
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                return symbols
+                    .iter()
+                    .map(|_| {
+                        Err(FetchError::RateLimited {
+                            provider: self.name(),
+                            retry_after: None,
+                        })
+                    })
+                    .collect();
+            }
+            let parsed = match response.error_for_status() {
+                Ok(response) => response.json().await,
+                Err(e) => Err(e),
+            };
+            body = match parsed {
+                Ok(body) => body,
+                Err(e) => return vec![Err(e.into()); symbols.len()],
+            };
+        }
+
+        symbols
+            .iter()
+            .zip(resolved)
+            .map(|(symbol, resolved)| {
+                let (id, currency) = resolved.ok_or_else(|| self.unknown(symbol))?;
+                let coin = body.get(id).ok_or_else(|| self.unknown(symbol))?;
+                let price = *coin.prices.get(&currency).ok_or_else(|| {
+                    FetchError::Parse(format!("no {currency} price for {symbol}"))
+                })?;
+                Ok(StockPrice {
+                    symbol: symbol.to_string(),
+                    price,
+                    source: self.name().to_string(),
+                    timestamp: coin
+                        .last_updated_at
+                        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+                        .unwrap_or_else(Utc::now),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,241 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use market_core::store::Candle;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::instrument;
+
+use super::{check_status, FetchError, Http, PriceSource, StockPrice};
+
+#[derive(Deserialize, Debug)]
+struct FinnhubQuote {
+    c: f64,  // current price
+    o: f64,  // open
+    h: f64,  // day high
+    l: f64,  // day low
+    pc: f64, // previous close
+}
+
+/// `/stock/candle` answer: one array per field, index `i` of each being bar `i`.
+#[derive(Deserialize, Debug)]
+struct CandleArrays {
+    s: String,
+    #[serde(default)]
+    t: Vec<i64>,
+    #[serde(default)]
+    o: Vec<f64>,
+    #[serde(default)]
+    h: Vec<f64>,
+    #[serde(default)]
+    l: Vec<f64>,
+    #[serde(default)]
+    c: Vec<f64>,
+    #[serde(default)]
+    v: Vec<f64>,
+}
+
+/// Bar width accepted by `/stock/candle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Minutes(u32),
+    Day,
+    Week,
+}
+
+impl Resolution {
+    /// Value of the `resolution` query parameter.
+    fn param(self) -> String {
+        match self {
+            Resolution::Minutes(minutes) => minutes.to_string(),
+            Resolution::Day => "D".to_string(),
+            Resolution::Week => "W".to_string(),
+        }
+    }
+
+    /// Label in `stock_candles.interval`, same naming as the roll-ups.
+    pub fn label(self) -> &'static str {
+        match self {
+            Resolution::Minutes(1) => "1m",
+            Resolution::Minutes(5) => "5m",
+            Resolution::Minutes(15) => "15m",
+            Resolution::Minutes(30) => "30m",
+            Resolution::Minutes(_) => "1h",
+            Resolution::Day => "1d",
+            Resolution::Week => "1w",
+        }
+    }
+
+    pub fn width(self) -> Duration {
+        match self {
+            Resolution::Minutes(minutes) => Duration::minutes(minutes.into()),
+            Resolution::Day => Duration::days(1),
+            Resolution::Week => Duration::weeks(1),
+        }
+    }
+
+    /// Longest range we ask for in one request; Finnhub truncates longer intraday spans.
+    pub fn max_span(self) -> Duration {
+        match self {
+            Resolution::Minutes(_) => Duration::days(30),
+            Resolution::Day | Resolution::Week => Duration::days(365),
+        }
+    }
+}
+
+impl FromStr for Resolution {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.to_uppercase().as_str() {
+            "1" => Ok(Resolution::Minutes(1)),
+            "5" => Ok(Resolution::Minutes(5)),
+            "15" => Ok(Resolution::Minutes(15)),
+            "30" => Ok(Resolution::Minutes(30)),
+            "60" => Ok(Resolution::Minutes(60)),
+            "D" => Ok(Resolution::Day),
+            "W" => Ok(Resolution::Week),
+            _ => Err(format!(
+                "unsupported resolution '{raw}' (expected 1, 5, 15, 30, 60, D or W)"
+            )),
+        }
+    }
+}
+
+pub struct FinnhubSource {
+    api_key: String,
+    http: Http,
+}
+
+impl FinnhubSource {
+    /// Stored in the `source` column of backfilled candles, apart from the `finnhub`
+    /// candles rolled up from live quotes.
+    pub const CANDLE_SOURCE: &'static str = "finnhub_candles";
+
+    pub fn new(api_key: String, http: Http) -> Self {
+        Self { api_key, http }
+    }
+
+    /// Bars starting in `from..to`, in one request; the caller keeps the span under
+    /// `Resolution::max_span`. A bar still in progress is left out.
+    #[instrument(skip(self))]
+    pub async fn candles(
+        &self,
+        symbol: &str,
+        resolution: Resolution,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Candle>, FetchError> {
+        let url = format!(
+            "https://finnhub.io/api/v1/stock/candle?symbol={}&resolution={}&from={}&to={}&token={}",
+            symbol,
+            resolution.param(),
+            from.timestamp(),
+            // `to` is inclusive on Finnhub's side
+            to.timestamp() - 1,
+            self.api_key
+        );
+
+        let resp = check_status(self.name(), self.http.get(&url).send().await?)?;
+        if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+            return Err(FetchError::RateLimited {
+                provider: self.name(),
+                retry_after: None,
+            });
+        }
+        let bars = resp.error_for_status()?.json::<CandleArrays>().await?;
+        match bars.s.as_str() {
+            "ok" => {}
+            "no_data" => return Ok(Vec::new()),
+            status => {
+                return Err(FetchError::Api {
+                    provider: self.name(),
+                    message: format!("candle status '{status}'"),
+                })
+            }
+        }
+
+        let n = bars.t.len();
+        let lengths = [
+            bars.o.len(),
+            bars.h.len(),
+            bars.l.len(),
+            bars.c.len(),
+            bars.v.len(),
+        ];
+        if lengths.iter().any(|&len| len != n) {
+            return Err(FetchError::Parse(format!(
+                "candle arrays of different lengths: t={n}, o/h/l/c/v={lengths:?}"
+            )));
+        }
+
+        let now = Utc::now();
+        let mut candles = Vec::with_capacity(n);
+        for i in 0..n {
+            let start = DateTime::from_timestamp(bars.t[i], 0)
+                .ok_or_else(|| FetchError::Parse(format!("invalid timestamp {}", bars.t[i])))?;
+            if start < from || start >= to || start + resolution.width() > now {
+                continue;
+            }
+            candles.push(Candle {
+                symbol: symbol.to_string(),
+                source: Self::CANDLE_SOURCE.to_string(),
+                interval: resolution.label(),
+                bucket_start: start,
+                open: bars.o[i],
+                high: bars.h[i],
+                low: bars.l[i],
+                close: bars.c[i],
+                samples: 1,
+            });
+        }
+        Ok(candles)
+    }
+}
+
+#[async_trait]
+impl PriceSource for FinnhubSource {
+    fn name(&self) -> &'static str {
+        "finnhub"
+    }
+
+    #[instrument(skip(self))]
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        let url = format!(
+            "https://finnhub.io/api/v1/quote?symbol={}&token={}",
+            symbol, self.api_key
+        );
+
+        let resp = check_status(self.name(), self.http.get(&url).send().await?)?;
+        if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+            return Err(FetchError::RateLimited {
+                provider: self.name(),
+                retry_after: None,
+            });
+        }
+        let resp = resp.error_for_status()?.json::<FinnhubQuote>().await?;
+
+        // Finnhub answers unknown symbols with an all-zero quote
+        if resp.c == 0.0 {
+            return Err(FetchError::UnknownSymbol {
+                provider: self.name(),
+                symbol: symbol.to_string(),
+            });
+        }
+
+        // Zero is how Finnhub says "not available"
+        let known = |v: f64| (v != 0.0).then_some(v);
+        Ok(StockPrice {
+            symbol: symbol.to_string(),
+            price: resp.c,
+            source: self.name().to_string(),
+            timestamp: chrono::Utc::now(),
+            open: known(resp.o),
+            high: known(resp.h),
+            low: known(resp.l),
+            prev_close: known(resp.pc),
+            ..Default::default()
+        })
+    }
+}
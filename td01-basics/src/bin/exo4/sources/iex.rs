@@ -0,0 +1,163 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::{instrument, warn};
+
+use super::{check_status, FetchError, Http, PriceSource, StockPrice};
+
+const PROVIDER: &str = "iex";
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct IexQuote {
+    /// Null outside trading hours on some plans.
+    latest_price: Option<f64>,
+    /// Milliseconds since epoch.
+    latest_update: Option<i64>,
+    previous_close: Option<f64>,
+    open: Option<f64>,
+    high: Option<f64>,
+    low: Option<f64>,
+}
+
+pub struct IexSource {
+    token: String,
+    http: Http,
+}
+
+impl IexSource {
+    pub fn new(token: String, http: Http) -> Self {
+        Self { token, http }
+    }
+}
+
+#[async_trait]
+impl PriceSource for IexSource {
+    fn name(&self) -> &'static str {
+        PROVIDER
+    }
+
+    #[instrument(skip(self))]
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        let url = format!(
+            "https://cloud.iexapis.com/stable/stock/{}/quote?token={}",
+            symbol, self.token
+        );
+
+        let resp = check_status(self.name(), self.http.get(&url).send().await?)?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => {
+                return Err(FetchError::UnknownSymbol {
+                    provider: self.name(),
+                    symbol: symbol.to_string(),
+                });
+            }
+            StatusCode::TOO_MANY_REQUESTS => {
+                return Err(FetchError::RateLimited {
+                    provider: self.name(),
+                    retry_after: None,
+                });
+            }
+            _ => {}
+        }
+
+        let quote: IexQuote = resp.error_for_status()?.json().await?;
+        stock_price(symbol, quote)
+    }
+}
+
+/// The price of a quote. Outside trading hours `latestPrice` can be null: the previous close
+/// is used instead, but stored under a distinct source so it is never mistaken for a live
+/// quote.
+fn stock_price(symbol: &str, quote: IexQuote) -> Result<StockPrice, FetchError> {
+    let timestamp = quote
+        .latest_update
+        .and_then(DateTime::from_timestamp_millis)
+        .unwrap_or_else(Utc::now);
+
+    let (price, source) = match (quote.latest_price, quote.previous_close) {
+        (Some(price), _) => (price, PROVIDER.to_string()),
+        (None, Some(previous_close)) => {
+            warn!(symbol = %symbol, "IEX latestPrice is null, using previousClose");
+            (previous_close, format!("{PROVIDER}_previous_close"))
+        }
+        (None, None) => {
+            return Err(FetchError::Parse(format!(
+                "no latestPrice or previousClose for {symbol}"
+            )));
+        }
+    };
+
+    Ok(StockPrice {
+        symbol: symbol.to_string(),
+        price,
+        source,
+        timestamp,
+        open: quote.open,
+        high: quote.high,
+        low: quote.low,
+        prev_close: quote.previous_close,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Trimmed from a `/stable/stock/AAPL/quote` answer after the close.
+    const CLOSED: &str = r#"{
+        "symbol": "AAPL",
+        "companyName": "Apple Inc",
+        "latestPrice": null,
+        "latestSource": "Close",
+        "latestUpdate": null,
+        "previousClose": 189.84,
+        "open": null,
+        "high": null,
+        "low": null,
+        "volume": null
+    }"#;
+
+    const OPEN: &str = r#"{
+        "symbol": "AAPL",
+        "companyName": "Apple Inc",
+        "latestPrice": 190.12,
+        "latestSource": "IEX real time price",
+        "latestUpdate": 1717000000123,
+        "previousClose": 189.84,
+        "open": 189.5,
+        "high": 191.0,
+        "low": 188.9
+    }"#;
+
+    #[test]
+    fn null_latest_price_falls_back_to_previous_close() {
+        let quote: IexQuote = serde_json::from_str(CLOSED).unwrap();
+        let price = stock_price("AAPL", quote).unwrap();
+        assert_eq!(price.source, "iex_previous_close");
+        assert_eq!(price.price, 189.84);
+        assert_eq!(price.prev_close, Some(189.84));
+    }
+
+    #[test]
+    fn latest_price_is_the_live_quote() {
+        let quote: IexQuote = serde_json::from_str(OPEN).unwrap();
+        let price = stock_price("AAPL", quote).unwrap();
+        assert_eq!(price.source, "iex");
+        assert_eq!(price.price, 190.12);
+        assert_eq!(price.timestamp.timestamp_millis(), 1_717_000_000_123);
+        assert_eq!(price.open, Some(189.5));
+    }
+
+    #[test]
+    fn no_price_at_all_is_an_error() {
+        let quote: IexQuote =
+            serde_json::from_str(r#"{"latestPrice": null, "previousClose": null}"#).unwrap();
+        assert!(matches!(
+            stock_price("AAPL", quote),
+            Err(FetchError::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tracing::instrument;
+
+use super::{AssetClass, FetchError, PriceSource, StockPrice};
+use crate::config::MockConfig;
+
+/// A uniform step in [-√3, √3] has a standard deviation of 1.
+const UNIT_STEP: f64 = 1.732_050_807_568_877_2;
+
+struct Walk {
+    rng: StdRng,
+    price: f64,
+    open: f64,
+    high: f64,
+    low: f64,
+}
+
+/// Random-walk prices for offline development and demos, stored as `source = mock`.
+///
+/// Each symbol walks on its own generator, seeded from the configured seed and the symbol,
+/// so a seeded run gives the same prices whatever the fetch order or concurrency.
+pub struct MockSource {
+    seed: u64,
+    volatility: f64,
+    latency: Duration,
+    walks: Mutex<HashMap<String, Walk>>,
+}
+
+impl MockSource {
+    pub fn new(cfg: &MockConfig) -> Self {
+        Self {
+            seed: cfg.seed.unwrap_or_else(rand::random),
+            volatility: cfg.volatility,
+            latency: cfg.latency,
+            walks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn start(&self, symbol: &str) -> Walk {
+        let mut rng = StdRng::seed_from_u64(self.seed ^ fnv1a(symbol));
+        let price = match AssetClass::of(symbol) {
+            AssetClass::Equity => rng.gen_range(20.0..500.0),
+            AssetClass::Crypto => rng.gen_range(1_000.0..60_000.0),
+            AssetClass::Forex => rng.gen_range(0.5..2.0),
+        };
+        Walk {
+            rng,
+            price,
+            open: price,
+            high: price,
+            low: price,
+        }
+    }
+}
+
+/// Stable across runs and platforms, unlike `DefaultHasher`.
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[async_trait]
+impl PriceSource for MockSource {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn asset_classes(&self) -> &'static [AssetClass] {
+        &[AssetClass::Equity, AssetClass::Crypto, AssetClass::Forex]
+    }
+
+    #[instrument(skip(self))]
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        let (price, delay) = {
+            let mut walks = self.walks.lock().unwrap();
+            let walk = walks
+                .entry(symbol.to_string())
+                .or_insert_with(|| self.start(symbol));
+            let step = walk.rng.gen_range(-UNIT_STEP..=UNIT_STEP) * self.volatility;
+            // Never reaches zero, whatever the volatility
+            walk.price = (walk.price * (1.0 + step)).max(walk.price * 0.5);
+            walk.high = walk.high.max(walk.price);
+            walk.low = walk.low.min(walk.price);
+            let delay = self.latency.mul_f64(walk.rng.gen_range(0.5..1.5));
+            let price = StockPrice {
+                symbol: symbol.to_string(),
+                price: walk.price,
+                source: self.name().to_string(),
+                timestamp: chrono::Utc::now(),
+                open: Some(walk.open),
+                high: Some(walk.high),
+                low: Some(walk.low),
+                ..Default::default()
+            };
+            (price, delay)
+        };
+        tokio::time::sleep(delay).await;
+        Ok(price)
+    }
+}
@@ -0,0 +1,478 @@
+//! Price providers. Each provider implements `PriceSource` and is registered in
+//! `build`, so the fetch loop doesn't need to know about any of them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::NaiveTime;
+use reqwest::StatusCode;
+use tracing::info;
+
+use crate::aliases::Aliased;
+use crate::breaker::Breaker;
+use crate::config::{Config, SourceConfig};
+use crate::deadline::Deadline;
+use crate::ratelimit::{Limits, RateLimited};
+use crate::retry::Retrying;
+
+mod alpha_vantage;
+mod binance;
+mod coingecko;
+mod finnhub;
+mod iex;
+mod mock;
+mod polygon;
+mod twelve_data;
+mod yahoo;
+
+pub use alpha_vantage::{AlphaVantageFxSource, AlphaVantageSource};
+pub use binance::BinanceSource;
+pub use coingecko::CoinGeckoSource;
+pub use finnhub::{FinnhubSource, Resolution};
+pub use iex::IexSource;
+pub use mock::MockSource;
+pub use polygon::PolygonSource;
+pub use twelve_data::TwelveDataSource;
+pub use yahoo::YahooSource;
+
+pub use market_core::StockPrice;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetClass {
+    Equity,
+    /// Trades 24/7, quoted as `BASE-QUOTE` (`BTC-USD`).
+    Crypto,
+    /// Currency pair, `EUR/USD` or Yahoo-style `EURUSD=X`.
+    Forex,
+}
+
+impl AssetClass {
+    /// The one place symbols are classified; sources are only handed symbols of the
+    /// classes they declare.
+    pub fn of(symbol: &str) -> Self {
+        if forex_pair(symbol).is_some() {
+            return AssetClass::Forex;
+        }
+        // `BRK-B` is an equity share class, `BTC-USD` is a pair: look at the quote side
+        match symbol.rsplit_once('-') {
+            Some((_, "USD" | "USDT" | "USDC" | "EUR" | "GBP" | "BTC" | "ETH")) => {
+                AssetClass::Crypto
+            }
+            _ => AssetClass::Equity,
+        }
+    }
+}
+
+/// `EUR/USD` or `EURUSD=X` as `("EUR", "USD")`.
+pub fn forex_pair(symbol: &str) -> Option<(&str, &str)> {
+    let is_code = |code: &str| code.len() == 3 && code.bytes().all(|b| b.is_ascii_uppercase());
+    let (from, to) = match symbol.split_once('/') {
+        Some(pair) => pair,
+        None => {
+            let pair = symbol.strip_suffix("=X")?;
+            if pair.len() != 6 || !pair.is_ascii() {
+                return None;
+            }
+            pair.split_at(3)
+        }
+    };
+    (is_code(from) && is_code(to)).then_some((from, to))
+}
+
+/// Why a fetch produced no price. Provider-level variants carry the provider name so the
+/// message alone says where it came from.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum FetchError {
+    /// The provider doesn't know this symbol (delisted, typo, wrong spelling).
+    #[error("{provider}: unknown symbol {symbol}")]
+    UnknownSymbol {
+        provider: &'static str,
+        symbol: String,
+    },
+    /// The provider asked us to slow down (HTTP 429 or equivalent). Worth retrying later.
+    #[error("{provider}: rate limited{}", retry_hint(.retry_after))]
+    RateLimited {
+        provider: &'static str,
+        retry_after: Option<Duration>,
+    },
+    /// Missing, invalid or unauthorized API key.
+    #[error("{provider}: authentication failed: {message}")]
+    Auth {
+        provider: &'static str,
+        message: String,
+    },
+    /// Our own request budget for this provider is used up, so nothing was sent.
+    #[error("{provider}: rate-budget exhausted ({window})")]
+    BudgetExhausted {
+        provider: &'static str,
+        window: &'static str,
+    },
+    /// The provider's circuit breaker is open after repeated failures, so nothing was sent.
+    #[error("{provider}: circuit open")]
+    CircuitOpen { provider: &'static str },
+    /// The provider answered with an error we have no better variant for.
+    #[error("{provider}: API error: {message}")]
+    Api {
+        provider: &'static str,
+        message: String,
+    },
+    /// Network failure or unexpected HTTP status. Shared so a failed batch request can be
+    /// reported against each of its symbols.
+    #[error("HTTP error: {0}")]
+    Http(Arc<reqwest::Error>),
+    /// The response did not have the shape we expected.
+    #[error("invalid response: {0}")]
+    Parse(String),
+    /// No result within the source's `fetch_timeout`; the attempt was abandoned.
+    #[error("{provider}: no answer within {after:?}")]
+    Timeout {
+        provider: &'static str,
+        after: Duration,
+    },
+    /// The quote parsed but is not a believable price (zero, infinite, far off the median).
+    #[error("{provider}: price rejected: {reason}")]
+    Rejected {
+        provider: &'static str,
+        reason: String,
+    },
+}
+
+fn retry_hint(retry_after: &Option<Duration>) -> String {
+    retry_after
+        .map(|after| format!(", retry after {}s", after.as_secs()))
+        .unwrap_or_default()
+}
+
+impl FetchError {
+    /// Worth another attempt: rate limits, timeouts, connection failures and 5xx answers.
+    /// Unknown symbols, bad keys and unparsable bodies will fail the same way again.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            FetchError::RateLimited { .. } | FetchError::Timeout { .. } => true,
+            FetchError::Http(e) => {
+                e.is_timeout() || e.is_connect() || e.status().is_some_and(|s| s.is_server_error())
+            }
+            FetchError::UnknownSymbol { .. }
+            | FetchError::Auth { .. }
+            | FetchError::BudgetExhausted { .. }
+            | FetchError::CircuitOpen { .. }
+            | FetchError::Api { .. }
+            | FetchError::Parse(_)
+            | FetchError::Rejected { .. } => false,
+        }
+    }
+
+    /// Short, stable name of the variant, for metric labels.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            FetchError::UnknownSymbol { .. } => "unknown_symbol",
+            FetchError::RateLimited { .. } => "rate_limited",
+            FetchError::Auth { .. } => "auth",
+            FetchError::BudgetExhausted { .. } => "budget_exhausted",
+            FetchError::CircuitOpen { .. } => "circuit_open",
+            FetchError::Api { .. } => "api",
+            FetchError::Http(_) => "http",
+            FetchError::Parse(_) => "parse",
+            FetchError::Timeout { .. } => "timeout",
+            FetchError::Rejected { .. } => "rejected",
+        }
+    }
+
+    /// Nothing went out: our own budget or the circuit breaker stopped the request.
+    pub fn is_skipped(&self) -> bool {
+        matches!(
+            self,
+            FetchError::BudgetExhausted { .. } | FetchError::CircuitOpen { .. }
+        )
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    /// The URL is dropped: most providers take the API key as a query parameter.
+    fn from(e: reqwest::Error) -> Self {
+        FetchError::Http(Arc::new(e.without_url()))
+    }
+}
+
+impl From<serde_json::Error> for FetchError {
+    fn from(e: serde_json::Error) -> Self {
+        FetchError::Parse(e.to_string())
+    }
+}
+
+impl From<std::num::ParseFloatError> for FetchError {
+    fn from(e: std::num::ParseFloatError) -> Self {
+        FetchError::Parse(e.to_string())
+    }
+}
+
+/// Handles the statuses every provider means the same way: 401/403 become `Auth` and 5xx
+/// an `Http` error. Other statuses are left to the caller, as several providers put a
+/// useful JSON body on their 4xx answers.
+fn check_status(
+    provider: &'static str,
+    resp: reqwest::Response,
+) -> Result<reqwest::Response, FetchError> {
+    let status = resp.status();
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        return Err(FetchError::Auth {
+            provider,
+            message: status.to_string(),
+        });
+    }
+    if status.is_server_error() {
+        return Err(resp.error_for_status().unwrap_err().into());
+    }
+    Ok(resp)
+}
+
+/// Used when a source has no `timeout` of its own.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Used when a source has no `fetch_timeout`: above `DEFAULT_TIMEOUT`, since some providers
+/// need more than one request per quote.
+const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One client for every provider, so connections (and TLS sessions) are reused across
+/// requests and cycles.
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .user_agent(concat!("exo4-aggregator/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .unwrap_or_default()
+});
+
+/// A source's handle on the shared client: every request gets the source's timeout.
+#[derive(Clone)]
+pub struct Http {
+    client: reqwest::Client,
+    timeout: Duration,
+}
+
+impl Http {
+    pub fn new(cfg: &SourceConfig) -> Self {
+        Self {
+            client: CLIENT.clone(),
+            timeout: cfg.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        }
+    }
+
+    pub fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client.get(url).timeout(self.timeout)
+    }
+}
+
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+    /// Value stored in the `source` column.
+    fn name(&self) -> &'static str;
+
+    /// Kinds of symbols this provider can quote; others are never passed to `fetch`.
+    fn asset_classes(&self) -> &'static [AssetClass] {
+        &[AssetClass::Equity]
+    }
+
+    fn supports(&self, symbol: &str) -> bool {
+        self.asset_classes().contains(&AssetClass::of(symbol))
+    }
+
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError>;
+
+    /// True when `fetch_many` is a single request rather than a loop over `fetch`.
+    fn has_batch_endpoint(&self) -> bool {
+        false
+    }
+
+    /// False while a circuit breaker keeps the source switched off.
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    /// Fetches several symbols, one result per symbol in the same order.
+    /// Providers with a batch endpoint override this to save requests.
+    async fn fetch_many(&self, symbols: &[&str]) -> Vec<Result<StockPrice, FetchError>> {
+        let mut results = Vec::with_capacity(symbols.len());
+        for symbol in symbols {
+            results.push(self.fetch(symbol).await);
+        }
+        results
+    }
+}
+
+/// Every name accepted by `--sources`.
+pub const KNOWN_SOURCES: &[&str] = &[
+    "alpha_vantage",
+    "alpha_vantage_fx",
+    "finnhub",
+    "yahoo",
+    "polygon",
+    "twelve_data",
+    "iex",
+    "coingecko",
+    "binance",
+    "mock",
+];
+
+/// Environment variable a keyed provider reads its key from, unless overridden in the config.
+fn default_key_env(name: &str) -> Option<&'static str> {
+    match name {
+        "alpha_vantage" | "alpha_vantage_fx" => Some("ALPHA_VANTAGE_API_KEY"),
+        "finnhub" => Some("FINNHUB_API_KEY"),
+        "polygon" => Some("POLYGON_API_KEY"),
+        "twelve_data" => Some("TWELVEDATA_API_KEY"),
+        "iex" => Some("IEX_TOKEN"),
+        _ => None,
+    }
+}
+
+/// With `names` (from `--sources`) exactly those sources are built and a missing key is an
+/// error. Otherwise every known source not disabled in the config is built, skipping keyed
+/// ones without credentials unless the config explicitly enables them. The mock source is
+/// only built when named or enabled.
+///
+/// `aliases` maps canonical symbols to each provider's ticker, see `Aliased`.
+pub fn build(
+    names: Option<&[String]>,
+    config: &Config,
+    aliases: &BTreeMap<String, BTreeMap<String, String>>,
+) -> Result<Vec<Box<dyn PriceSource>>, String> {
+    let settings = &config.sources;
+    let explicit = names.is_some();
+    let names: Vec<&str> = match names {
+        Some(names) => names.iter().map(String::as_str).collect(),
+        None => KNOWN_SOURCES.to_vec(),
+    };
+
+    let mut sources = Vec::new();
+    let mut active = Vec::new();
+    for name in names {
+        if !KNOWN_SOURCES.contains(&name) {
+            return Err(format!(
+                "unknown source '{}' (known: {})",
+                name,
+                KNOWN_SOURCES.join(", ")
+            ));
+        }
+        let cfg = settings.get(name).cloned().unwrap_or_default();
+        if !explicit && cfg.enabled == Some(false) {
+            continue;
+        }
+        if !explicit && name == "mock" && cfg.enabled != Some(true) {
+            continue;
+        }
+
+        let api_key = match api_key(name, &cfg) {
+            Ok(key) => key,
+            Err(e) if explicit || cfg.enabled == Some(true) => return Err(e),
+            Err(e) => {
+                info!(source = name, reason = %e, "Skipping source");
+                continue;
+            }
+        };
+
+        let limits = limits(name, &cfg)?;
+        let mut source = match name {
+            "mock" => Box::new(MockSource::new(&config.mock)),
+            _ => by_name(name, api_key, Http::new(&cfg)),
+        };
+        let tickers: HashMap<String, String> = aliases
+            .iter()
+            .filter_map(|(symbol, by_source)| Some((symbol.clone(), by_source.get(name)?.clone())))
+            .collect();
+        if !tickers.is_empty() {
+            source = Box::new(Aliased::new(source, tickers));
+        }
+        source = Box::new(Deadline::new(
+            source,
+            cfg.fetch_timeout.unwrap_or(DEFAULT_FETCH_TIMEOUT),
+        ));
+        if !limits.is_unlimited() {
+            source = Box::new(RateLimited::new(source, limits));
+        }
+        let source = Box::new(Retrying::new(source, cfg.retry.unwrap_or_default()));
+        sources.push(Box::new(Breaker::new(source, cfg.breaker.unwrap_or_default())) as _);
+        active.push(format!("{name} ({})", describe(&limits)));
+    }
+    if !active.is_empty() {
+        info!(sources = %active.join(", "), "Active sources");
+    }
+    Ok(sources)
+}
+
+fn describe(limits: &Limits) -> String {
+    match (limits.per_minute, limits.per_day) {
+        (None, None) => "unlimited".to_string(),
+        (Some(m), None) => format!("{m}/min"),
+        (None, Some(d)) => format!("{d}/day"),
+        (Some(m), Some(d)) => format!("{m}/min, {d}/day"),
+    }
+}
+
+/// Key of a keyed provider, read from `api_key_env` or its default variable; empty for
+/// providers that don't need one.
+pub fn api_key(name: &str, cfg: &SourceConfig) -> Result<String, String> {
+    match cfg.api_key_env.as_deref().or(default_key_env(name)) {
+        Some(var) => env::var(var).map_err(|_| format!("source '{name}' requires {var} to be set")),
+        None => Ok(String::new()),
+    }
+}
+
+/// Request budget configured for a source.
+pub fn limits(name: &str, cfg: &SourceConfig) -> Result<Limits, String> {
+    Ok(Limits {
+        per_minute: cfg.requests_per_minute,
+        per_day: cfg.requests_per_day,
+        daily_reset: parse_daily_reset(cfg.daily_reset.as_deref())
+            .map_err(|e| format!("source '{name}': {e}"))?,
+    })
+}
+
+/// `"HH:MM"` in UTC, midnight when unset.
+fn parse_daily_reset(raw: Option<&str>) -> Result<NaiveTime, String> {
+    match raw {
+        None => Ok(NaiveTime::MIN),
+        Some(raw) => NaiveTime::parse_from_str(raw, "%H:%M")
+            .map_err(|e| format!("invalid daily_reset '{raw}' (expected HH:MM): {e}")),
+    }
+}
+
+/// Exchanges Alpha Vantage knows, under its own suffixes (`TSCO.LON`, `MBG.DEX`).
+const ALPHA_VANTAGE_EXCHANGES: &[&str] = &["LON", "DEX", "TRT", "TRV", "BSE", "SHH", "SHZ"];
+
+/// Why `source` is known to fail on `ticker`, if it is. Only covers what the ticker alone
+/// gives away: an exchange suffix (`VOW3.DE`) or a share class (`BRK.B`).
+pub fn known_unsupported(source: &str, ticker: &str) -> Option<&'static str> {
+    let suffix = ticker.rsplit_once('.').map(|(_, suffix)| suffix);
+    let exchange = suffix.filter(|s| s.len() >= 2 && s.bytes().all(|b| b.is_ascii_alphabetic()));
+    let share_class = suffix.is_some_and(|s| matches!(s, "A" | "B" | "C"));
+    match source {
+        "iex" | "polygon" if exchange.is_some() => Some("US listings only"),
+        "finnhub" if exchange.is_some() => Some("non-US listings need a paid plan"),
+        "alpha_vantage" if exchange.is_some_and(|s| !ALPHA_VANTAGE_EXCHANGES.contains(&s)) => {
+            Some("uses its own exchange suffixes (.LON, .DEX, .TRT, ...)")
+        }
+        "alpha_vantage" | "yahoo" if share_class => {
+            Some("writes share classes with a dash (BRK-B)")
+        }
+        _ => None,
+    }
+}
+
+fn by_name(name: &str, api_key: String, http: Http) -> Box<dyn PriceSource> {
+    match name {
+        "alpha_vantage" => Box::new(AlphaVantageSource::new(api_key, http)),
+        "alpha_vantage_fx" => Box::new(AlphaVantageFxSource::new(api_key, http)),
+        "finnhub" => Box::new(FinnhubSource::new(api_key, http)),
+        "polygon" => Box::new(PolygonSource::new(api_key, http)),
+        "twelve_data" => Box::new(TwelveDataSource::new(api_key, http)),
+        "iex" => Box::new(IexSource::new(api_key, http)),
+        "coingecko" => Box::new(CoinGeckoSource::from_env(http)),
+        "binance" => Box::new(BinanceSource::new(http)),
+        _ => Box::new(YahooSource::from_env(http)),
+    }
+}
@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::DateTime;
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::instrument;
+
+use super::{check_status, FetchError, Http, PriceSource, StockPrice};
+
+const PROVIDER: &str = "polygon";
+
+#[derive(Deserialize, Debug)]
+struct LastTradeResponse {
+    results: Option<LastTrade>,
+    message: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LastTrade {
+    p: f64, // trade price
+    t: i64, // SIP timestamp, nanoseconds since epoch
+}
+
+pub struct PolygonSource {
+    api_key: String,
+    http: Http,
+}
+
+impl PolygonSource {
+    pub fn new(api_key: String, http: Http) -> Self {
+        Self { api_key, http }
+    }
+}
+
+#[async_trait]
+impl PriceSource for PolygonSource {
+    fn name(&self) -> &'static str {
+        PROVIDER
+    }
+
+    #[instrument(skip(self))]
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        let url = format!(
+            "https://api.polygon.io/v2/last/trade/{}?apiKey={}",
+            symbol, self.api_key
+        );
+
+        let resp = check_status(self.name(), self.http.get(&url).send().await?)?;
+        match resp.status() {
+            StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = resp
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .map(Duration::from_secs);
+                return Err(FetchError::RateLimited {
+                    provider: self.name(),
+                    retry_after,
+                });
+            }
+            StatusCode::NOT_FOUND => {
+                return Err(FetchError::UnknownSymbol {
+                    provider: self.name(),
+                    symbol: symbol.to_string(),
+                });
+            }
+            _ => {}
+        }
+
+        let body: LastTradeResponse = resp.json().await?;
+        stock_price(symbol, body)
+    }
+}
+
+/// The price of the last trade, or the error Polygon sent instead.
+fn stock_price(symbol: &str, body: LastTradeResponse) -> Result<StockPrice, FetchError> {
+    let trade = match body.results {
+        Some(trade) => trade,
+        None => {
+            return Err(FetchError::Api {
+                provider: PROVIDER,
+                message: body.error.or(body.message).unwrap_or_default(),
+            });
+        }
+    };
+
+    Ok(StockPrice {
+        symbol: symbol.to_string(),
+        price: trade.p,
+        source: PROVIDER.to_string(),
+        timestamp: DateTime::from_timestamp_nanos(trade.t),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `/v2/last/trade/AAPL` answer, conditions and exchange fields included.
+    const LAST_TRADE: &str = r#"{
+        "request_id": "f05562305bd26ced64b98ed68b3c5d96",
+        "results": {
+            "T": "AAPL",
+            "c": [37],
+            "f": 1617901342969796400,
+            "i": "118749",
+            "p": 129.8473,
+            "q": 3135876,
+            "r": 202,
+            "s": 25,
+            "t": 1617901342969834000,
+            "x": 4,
+            "y": 1617901342968000000,
+            "z": 3
+        },
+        "status": "OK"
+    }"#;
+
+    #[test]
+    fn sip_timestamp_is_in_nanoseconds() {
+        let body: LastTradeResponse = serde_json::from_str(LAST_TRADE).unwrap();
+        let price = stock_price("AAPL", body).unwrap();
+        assert_eq!(price.price, 129.8473);
+        assert_eq!(price.source, "polygon");
+        assert_eq!(
+            price.timestamp.to_rfc3339(),
+            "2021-04-08T17:02:22.969834+00:00"
+        );
+    }
+
+    #[test]
+    fn no_results_is_the_api_error() {
+        let body: LastTradeResponse = serde_json::from_str(
+            r#"{"status": "NOT_AUTHORIZED", "request_id": "1", "message": "You are not entitled to this data."}"#,
+        )
+        .unwrap();
+        match stock_price("AAPL", body) {
+            Err(FetchError::Api { provider, message }) => {
+                assert_eq!(provider, "polygon");
+                assert_eq!(message, "You are not entitled to this data.");
+            }
+            other => panic!("expected an API error, got {other:?}"),
+        }
+    }
+}
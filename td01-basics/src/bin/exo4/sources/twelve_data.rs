@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use tracing::instrument;
+
+use super::{check_status, FetchError, Http, PriceSource, StockPrice};
+
+/// Twelve Data answers errors with HTTP 200 and a `{"code":...,"message":...}` body,
+/// so every payload (and every entry of a batch payload) can be either shape.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum PriceOrError {
+    Price { price: String },
+    Error { code: u16, message: String },
+}
+
+pub struct TwelveDataSource {
+    api_key: String,
+    http: Http,
+}
+
+impl TwelveDataSource {
+    pub fn new(api_key: String, http: Http) -> Self {
+        Self { api_key, http }
+    }
+
+    async fn request(&self, symbols: &str) -> Result<String, FetchError> {
+        let url = format!(
+            "https://api.twelvedata.com/price?symbol={}&apikey={}",
+            symbols, self.api_key
+        );
+        let resp = check_status(self.name(), self.http.get(&url).send().await?)?;
+        Ok(resp.error_for_status()?.text().await?)
+    }
+
+    fn to_result(&self, symbol: &str, item: PriceOrError) -> Result<StockPrice, FetchError> {
+        match item {
+            PriceOrError::Price { price } => Ok(StockPrice {
+                symbol: symbol.to_string(),
+                price: price.parse()?,
+                source: self.name().to_string(),
+                timestamp: chrono::Utc::now(),
+                ..Default::default()
+            }),
+            PriceOrError::Error { code, message } => Err(self.to_error(symbol, code, &message)),
+        }
+    }
+
+    fn to_error(&self, symbol: &str, code: u16, message: &str) -> FetchError {
+        match code {
+            429 => FetchError::RateLimited {
+                provider: self.name(),
+                retry_after: None,
+            },
+            400 | 404 => FetchError::UnknownSymbol {
+                provider: self.name(),
+                symbol: symbol.to_string(),
+            },
+            401 | 403 => FetchError::Auth {
+                provider: self.name(),
+                message: message.to_string(),
+            },
+            _ => FetchError::Api {
+                provider: self.name(),
+                message: format!("{code}: {message}"),
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSource for TwelveDataSource {
+    fn name(&self) -> &'static str {
+        "twelve_data"
+    }
+
+    fn has_batch_endpoint(&self) -> bool {
+        true
+    }
+
+    #[instrument(skip(self))]
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        let text = self.request(symbol).await?;
+        let item: PriceOrError = serde_json::from_str(&text)?;
+        self.to_result(symbol, item)
+    }
+
+    /// One request for the whole batch: `symbol=AAPL,MSFT` returns a map keyed by symbol.
+    #[instrument(skip(self))]
+    async fn fetch_many(&self, symbols: &[&str]) -> Vec<Result<StockPrice, FetchError>> {
+        if symbols.len() <= 1 {
+            let mut results = Vec::new();
+            for symbol in symbols {
+                results.push(self.fetch(symbol).await);
+            }
+            return results;
+        }
+
+        let text = match self.request(&symbols.join(",")).await {
+            Ok(text) => text,
+            Err(e) => return vec![Err(e); symbols.len()],
+        };
+
+        // A whole-request error (rate limit, bad key) comes back as a single error object
+        if let Ok(PriceOrError::Error { code, message }) = serde_json::from_str(&text) {
+            return symbols
+                .iter()
+                .map(|symbol| Err(self.to_error(symbol, code, &message)))
+                .collect();
+        }
+
+        let mut batch: HashMap<String, PriceOrError> = match serde_json::from_str(&text) {
+            Ok(batch) => batch,
+            Err(e) => {
+                let e = FetchError::Parse(format!("invalid batch response: {e}"));
+                return vec![Err(e); symbols.len()];
+            }
+        };
+
+        symbols
+            .iter()
+            .map(|symbol| match batch.remove(*symbol) {
+                Some(item) => self.to_result(symbol, item),
+                None => Err(FetchError::UnknownSymbol {
+                    provider: self.name(),
+                    symbol: symbol.to_string(),
+                }),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::env;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::header::USER_AGENT;
+use serde::Deserialize;
+use tracing::instrument;
+
+use super::{check_status, FetchError, Http, PriceSource, StockPrice};
+
+#[derive(Deserialize, Debug)]
+struct ChartResponse {
+    chart: Chart,
+}
+
+#[derive(Deserialize, Debug)]
+struct Chart {
+    result: Option<Vec<ChartResult>>,
+    error: Option<ChartError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChartResult {
+    meta: ChartMeta,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ChartMeta {
+    regular_market_price: Option<f64>,
+    regular_market_time: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChartError {
+    code: String,
+    description: Option<String>,
+}
+
+/// Public chart endpoint, no API key needed.
+pub struct YahooSource {
+    /// Our symbol -> Yahoo symbol (e.g. `BRK.B` -> `BRK-B`).
+    symbol_map: HashMap<String, String>,
+    http: Http,
+}
+
+impl YahooSource {
+    /// Reads the optional mapping from `YAHOO_SYMBOL_MAP` (`BRK.B=BRK-B,BF.B=BF-B`).
+    pub fn from_env(http: Http) -> Self {
+        let symbol_map = env::var("YAHOO_SYMBOL_MAP")
+            .map(|raw| parse_symbol_map(&raw))
+            .unwrap_or_default();
+        Self { symbol_map, http }
+    }
+
+    fn provider_symbol<'a>(&'a self, symbol: &'a str) -> &'a str {
+        self.symbol_map
+            .get(symbol)
+            .map(String::as_str)
+            .unwrap_or(symbol)
+    }
+}
+
+fn parse_symbol_map(raw: &str) -> HashMap<String, String> {
+    raw.split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(ours, theirs)| (ours.trim().to_string(), theirs.trim().to_string()))
+        .filter(|(ours, theirs)| !ours.is_empty() && !theirs.is_empty())
+        .collect()
+}
+
+#[async_trait]
+impl PriceSource for YahooSource {
+    fn name(&self) -> &'static str {
+        "yahoo"
+    }
+
+    #[instrument(skip(self))]
+    async fn fetch(&self, symbol: &str) -> Result<StockPrice, FetchError> {
+        let url = format!(
+            "https://query1.finance.yahoo.com/v8/finance/chart/{}",
+            self.provider_symbol(symbol)
+        );
+
+        // Yahoo rejects requests without a browser-ish user agent
+        let resp = self
+            .http
+            .get(&url)
+            .header(USER_AGENT, "Mozilla/5.0")
+            .send()
+            .await?;
+        let text = check_status(self.name(), resp)?.text().await?;
+
+        // Unknown symbols come back as 404 with a JSON error body, so parse before checking status
+        let resp: ChartResponse = serde_json::from_str(&text)?;
+        if let Some(error) = resp.chart.error {
+            if error.code == "Not Found" {
+                return Err(FetchError::UnknownSymbol {
+                    provider: self.name(),
+                    symbol: symbol.to_string(),
+                });
+            }
+            return Err(FetchError::Api {
+                provider: self.name(),
+                message: format!("{} ({})", error.code, error.description.unwrap_or_default()),
+            });
+        }
+
+        let meta = resp
+            .chart
+            .result
+            .and_then(|r| r.into_iter().next())
+            .map(|r| r.meta)
+            .ok_or_else(|| FetchError::UnknownSymbol {
+                provider: self.name(),
+                symbol: symbol.to_string(),
+            })?;
+        let price = meta
+            .regular_market_price
+            .ok_or_else(|| FetchError::Parse(format!("no regular market price for {symbol}")))?;
+
+        Ok(StockPrice {
+            symbol: symbol.to_string(),
+            price,
+            source: self.name().to_string(),
+            timestamp: meta
+                .regular_market_time
+                .and_then(|secs| DateTime::from_timestamp(secs, 0))
+                .unwrap_or_else(Utc::now),
+            ..Default::default()
+        })
+    }
+}
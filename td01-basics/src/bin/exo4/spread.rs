@@ -0,0 +1,60 @@
+//! Per-cycle view of how far apart the sources are, from the prices just fetched. Unlike
+//! `divergence` it alerts on nothing; it is there to eyeball provider quality in the logs.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::Utc;
+use tracing::info;
+
+use crate::sources::StockPrice;
+
+/// Logs min/max/mean and spread per symbol quoted by at least two sources, then the symbols
+/// only one source had a fresh quote for. Quotes older than `freshness` or flagged stale
+/// are left out.
+pub fn log_summary(prices: &[StockPrice], freshness: Duration) {
+    let now = Utc::now();
+    let freshness = chrono::Duration::from_std(freshness).unwrap_or(chrono::Duration::MAX);
+    let mut by_symbol: BTreeMap<&str, Vec<&StockPrice>> = BTreeMap::new();
+    for price in prices
+        .iter()
+        .filter(|p| !p.stale && now - p.timestamp <= freshness)
+    {
+        by_symbol.entry(&price.symbol).or_default().push(price);
+    }
+
+    let mut single = Vec::new();
+    for (symbol, quotes) in by_symbol {
+        let (Some(low), Some(high)) = (
+            quotes.iter().min_by(|a, b| a.price.total_cmp(&b.price)),
+            quotes.iter().max_by(|a, b| a.price.total_cmp(&b.price)),
+        ) else {
+            continue;
+        };
+        if quotes.len() < 2 {
+            single.push(format!("{symbol} ({})", low.source));
+            continue;
+        }
+        let mean = quotes.iter().map(|p| p.price).sum::<f64>() / quotes.len() as f64;
+        let spread = high.price - low.price;
+        info!(
+            symbol,
+            sources = quotes.len(),
+            min = low.price,
+            max = high.price,
+            mean,
+            spread,
+            spread_pct = spread / mean * 100.0,
+            high_source = %high.source,
+            low_source = %low.source,
+            "Cross-source spread"
+        );
+    }
+    if !single.is_empty() {
+        info!(
+            count = single.len(),
+            symbols = %single.join(", "),
+            "Symbols with a single fresh source"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! Quotes a provider keeps repeating: a delayed free tier, or a closed market answered with
+//! the last trade forever. Such a quote is still a price, but not a fresh one.
+
+use std::collections::{BTreeMap, HashMap};
+
+use tracing::info;
+
+use crate::config::StalenessConfig;
+use crate::cycle::FetchOutcome;
+use crate::sources::StockPrice;
+
+/// What makes two quotes the same. Most providers don't say when their quote is from, so
+/// the timestamp (often the fetch time) is left out.
+#[derive(PartialEq)]
+struct Quote {
+    price: f64,
+    open: Option<f64>,
+    high: Option<f64>,
+    low: Option<f64>,
+    prev_close: Option<f64>,
+}
+
+impl Quote {
+    fn of(price: &StockPrice) -> Self {
+        Self {
+            price: price.price,
+            open: price.open,
+            high: price.high,
+            low: price.low,
+            prev_close: price.prev_close,
+        }
+    }
+}
+
+struct Last {
+    quote: Quote,
+    /// Identical quotes received since it last changed.
+    repeats: u32,
+}
+
+/// Last quote per (symbol, source).
+#[derive(Default)]
+pub struct Staleness {
+    last: HashMap<(String, &'static str), Last>,
+}
+
+impl Staleness {
+    /// Sets `stale` on quotes repeated more than `after` times, and logs which sources sent
+    /// stale quotes this cycle.
+    pub fn check(&mut self, cfg: &StalenessConfig, outcomes: &mut [FetchOutcome]) {
+        let mut stale: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for outcome in outcomes.iter_mut() {
+            let Ok(price) = &mut outcome.result else {
+                continue;
+            };
+            let quote = Quote::of(price);
+            let key = (outcome.symbol.clone(), outcome.source);
+            let repeats = match self.last.get_mut(&key) {
+                Some(last) if last.quote == quote => {
+                    last.repeats += 1;
+                    last.repeats
+                }
+                _ => {
+                    self.last.insert(key, Last { quote, repeats: 0 });
+                    0
+                }
+            };
+            price.stale = repeats >= cfg.after;
+        }
+
+        for outcome in outcomes.iter() {
+            if outcome.result.as_ref().is_ok_and(|price| price.stale) {
+                stale
+                    .entry(outcome.source)
+                    .or_default()
+                    .push(&outcome.symbol);
+            }
+        }
+        for (source, symbols) in stale {
+            info!(
+                source,
+                stale = symbols.len(),
+                symbols = %symbols.join(","),
+                "Provider is repeating the same quotes"
+            );
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! `exo4 stats`: how each source has been doing, from the attempts stored in `fetch_stats`.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::{Args, ValueEnum};
+use market_core::store::{PriceQuery, PriceStore, SourceStats};
+use serde_json::json;
+
+use crate::export::{parse_since, parse_until};
+
+/// Range used when `--since` is not given.
+const DEFAULT_DAYS: i64 = 7;
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// Only attempts for this symbol
+    #[arg(long)]
+    symbol: Option<String>,
+
+    /// Only this source (e.g. finnhub)
+    #[arg(long)]
+    source: Option<String>,
+
+    /// First day (YYYY-MM-DD) or instant (RFC 3339) to include [default: 7 days ago]
+    #[arg(long, value_parser = parse_since)]
+    since: Option<DateTime<Utc>>,
+
+    /// Last day to include (YYYY-MM-DD, whole day) or instant to stop before (RFC 3339)
+    #[arg(long, value_parser = parse_until)]
+    until: Option<DateTime<Utc>>,
+
+    #[arg(long, value_enum, default_value_t = Output::Table)]
+    output: Output,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Output {
+    Table,
+    Json,
+}
+
+/// Prints success rate and p95 latency per source; `Ok(false)` when nothing was recorded
+/// in the range, so the caller can exit non-zero.
+pub async fn run(
+    store: &dyn PriceStore,
+    args: &StatsArgs,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let since = args
+        .since
+        .unwrap_or_else(|| Utc::now() - chrono::Duration::days(DEFAULT_DAYS));
+    let query = PriceQuery {
+        symbol: args.symbol.as_deref().map(str::to_uppercase),
+        source: args.source.clone(),
+        since: Some(since),
+        until: args.until,
+    };
+    let rows = store.source_stats(&query).await?;
+    if rows.is_empty() {
+        eprintln!(
+            "no fetch attempts recorded since {}",
+            since.to_rfc3339_opts(SecondsFormat::Secs, true)
+        );
+        return Ok(false);
+    }
+
+    match args.output {
+        Output::Json => {
+            let out: Vec<_> = rows
+                .iter()
+                .map(|s| {
+                    json!({
+                        "source": s.source,
+                        "attempts": s.attempts,
+                        "successes": s.successes,
+                        "success_rate": success_rate(s),
+                        "p95_latency_ms": s.p95_latency_ms,
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&out)?);
+        }
+        Output::Table => print_table(&rows),
+    }
+    Ok(true)
+}
+
+fn success_rate(stats: &SourceStats) -> f64 {
+    stats.successes as f64 / stats.attempts.max(1) as f64
+}
+
+fn print_table(rows: &[SourceStats]) {
+    let cells: Vec<[String; 4]> = rows
+        .iter()
+        .map(|s| {
+            [
+                s.source.clone(),
+                s.attempts.to_string(),
+                format!("{:.1}%", success_rate(s) * 100.0),
+                s.p95_latency_ms
+                    .map(|ms| format!("{ms} ms"))
+                    .unwrap_or_else(|| "-".to_string()),
+            ]
+        })
+        .collect();
+    let header = ["SOURCE", "ATTEMPTS", "SUCCESS", "P95 LATENCY"];
+    let mut widths = header.map(str::len);
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    println!(
+        "{:<w0$}  {:>w1$}  {:>w2$}  {:>w3$}",
+        header[0],
+        header[1],
+        header[2],
+        header[3],
+        w0 = widths[0],
+        w1 = widths[1],
+        w2 = widths[2],
+        w3 = widths[3]
+    );
+    for [source, attempts, success, p95] in &cells {
+        println!(
+            "{source:<w0$}  {attempts:>w1$}  {success:>w2$}  {p95:>w3$}",
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3]
+        );
+    }
+}
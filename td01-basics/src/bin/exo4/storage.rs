@@ -0,0 +1,82 @@
+//! Writing prices and fetch statistics to the store.
+
+use market_core::store::{FetchStat, Indicator, PortfolioSnapshot, PriceStore, StorageError};
+use tracing::{debug, error, instrument, warn};
+
+use crate::sources::StockPrice;
+
+/// Saves prices in one statement. If the database rejects the batch, falls back to one
+/// insert per row so a single bad row doesn't lose the rest. Returns the rows written, rows
+/// already stored skipped; an error means the database could not be reached and nothing
+/// was written.
+#[instrument(skip_all, fields(rows = prices.len()))]
+pub async fn save_prices(
+    store: &dyn PriceStore,
+    prices: &[StockPrice],
+) -> Result<u64, StorageError> {
+    if prices.is_empty() {
+        return Ok(0);
+    }
+    let error = match store.save_batch(prices).await {
+        Ok(written) => {
+            log_duplicates(prices.len() as u64 - written);
+            return Ok(written);
+        }
+        Err(error) => error,
+    };
+    if error.is_unreachable() {
+        return Err(error);
+    }
+    warn!(error = %error, "Batch insert failed, falling back to per-row inserts");
+
+    let (mut written, mut duplicates) = (0, 0);
+    for price in prices {
+        match store.save(price).await {
+            Ok(true) => {
+                written += 1;
+                debug!(
+                    symbol = %price.symbol,
+                    price = %price.price,
+                    source = %price.source,
+                    "Saved price to database"
+                );
+            }
+            Ok(false) => duplicates += 1,
+            Err(e) => error!(error = %e, "Failed to save price"),
+        }
+    }
+    log_duplicates(duplicates);
+    Ok(written)
+}
+
+/// Records the cycle's attempts; a failure is logged and otherwise ignored.
+pub async fn save_fetch_stats(store: &dyn PriceStore, stats: &[FetchStat]) {
+    if stats.is_empty() {
+        return;
+    }
+    if let Err(e) = store.save_fetch_stats(stats).await {
+        warn!(error = %e, rows = stats.len(), "Failed to save fetch statistics");
+    }
+}
+
+/// Writes moving-average values; a failure is logged and otherwise ignored.
+pub async fn save_indicators(store: &dyn PriceStore, rows: &[Indicator]) {
+    if rows.is_empty() {
+        return;
+    }
+    if let Err(e) = store.save_indicators(rows).await {
+        error!(error = %e, rows = rows.len(), "Failed to save indicators");
+    }
+}
+
+pub async fn save_portfolio_snapshot(store: &dyn PriceStore, snapshot: &PortfolioSnapshot) {
+    if let Err(e) = store.save_portfolio_snapshot(snapshot).await {
+        error!(error = %e, "Failed to save portfolio snapshot");
+    }
+}
+
+fn log_duplicates(duplicates: u64) {
+    if duplicates > 0 {
+        debug!(duplicates, "Skipped rows already stored");
+    }
+}
@@ -0,0 +1,148 @@
+//! Last check before storage: a zero from a closed market or a mangled field must not end up
+//! in `stock_prices`, where it would drag averages down and set off divergence alerts.
+//! Checked after currency conversion, so the median compares like with like.
+
+use std::collections::{HashMap, VecDeque};
+
+use market_core::store::PriceStore;
+use tracing::{info, warn};
+
+use crate::config::ValidationConfig;
+use crate::cycle::FetchOutcome;
+use crate::sources::{FetchError, StockPrice};
+
+/// Below this many accepted prices there is no median to compare against.
+const MIN_SAMPLES: usize = 3;
+
+/// Consecutive off-median prices after which the new level is taken as real (a split, a
+/// redenomination) and the history restarts from it.
+const RESET_AFTER: usize = 5;
+
+#[derive(Default)]
+struct Recent {
+    /// Accepted prices, oldest first.
+    prices: VecDeque<f64>,
+    off_median_in_a_row: usize,
+}
+
+impl Recent {
+    fn push(&mut self, price: f64, window: usize) {
+        self.prices.push_back(price);
+        while self.prices.len() > window {
+            self.prices.pop_front();
+        }
+    }
+
+    fn median(&self) -> Option<f64> {
+        if self.prices.len() < MIN_SAMPLES {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.prices.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let mid = sorted.len() / 2;
+        Some(if sorted.len().is_multiple_of(2) {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
+        })
+    }
+}
+
+/// Recent accepted prices per symbol, all sources together. A symbol seen for the first time
+/// is warmed up from `stock_prices`, so a restart doesn't open a window for bad values.
+#[derive(Default)]
+pub struct Validator {
+    recent: HashMap<String, Recent>,
+}
+
+impl Validator {
+    /// Turns unbelievable prices into failed outcomes, logged with the quote as received.
+    pub async fn check(
+        &mut self,
+        store: &dyn PriceStore,
+        cfg: &ValidationConfig,
+        outcomes: &mut [FetchOutcome],
+    ) {
+        for outcome in outcomes {
+            let Ok(price) = &outcome.result else {
+                continue;
+            };
+            if !self.recent.contains_key(&price.symbol) {
+                let recent = warm_up(store, cfg, price).await;
+                self.recent.insert(price.symbol.clone(), recent);
+            }
+            let Some(recent) = self.recent.get_mut(&price.symbol) else {
+                continue;
+            };
+
+            let Some(reason) = problem(price, recent, cfg) else {
+                recent.off_median_in_a_row = 0;
+                recent.push(price.price, cfg.window);
+                continue;
+            };
+            warn!(
+                symbol = %price.symbol,
+                source = outcome.source,
+                price = price.price,
+                reason = %reason,
+                quote = ?price,
+                "Rejected price"
+            );
+            outcome.result = Err(FetchError::Rejected {
+                provider: outcome.source,
+                reason,
+            });
+        }
+    }
+}
+
+/// Why `price` can't be stored, if it can't. Off-median prices count towards a reset.
+fn problem(price: &StockPrice, recent: &mut Recent, cfg: &ValidationConfig) -> Option<String> {
+    if !price.price.is_finite() {
+        return Some(format!("{} is not a number", price.price));
+    }
+    if price.price <= 0.0 {
+        return Some(format!("{} is not positive", price.price));
+    }
+    let median = recent.median()?;
+    let factor = (price.price / median).max(median / price.price);
+    if factor <= cfg.max_factor {
+        return None;
+    }
+    recent.off_median_in_a_row += 1;
+    if recent.off_median_in_a_row >= RESET_AFTER {
+        info!(
+            symbol = %price.symbol,
+            price = price.price,
+            median,
+            "Prices keep landing far from the median, taking them as the new level"
+        );
+        recent.prices.clear();
+        return None;
+    }
+    Some(format!(
+        "{factor:.1}x away from the recent median {median} (limit {}x)",
+        cfg.max_factor
+    ))
+}
+
+async fn warm_up(store: &dyn PriceStore, cfg: &ValidationConfig, price: &StockPrice) -> Recent {
+    let mut recent = Recent::default();
+    let limit = u32::try_from(cfg.window).unwrap_or(u32::MAX);
+    match store
+        .recent(&price.symbol, None, price.timestamp, limit)
+        .await
+    {
+        Ok(history) => {
+            for old in history.iter().filter(|old| old.price > 0.0) {
+                recent.push(old.price, cfg.window);
+            }
+        }
+        Err(e) => warn!(
+            symbol = %price.symbol,
+            error = %e,
+            "Cannot load history for price validation, starting empty"
+        ),
+    }
+    recent
+}
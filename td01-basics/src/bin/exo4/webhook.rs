@@ -0,0 +1,32 @@
+//! Alert delivery: a JSON POST to a user-supplied URL, off the fetch loop.
+
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use serde_json::Value;
+use tracing::{debug, warn};
+
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default()
+});
+
+/// Sends `payload` in the background. Failures are logged, never returned: a broken webhook
+/// must not stop the aggregator. The URL is left out of the logs since it usually holds a token.
+pub fn post(url: &str, kind: &'static str, payload: Value) {
+    let url = url.to_string();
+    tokio::spawn(async move {
+        let sent = CLIENT
+            .post(&url)
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status());
+        match sent {
+            Ok(_) => debug!(kind, "Alert delivered"),
+            Err(e) => warn!(kind, error = %e.without_url(), "Alert delivery failed"),
+        }
+    });
+}
@@ -0,0 +1,191 @@
+//! Background price writer. Cycles hand their prices over a channel and move on; the writer
+//! batches them into bulk inserts, by count or every `flush_interval`, so database latency
+//! never slows fetching. While the database is unreachable prices wait in a bounded buffer,
+//! the oldest dropped first, and the write is retried with backoff.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use market_core::store::{FetchStat, Indicator, PortfolioSnapshot, PriceStore, StorageError};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep_until, Instant, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+use crate::config::WriterConfig;
+use crate::metrics::metrics;
+use crate::sources::StockPrice;
+use crate::storage;
+
+const BASE_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+type Write = BoxFuture<'static, (Vec<StockPrice>, Result<u64, StorageError>)>;
+
+pub struct Writer {
+    store: Arc<dyn PriceStore>,
+    queue: mpsc::UnboundedSender<StockPrice>,
+    task: JoinHandle<()>,
+}
+
+impl Writer {
+    pub fn spawn(store: Arc<dyn PriceStore>, cfg: WriterConfig) -> Self {
+        let (queue, rx) = mpsc::unbounded_channel();
+        let task = tokio::spawn(run(store.clone(), cfg, rx));
+        Self { store, queue, task }
+    }
+
+    /// Hands `prices` to the writer without waiting.
+    pub fn send(&self, prices: &[StockPrice]) {
+        for price in prices {
+            if self.queue.send(price.clone()).is_err() {
+                warn!("Price writer has stopped, prices are not saved");
+                return;
+            }
+        }
+    }
+
+    /// Fetch statistics, moving averages and the portfolio snapshot are derived data:
+    /// written in the background on a best-effort basis, neither buffered nor retried.
+    pub fn send_derived(
+        &self,
+        stats: Vec<FetchStat>,
+        indicators: Vec<Indicator>,
+        portfolio: Option<PortfolioSnapshot>,
+    ) {
+        if stats.is_empty() && indicators.is_empty() && portfolio.is_none() {
+            return;
+        }
+        let store = self.store.clone();
+        tokio::spawn(async move {
+            storage::save_fetch_stats(store.as_ref(), &stats).await;
+            storage::save_indicators(store.as_ref(), &indicators).await;
+            if let Some(snapshot) = portfolio {
+                storage::save_portfolio_snapshot(store.as_ref(), &snapshot).await;
+            }
+        });
+    }
+
+    /// Writes what is still buffered, giving up after `grace` if the database stays away.
+    pub async fn close(self, grace: Duration) {
+        drop(self.queue);
+        let mut task = self.task;
+        if tokio::time::timeout(grace, &mut task).await.is_err() {
+            warn!("Price writer not drained in time, unsaved prices are lost");
+            task.abort();
+        }
+    }
+}
+
+async fn run(
+    store: Arc<dyn PriceStore>,
+    cfg: WriterConfig,
+    mut rx: mpsc::UnboundedReceiver<StockPrice>,
+) {
+    let mut buffer: VecDeque<StockPrice> = VecDeque::new();
+    let mut flush = interval(cfg.flush_interval);
+    flush.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut writing: Option<Write> = None;
+    let mut flush_due = false;
+    let mut closed = false;
+    // Consecutive failed writes, and when the next attempt may start
+    let mut failures = 0u32;
+    let mut retry_at: Option<Instant> = None;
+    // Dropped since the last warning, reported once per flush interval
+    let mut dropped = 0;
+
+    loop {
+        let ready = retry_at.is_none_or(|at| at <= Instant::now());
+        if writing.is_none()
+            && ready
+            && !buffer.is_empty()
+            && (flush_due || closed || buffer.len() >= cfg.batch_size)
+        {
+            let rows = buffer.len().min(cfg.batch_size);
+            let batch: Vec<StockPrice> = buffer.drain(..rows).collect();
+            flush_due = !buffer.is_empty();
+            retry_at = None;
+            let store = store.clone();
+            writing = Some(
+                async move {
+                    let result = storage::save_prices(store.as_ref(), &batch).await;
+                    (batch, result)
+                }
+                .boxed(),
+            );
+        }
+        metrics().set_writer_buffered(buffer.len());
+        if closed && buffer.is_empty() && writing.is_none() {
+            break;
+        }
+
+        tokio::select! {
+            price = rx.recv(), if !closed => match price {
+                Some(price) => {
+                    buffer.push_back(price);
+                    dropped += drop_oldest(&mut buffer, cfg.buffer);
+                }
+                None => closed = true,
+            },
+            _ = flush.tick() => {
+                flush_due = true;
+                if dropped > 0 {
+                    warn!(dropped, capacity = cfg.buffer, "Price writer buffer full, dropped the oldest prices");
+                    dropped = 0;
+                }
+            },
+            (batch, result) = async { writing.as_mut().expect("guarded by the branch condition").await }, if writing.is_some() => {
+                writing = None;
+                match result {
+                    Ok(written) => {
+                        if failures > 0 {
+                            info!(failures, "Database writes resumed");
+                            failures = 0;
+                        }
+                        metrics().rows_inserted(written);
+                        debug!(rows = batch.len(), written, buffered = buffer.len(), "Wrote prices");
+                    }
+                    Err(e) => {
+                        failures += 1;
+                        let delay = backoff(failures);
+                        if failures == 1 {
+                            warn!(error = %e, buffered = buffer.len() + batch.len(), "Cannot write prices, buffering until the database is back");
+                        } else {
+                            debug!(error = %e, failures, retry_in_ms = delay.as_millis() as u64, "Price write failed again");
+                        }
+                        for price in batch.into_iter().rev() {
+                            buffer.push_front(price);
+                        }
+                        dropped += drop_oldest(&mut buffer, cfg.buffer);
+                        retry_at = Some(Instant::now() + delay);
+                        flush_due = true;
+                    }
+                }
+            },
+            _ = sleep_until(retry_at.unwrap_or_else(Instant::now)), if retry_at.is_some() && writing.is_none() => {
+                retry_at = None;
+            },
+        }
+    }
+    debug!("Price writer drained");
+}
+
+/// Keeps the newest `capacity` prices; returns how many were dropped.
+fn drop_oldest(buffer: &mut VecDeque<StockPrice>, capacity: usize) -> u64 {
+    let excess = buffer.len().saturating_sub(capacity);
+    if excess > 0 {
+        buffer.drain(..excess);
+        metrics().writer_dropped(excess as u64);
+    }
+    excess as u64
+}
+
+/// `BASE_RETRY_DELAY * 2^(failures - 1)`, capped at `MAX_RETRY_DELAY`.
+fn backoff(failures: u32) -> Duration {
+    BASE_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(failures - 1))
+        .min(MAX_RETRY_DELAY)
+}
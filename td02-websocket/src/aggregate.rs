@@ -0,0 +1,137 @@
+//! Rolling per-symbol aggregates for `ws_dashboard`: every `--aggregate-every` (`10s`,
+//! `0s` turns them off) each symbol priced within the last `--aggregate-window` (`60s`)
+//! gets an `aggregate` message with the average across sources and the spread between them.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use clap::Args;
+use log::debug;
+use market_core::PriceUpdate;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{interval, Duration, Instant, MissedTickBehavior};
+
+use crate::args;
+use crate::protocol::{Aggregate, ServerMessage};
+
+const DEFAULT_EVERY: Duration = Duration::from_secs(10);
+const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct AggregateArgs {
+    /// Time between two rounds of aggregates, 0s for none [default: 10s]
+    #[arg(long, value_parser = args::duration)]
+    pub aggregate_every: Option<Duration>,
+
+    /// Span of the prices an aggregate averages [default: 60s]
+    #[arg(long, value_parser = args::nonzero_duration)]
+    pub aggregate_window: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AggregateConfig {
+    /// `None` sends no aggregates.
+    pub every: Option<Duration>,
+    pub window: Duration,
+}
+
+impl Default for AggregateConfig {
+    fn default() -> Self {
+        Self {
+            every: Some(DEFAULT_EVERY),
+            window: DEFAULT_WINDOW,
+        }
+    }
+}
+
+impl AggregateConfig {
+    pub fn from_args(args: &AggregateArgs) -> Self {
+        let every = args.aggregate_every.unwrap_or(DEFAULT_EVERY);
+        Self {
+            every: (!every.is_zero()).then_some(every),
+            window: args.aggregate_window.unwrap_or(DEFAULT_WINDOW),
+        }
+    }
+}
+
+/// Prices of one symbol received within the window, oldest first.
+#[derive(Default)]
+struct Window(VecDeque<(Instant, String, f64)>);
+
+impl Window {
+    fn prune(&mut self, window: Duration) {
+        while self
+            .0
+            .front()
+            .is_some_and(|(received, _, _)| received.elapsed() > window)
+        {
+            self.0.pop_front();
+        }
+    }
+
+    /// Each source's average over the window, then their average and range.
+    fn aggregate(&self, symbol: &str, window: Duration) -> Option<Aggregate> {
+        let mut per_source: BTreeMap<&str, (f64, usize)> = BTreeMap::new();
+        for (_, source, price) in &self.0 {
+            let (sum, count) = per_source.entry(source).or_default();
+            *sum += price;
+            *count += 1;
+        }
+        let means: Vec<f64> = per_source
+            .values()
+            .map(|(sum, count)| sum / *count as f64)
+            .collect();
+        if means.is_empty() {
+            return None;
+        }
+        let max = means.iter().copied().fold(f64::MIN, f64::max);
+        let min = means.iter().copied().fold(f64::MAX, f64::min);
+        Some(Aggregate {
+            symbol: symbol.to_string(),
+            avg: means.iter().sum::<f64>() / means.len() as f64,
+            // A single source has nothing to be apart from
+            spread: (means.len() > 1).then_some(max - min),
+            sources: means.len(),
+            window_secs: window.as_secs(),
+        })
+    }
+}
+
+/// Reads the prices from `rx` and sends the aggregates to `tx` until the price channel
+/// closes.
+pub async fn aggregate(
+    window_length: Duration,
+    every: Duration,
+    mut rx: broadcast::Receiver<PriceUpdate>,
+    tx: broadcast::Sender<ServerMessage>,
+) {
+    let mut windows: BTreeMap<String, Window> = BTreeMap::new();
+    let mut ticker = interval(every);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            update = rx.recv() => match update {
+                Ok(price) => windows
+                    .entry(price.symbol)
+                    .or_default()
+                    .0
+                    .push_back((Instant::now(), price.source, price.price)),
+                // Only costs accuracy until the missed prices leave the window
+                Err(RecvError::Lagged(missed)) => debug!("Aggregates missed {missed} prices"),
+                Err(RecvError::Closed) => return,
+            },
+            _ = ticker.tick() => {
+                windows.retain(|_, window| {
+                    window.prune(window_length);
+                    !window.0.is_empty()
+                });
+                for (symbol, window) in &windows {
+                    if let Some(aggregate) = window.aggregate(symbol, window_length) {
+                        let _ = tx.send(ServerMessage::Aggregate(aggregate));
+                    }
+                }
+            }
+        }
+    }
+}
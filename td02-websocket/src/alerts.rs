@@ -0,0 +1,282 @@
+//! Threshold alerts pushed to every `ws_dashboard` client as `alert` messages: a symbol
+//! moving more than `move_pct` percent within `window`, or its sources more than
+//! `spread_pct` percent apart. Rules come from a TOML file (`--alerts alerts.toml`, else
+//! `WS_ALERTS`):
+//!
+//! ```toml
+//! [[rule]]
+//! name = "big move"      # optional, "rule N" otherwise
+//! symbols = ["AAPL"]     # optional, every symbol otherwise
+//! move_pct = 2.0
+//! window = "5m"
+//! cooldown = "10m"       # per rule and symbol, 5m by default
+//!
+//! [[rule]]
+//! spread_pct = 0.5
+//! ```
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use log::{debug, warn};
+use market_core::PriceUpdate;
+use serde::{Deserialize, Deserializer};
+
+use crate::protocol::{Alert, AlertKind};
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct AlertsArgs {
+    /// TOML file of alert rules [default: WS_ALERTS, else none]
+    #[arg(long, value_name = "FILE")]
+    pub alerts: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertsConfig {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<AlertRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    pub name: Option<String>,
+    /// Empty for every symbol.
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    pub move_pct: Option<f64>,
+    #[serde(default = "default_window", deserialize_with = "duration")]
+    pub window: Duration,
+    pub spread_pct: Option<f64>,
+    #[serde(default = "default_cooldown", deserialize_with = "duration")]
+    pub cooldown: Duration,
+}
+
+fn default_window() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+fn default_cooldown() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+/// `"5m"`, `"30s"`...
+fn duration<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    let raw = String::deserialize(d)?;
+    humantime::parse_duration(&raw).map_err(serde::de::Error::custom)
+}
+
+impl AlertsConfig {
+    /// `--alerts FILE`, else `WS_ALERTS`; no rules without either.
+    pub fn from_args(args: &AlertsArgs) -> Result<Self, String> {
+        let path = args.alerts.clone().or_else(|| {
+            std::env::var("WS_ALERTS")
+                .ok()
+                .filter(|path| !path.trim().is_empty())
+                .map(PathBuf::from)
+        });
+        match path {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut cfg: Self =
+            toml::from_str(&content).map_err(|e| format!("{}: {e}", path.display()))?;
+        for (i, rule) in cfg.rules.iter_mut().enumerate() {
+            let name = rule.name.get_or_insert_with(|| format!("rule {}", i + 1));
+            if rule.move_pct.is_none() && rule.spread_pct.is_none() {
+                return Err(format!(
+                    "{}: {name} needs move_pct or spread_pct",
+                    path.display()
+                ));
+            }
+            if [rule.move_pct, rule.spread_pct]
+                .into_iter()
+                .flatten()
+                .any(|pct| !pct.is_finite() || pct <= 0.0)
+            {
+                return Err(format!(
+                    "{}: {name} thresholds must be positive percentages",
+                    path.display()
+                ));
+            }
+            for symbol in &mut rule.symbols {
+                *symbol = symbol.trim().to_uppercase();
+            }
+        }
+        Ok(cfg)
+    }
+}
+
+impl AlertRule {
+    fn applies_to(&self, symbol: &str) -> bool {
+        self.symbols.is_empty() || self.symbols.iter().any(|s| s == symbol)
+    }
+
+    fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or_default()
+    }
+}
+
+/// `(quote time, price)`, oldest first.
+type Samples = VecDeque<(DateTime<Utc>, f64)>;
+
+/// Recent prices per symbol and source, checked against the rules as each update is
+/// published. Owned by the task publishing prices, so it takes no lock.
+pub struct Alerts {
+    rules: Vec<AlertRule>,
+    /// Trimmed to the longest move window.
+    samples: HashMap<(String, String), Samples>,
+    /// Last price of each source per symbol, for the spread.
+    latest: HashMap<String, BTreeMap<String, f64>>,
+    longest_window: chrono::Duration,
+    /// Last alert per (rule, symbol).
+    last_alert: HashMap<(usize, String), DateTime<Utc>>,
+}
+
+impl Alerts {
+    pub fn new(cfg: AlertsConfig) -> Self {
+        let longest_window = cfg
+            .rules
+            .iter()
+            .filter(|rule| rule.move_pct.is_some())
+            .map(|rule| rule.window)
+            .max()
+            .unwrap_or_default();
+        Self {
+            rules: cfg.rules,
+            samples: HashMap::new(),
+            latest: HashMap::new(),
+            longest_window: chrono::Duration::from_std(longest_window)
+                .unwrap_or(chrono::Duration::MAX),
+            last_alert: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The alerts `update` sets off, already logged.
+    pub fn check(&mut self, update: &PriceUpdate) -> Vec<Alert> {
+        if self.rules.is_empty() {
+            return Vec::new();
+        }
+        let samples = self
+            .samples
+            .entry((update.symbol.clone(), update.source.clone()))
+            .or_default();
+        while samples
+            .front()
+            .is_some_and(|(at, _)| update.timestamp - *at > self.longest_window)
+        {
+            samples.pop_front();
+        }
+        samples.push_back((update.timestamp, update.price));
+        let sources = self.latest.entry(update.symbol.clone()).or_default();
+        sources.insert(update.source.clone(), update.price);
+
+        let mut alerts = Vec::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            if !rule.applies_to(&update.symbol) {
+                continue;
+            }
+            let mut found = Vec::new();
+
+            if let Some(threshold) = rule.move_pct {
+                let window =
+                    chrono::Duration::from_std(rule.window).unwrap_or(chrono::Duration::MAX);
+                // Oldest price of this source still within the rule's window
+                if let Some(&(since, reference)) = samples
+                    .iter()
+                    .find(|(at, _)| update.timestamp - *at <= window)
+                    .filter(|(_, reference)| *reference > 0.0)
+                {
+                    let change = (update.price - reference) / reference * 100.0;
+                    if change.abs() >= threshold {
+                        found.push((
+                            AlertKind::Move,
+                            change,
+                            threshold,
+                            format!(
+                                "{} {change:+.2}% ({reference:.2} -> {:.2}) on {} since {}",
+                                update.symbol,
+                                update.price,
+                                update.source,
+                                since.to_rfc3339()
+                            ),
+                        ));
+                    }
+                }
+            }
+
+            if let Some(threshold) = rule.spread_pct {
+                if sources.len() > 1 {
+                    let max = sources.values().copied().fold(f64::MIN, f64::max);
+                    let min = sources.values().copied().fold(f64::MAX, f64::min);
+                    let avg = sources.values().sum::<f64>() / sources.len() as f64;
+                    let spread = if avg > 0.0 {
+                        (max - min) / avg * 100.0
+                    } else {
+                        0.0
+                    };
+                    if spread >= threshold {
+                        found.push((
+                            AlertKind::Spread,
+                            spread,
+                            threshold,
+                            format!(
+                                "{} sources {spread:.2}% apart ({min:.2} to {max:.2} across {})",
+                                update.symbol,
+                                sources.len()
+                            ),
+                        ));
+                    }
+                }
+            }
+
+            for (kind, value_pct, threshold_pct, message) in found {
+                let now = Utc::now();
+                let key = (i, update.symbol.clone());
+                let cooldown =
+                    chrono::Duration::from_std(rule.cooldown).unwrap_or(chrono::Duration::MAX);
+                if self
+                    .last_alert
+                    .get(&key)
+                    .is_some_and(|last| now - *last < cooldown)
+                {
+                    debug!(
+                        "Alert '{}' on {} held back by its cooldown",
+                        rule.name(),
+                        update.symbol
+                    );
+                    continue;
+                }
+                self.last_alert.insert(key, now);
+                warn!("Alert '{}': {message}", rule.name());
+                alerts.push(Alert {
+                    kind,
+                    rule: rule.name().to_string(),
+                    symbol: update.symbol.clone(),
+                    value_pct,
+                    threshold_pct,
+                    message,
+                    timestamp: now,
+                });
+            }
+        }
+        alerts
+    }
+}
@@ -0,0 +1,28 @@
+//! Value parsers shared by the command-line options of the binaries, for clap's
+//! `value_parser`. Options are declared next to what they configure, as `Args` structs the
+//! binaries flatten.
+
+use std::time::Duration;
+
+/// `5s`, `500ms`, `10m`..., zero included.
+pub fn duration(raw: &str) -> Result<Duration, String> {
+    humantime::parse_duration(raw.trim())
+        .map_err(|_| "expected a duration such as 5s, 500ms or 10m".to_string())
+}
+
+/// A duration above zero.
+pub fn nonzero_duration(raw: &str) -> Result<Duration, String> {
+    match duration(raw)? {
+        duration if duration.is_zero() => Err("expected a duration above zero".to_string()),
+        duration => Ok(duration),
+    }
+}
+
+/// A whole number above zero.
+pub fn positive(raw: &str) -> Result<usize, String> {
+    raw.trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| "expected a positive number".to_string())
+}
@@ -0,0 +1,218 @@
+//! Optional shared-token auth (`WS_AUTH_TOKEN`). The client gives the token on the upgrade
+//! URL (`ws://host:port/?token=...`) or, from clients that can't set it there, as its first
+//! message: `{"action":"auth","token":"..."}` within `AUTH_DEADLINE`. The JSON endpoints
+//! of the HTTP side take it on the URL only.
+
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use log::{error, info, warn};
+use subtle::ConstantTimeEq;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{accept_hdr_async, WebSocketStream};
+
+use crate::connections::Peer;
+use crate::protocol::{self, ClientCommand, Format, ServerMessage};
+use crate::throttle;
+use crate::ClientConfig;
+
+/// How long a client without a token on the URL has to send the auth message.
+pub const AUTH_DEADLINE: Duration = Duration::from_secs(5);
+
+/// `WS_AUTH_TOKEN`, when set and not blank.
+pub fn token_from_env() -> Option<String> {
+    non_blank_env("WS_AUTH_TOKEN")
+}
+
+/// `WS_ADMIN_TOKEN`, which unlocks the connection list of `stats`, `admin_list`, `admin_kick`
+/// and `announce`.
+pub fn admin_token_from_env() -> Option<String> {
+    non_blank_env("WS_ADMIN_TOKEN")
+}
+
+fn non_blank_env(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .filter(|token| !token.trim().is_empty())
+}
+
+/// A client past the handshake, with what it asked for on the upgrade URL.
+pub struct Upgraded {
+    pub ws: WebSocketStream<TcpStream>,
+    /// `?format=msgpack`, JSON by default.
+    pub format: Format,
+    pub path: String,
+    /// `?throttle=2`, price updates per second and per symbol and source.
+    pub throttle: Option<f64>,
+}
+
+/// WebSocket handshake, refused with a 404 for paths `client.routes` doesn't know and a 403
+/// for origins `client.origins` doesn't allow, then the token check when `client.auth_token`
+/// is set. `None` when the client is gone or was turned away; the reason is logged with the
+/// peer. A format or throttle the URL gets wrong is logged and ignored.
+pub async fn handshake(stream: TcpStream, peer: Peer, client: &ClientConfig) -> Option<Upgraded> {
+    let token = client.auth_token.as_deref();
+    let origins = &client.origins;
+    let mut url_token = None;
+    let mut url_format = None;
+    let mut url_throttle = None;
+    let mut path = String::new();
+    let mut refused = None;
+    // The error type is tungstenite's, not ours to shrink
+    #[allow(clippy::result_large_err)]
+    let callback = |request: &Request, response: Response| {
+        path = request.uri().path().to_string();
+        if let Err(not_found) = client.routes.check(request) {
+            refused = Some(format!("no WebSocket on {path}"));
+            return Err(not_found);
+        }
+        if let Err(origin) = origins.check(request) {
+            refused = Some(format!("origin {origin} not allowed"));
+            let mut forbidden = ErrorResponse::new(Some("Origin not allowed\n".to_string()));
+            *forbidden.status_mut() = StatusCode::FORBIDDEN;
+            return Err(forbidden);
+        }
+        let query = request.uri().query();
+        url_token = query.and_then(|query| query_param(query, "token"));
+        url_format = query.and_then(|query| query_param(query, "format"));
+        url_throttle = query.and_then(|query| query_param(query, "throttle"));
+        Ok(response)
+    };
+    let mut ws = match accept_hdr_async(stream, callback).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            match refused {
+                Some(reason) => warn!("Refused {peer}: {reason}"),
+                None => error!("WebSocket handshake failed for {peer}: {e}"),
+            }
+            return None;
+        }
+    };
+    let format = match url_format {
+        Some(format) => Format::parse(&format).unwrap_or_else(|| {
+            warn!("Unknown format '{format}' asked by {peer}, sending JSON");
+            Format::Json
+        }),
+        None => Format::Json,
+    };
+    let throttle = url_throttle.and_then(|raw| {
+        let rate = raw
+            .parse::<f64>()
+            .map_err(|_| format!("expected a number, got '{raw}'"))
+            .and_then(throttle::parse_rate);
+        rate.unwrap_or_else(|e| {
+            warn!("Bad throttle asked by {peer}, not throttling: {e}");
+            None
+        })
+    });
+    let upgraded = |ws| Upgraded {
+        ws,
+        format,
+        path,
+        throttle,
+    };
+    let Some(expected) = token else {
+        return Some(upgraded(ws));
+    };
+
+    let (given, method) = match url_token {
+        Some(given) => (Some(given), "url"),
+        None => (first_message_token(&mut ws).await, "message"),
+    };
+    match given {
+        Some(given) if matches(&given, expected) => {
+            info!("Client {peer} authenticated ({method})");
+            Some(upgraded(ws))
+        }
+        given => {
+            let reason = if given.is_some() {
+                "wrong token"
+            } else {
+                "no token"
+            };
+            warn!("Authentication failed for {peer}: {reason} ({method})");
+            let _ = ws
+                .send(ServerMessage::error("unauthorized").encode(format, client.layout))
+                .await;
+            let _ = ws
+                .send(protocol::close(CloseCode::Policy, "unauthorized"))
+                .await;
+            None
+        }
+    }
+}
+
+/// Whether `query` carries `token=expected`, for plain HTTP requests.
+pub fn query_authorized(query: Option<&str>, expected: &str) -> bool {
+    query
+        .and_then(|query| query_param(query, "token"))
+        .is_some_and(|given| matches(&given, expected))
+}
+
+/// Whether a `stats` command carries the admin token; never without one configured.
+pub fn is_admin(given: Option<&str>, expected: Option<&str>) -> bool {
+    matches!((given, expected), (Some(given), Some(expected)) if matches(given, expected))
+}
+
+/// The token of an `auth` command sent within the deadline. Pings may come first.
+async fn first_message_token(ws: &mut WebSocketStream<TcpStream>) -> Option<String> {
+    let first_text = async {
+        while let Some(Ok(message)) = ws.next().await {
+            match message {
+                Message::Text(text) => return Some(text),
+                Message::Close(_) => return None,
+                _ => {}
+            }
+        }
+        None
+    };
+    let text = timeout(AUTH_DEADLINE, first_text).await.ok()??;
+    match ClientCommand::parse(&text) {
+        Ok(ClientCommand::Auth { token }) => Some(token),
+        _ => None,
+    }
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| percent_decode(value))
+}
+
+/// `%XX` escapes and `+`, as browsers encode query values.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Constant time for tokens of the same length; only the length can leak.
+fn matches(given: &str, expected: &str) -> bool {
+    given.as_bytes().ct_eq(expected.as_bytes()).into()
+}
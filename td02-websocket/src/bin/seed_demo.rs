@@ -1,40 +1,53 @@
 use chrono::Utc;
+use clap::Parser;
 use dotenvy::dotenv;
+use market_core::store::{self, PoolOptions};
+use market_core::StockPrice;
 use rand::Rng;
-use sqlx::postgres::PgPoolOptions;
+use td02_websocket::cli::DatabaseArgs;
+
+#[derive(Parser, Debug)]
+#[command(about = "One random price per demo symbol and source")]
+struct Cli {
+    #[command(flatten)]
+    database: DatabaseArgs,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Cli::parse();
     dotenv().ok();
     dotenvy::from_filename("td01-basics/.env").ok();
 
-    let database_url =
-        std::env::var("DATABASE_URL").expect("DATABASE_URL must be set in .env or environment");
-    let pool = PgPoolOptions::new()
-        .max_connections(3)
-        .connect(&database_url)
-        .await?;
+    // Schema lives in migrations/ at the workspace root
+    let pool = PoolOptions {
+        max_connections: 3,
+        ..PoolOptions::default()
+    };
+    let store = store::connect(
+        &args.database.url(),
+        &pool.with_env()?,
+        args.database.migrate(),
+    )
+    .await?;
 
     let symbols = ["AAPL", "GOOGL", "MSFT"];
     let sources = ["alpha_vantage", "finnhub"];
     let mut rng = rand::thread_rng();
-    let now = Utc::now().timestamp();
+    let now = Utc::now();
 
     for symbol in symbols {
         for source in sources {
-            let price: f32 = rng.gen_range(120.0..220.0) as f32;
-            sqlx::query!(
-                r#"
-                INSERT INTO stock_prices (symbol, price, source, timestamp)
-                VALUES ($1, $2, $3, $4)
-                "#,
-                symbol,
-                price,
-                source,
-                now
-            )
-            .execute(&pool)
-            .await?;
+            let price: f64 = rng.gen_range(120.0..220.0);
+            store
+                .save(&StockPrice {
+                    symbol: symbol.to_string(),
+                    price,
+                    source: source.to_string(),
+                    timestamp: now,
+                    ..Default::default()
+                })
+                .await?;
             println!("Seeded {symbol} from {source} at ${price:.2}");
         }
     }
@@ -1,58 +1,171 @@
+//! Inserts simulated quotes into stock_prices on a timer, for `ws_dashboard` without API
+//! keys. Prices follow the random walks of `ws_broadcast`'s simulator: `--symbols AAPL,TSLA`,
+//! `--sources a,b`, `--period 3s` (or `SEED_PERIOD_SECS`), `--volatility X` (0.002),
+//! `--spread X` (0.001) and `--seed N` for the same prices on every run.
+//!
+//! Each tick is one batch insert in a transaction. After a failed tick the next waits
+//! twice as long (up to a minute); `--max-failures N` (10) failed ticks in a row exit
+//! non-zero. Ctrl+C or SIGTERM lets the tick in flight finish, prints a summary and closes
+//! the pool.
+
+use std::time::Instant;
+
 use chrono::Utc;
+use clap::Parser;
 use dotenvy::dotenv;
-use rand::Rng;
-use sqlx::postgres::PgPoolOptions;
-use tokio::time::{sleep, Duration};
+use market_core::store::{self, PoolOptions};
+use market_core::{PriceUpdate, StockPrice};
+use td02_websocket::args;
+use td02_websocket::cli::DatabaseArgs;
+use td02_websocket::shutdown::ShutdownSignal;
+use td02_websocket::simulator::{Market, SimulatorArgs, SimulatorConfig};
+use tokio::time::{interval, sleep, Duration, MissedTickBehavior};
+
+const DEFAULT_PERIOD: Duration = Duration::from_secs(3);
+/// Longest wait after failed ticks.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Parser, Debug)]
+#[command(about = "Simulated quotes into stock_prices on a timer")]
+struct Cli {
+    /// Time between two ticks (e.g. 500ms) [default: SEED_PERIOD_SECS, else 3s]
+    #[arg(long, value_parser = args::nonzero_duration)]
+    period: Option<Duration>,
+
+    /// Failed ticks in a row before giving up
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    max_failures: u32,
+
+    #[command(flatten)]
+    simulator: SimulatorArgs,
+
+    #[command(flatten)]
+    database: DatabaseArgs,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Cli::parse();
     dotenv().ok();
     dotenvy::from_filename("td01-basics/.env").ok();
 
-    let database_url = std::env::var("DATABASE_URL")
-        .expect("DATABASE_URL must be set in .env or environment");
-    let pool = PgPoolOptions::new()
-        .max_connections(3)
-        .connect(&database_url)
-        .await?;
-
-    let symbols = ["AAPL", "GOOGL", "MSFT"];
-    let sources = ["alpha_vantage", "finnhub"];
-    let mut rng = rand::thread_rng();
-    let period = std::env::var("SEED_PERIOD_SECS")
-        .ok()
-        .and_then(|v| v.parse::<u64>().ok())
-        .unwrap_or(3);
+    let mut cfg = SimulatorConfig::from_args(&args.simulator)?;
+    if cfg.market_hours.is_some() {
+        return Err("--market-hours is only supported by ws_broadcast".into());
+    }
+    cfg.tick = args.period.unwrap_or_else(period_from_env);
+    let max_failures = args.max_failures;
+    let mut signal = ShutdownSignal::new()?;
+
+    // Schema lives in migrations/ at the workspace root
+    let pool = PoolOptions {
+        max_connections: 3,
+        ..PoolOptions::default()
+    };
+    let store = store::connect(
+        &args.database.url(),
+        &pool.with_env()?,
+        args.database.migrate(),
+    )
+    .await?;
 
     println!(
-        "Seeding stream every {period}s into stock_prices (symbols: {:?}, sources: {:?})",
-        symbols, sources
+        "Seeding stream every {} into stock_prices (symbols: {}, sources: {}, volatility {}, spread {}, seed {})",
+        humantime::format_duration(cfg.tick),
+        cfg.symbols.join(","),
+        cfg.sources.join(","),
+        cfg.volatility,
+        cfg.spread,
+        cfg.seed.map_or("random".to_string(), |seed| seed.to_string()),
     );
 
-    loop {
-        let now = Utc::now().timestamp();
-        for symbol in symbols {
-            for source in sources {
-                let price: f32 = rng.gen_range(120.0..220.0) as f32;
-                if let Err(e) = sqlx::query!(
-                    r#"
-                    INSERT INTO stock_prices (symbol, price, source, timestamp)
-                    VALUES ($1, $2, $3, $4)
-                    "#,
-                    symbol,
-                    price,
-                    source,
-                    now
-                )
-                .execute(&pool)
-                .await
-                {
-                    eprintln!("Insert failed for {symbol}/{source}: {e}");
-                } else {
-                    println!("Inserted {symbol} from {source} at ${price:.2}");
+    let period = cfg.tick;
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut market = Market::new(cfg, Utc::now());
+    let started = Instant::now();
+    let mut inserted = 0;
+    let mut failures = 0;
+
+    let outcome = loop {
+        // Signals are only looked at between ticks, so the one in flight always finishes
+        tokio::select! {
+            _ = ticker.tick() => {}
+            name = signal.recv() => {
+                println!("{name} received, stopping");
+                break Ok(());
+            }
+        }
+
+        let rows: Vec<StockPrice> = market
+            .tick(Utc::now())
+            .into_iter()
+            .map(stock_price)
+            .collect();
+        match store.save_batch(&rows).await {
+            Ok(count) => {
+                failures = 0;
+                inserted += count;
+                for row in &rows {
+                    println!(
+                        "Inserted {} from {} at ${:.2}",
+                        row.symbol, row.source, row.price
+                    );
+                }
+            }
+            Err(e) => {
+                failures += 1;
+                eprintln!(
+                    "Insert of {} rows failed ({failures} in a row): {e}",
+                    rows.len()
+                );
+                if failures >= max_failures {
+                    break Err(format!(
+                        "{failures} ticks in a row failed to insert, giving up"
+                    ));
+                }
+                let backoff = period
+                    .saturating_mul(1 << failures.min(16))
+                    .min(MAX_BACKOFF);
+                eprintln!("Retrying in {}", humantime::format_duration(backoff));
+                tokio::select! {
+                    _ = sleep(backoff) => ticker.reset_immediately(),
+                    name = signal.recv() => {
+                        println!("{name} received, stopping");
+                        break Ok(());
+                    }
                 }
             }
         }
-        sleep(Duration::from_secs(period)).await;
+    };
+
+    println!(
+        "Inserted {inserted} rows in {}",
+        humantime::format_duration(Duration::from_secs(started.elapsed().as_secs()))
+    );
+    store.close().await;
+    Ok(outcome?)
+}
+
+/// `SEED_PERIOD_SECS`, else 3 seconds.
+fn period_from_env() -> Duration {
+    std::env::var("SEED_PERIOD_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .map_or(DEFAULT_PERIOD, Duration::from_secs)
+}
+
+/// The stored part of a simulated quote; bid, ask and volume have no column.
+fn stock_price(quote: PriceUpdate) -> StockPrice {
+    StockPrice {
+        symbol: quote.symbol,
+        price: quote.price,
+        source: quote.source,
+        timestamp: quote.timestamp,
+        open: quote.open,
+        high: quote.high,
+        low: quote.low,
+        prev_close: quote.prev_close,
+        ..Default::default()
     }
 }
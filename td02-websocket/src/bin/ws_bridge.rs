@@ -0,0 +1,48 @@
+use clap::Parser;
+use log::info;
+use market_core::store::{self, PoolOptions};
+use td02_websocket::bridge::{self, BridgeArgs, BridgeConfig};
+use td02_websocket::cli::{self, DatabaseArgs};
+use td02_websocket::shutdown::ShutdownSignal;
+use tokio::sync::watch;
+
+#[derive(Parser, Debug)]
+#[command(about = "Live Binance prices into stock_prices")]
+struct Cli {
+    #[command(flatten)]
+    bridge: BridgeArgs,
+
+    #[command(flatten)]
+    database: DatabaseArgs,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Cli::parse();
+    cli::init();
+
+    let cfg = BridgeConfig::from_args(&args.bridge)?;
+    let mut signal = ShutdownSignal::new()?;
+
+    // Schema lives in migrations/ at the workspace root
+    let pool = PoolOptions {
+        max_connections: 2,
+        ..PoolOptions::default()
+    };
+    let store = store::connect(
+        &args.database.url(),
+        &pool.with_env()?,
+        args.database.migrate(),
+    )
+    .await?;
+    info!("Connected to {} database", store.backend());
+
+    let (stop, shutdown) = watch::channel(false);
+    let bridge = tokio::spawn(bridge::run(cfg, store.clone(), shutdown));
+    let name = signal.recv().await;
+    info!("{name} received, writing the last prices");
+    let _ = stop.send(true);
+    bridge.await?;
+    store.close().await;
+    Ok(())
+}
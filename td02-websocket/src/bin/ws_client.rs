@@ -0,0 +1,559 @@
+//! Command-line client for the price feeds: `ws_client [URL] [--symbols AAPL,TSLA] [--json]
+//! [--stats] [--announce MESSAGE [--level warning]] [--record feed.jsonl] [--reconnect]`. Prints one aligned row per price; exits non-zero when the connection fails or
+//! the server closes it for any reason but a normal close or shutdown, so scripts can use it
+//! as a smoke test. With `--reconnect` it keeps trying instead, until Ctrl+C.
+//!
+//! `--latency [--ping-every 1s] [--pings 20]` prints round trips instead of prices, like
+//! `ping`: one line per `pong`, then at Ctrl+C or after the last one the RTT percentiles, the
+//! server's clock offset and the delivery delay of the prices received meanwhile.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use chrono::Utc;
+use clap::Parser;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use market_core::PriceUpdate;
+use td02_websocket::args;
+use td02_websocket::latency::{percentiles, sorted, Pinger};
+use td02_websocket::protocol::{self, AnnouncementLevel, ClientCommand, ServerMessage};
+use td02_websocket::reconnect::{ConnectionState, FeedEvent, ReconnectConfig, ResilientClient};
+use td02_websocket::recording::Recorder;
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio::time::{sleep_until, timeout, Duration, Instant};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+type Ws = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const DEFAULT_URL: &str = "ws://127.0.0.1:8082";
+
+/// How long `--stats` and `--announce` wait for their reply, and Ctrl+C for the server's Close.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const BOLD: &str = "\x1b[1m";
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Parser, Debug)]
+#[command(about = "Command-line client for the price feeds")]
+struct Args {
+    /// Server to connect to
+    #[arg(default_value = DEFAULT_URL)]
+    url: String,
+
+    /// Only these symbols, comma separated [default: all of them]
+    #[arg(long, value_delimiter = ',')]
+    symbols: Vec<String>,
+
+    /// Print the frames as received
+    #[arg(long)]
+    json: bool,
+
+    /// Print the server's stats and exit
+    #[arg(long)]
+    stats: bool,
+
+    /// Send an announcement to every client (admin token from WS_ADMIN_TOKEN) and exit
+    #[arg(long, value_name = "MESSAGE")]
+    announce: Option<String>,
+
+    /// Level of the --announce message
+    #[arg(long, value_enum, default_value_t = AnnouncementLevel::Info, requires = "announce")]
+    level: AnnouncementLevel,
+
+    /// Also write every frame received to this JSON lines file
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+
+    /// Connect again whenever the connection drops, until Ctrl+C
+    #[arg(long)]
+    reconnect: bool,
+
+    /// Print round trips instead of prices
+    #[arg(long)]
+    latency: bool,
+
+    /// Time between two pings of --latency
+    #[arg(long, default_value = "1s", value_parser = args::nonzero_duration)]
+    ping_every: Duration,
+
+    /// Pings --latency sends before it stops [default: until Ctrl+C]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pings: Option<u64>,
+}
+
+/// The arguments, symbols uppercase.
+fn args() -> Args {
+    let mut args = Args::parse();
+    args.symbols = args
+        .symbols
+        .iter()
+        .map(|symbol| symbol.trim().to_uppercase())
+        .filter(|symbol| !symbol.is_empty())
+        .collect();
+    args
+}
+
+/// Turns server frames into output: raw with `--json`, else one row per price, colored
+/// when stdout is a terminal and `NO_COLOR` is unset. Notices go to stderr.
+struct Printer {
+    json: bool,
+    color: bool,
+    /// Last price per (symbol, source), to color moves up and down.
+    last: HashMap<(String, String), f64>,
+}
+
+impl Printer {
+    fn new(json: bool) -> Self {
+        Self {
+            json,
+            color: std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            last: HashMap::new(),
+        }
+    }
+
+    fn message(&mut self, text: &str) {
+        if self.json {
+            println!("{text}");
+            return;
+        }
+        match serde_json::from_str::<ServerMessage>(text) {
+            Ok(ServerMessage::Price(price)) => self.price(&price),
+            Ok(ServerMessage::Snapshot { prices, stale }) => {
+                for price in &prices {
+                    self.price(price);
+                }
+                for stale in &stale {
+                    let line = format!(
+                        "No price of {} from {} for {}s",
+                        stale.symbol, stale.source, stale.age_secs
+                    );
+                    eprintln!("{}", self.paint(RED, line));
+                }
+            }
+            Ok(ServerMessage::Aggregate(aggregate)) => {
+                let spread = aggregate
+                    .spread
+                    .map_or("-".to_string(), |spread| format!("{spread:.2}"));
+                let line = format!(
+                    "{:<8} {:>12.2}  avg of {} sources, spread {spread} over {}s",
+                    aggregate.symbol, aggregate.avg, aggregate.sources, aggregate.window_secs
+                );
+                println!("{}", self.paint(DIM, line));
+            }
+            Ok(ServerMessage::Candle(candle)) => {
+                let line = format!(
+                    "{:<8} {:>12.2}  candle {} O {:.2} H {:.2} L {:.2}",
+                    candle.symbol,
+                    candle.close,
+                    candle.start.format("%H:%M"),
+                    candle.open,
+                    candle.high,
+                    candle.low
+                );
+                println!("{}", self.paint(DIM, line));
+            }
+            // Only worth a line when something is wrong
+            Ok(ServerMessage::Status { db_ok, .. }) => {
+                if !db_ok {
+                    eprintln!("Server reports its database unavailable")
+                }
+            }
+            Ok(ServerMessage::Alert(alert)) => {
+                eprintln!("Alert '{}': {}", alert.rule, alert.message)
+            }
+            Ok(ServerMessage::Stale(stale)) => {
+                let line = format!(
+                    "No price of {} from {} for {}s, last at {}",
+                    stale.symbol,
+                    stale.source,
+                    stale.age_secs,
+                    stale.last_update.format("%H:%M:%S")
+                );
+                eprintln!("{}", self.paint(RED, line));
+            }
+            Ok(ServerMessage::Resumed {
+                symbol,
+                source,
+                gap_secs,
+                ..
+            }) => eprintln!("{symbol} from {source} resumed after {gap_secs}s"),
+            Ok(ServerMessage::Announcement(announcement)) => {
+                let line = format!("Announcement: {}", announcement.message);
+                let color = match announcement.level {
+                    AnnouncementLevel::Info => BOLD,
+                    AnnouncementLevel::Warning => RED,
+                };
+                eprintln!("{}", self.paint(color, line));
+            }
+            Ok(ServerMessage::Connected {
+                message,
+                version,
+                connection_id,
+            }) => {
+                match connection_id {
+                    Some(id) => eprintln!("{message} (connection #{id})"),
+                    None => eprintln!("{message}"),
+                }
+                if version != protocol::PROTOCOL_VERSION {
+                    eprintln!(
+                        "Server speaks protocol version {version}, this client version {}",
+                        protocol::PROTOCOL_VERSION
+                    );
+                }
+            }
+            Ok(ServerMessage::Lagged { missed }) => {
+                eprintln!("Fell behind the feed, {missed} updates skipped")
+            }
+            Ok(ServerMessage::Subscription {
+                all,
+                symbols,
+                not_seen,
+                ..
+            }) => {
+                let symbols = if all {
+                    "all symbols".to_string()
+                } else {
+                    symbols.join(", ")
+                };
+                eprintln!("Subscribed to {symbols}");
+                if !not_seen.is_empty() {
+                    eprintln!("No price seen yet for {}", not_seen.join(", "));
+                }
+            }
+            Ok(ServerMessage::Error { message }) => eprintln!("Server error: {message}"),
+            // Replies to commands this client doesn't send, and non-protocol text (ws_echo)
+            _ => println!("{text}"),
+        }
+    }
+
+    fn price(&mut self, price: &PriceUpdate) {
+        let key = (price.symbol.clone(), price.source.clone());
+        let move_color = match self.last.insert(key, price.price) {
+            Some(previous) if price.price > previous => GREEN,
+            Some(previous) if price.price < previous => RED,
+            _ => "",
+        };
+        let age = (Utc::now() - price.timestamp).num_milliseconds().max(0) as f64 / 1000.0;
+        let stale = if price.stale { " stale" } else { "" };
+
+        let symbol = self.paint(BOLD, format!("{:<8}", price.symbol));
+        let value = self.paint(move_color, format!("{:>12.2}", price.price));
+        let age = self.paint(DIM, format!("{:>8}", format!("{age:.1}s")));
+        println!("{symbol} {value}  {:<14} {age}{stale}", price.source);
+    }
+
+    /// `text` in `color`; padding is applied before, so escapes don't break the alignment.
+    fn paint(&self, color: &str, text: String) -> String {
+        if self.color && !color.is_empty() {
+            format!("{color}{text}{RESET}")
+        } else {
+            text
+        }
+    }
+}
+
+async fn send(write: &mut SplitSink<Ws, Message>, command: &ClientCommand) -> Result<(), String> {
+    let text = serde_json::to_string(command).expect("client commands always serialize");
+    write
+        .send(Message::Text(text))
+        .await
+        .map_err(|e| format!("cannot send command: {e}"))
+}
+
+/// A normal close or a server shutdown ends the client cleanly, anything else is a failure.
+fn closed(frame: Option<CloseFrame>) -> Result<(), String> {
+    match frame {
+        Some(frame) if !matches!(frame.code, CloseCode::Normal | CloseCode::Away) => Err(format!(
+            "server closed the connection: {} {}",
+            frame.code, frame.reason
+        )),
+        Some(frame) => {
+            eprintln!("Server closed the connection: {}", frame.reason);
+            Ok(())
+        }
+        None => {
+            eprintln!("Server closed the connection");
+            Ok(())
+        }
+    }
+}
+
+/// The first message `wanted` accepts, as text, skipping the prices that arrive before it.
+/// A server error is returned as the failure.
+async fn reply(
+    read: &mut SplitStream<Ws>,
+    what: &str,
+    wanted: impl Fn(&ServerMessage) -> bool,
+) -> Result<String, String> {
+    let reply = async {
+        while let Some(message) = read.next().await {
+            match message.map_err(|e| format!("connection error: {e}"))? {
+                Message::Text(text) => match serde_json::from_str::<ServerMessage>(&text) {
+                    Ok(message) if wanted(&message) => return Ok(text),
+                    Ok(ServerMessage::Error { message }) => {
+                        return Err(format!("server error: {message}"))
+                    }
+                    _ => {}
+                },
+                Message::Close(frame) => {
+                    closed(frame)?;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        Err(format!("connection closed before the {what} reply"))
+    };
+    timeout(REPLY_TIMEOUT, reply)
+        .await
+        .map_err(|_| format!("no {what} reply within {REPLY_TIMEOUT:?}"))?
+}
+
+/// Sends `stats` and prints the reply.
+async fn stats(read: &mut SplitStream<Ws>, json: bool) -> Result<(), String> {
+    let text = reply(read, "stats", |message| {
+        matches!(message, ServerMessage::Stats { .. })
+    })
+    .await?;
+
+    if json {
+        println!("{text}");
+    } else {
+        let value: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+        println!("{}", serde_json::to_string_pretty(&value).unwrap_or(text));
+    }
+    Ok(())
+}
+
+/// Pings every `every` until Ctrl+C or the `count`-th pong, printing each round trip, then
+/// the summary.
+async fn latency(
+    write: &mut SplitSink<Ws, Message>,
+    read: &mut SplitStream<Ws>,
+    every: Duration,
+    count: Option<u64>,
+) -> Result<(), String> {
+    let mut pinger = Pinger::new(Some(every));
+    let mut rtts = Vec::new();
+    let mut delivery = Vec::new();
+    // Once the last ping is out, how long its pong gets
+    let mut deadline = None;
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        if deadline.is_none() && count.is_some_and(|count| pinger.sent() >= count) {
+            deadline = Some(Instant::now() + REPLY_TIMEOUT);
+        }
+        let message = tokio::select! {
+            message = read.next() => message,
+            _ = pinger.due(), if deadline.is_none() => {
+                send(write, &pinger.ping()).await?;
+                continue;
+            }
+            _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => break,
+            _ = &mut ctrl_c => break,
+        };
+        match message {
+            Some(Ok(Message::Text(text))) => match serde_json::from_str::<ServerMessage>(&text) {
+                Ok(ServerMessage::Price(price)) => delivery.extend(pinger.delivery_ms(&price)),
+                Ok(ServerMessage::Error { message }) => eprintln!("Server error: {message}"),
+                // The first ping once the server is done setting the connection up
+                Ok(ServerMessage::Connected { .. }) if pinger.sent() == 0 => {
+                    send(write, &pinger.ping()).await?
+                }
+                Ok(message) => {
+                    let Some(round_trip) = pinger.pong(&message) else {
+                        continue;
+                    };
+                    let rtt = round_trip.rtt.as_secs_f64() * 1000.0;
+                    rtts.push(rtt);
+                    println!(
+                        "pong {}: rtt {rtt:.2} ms, clock offset {:+.2} ms",
+                        round_trip.nonce, round_trip.offset_ms
+                    );
+                    if count.is_some_and(|count| round_trip.nonce >= count) {
+                        break;
+                    }
+                }
+                Err(_) => {}
+            },
+            Some(Ok(Message::Close(frame))) => {
+                closed(frame)?;
+                break;
+            }
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(format!("connection error: {e}")),
+            None => return Err("connection dropped without a Close frame".to_string()),
+        }
+    }
+
+    let rtts = sorted(rtts);
+    let delivery = sorted(delivery);
+    println!(
+        "{} pings, {} pongs, {} lost",
+        pinger.sent(),
+        rtts.len(),
+        pinger.lost()
+    );
+    println!("rtt ms       {}", percentiles(&rtts));
+    if let Some(offset) = pinger.offset_ms() {
+        println!("offset ms    {offset:+.2} (server clock minus this one)");
+    }
+    println!(
+        "delivery ms  {}  ({} prices)",
+        percentiles(&delivery),
+        delivery.len()
+    );
+    Ok(())
+}
+
+fn recorder(args: &Args) -> Result<Option<Recorder>, String> {
+    args.record
+        .as_ref()
+        .map(|path| {
+            Recorder::create(path).map_err(|e| format!("cannot create {}: {e}", path.display()))
+        })
+        .transpose()
+}
+
+/// Prints the feed through every reconnection, with the connection changes on stderr.
+async fn follow(args: Args) -> Result<(), String> {
+    let mut printer = Printer::new(args.json);
+    let mut recorder = recorder(&args)?;
+    let (shutdown, shutdown_rx) = watch::channel(false);
+    let mut client = ResilientClient::connect(
+        args.url.as_str(),
+        args.symbols,
+        ReconnectConfig::default(),
+        shutdown_rx,
+    );
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        let event = tokio::select! {
+            event = client.next() => event,
+            _ = &mut ctrl_c, if !*shutdown.borrow() => {
+                let _ = shutdown.send(true);
+                continue;
+            }
+        };
+        match event {
+            Some(FeedEvent::Message(message)) => {
+                let text =
+                    serde_json::to_string(&message).expect("server messages always serialize");
+                if let Some(recorder) = &mut recorder {
+                    recorder
+                        .record(&text)
+                        .map_err(|e| format!("cannot record the feed: {e}"))?;
+                }
+                printer.message(&text);
+            }
+            Some(FeedEvent::State(ConnectionState::Connecting { attempt })) if attempt > 1 => {
+                eprintln!("Connecting to {} (attempt {attempt})", args.url)
+            }
+            Some(FeedEvent::State(ConnectionState::Connecting { .. })) => {}
+            Some(FeedEvent::State(ConnectionState::Connected)) => {
+                eprintln!("Connected to {}", args.url)
+            }
+            Some(FeedEvent::State(ConnectionState::Disconnected { reason, retry_in })) => {
+                eprintln!("Disconnected ({reason}), retrying in {retry_in:.1?}")
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args = args();
+    if args.reconnect && !args.stats && args.announce.is_none() && !args.latency {
+        return Ok(follow(args).await?);
+    }
+    let (ws, _) = connect_async(args.url.as_str())
+        .await
+        .map_err(|e| format!("cannot connect to {}: {e}", args.url))?;
+    let (mut write, mut read) = ws.split();
+
+    if !args.symbols.is_empty() {
+        let symbols = args.symbols.clone();
+        send(&mut write, &ClientCommand::Subscribe { symbols }).await?;
+    }
+    if args.stats {
+        let admin_token = std::env::var("WS_ADMIN_TOKEN").ok();
+        send(&mut write, &ClientCommand::Stats { admin_token }).await?;
+        stats(&mut read, args.json).await?;
+        let _ = write.send(protocol::close(CloseCode::Normal, "done")).await;
+        return Ok(());
+    }
+    if let Some(message) = args.announce {
+        let admin_token = std::env::var("WS_ADMIN_TOKEN").ok();
+        let command = ClientCommand::Announce {
+            message,
+            level: args.level,
+            admin_token,
+        };
+        send(&mut write, &command).await?;
+        // Sent to every client, this one included
+        reply(&mut read, "announce", |message| {
+            matches!(message, ServerMessage::Announcement(_))
+        })
+        .await?;
+        eprintln!("Announcement sent");
+        let _ = write.send(protocol::close(CloseCode::Normal, "done")).await;
+        return Ok(());
+    }
+    if args.latency {
+        latency(&mut write, &mut read, args.ping_every, args.pings).await?;
+        let _ = write.send(protocol::close(CloseCode::Normal, "done")).await;
+        return Ok(());
+    }
+
+    let mut printer = Printer::new(args.json);
+    let mut recorder = recorder(&args)?;
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        let message = tokio::select! {
+            message = read.next() => message,
+            _ = &mut ctrl_c => {
+                let _ = write.send(protocol::close(CloseCode::Normal, "client exiting")).await;
+                // Wait for the server's Close, so the connection ends cleanly on both sides
+                let _ = timeout(REPLY_TIMEOUT, async {
+                    while let Some(Ok(message)) = read.next().await {
+                        if matches!(message, Message::Close(_)) {
+                            break;
+                        }
+                    }
+                })
+                .await;
+                return Ok(());
+            }
+        };
+        match message {
+            Some(Ok(Message::Text(text))) => {
+                if let Some(recorder) = &mut recorder {
+                    recorder
+                        .record(&text)
+                        .map_err(|e| format!("cannot record the feed: {e}"))?;
+                }
+                printer.message(&text);
+            }
+            Some(Ok(Message::Close(frame))) => return Ok(closed(frame)?),
+            // Pings are answered by tungstenite
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(format!("connection error: {e}").into()),
+            None => return Err("connection dropped without a Close frame".into()),
+        }
+    }
+}
@@ -0,0 +1,412 @@
+//! Load test for the price feeds: `ws_loadtest [URL] --clients 500 --ramp 10s`. Opens the
+//! clients spread over the ramp, optionally subscribes each to `--subscribe K` random symbols
+//! of `--symbols`, and on Ctrl+C (or after `--duration`) closes them and prints message rates,
+//! latency and failures; `--csv FILE` adds one row per client.
+//!
+//! Latency is measured as by `ws_client --latency`: each client sends a `ping` every
+//! `--ping-every` (1s, `0s` for none) for the round trip time, and the delivery delay of each
+//! price is its receive time minus its `sent_at`, corrected by the clock offset the pings
+//! give; the same on `ws_broadcast` and `ws_dashboard`, whatever the age of the data.
+
+use std::error::Error;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+use clap::Parser;
+use futures_util::{SinkExt, StreamExt};
+use rand::seq::SliceRandom;
+use td02_websocket::args;
+use td02_websocket::latency::{percentile, percentiles, sorted, Pinger};
+use td02_websocket::protocol::{self, ClientCommand, ServerMessage};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, timeout, Duration, Instant};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+
+const DEFAULT_URL: &str = "ws://127.0.0.1:8081";
+const DEFAULT_SYMBOLS: &str = "AAPL,GOOGL,MSFT";
+
+/// How long a client waits for the server's Close once it sent its own.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Parser, Debug)]
+#[command(about = "Load test for the price feeds")]
+struct Args {
+    /// Server to load
+    #[arg(default_value = DEFAULT_URL)]
+    url: String,
+
+    /// Clients to open
+    #[arg(long, default_value_t = 100, value_parser = args::positive)]
+    clients: usize,
+
+    /// Time over which the clients are opened (e.g. 10s) [default: all at once]
+    #[arg(long, default_value = "0s", value_parser = args::duration)]
+    ramp: Duration,
+
+    /// Stop after this long [default: at Ctrl+C]
+    #[arg(long, value_parser = args::duration)]
+    duration: Option<Duration>,
+
+    /// Symbols --subscribe picks from, comma separated
+    #[arg(long, value_delimiter = ',', default_value = DEFAULT_SYMBOLS)]
+    symbols: Vec<String>,
+
+    /// Subscribe each client to this many random symbols [default: all of them]
+    #[arg(long, value_parser = args::positive)]
+    subscribe: Option<usize>,
+
+    /// Time between two pings of each client, 0s for none
+    #[arg(long, default_value = "1s", value_parser = args::duration)]
+    ping_every: Duration,
+
+    /// Also write one row per client to this CSV file
+    #[arg(long, value_name = "FILE")]
+    csv: Option<PathBuf>,
+}
+
+/// The arguments, symbols uppercase.
+fn args() -> Args {
+    let mut args = Args::parse();
+    args.symbols = args
+        .symbols
+        .iter()
+        .map(|symbol| symbol.trim().to_uppercase())
+        .filter(|symbol| !symbol.is_empty())
+        .collect();
+    args
+}
+
+/// How a client's connection ended.
+#[derive(Debug, Clone, PartialEq)]
+enum End {
+    /// Closed by the load test itself.
+    Closed,
+    ConnectFailed(String),
+    /// Close frame from the server, with its code and reason.
+    ServerClose(String),
+    Error(String),
+}
+
+impl End {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::ConnectFailed(_) => "connect_failed",
+            Self::ServerClose(_) => "server_close",
+            Self::Error(_) => "error",
+        }
+    }
+
+    fn detail(&self) -> &str {
+        match self {
+            Self::Closed => "",
+            Self::ConnectFailed(detail) | Self::ServerClose(detail) | Self::Error(detail) => detail,
+        }
+    }
+}
+
+struct ClientReport {
+    id: usize,
+    /// Time spent connected.
+    connected: Duration,
+    messages: u64,
+    /// Milliseconds, one per pong, and one per price with a `sent_at`.
+    rtts: Vec<f64>,
+    delivery: Vec<f64>,
+    /// Pings sent, and those whose pong never came.
+    pings: u64,
+    lost: u64,
+    end: End,
+}
+
+impl ClientReport {
+    fn rate(&self) -> f64 {
+        match self.connected.as_secs_f64() {
+            secs if secs > 0.0 => self.messages as f64 / secs,
+            _ => 0.0,
+        }
+    }
+}
+
+async fn client(
+    id: usize,
+    url: String,
+    subscribe: Option<Vec<String>>,
+    ping_every: Option<Duration>,
+    mut stop: watch::Receiver<bool>,
+) -> ClientReport {
+    let mut report = ClientReport {
+        id,
+        connected: Duration::ZERO,
+        messages: 0,
+        rtts: Vec::new(),
+        delivery: Vec::new(),
+        pings: 0,
+        lost: 0,
+        end: End::Closed,
+    };
+    let ws = tokio::select! {
+        ws = connect_async(url.as_str()) => ws,
+        _ = stop.changed() => return report,
+    };
+    let (mut write, mut read) = match ws {
+        Ok((ws, _)) => ws.split(),
+        Err(e) => {
+            report.end = End::ConnectFailed(e.to_string());
+            return report;
+        }
+    };
+    let started = Instant::now();
+
+    if let Some(symbols) = subscribe {
+        let command = serde_json::to_string(&ClientCommand::Subscribe { symbols })
+            .expect("client commands always serialize");
+        if let Err(e) = write.send(Message::Text(command)).await {
+            report.end = End::Error(e.to_string());
+            return report;
+        }
+    }
+
+    let mut pinger = Pinger::new(ping_every);
+
+    report.end = loop {
+        let message = tokio::select! {
+            message = read.next() => message,
+            _ = pinger.due() => {
+                let ping = serde_json::to_string(&pinger.ping())
+                    .expect("client commands always serialize");
+                if let Err(e) = write.send(Message::Text(ping)).await {
+                    break End::Error(e.to_string());
+                }
+                continue;
+            }
+            _ = stop.changed() => {
+                let _ = write.send(protocol::close(CloseCode::Normal, "load test done")).await;
+                let _ = timeout(CLOSE_TIMEOUT, async {
+                    while let Some(Ok(message)) = read.next().await {
+                        if matches!(message, Message::Close(_)) {
+                            break;
+                        }
+                    }
+                })
+                .await;
+                break End::Closed;
+            }
+        };
+        match message {
+            Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                Ok(ServerMessage::Price(price)) => {
+                    report.messages += 1;
+                    report.delivery.extend(pinger.delivery_ms(&price));
+                }
+                Ok(message) => {
+                    if let Some(round_trip) = pinger.pong(&message) {
+                        report.rtts.push(round_trip.rtt.as_secs_f64() * 1000.0);
+                    } else {
+                        report.messages += 1;
+                    }
+                }
+                Err(_) => report.messages += 1,
+            },
+            Some(Ok(Message::Close(frame))) => {
+                let detail = frame
+                    .map(|frame| format!("{} {}", frame.code, frame.reason))
+                    .unwrap_or_default();
+                break End::ServerClose(detail);
+            }
+            Some(Ok(_)) => {}
+            Some(Err(e)) => break End::Error(e.to_string()),
+            None => break End::Error("connection dropped".to_string()),
+        }
+    };
+    report.connected = started.elapsed();
+    report.pings = pinger.sent();
+    report.lost = pinger.lost();
+    report
+}
+
+fn ms(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| format!("{value:.1}"))
+}
+
+fn summary(reports: &[ClientReport], elapsed: Duration) -> String {
+    let count = |label: &str| reports.iter().filter(|r| r.end.label() == label).count();
+    let failed = count("connect_failed");
+    let connected: Vec<&ClientReport> = reports
+        .iter()
+        .filter(|r| !matches!(r.end, End::ConnectFailed(_)))
+        .collect();
+    let messages: u64 = reports.iter().map(|r| r.messages).sum();
+    // Clients turned away right after connecting would skew the rates
+    let rates = sorted(
+        reports
+            .iter()
+            .filter(|r| r.end == End::Closed)
+            .map(|r| r.rate())
+            .collect(),
+    );
+    let mean_rate = rates.iter().sum::<f64>() / rates.len().max(1) as f64;
+    let rtts = sorted(reports.iter().flat_map(|r| r.rtts.clone()).collect());
+    let delivery = sorted(reports.iter().flat_map(|r| r.delivery.clone()).collect());
+    let pings: u64 = reports.iter().map(|r| r.pings).sum();
+    let lost: u64 = reports.iter().map(|r| r.lost).sum();
+
+    let rows = [
+        (
+            "clients",
+            format!(
+                "{} ({} connected, {failed} failed to connect)",
+                reports.len(),
+                connected.len()
+            ),
+        ),
+        ("duration", format!("{:.1}s", elapsed.as_secs_f64())),
+        (
+            "ends",
+            format!(
+                "{} closed by the test, {} closed by the server, {} errors",
+                count("closed"),
+                count("server_close"),
+                count("error")
+            ),
+        ),
+        (
+            "messages",
+            format!(
+                "{messages} ({mean_rate:.2}/s per client still connected at the end, min {}, max {})",
+                ms(rates.first().copied()),
+                ms(rates.last().copied())
+            ),
+        ),
+        (
+            "rtt ms",
+            format!(
+                "{}  ({pings} pings, {} pongs, {lost} lost)",
+                percentiles(&rtts),
+                rtts.len()
+            ),
+        ),
+        (
+            "delivery ms",
+            format!("{}  ({} prices)", percentiles(&delivery), delivery.len()),
+        ),
+    ];
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let mut table = String::new();
+    for (name, value) in rows {
+        let _ = writeln!(table, "{name:<width$}  {value}");
+    }
+
+    // The most common failure reasons, so a saturated server is easy to spot
+    let mut reasons: Vec<(String, usize)> = Vec::new();
+    for detail in reports
+        .iter()
+        .map(|r| r.end.detail())
+        .filter(|d| !d.is_empty())
+    {
+        match reasons.iter_mut().find(|(reason, _)| reason == detail) {
+            Some((_, n)) => *n += 1,
+            None => reasons.push((detail.to_string(), 1)),
+        }
+    }
+    reasons.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+    for (reason, n) in reasons.iter().take(5) {
+        let _ = writeln!(table, "{:<width$}  {n} x {reason}", "failure");
+    }
+    table
+}
+
+fn csv(reports: &[ClientReport]) -> String {
+    let mut out = String::from(
+        "client,connected_s,messages,rate_per_s,rtt_p50_ms,rtt_p95_ms,rtt_max_ms,pings_lost,delivery_p50_ms,delivery_p95_ms,delivery_max_ms,end,detail\n",
+    );
+    for report in reports {
+        let rtts = sorted(report.rtts.clone());
+        let delivery = sorted(report.delivery.clone());
+        let _ = writeln!(
+            out,
+            "{},{:.3},{},{:.3},{},{},{},{},{},{},{},{},\"{}\"",
+            report.id,
+            report.connected.as_secs_f64(),
+            report.messages,
+            report.rate(),
+            ms(percentile(&rtts, 50.0)),
+            ms(percentile(&rtts, 95.0)),
+            ms(rtts.last().copied()),
+            report.lost,
+            ms(percentile(&delivery, 50.0)),
+            ms(percentile(&delivery, 95.0)),
+            ms(delivery.last().copied()),
+            report.end.label(),
+            report.end.detail().replace('"', "\"\"")
+        );
+    }
+    out
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args = args();
+    if args.subscribe.is_some_and(|k| k > args.symbols.len()) {
+        return Err("--subscribe: more symbols than --symbols lists".into());
+    }
+    let (stop_tx, stop) = watch::channel(false);
+    eprintln!(
+        "Opening {} clients to {} over {:?}, Ctrl+C to stop",
+        args.clients, args.url, args.ramp
+    );
+
+    let started = Instant::now();
+    let mut clients = JoinSet::new();
+    let ramp = async {
+        for id in 0..args.clients {
+            let start_at = args.ramp.mul_f64(id as f64 / args.clients as f64);
+            sleep(start_at.saturating_sub(started.elapsed())).await;
+            let subscribe = args.subscribe.map(|k| {
+                args.symbols
+                    .choose_multiple(&mut rand::thread_rng(), k)
+                    .cloned()
+                    .collect()
+            });
+            clients.spawn(client(
+                id,
+                args.url.clone(),
+                subscribe,
+                Some(args.ping_every).filter(|every| !every.is_zero()),
+                stop.clone(),
+            ));
+        }
+        eprintln!("All {} clients started", args.clients);
+        std::future::pending::<()>().await;
+    };
+    let deadline = async {
+        match args.duration {
+            Some(duration) => sleep(args.ramp + duration).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = ramp => {}
+        _ = deadline => {}
+        _ = tokio::signal::ctrl_c() => eprintln!("Stopping, closing the connections"),
+    }
+    let elapsed = started.elapsed();
+    let _ = stop_tx.send(true);
+
+    let mut reports = Vec::with_capacity(args.clients);
+    while let Some(report) = clients.join_next().await {
+        reports.extend(report.ok());
+    }
+    reports.sort_by_key(|report| report.id);
+
+    print!("{}", summary(&reports, elapsed));
+    if let Some(path) = &args.csv {
+        std::fs::write(path, csv(&reports))
+            .map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+        eprintln!("Per-client results written to {}", path.display());
+    }
+    Ok(())
+}
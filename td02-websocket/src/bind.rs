@@ -0,0 +1,127 @@
+//! Listen addresses of the WebSocket servers: every `--bind` (repeatable), else `WS_BIND`
+//! (comma-separated), else `127.0.0.1`, with the binary's default port. An address may carry
+//! its own port (`0.0.0.0:9000`, `[::1]:9000`, `:9000`) and `--port` overrides them all;
+//! port 0 picks a free one. `--bind 0.0.0.0 --bind [::]` listens on IPv4 and IPv6 at once,
+//! `[::]` alone takes both on dual-stack hosts.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
+
+use clap::Args;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Pending connections per listener.
+const BACKLOG: i32 = 1024;
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct BindArgs {
+    /// Address to listen on, repeatable (0.0.0.0, [::]:9000, :9000) [default: WS_BIND, else
+    /// 127.0.0.1]
+    #[arg(long, value_name = "ADDR")]
+    pub bind: Vec<String>,
+
+    /// Port of every address, 0 for a free one
+    #[arg(long)]
+    pub port: Option<u16>,
+}
+
+pub fn bind_addrs(args: &BindArgs, default_port: u16) -> Result<Vec<SocketAddr>, String> {
+    let mut addrs = args
+        .bind
+        .iter()
+        .map(|raw| parse(raw, default_port).map_err(|e| format!("--bind: {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    if addrs.is_empty() {
+        addrs = match std::env::var("WS_BIND")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+        {
+            Some(raw) => raw
+                .split(',')
+                .map(|raw| parse(raw, default_port).map_err(|e| format!("WS_BIND: {e}")))
+                .collect::<Result<_, _>>()?,
+            None => vec![SocketAddr::new(Ipv4Addr::LOCALHOST.into(), default_port)],
+        };
+    }
+    if let Some(port) = args.port {
+        for addr in &mut addrs {
+            addr.set_port(port);
+        }
+    }
+    Ok(addrs)
+}
+
+/// Binds every address, failing with the one that could not be. IPv6 listeners only take
+/// IPv6 when an IPv4 address is bound too, so `0.0.0.0:8081` and `[::]:8081` don't collide.
+/// Needs a Tokio runtime.
+pub fn listen(addrs: &[SocketAddr]) -> io::Result<Vec<TcpListener>> {
+    let v6_only = addrs.iter().any(SocketAddr::is_ipv4);
+    addrs
+        .iter()
+        .map(|addr| {
+            bind(*addr, v6_only)
+                .map_err(|e| io::Error::new(e.kind(), format!("cannot bind {addr}: {e}")))
+        })
+        .collect()
+}
+
+fn bind(addr: SocketAddr, v6_only: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6_only)?;
+    }
+    // As `TcpListener::bind` does, so a restart doesn't wait for old connections to time out
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(BACKLOG)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Where `listeners` actually listen, ports picked for port 0 included.
+pub fn local_addrs(listeners: &[TcpListener]) -> io::Result<Vec<SocketAddr>> {
+    listeners.iter().map(TcpListener::local_addr).collect()
+}
+
+/// The client's address, IPv4 clients of a dual-stack listener as plain IPv4 rather than
+/// `[::ffff:...]`.
+pub fn peer_addr(stream: &TcpStream) -> io::Result<SocketAddr> {
+    let addr = stream.peer_addr()?;
+    Ok(SocketAddr::new(addr.ip().to_canonical(), addr.port()))
+}
+
+/// `host`, `host:port` or `:port`; IPv6 literals bare (`::1`) or in brackets (`[::1]:9000`).
+fn parse(raw: &str, default_port: u16) -> Result<SocketAddr, String> {
+    let raw = raw.trim();
+    if let Ok(addr) = raw.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    if let Ok(ip) = raw
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        return Ok(SocketAddr::new(ip, default_port));
+    }
+    if let Some(port) = raw.strip_prefix(':') {
+        let port = port
+            .parse::<u16>()
+            .map_err(|_| format!("expected a port after ':', got '{raw}'"))?;
+        return Ok(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port));
+    }
+    // Host names, e.g. localhost
+    let with_port = if raw.contains(':') {
+        raw.to_string()
+    } else {
+        format!("{raw}:{default_port}")
+    };
+    with_port
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| {
+            format!("expected an address such as 0.0.0.0:8080 or [::1]:8080, got '{raw}'")
+        })
+}
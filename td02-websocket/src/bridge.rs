@@ -0,0 +1,567 @@
+//! `ws_bridge`: live crypto prices from Binance's public WebSocket streams, without polling.
+//! Every tick of `--symbols` (`BTC-USD,ETH-USD`, quoted in USDT on Binance like the
+//! aggregator does) becomes a `stock_prices` row from source `binance_ws`, inserted in
+//! batches of `--batch-size` (500) or every `--flush-every` (`1s`). On Postgres each insert
+//! also sends `NOTIFY stock_prices`, so `ws_dashboard` pushes the prices as they land.
+//!
+//! `--stream ticker` (the default) gives one 24h ticker per symbol and second, with the
+//! rolling open, high and low; `--stream trade` every trade, trades of the same millisecond
+//! collapsing into the first. A lost connection is opened again after a backoff doubling
+//! up to `--max-backoff` (`60s`). Binance closes connections after 24 hours, so a new one
+//! is opened a little before and swapped in once it is up, without a gap. Per-tick work is
+//! never logged: a summary comes every `--log-every` (`30s`), and repeated errors at most
+//! once per summary.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+
+use chrono::DateTime;
+use clap::{Args, ValueEnum};
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use market_core::store::PriceStore;
+use market_core::StockPrice;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio::time::{interval, sleep, sleep_until, Duration, Instant, MissedTickBehavior};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use crate::args;
+use crate::protocol;
+use crate::reconnect::{jitter, ReconnectConfig};
+use crate::shutdown::ShutdownRx;
+
+type Ws = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+pub const SOURCE: &str = "binance_ws";
+
+const DEFAULT_URL: &str = "wss://stream.binance.com:9443";
+const DEFAULT_SYMBOLS: &str = "BTC-USD,ETH-USD";
+const DEFAULT_BATCH_SIZE: usize = 500;
+
+/// Binance ends a connection at 24 hours; the next one is opened this long after.
+const RECYCLE_AFTER: Duration = Duration::from_secs(23 * 3600 + 30 * 60);
+
+/// Wait before trying to recycle again when opening the new connection failed.
+const RECYCLE_RETRY: Duration = Duration::from_secs(60);
+
+/// Prices waiting for the database, past which the oldest are dropped.
+const MAX_PENDING: usize = 10_000;
+
+/// Binance refuses a combined stream of more than this.
+const MAX_STREAMS: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StreamKind {
+    /// One 24h ticker per symbol and second
+    Ticker,
+    /// Every trade
+    Trade,
+}
+
+impl StreamKind {
+    fn suffix(self) -> &'static str {
+        match self {
+            Self::Ticker => "ticker",
+            Self::Trade => "trade",
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct BridgeArgs {
+    /// Pairs to follow, comma separated, quoted in USDT on Binance
+    #[arg(long, value_delimiter = ',', default_value = DEFAULT_SYMBOLS)]
+    pub symbols: Vec<String>,
+
+    #[arg(long, value_enum, default_value_t = StreamKind::Ticker)]
+    pub stream: StreamKind,
+
+    /// Base of the stream URL [default: BINANCE_WS_URL, else wss://stream.binance.com:9443]
+    #[arg(long)]
+    pub url: Option<String>,
+
+    /// Rows per insert
+    #[arg(long, default_value_t = DEFAULT_BATCH_SIZE, value_parser = batch_size)]
+    pub batch_size: usize,
+
+    /// Longest a price waits for its insert
+    #[arg(long, default_value = "1s", value_parser = args::nonzero_duration)]
+    pub flush_every: Duration,
+
+    /// Longest wait before opening a lost connection again
+    #[arg(long, default_value = "60s", value_parser = args::nonzero_duration)]
+    pub max_backoff: Duration,
+
+    /// Time between two summaries in the logs
+    #[arg(long, default_value = "30s", value_parser = args::nonzero_duration)]
+    pub log_every: Duration,
+}
+
+fn batch_size(raw: &str) -> Result<usize, String> {
+    raw.trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|size| (1..=MAX_PENDING).contains(size))
+        .ok_or_else(|| format!("expected 1 to {MAX_PENDING} rows"))
+}
+
+#[derive(Debug, Clone)]
+pub struct BridgeConfig {
+    /// Base of the stream URL, `/stream?streams=...` is added.
+    pub url: String,
+    /// `BTC-USD` form, as stored.
+    pub symbols: Vec<String>,
+    pub stream: StreamKind,
+    pub reconnect: ReconnectConfig,
+    pub batch_size: usize,
+    pub flush_every: Duration,
+    pub log_every: Duration,
+}
+
+impl BridgeConfig {
+    pub fn from_args(args: &BridgeArgs) -> Result<Self, String> {
+        let symbols: Vec<String> = args
+            .symbols
+            .iter()
+            .map(|symbol| symbol.trim().to_uppercase())
+            .filter(|symbol| !symbol.is_empty())
+            .collect();
+        if symbols.is_empty()
+            || symbols.len() > MAX_STREAMS
+            || symbols
+                .iter()
+                .any(|symbol| binance_symbol(symbol).is_none())
+        {
+            return Err(format!(
+                "--symbols: expected up to {MAX_STREAMS} pairs such as BTC-USD,ETH-USD, got '{}'",
+                args.symbols.join(",")
+            ));
+        }
+        let url = args
+            .url
+            .clone()
+            .or_else(|| std::env::var("BINANCE_WS_URL").ok())
+            .unwrap_or_else(|| DEFAULT_URL.to_string());
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            symbols,
+            stream: args.stream,
+            reconnect: ReconnectConfig {
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: args.max_backoff,
+            },
+            batch_size: args.batch_size,
+            flush_every: args.flush_every,
+            log_every: args.log_every,
+        })
+    }
+
+    /// The combined stream of every symbol.
+    fn stream_url(&self) -> String {
+        let streams: Vec<String> = self
+            .symbols
+            .iter()
+            .filter_map(|symbol| binance_symbol(symbol))
+            .map(|pair| format!("{}@{}", pair.to_lowercase(), self.stream.suffix()))
+            .collect();
+        format!("{}/stream?streams={}", self.url, streams.join("/"))
+    }
+}
+
+/// `BTC-USD` -> `BTCUSDT`: Binance has no USD spot books, USDT is the closest quote.
+fn binance_symbol(symbol: &str) -> Option<String> {
+    let (base, quote) = symbol.split_once('-')?;
+    let quote = match quote {
+        "USD" => "USDT",
+        other => other,
+    };
+    let valid = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric());
+    (valid(base) && valid(quote)).then(|| format!("{base}{quote}").to_uppercase())
+}
+
+/// A frame of a combined stream.
+#[derive(Debug, Deserialize)]
+struct Combined {
+    data: Event,
+}
+
+/// Prices are strings on the wire, times Unix milliseconds.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "e")]
+enum Event {
+    #[serde(rename = "24hrTicker")]
+    Ticker {
+        #[serde(rename = "s")]
+        pair: String,
+        #[serde(rename = "E")]
+        time: i64,
+        #[serde(rename = "c")]
+        last: String,
+        #[serde(rename = "o")]
+        open: String,
+        #[serde(rename = "h")]
+        high: String,
+        #[serde(rename = "l")]
+        low: String,
+    },
+    #[serde(rename = "trade")]
+    Trade {
+        #[serde(rename = "s")]
+        pair: String,
+        #[serde(rename = "T")]
+        time: i64,
+        #[serde(rename = "p")]
+        price: String,
+    },
+}
+
+/// The row for a frame, `Err` with why when it isn't one of ours.
+fn parse(text: &str, symbols: &HashMap<String, String>) -> Result<StockPrice, String> {
+    let Combined { data } = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let number = |raw: &str| {
+        raw.parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite() && *value > 0.0)
+            .ok_or_else(|| format!("invalid price {raw:?}"))
+    };
+    let (pair, time, price, range) = match &data {
+        Event::Ticker {
+            pair,
+            time,
+            last,
+            open,
+            high,
+            low,
+        } => (
+            pair,
+            *time,
+            number(last)?,
+            Some((number(open)?, number(high)?, number(low)?)),
+        ),
+        Event::Trade { pair, time, price } => (pair, *time, number(price)?, None),
+    };
+    let symbol = symbols
+        .get(pair)
+        .ok_or_else(|| format!("unexpected pair {pair}"))?;
+    let timestamp =
+        DateTime::from_timestamp_millis(time).ok_or_else(|| format!("invalid time {time}"))?;
+    Ok(StockPrice {
+        symbol: symbol.clone(),
+        price,
+        source: SOURCE.to_string(),
+        timestamp,
+        open: range.map(|(open, _, _)| open),
+        high: range.map(|(_, high, _)| high),
+        low: range.map(|(_, _, low)| low),
+        ..Default::default()
+    })
+}
+
+/// Logs a repeated problem once, then at most once per `every`, with how many occurrences
+/// went unlogged in between.
+struct Throttled {
+    every: Duration,
+    last: Option<Instant>,
+    unlogged: u64,
+}
+
+impl Throttled {
+    fn new(every: Duration) -> Self {
+        Self {
+            every,
+            last: None,
+            unlogged: 0,
+        }
+    }
+
+    /// `Some` when this one is to be logged, with a note of those that weren't.
+    fn ready(&mut self) -> Option<String> {
+        let now = Instant::now();
+        if self.last.is_some_and(|last| now < last + self.every) {
+            self.unlogged += 1;
+            return None;
+        }
+        self.last = Some(now);
+        Some(match std::mem::take(&mut self.unlogged) {
+            0 => String::new(),
+            unlogged => format!(" ({unlogged} more since the last warning)"),
+        })
+    }
+}
+
+/// Streams `cfg.symbols` into `store` until `shutdown` turns true or its sender is dropped;
+/// the prices already received are written before it returns.
+pub async fn run(cfg: BridgeConfig, store: Arc<dyn PriceStore>, shutdown: ShutdownRx) {
+    info!(
+        "Bridging {} {} streams from {} into stock_prices as {SOURCE} (batches of {}, every {})",
+        cfg.symbols.join(","),
+        cfg.stream.suffix(),
+        cfg.url,
+        cfg.batch_size,
+        humantime::format_duration(cfg.flush_every)
+    );
+    let (tx, rx) = mpsc::channel(MAX_PENDING);
+    let writer = tokio::spawn(write(store, rx, cfg.clone()));
+    read(cfg, tx, shutdown).await;
+    // The reader dropped its sender: the writer flushes and ends
+    if let Err(e) = writer.await {
+        warn!("Writer task failed: {e}");
+    }
+}
+
+enum Ended {
+    Shutdown,
+    Lost(String),
+}
+
+/// Connects, and connects again, until shut down.
+async fn read(cfg: BridgeConfig, tx: mpsc::Sender<StockPrice>, mut shutdown: ShutdownRx) {
+    let url = cfg.stream_url();
+    let symbols: HashMap<String, String> = cfg
+        .symbols
+        .iter()
+        .filter_map(|symbol| Some((binance_symbol(symbol)?, symbol.clone())))
+        .collect();
+    let mut counts = Counts::new(cfg.log_every);
+    let mut backoff = cfg.reconnect.initial_backoff;
+    let mut attempt = 0u32;
+    while !*shutdown.borrow() {
+        attempt += 1;
+        let connected = tokio::select! {
+            connected = connect_async(url.as_str()) => connected,
+            _ = shutdown.changed() => return,
+        };
+        let reason = match connected {
+            Ok((ws, _)) => {
+                info!("Connected to {}", cfg.url);
+                attempt = 0;
+                backoff = cfg.reconnect.initial_backoff;
+                let session = Session {
+                    url: &url,
+                    symbols: &symbols,
+                    tx: &tx,
+                    counts: &mut counts,
+                };
+                match session.run(ws, &mut shutdown).await {
+                    Ended::Shutdown => return,
+                    Ended::Lost(reason) => reason,
+                }
+            }
+            Err(e) => e.to_string(),
+        };
+        let retry_in = jitter(backoff);
+        warn!(
+            "Binance stream down ({reason}), retrying in {} (attempt {})",
+            humantime::format_duration(Duration::from_millis(retry_in.as_millis() as u64)),
+            attempt + 1
+        );
+        tokio::select! {
+            _ = sleep(retry_in) => {}
+            _ = shutdown.changed() => return,
+        }
+        backoff = (backoff * 2).min(cfg.reconnect.max_backoff);
+    }
+}
+
+/// What the reader has seen since its last summary.
+struct Counts {
+    every: Duration,
+    next: Instant,
+    by_symbol: BTreeMap<String, u64>,
+    dropped: u64,
+    skipped: Throttled,
+}
+
+impl Counts {
+    fn new(every: Duration) -> Self {
+        Self {
+            every,
+            next: Instant::now() + every,
+            by_symbol: BTreeMap::new(),
+            dropped: 0,
+            skipped: Throttled::new(every),
+        }
+    }
+
+    fn summary(&mut self) {
+        self.next = Instant::now() + self.every;
+        let received: u64 = self.by_symbol.values().sum();
+        let by_symbol = std::mem::take(&mut self.by_symbol)
+            .into_iter()
+            .map(|(symbol, count)| format!("{symbol} {count}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let dropped = std::mem::take(&mut self.dropped);
+        match (received, dropped) {
+            (0, 0) => info!("No tick received in {}", humantime::format_duration(self.every)),
+            (_, 0) => info!("Received {received} ticks ({by_symbol})"),
+            _ => warn!(
+                "Received {received} ticks ({by_symbol}), {dropped} dropped while the database was behind"
+            ),
+        }
+    }
+}
+
+struct Session<'a> {
+    url: &'a str,
+    symbols: &'a HashMap<String, String>,
+    tx: &'a mpsc::Sender<StockPrice>,
+    counts: &'a mut Counts,
+}
+
+impl Session<'_> {
+    /// One connection, swapped for a new one before Binance's 24 hours are up, until it is
+    /// lost or shut down.
+    async fn run(mut self, mut ws: Ws, shutdown: &mut ShutdownRx) -> Ended {
+        let mut recycle_at = Instant::now() + RECYCLE_AFTER;
+        loop {
+            tokio::select! {
+                message = ws.next() => match message {
+                    Some(Ok(Message::Text(text))) => self.tick(&text),
+                    Some(Ok(Message::Close(frame))) => {
+                        return Ended::Lost(frame.map_or("closed".to_string(), |frame| {
+                            format!("closed: {} {}", frame.code, frame.reason)
+                        }));
+                    }
+                    // Binance pings every few minutes, tungstenite answers
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Ended::Lost(e.to_string()),
+                    None => return Ended::Lost("connection dropped".to_string()),
+                },
+                _ = sleep_until(recycle_at) => match connect_async(self.url).await {
+                    Ok((next, _)) => {
+                        let _ = ws.close(None).await;
+                        ws = next;
+                        recycle_at = Instant::now() + RECYCLE_AFTER;
+                        info!("Swapped in a new Binance connection ahead of the 24h limit");
+                    }
+                    Err(e) => {
+                        warn!("Cannot open the next Binance connection, keeping this one: {e}");
+                        recycle_at = Instant::now() + RECYCLE_RETRY;
+                    }
+                },
+                _ = sleep_until(self.counts.next) => self.counts.summary(),
+                _ = shutdown.changed() => {
+                    let _ = ws.send(protocol::close(CloseCode::Normal, "bridge exiting")).await;
+                    return Ended::Shutdown;
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self, text: &str) {
+        let price = match parse(text, self.symbols) {
+            Ok(price) => price,
+            Err(reason) => {
+                if let Some(more) = self.counts.skipped.ready() {
+                    warn!("Skipping a Binance message, {reason}{more}: {text:.200}");
+                }
+                return;
+            }
+        };
+        *self
+            .counts
+            .by_symbol
+            .entry(price.symbol.clone())
+            .or_default() += 1;
+        if self.tx.try_send(price).is_err() {
+            self.counts.dropped += 1;
+        }
+    }
+}
+
+/// Inserts what the reader sends, in batches, until its sender is dropped.
+async fn write(store: Arc<dyn PriceStore>, mut rx: mpsc::Receiver<StockPrice>, cfg: BridgeConfig) {
+    let mut pending: VecDeque<StockPrice> = VecDeque::new();
+    let mut flush = interval(cfg.flush_every);
+    flush.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut summary = interval(cfg.log_every);
+    summary.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    summary.tick().await;
+    let mut failed = Throttled::new(cfg.log_every);
+    let mut failing = false;
+    let mut written = Written::default();
+
+    loop {
+        let open = tokio::select! {
+            price = rx.recv() => match price {
+                Some(price) => {
+                    pending.push_back(price);
+                    if pending.len() < cfg.batch_size {
+                        continue;
+                    }
+                    true
+                }
+                None => false,
+            },
+            _ = flush.tick() => true,
+            _ = summary.tick() => {
+                written.summary();
+                continue;
+            }
+        };
+
+        // Batches of at most `batch_size`, so a backlog doesn't make one huge statement
+        while !pending.is_empty() {
+            let batch: Vec<StockPrice> = pending.iter().take(cfg.batch_size).cloned().collect();
+            match store.save_batch(&batch).await {
+                Ok(count) => {
+                    if failing {
+                        info!("Inserting into stock_prices again");
+                        failing = false;
+                    }
+                    pending.drain(..batch.len());
+                    written.inserted += count;
+                    written.duplicates += batch.len() as u64 - count;
+                }
+                Err(e) => {
+                    failing = true;
+                    if let Some(more) = failed.ready() {
+                        warn!(
+                            "Insert of {} prices failed{more}, kept for the next flush: {e}",
+                            batch.len()
+                        );
+                    }
+                    // The oldest go first when the outage outlasts the buffer
+                    let over = pending.len().saturating_sub(MAX_PENDING);
+                    pending.drain(..over);
+                    written.dropped += over as u64;
+                    break;
+                }
+            }
+        }
+
+        if !open {
+            written.summary();
+            if !pending.is_empty() {
+                warn!("{} prices not written on exit", pending.len());
+            }
+            return;
+        }
+    }
+}
+
+/// What the writer did since its last summary.
+#[derive(Default)]
+struct Written {
+    inserted: u64,
+    /// Already in `stock_prices`, same symbol, source and time.
+    duplicates: u64,
+    dropped: u64,
+}
+
+impl Written {
+    fn summary(&mut self) {
+        let Self {
+            inserted,
+            duplicates,
+            dropped,
+        } = std::mem::take(self);
+        if inserted > 0 || duplicates > 0 || dropped > 0 {
+            info!("Inserted {inserted} prices, {duplicates} already stored, {dropped} dropped");
+        }
+    }
+}
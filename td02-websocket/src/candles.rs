@@ -0,0 +1,98 @@
+//! One-minute candles for `ws_dashboard`, built from the prices it broadcasts, all sources
+//! together, by minute of reception. Each closed candle goes out as a `candle` message
+//! right after its minute ends; the last `KEPT_CANDLES` per symbol answer `candles`.
+//! A symbol without a price in a minute gets no candle for it.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use log::debug;
+use market_core::PriceUpdate;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{sleep, Duration};
+
+use crate::protocol::{Candle, ServerMessage};
+
+/// Closed candles kept per symbol.
+pub const KEPT_CANDLES: usize = 60;
+
+/// Closed candles per symbol, oldest first.
+#[derive(Debug, Default)]
+pub struct CandleHistory(RwLock<HashMap<String, VecDeque<Candle>>>);
+
+impl CandleHistory {
+    /// The last `limit` closed candles of `symbol`, oldest first.
+    pub fn last(&self, symbol: &str, limit: usize) -> Vec<Candle> {
+        let history = self.0.read().unwrap();
+        let Some(candles) = history.get(symbol) else {
+            return Vec::new();
+        };
+        candles
+            .iter()
+            .skip(candles.len().saturating_sub(limit))
+            .cloned()
+            .collect()
+    }
+
+    fn push(&self, candle: Candle) {
+        let mut history = self.0.write().unwrap();
+        let candles = history.entry(candle.symbol.clone()).or_default();
+        if candles.len() == KEPT_CANDLES {
+            candles.pop_front();
+        }
+        candles.push_back(candle);
+    }
+}
+
+fn minute_start(at: DateTime<Utc>) -> DateTime<Utc> {
+    at.duration_trunc(TimeDelta::minutes(1)).unwrap_or(at)
+}
+
+/// Until the next wall-clock minute, plus a hair so the clock is past it when we wake.
+fn until_next_minute() -> Duration {
+    let now = Utc::now();
+    let next = minute_start(now) + TimeDelta::minutes(1);
+    (next - now).to_std().unwrap_or_default() + Duration::from_millis(5)
+}
+
+/// Reads the prices from `rx`, and sends each candle to `tx` and `history` when its minute
+/// ends, until the price channel closes.
+pub async fn candles(
+    mut rx: broadcast::Receiver<PriceUpdate>,
+    tx: broadcast::Sender<ServerMessage>,
+    history: Arc<CandleHistory>,
+) {
+    // Candles of the minute in progress
+    let mut open: BTreeMap<String, Candle> = BTreeMap::new();
+    let mut start = minute_start(Utc::now());
+
+    loop {
+        tokio::select! {
+            update = rx.recv() => match update {
+                Ok(price) => {
+                    let candle = open.entry(price.symbol.clone()).or_insert_with(|| Candle {
+                        symbol: price.symbol,
+                        open: price.price,
+                        high: price.price,
+                        low: price.price,
+                        close: price.price,
+                        start,
+                    });
+                    candle.high = candle.high.max(price.price);
+                    candle.low = candle.low.min(price.price);
+                    candle.close = price.price;
+                }
+                Err(RecvError::Lagged(missed)) => debug!("Candles missed {missed} prices"),
+                Err(RecvError::Closed) => return,
+            },
+            _ = sleep(until_next_minute()) => {
+                for (_, candle) in std::mem::take(&mut open) {
+                    history.push(candle.clone());
+                    let _ = tx.send(ServerMessage::Candle(candle));
+                }
+                start = minute_start(Utc::now());
+            }
+        }
+    }
+}
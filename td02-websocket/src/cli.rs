@@ -0,0 +1,278 @@
+//! What the server binaries share: `.env` loading, logging, their command lines, and running
+//! one server or several until SIGINT or SIGTERM. `td02 serve all` runs every server in one
+//! process, or those of `--servers echo,dashboard`. When several run, each gets a port of its
+//! own: `--echo-port` (8080), `--broadcast-port` (8081) and `--dashboard-port` (8082) replace
+//! the ports of `--bind` and `WS_BIND`, and `--port` is refused. The other options go to every
+//! server that has them; `--metrics-port` and `--persist-stream` go to the dashboard only when
+//! it runs, as two servers can't share them.
+
+use std::net::SocketAddr;
+
+use clap::{Args, Parser, ValueEnum};
+use env_logger::{Builder, Target};
+use futures_util::future::try_join_all;
+use log::LevelFilter;
+use market_core::store;
+
+use crate::servers::broadcast::{self, BroadcastArgs, BroadcastConfig};
+use crate::servers::dashboard::{self, DashboardArgs, DashboardConfig};
+use crate::servers::echo::{self, EchoArgs, EchoConfig};
+use crate::servers::{FeedArgs, ServerArgs};
+use crate::shutdown::ServerHandle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Server {
+    Echo,
+    Broadcast,
+    Dashboard,
+}
+
+/// A server with its settings, ready to run.
+pub enum ServerConfig {
+    Echo(EchoConfig),
+    Broadcast(BroadcastConfig),
+    Dashboard(DashboardConfig),
+}
+
+/// The database of the binaries that store or read prices.
+#[derive(Args, Debug, Clone, Default)]
+pub struct DatabaseArgs {
+    /// Database URL, postgres://... or sqlite://... [default: DATABASE_URL, else
+    /// sqlite://prices.db]
+    #[arg(long)]
+    pub db: Option<String>,
+
+    /// Don't apply pending database migrations at startup (for users without DDL rights)
+    #[arg(long)]
+    pub skip_migrations: bool,
+}
+
+impl DatabaseArgs {
+    /// `--db`, else `DATABASE_URL`, else the local SQLite file.
+    pub fn url(&self) -> String {
+        store::database_url(self.db.clone())
+    }
+
+    pub fn migrate(&self) -> bool {
+        !self.skip_migrations
+    }
+}
+
+/// `ws_echo`, `td02 serve echo`.
+#[derive(Parser, Debug)]
+#[command(about = "Echo server, or chat relay with --mode chat (port 8080)")]
+pub struct EchoCommand {
+    #[command(flatten)]
+    pub server: ServerArgs,
+
+    #[command(flatten)]
+    pub echo: EchoArgs,
+}
+
+impl EchoCommand {
+    pub fn configs(&self) -> Result<Vec<ServerConfig>, String> {
+        let cfg = EchoConfig::from_args(&self.server, &self.echo)?;
+        Ok(vec![ServerConfig::Echo(cfg)])
+    }
+}
+
+/// `ws_broadcast`, `td02 serve broadcast`.
+#[derive(Parser, Debug)]
+#[command(about = "Simulated or replayed prices (port 8081)")]
+pub struct BroadcastCommand {
+    #[command(flatten)]
+    pub server: ServerArgs,
+
+    #[command(flatten)]
+    pub feed: FeedArgs,
+
+    #[command(flatten)]
+    pub broadcast: BroadcastArgs,
+}
+
+impl BroadcastCommand {
+    pub fn configs(&self) -> Result<Vec<ServerConfig>, String> {
+        let cfg = BroadcastConfig::from_args(&self.server, &self.feed, &self.broadcast)?;
+        Ok(vec![ServerConfig::Broadcast(cfg)])
+    }
+}
+
+/// `ws_dashboard`, `td02 serve dashboard`.
+#[derive(Parser, Debug)]
+#[command(about = "Prices from the database, with the dashboard page (port 8082)")]
+pub struct DashboardCommand {
+    #[command(flatten)]
+    pub server: ServerArgs,
+
+    #[command(flatten)]
+    pub feed: FeedArgs,
+
+    #[command(flatten)]
+    pub dashboard: DashboardArgs,
+}
+
+impl DashboardCommand {
+    pub fn configs(&self) -> Result<Vec<ServerConfig>, String> {
+        let cfg = DashboardConfig::from_args(&self.server, &self.feed, &self.dashboard)?;
+        Ok(vec![ServerConfig::Dashboard(cfg)])
+    }
+}
+
+/// `td02 serve all`: the options of every server, each once.
+#[derive(Parser, Debug)]
+#[command(about = "Every server side by side in one process")]
+pub struct AllCommand {
+    /// Servers to run, comma separated [default: all of them]
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub servers: Vec<Server>,
+
+    /// Port of the echo server
+    #[arg(long, default_value_t = echo::DEFAULT_PORT)]
+    pub echo_port: u16,
+
+    /// Port of the broadcast server
+    #[arg(long, default_value_t = broadcast::DEFAULT_PORT)]
+    pub broadcast_port: u16,
+
+    /// Port of the dashboard server
+    #[arg(long, default_value_t = dashboard::DEFAULT_PORT)]
+    pub dashboard_port: u16,
+
+    #[command(flatten)]
+    pub server: ServerArgs,
+
+    #[command(flatten)]
+    pub feed: FeedArgs,
+
+    #[command(flatten)]
+    pub echo: EchoArgs,
+
+    #[command(flatten)]
+    pub broadcast: BroadcastArgs,
+
+    #[command(flatten)]
+    pub dashboard: DashboardArgs,
+}
+
+impl AllCommand {
+    /// Those of `--servers`, in the order given, on their own ports when there are several.
+    pub fn configs(&self) -> Result<Vec<ServerConfig>, String> {
+        let mut servers = Vec::new();
+        for &server in &self.servers {
+            if !servers.contains(&server) {
+                servers.push(server);
+            }
+        }
+        if servers.is_empty() {
+            servers = vec![Server::Echo, Server::Broadcast, Server::Dashboard];
+        }
+        let together = servers.len() > 1;
+        if together && self.server.bind.port.is_some() {
+            return Err("--port: each server has its own port when several run, see --echo-port, --broadcast-port and --dashboard-port".to_string());
+        }
+        let with_dashboard = servers.contains(&Server::Dashboard);
+        let set_port = |addrs: &mut [SocketAddr], port: u16| {
+            if together {
+                for addr in addrs {
+                    addr.set_port(port);
+                }
+            }
+        };
+
+        let mut configs = Vec::new();
+        for server in servers {
+            configs.push(match server {
+                Server::Echo => {
+                    let mut cfg = EchoConfig::from_args(&self.server, &self.echo)?;
+                    set_port(&mut cfg.addrs, self.echo_port);
+                    ServerConfig::Echo(cfg)
+                }
+                Server::Broadcast => {
+                    let mut cfg =
+                        BroadcastConfig::from_args(&self.server, &self.feed, &self.broadcast)?;
+                    set_port(&mut cfg.addrs, self.broadcast_port);
+                    if together && with_dashboard {
+                        cfg.metrics_port = None;
+                        cfg.persist = None;
+                    }
+                    ServerConfig::Broadcast(cfg)
+                }
+                Server::Dashboard => {
+                    let mut cfg =
+                        DashboardConfig::from_args(&self.server, &self.feed, &self.dashboard)?;
+                    set_port(&mut cfg.addrs, self.dashboard_port);
+                    ServerConfig::Dashboard(cfg)
+                }
+            });
+        }
+        Ok(configs)
+    }
+}
+
+/// `.env` (or `td01-basics/.env`) into the environment, and info logs on stdout.
+pub fn init() {
+    dotenvy::dotenv().ok();
+    dotenvy::from_filename("td01-basics/.env").ok();
+
+    Builder::new()
+        .target(Target::Stdout)
+        .filter_level(LevelFilter::Info)
+        .init();
+}
+
+/// Starts the servers of `configs` and waits until they all stopped.
+pub async fn serve(configs: Vec<ServerConfig>) -> Result<(), Box<dyn std::error::Error>> {
+    // Started one after the other; a server failing to start drops, and so stops, the others
+    let mut handles = Vec::new();
+    for cfg in configs {
+        let handle = match cfg {
+            ServerConfig::Echo(cfg) => echo::run(cfg).await?,
+            ServerConfig::Broadcast(cfg) => broadcast::run(cfg).await?,
+            ServerConfig::Dashboard(cfg) => dashboard::run(cfg).await?,
+        };
+        handles.push(handle);
+    }
+
+    ServerHandle::shutdown_all_on_signals(&handles)?;
+    try_join_all(handles.into_iter().map(ServerHandle::wait)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn commands_are_well_formed() {
+        EchoCommand::command().debug_assert();
+        BroadcastCommand::command().debug_assert();
+        DashboardCommand::command().debug_assert();
+        AllCommand::command().debug_assert();
+    }
+
+    #[test]
+    fn all_takes_each_option_once() {
+        let all = AllCommand::try_parse_from([
+            "td02",
+            "--servers=dashboard,echo,dashboard",
+            "--echo-port",
+            "9000",
+            "--stale-after=30s",
+            "--mode",
+            "chat",
+        ])
+        .unwrap();
+        assert_eq!(
+            all.servers,
+            [Server::Dashboard, Server::Echo, Server::Dashboard]
+        );
+        assert_eq!(all.echo_port, 9000);
+        assert_eq!(all.dashboard_port, dashboard::DEFAULT_PORT);
+        assert_eq!(all.dashboard.staleness.stale_after.len(), 1);
+
+        assert!(AllCommand::try_parse_from(["td02", "--tick-ms"]).is_err());
+        assert!(EchoCommand::try_parse_from(["td02", "--tick-ms", "100"]).is_err());
+    }
+}
@@ -0,0 +1,487 @@
+//! Active client count, with an optional cap (`--max-connections N`), and the counters and
+//! per-connection details behind `stats`. Clients over the cap still get the WebSocket
+//! handshake, so browsers see why they were turned away.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use futures_util::SinkExt;
+use log::warn;
+use market_core::PriceUpdate;
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, Notify};
+use tokio_tungstenite::accept_async;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::protocol::{
+    self, Announcement, ConnectionInfo, Format, FormatCounts, Layout, ServerMessage, ServerStats,
+};
+use crate::subscription::Subscription;
+
+#[derive(Debug)]
+pub struct Connections {
+    started: Instant,
+    active: AtomicUsize,
+    max: Option<usize>,
+    /// Clients let in since the start, counted before their handshake.
+    accepted: AtomicU64,
+    closed: AtomicU64,
+    /// Inbound messages dropped by the rate limit, all connections together.
+    rate_limited: AtomicU64,
+    /// Updates held back before broadcasting as too close to the previous one.
+    suppressed: AtomicU64,
+    /// Prices delivered to clients, and prices they lost to lag, full send queues or failed sends.
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    /// Times a client fell behind the channel.
+    lagged: AtomicU64,
+    /// Clients closed for letting their send queue fill up.
+    slow: AtomicU64,
+    json_clients: AtomicUsize,
+    msgpack_clients: AtomicUsize,
+    channel: Option<Channel>,
+    /// Lag events in the current minute of uptime, for `LAG_WARN_PER_MINUTE`.
+    lag_minute: AtomicU64,
+    lag_in_minute: AtomicU64,
+    next_id: AtomicU64,
+    clients: Mutex<BTreeMap<u64, Arc<Client>>>,
+    /// `announce` messages, for every client loop.
+    announcements: broadcast::Sender<Announcement>,
+}
+
+/// A connection as named in the logs, `#12 127.0.0.1:52000`: the address alone is
+/// ambiguous behind NAT, the id is the one in its welcome and in `admin_list`.
+#[derive(Debug, Clone, Copy)]
+pub struct Peer {
+    pub id: u64,
+    pub addr: SocketAddr,
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {}", self.id, self.addr)
+    }
+}
+
+#[derive(Debug)]
+struct Channel {
+    /// Weak, so shutting the feed down still closes the channel.
+    sender: broadcast::WeakSender<PriceUpdate>,
+    capacity: usize,
+}
+
+/// What `stats` lists about one connection.
+#[derive(Debug)]
+struct Client {
+    addr: SocketAddr,
+    /// The path it upgraded on.
+    path: String,
+    connected_at: DateTime<Utc>,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    lagged: AtomicU64,
+    /// Prices its throttle replaced by newer ones.
+    coalesced: AtomicU64,
+    /// Notified by `admin_kick`.
+    kick: Notify,
+    state: Mutex<ClientState>,
+}
+
+#[derive(Debug)]
+struct ClientState {
+    format: Format,
+    /// `None` while subscribed to every symbol.
+    symbols: Option<Vec<String>>,
+    excluded: Vec<String>,
+    throttle: Option<f64>,
+}
+
+impl Connections {
+    pub fn new(max: Option<usize>) -> Self {
+        Self {
+            started: Instant::now(),
+            active: AtomicUsize::new(0),
+            max,
+            accepted: AtomicU64::new(0),
+            closed: AtomicU64::new(0),
+            rate_limited: AtomicU64::new(0),
+            suppressed: AtomicU64::new(0),
+            sent: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            lagged: AtomicU64::new(0),
+            slow: AtomicU64::new(0),
+            json_clients: AtomicUsize::new(0),
+            msgpack_clients: AtomicUsize::new(0),
+            channel: None,
+            lag_minute: AtomicU64::new(0),
+            lag_in_minute: AtomicU64::new(0),
+            next_id: AtomicU64::new(1),
+            clients: Mutex::new(BTreeMap::new()),
+            announcements: broadcast::channel(ANNOUNCEMENT_CAPACITY).0,
+        }
+    }
+
+    /// Reports the depth and receivers of `channel`, created with `capacity`, in `stats`.
+    pub fn with_channel(
+        mut self,
+        channel: &broadcast::Sender<PriceUpdate>,
+        capacity: usize,
+    ) -> Self {
+        self.channel = Some(Channel {
+            sender: channel.downgrade(),
+            capacity,
+        });
+        self
+    }
+
+    /// Names a connection just accepted, before its handshake, with the next id.
+    pub fn peer(&self, addr: SocketAddr) -> Peer {
+        Peer {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            addr,
+        }
+    }
+
+    /// Counts a new client and returns the active count, or `None` at capacity.
+    pub fn open(&self) -> Option<usize> {
+        let active = self
+            .active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+                self.max
+                    .is_none_or(|max| active < max)
+                    .then_some(active + 1)
+            })
+            .ok()
+            .map(|previous| previous + 1);
+        if active.is_some() {
+            self.accepted.fetch_add(1, Ordering::Relaxed);
+        }
+        active
+    }
+
+    /// Returns the clients still active.
+    pub fn close(&self) -> usize {
+        self.closed.fetch_add(1, Ordering::Relaxed);
+        self.active.fetch_sub(1, Ordering::SeqCst) - 1
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    pub fn max(&self) -> Option<usize> {
+        self.max
+    }
+
+    pub fn closed_total(&self) -> u64 {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    pub fn count_rate_limited(&self) {
+        self.rate_limited.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn rate_limited(&self) -> u64 {
+        self.rate_limited.load(Ordering::Relaxed)
+    }
+
+    pub fn count_slow(&self) {
+        self.slow.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count_suppressed(&self) {
+        self.suppressed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Lists a client that completed its handshake until the slot is dropped.
+    pub fn register(
+        &self,
+        peer: Peer,
+        path: &str,
+        format: Format,
+        layout: Layout,
+    ) -> ClientSlot<'_> {
+        let id = peer.id;
+        let client = Arc::new(Client {
+            addr: peer.addr,
+            path: path.to_string(),
+            connected_at: Utc::now(),
+            sent: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            lagged: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+            kick: Notify::new(),
+            state: Mutex::new(ClientState {
+                format,
+                symbols: None,
+                excluded: Vec::new(),
+                throttle: None,
+            }),
+        });
+        self.clients.lock().unwrap().insert(id, client.clone());
+        self.format_clients(format).fetch_add(1, Ordering::Relaxed);
+        ClientSlot {
+            connections: self,
+            id,
+            client,
+            format,
+            layout,
+        }
+    }
+
+    pub fn stats(&self) -> ServerStats {
+        let sender = self
+            .channel
+            .as_ref()
+            .and_then(|channel| channel.sender.upgrade());
+        ServerStats {
+            uptime_seconds: self.uptime().as_secs(),
+            active_connections: self.active(),
+            max_connections: self.max,
+            connections_total: self.accepted.load(Ordering::Relaxed),
+            messages_sent: self.sent.load(Ordering::Relaxed),
+            messages_dropped: self.dropped.load(Ordering::Relaxed),
+            lagged_total: self.lagged.load(Ordering::Relaxed),
+            slow_disconnects_total: self.slow.load(Ordering::Relaxed),
+            channel_capacity: self.channel.as_ref().map(|channel| channel.capacity),
+            channel_depth: sender.as_ref().map(|sender| sender.len()),
+            subscribers: sender.as_ref().map(|sender| sender.receiver_count()),
+            rate_limited_total: self.rate_limited(),
+            updates_suppressed: self.suppressed.load(Ordering::Relaxed),
+            formats: FormatCounts {
+                json: self.format_clients(Format::Json).load(Ordering::Relaxed),
+                msgpack: self.format_clients(Format::Msgpack).load(Ordering::Relaxed),
+            },
+        }
+    }
+
+    /// Closes connection `id`; `false` when there is none.
+    pub fn kick(&self, id: u64) -> bool {
+        match self.clients.lock().unwrap().get(&id) {
+            Some(client) => {
+                // Stored if the connection isn't waiting right now, so it's never missed
+                client.kick.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sends `announcement` to every client loop; returns how many there are.
+    pub fn announce(&self, announcement: Announcement) -> usize {
+        self.announcements.send(announcement).unwrap_or(0)
+    }
+
+    /// Announcements from now on.
+    pub fn announcements(&self) -> broadcast::Receiver<Announcement> {
+        self.announcements.subscribe()
+    }
+
+    /// Every connection past its handshake, oldest first.
+    pub fn list(&self) -> Vec<ConnectionInfo> {
+        self.clients
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, client)| {
+                let state = client.state.lock().unwrap();
+                ConnectionInfo {
+                    id: *id,
+                    addr: client.addr.to_string(),
+                    path: client.path.clone(),
+                    connected_at: client.connected_at,
+                    format: state.format,
+                    subscribed_symbols: state.symbols.as_ref().map(Vec::len),
+                    symbols: state.symbols.clone(),
+                    excluded: state.excluded.clone(),
+                    messages_sent: client.sent.load(Ordering::Relaxed),
+                    messages_dropped: client.dropped.load(Ordering::Relaxed),
+                    lagged: client.lagged.load(Ordering::Relaxed),
+                    throttle_per_sec: state.throttle,
+                    messages_coalesced: client.coalesced.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+
+    fn format_clients(&self, format: Format) -> &AtomicUsize {
+        match format {
+            Format::Json => &self.json_clients,
+            Format::Msgpack => &self.msgpack_clients,
+        }
+    }
+}
+
+/// A connection's entry in `stats`, with its frame format; removed when dropped.
+pub struct ClientSlot<'a> {
+    connections: &'a Connections,
+    id: u64,
+    client: Arc<Client>,
+    format: Format,
+    layout: Layout,
+}
+
+impl<'a> ClientSlot<'a> {
+    pub fn set_format(&mut self, format: Format) {
+        if format != self.format {
+            self.connections
+                .format_clients(self.format)
+                .fetch_sub(1, Ordering::Relaxed);
+            self.connections
+                .format_clients(format)
+                .fetch_add(1, Ordering::Relaxed);
+            self.format = format;
+            self.client.state.lock().unwrap().format = format;
+        }
+    }
+
+    pub fn set_throttle(&self, max_per_sec: Option<f64>) {
+        self.client.state.lock().unwrap().throttle = max_per_sec;
+    }
+
+    pub fn set_subscribed(&self, subscription: &Subscription) {
+        let mut state = self.client.state.lock().unwrap();
+        state.symbols = subscription.symbols();
+        state.excluded = subscription.excluded();
+    }
+
+    /// Resolves once an admin kicked this connection.
+    pub async fn kicked(&self) {
+        self.client.kick.notified().await
+    }
+
+    /// `message` encoded in this client's format and the server's layout.
+    pub fn frame(&self, message: &ServerMessage) -> Message {
+        message.encode(self.format, self.layout)
+    }
+
+    /// A price went out to this client.
+    pub fn sent(&self) {
+        self.tally().sent();
+    }
+
+    /// A price this client never got.
+    pub fn dropped(&self) {
+        self.client.dropped.fetch_add(1, Ordering::Relaxed);
+        self.connections.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts prices sent from elsewhere than the client loop, its writer.
+    pub fn tally(&self) -> Tally<'a> {
+        Tally {
+            connections: self.connections,
+            client: self.client.clone(),
+        }
+    }
+
+    /// The client fell behind the channel and lost `missed` prices. Warns once a minute
+    /// when lag gets frequent across all clients.
+    pub fn lagged(&self, missed: u64) {
+        let connections = self.connections;
+        self.client.lagged.fetch_add(1, Ordering::Relaxed);
+        self.client.dropped.fetch_add(missed, Ordering::Relaxed);
+        connections.lagged.fetch_add(1, Ordering::Relaxed);
+        connections.dropped.fetch_add(missed, Ordering::Relaxed);
+
+        let minute = connections.started.elapsed().as_secs() / 60;
+        if connections.lag_minute.swap(minute, Ordering::Relaxed) != minute {
+            connections.lag_in_minute.store(0, Ordering::Relaxed);
+        }
+        if connections.lag_in_minute.fetch_add(1, Ordering::Relaxed) + 1 == LAG_WARN_PER_MINUTE {
+            warn!(
+                "{LAG_WARN_PER_MINUTE} lag events within a minute: clients are too slow or the \
+                 channel is too small{}",
+                connections
+                    .channel
+                    .as_ref()
+                    .map(|channel| format!(" (--channel-capacity {})", channel.capacity))
+                    .unwrap_or_default()
+            );
+        }
+    }
+
+    /// Lag events of this connection.
+    pub fn lagged_events(&self) -> u64 {
+        self.client.lagged.load(Ordering::Relaxed)
+    }
+
+    /// A price its throttle replaced by a newer one.
+    pub fn coalesced(&self) {
+        self.client.coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Prices its throttle replaced so far.
+    pub fn coalesced_events(&self) -> u64 {
+        self.client.coalesced.load(Ordering::Relaxed)
+    }
+
+    /// Prices this connection was sent.
+    pub fn messages_sent(&self) -> u64 {
+        self.client.sent.load(Ordering::Relaxed)
+    }
+}
+
+/// The sent counters of a connection, apart from its slot.
+pub struct Tally<'a> {
+    connections: &'a Connections,
+    client: Arc<Client>,
+}
+
+impl Tally<'_> {
+    pub fn sent(&self) {
+        self.client.sent.fetch_add(1, Ordering::Relaxed);
+        self.connections.sent.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for ClientSlot<'_> {
+    fn drop(&mut self) {
+        self.connections
+            .format_clients(self.format)
+            .fetch_sub(1, Ordering::Relaxed);
+        self.connections.clients.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Lag events within a minute, all clients together, that get a warning.
+const LAG_WARN_PER_MINUTE: u64 = 10;
+
+/// Announcements a client loop may fall behind before missing some.
+const ANNOUNCEMENT_CAPACITY: usize = 16;
+
+/// Capacity of the broadcast channel without `--channel-capacity`.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 100;
+
+/// `--channel-capacity N`: prices a client may fall behind before it lags. Tokio's
+/// broadcast channel takes at most half of `usize`.
+pub fn channel_capacity(raw: &str) -> Result<usize, String> {
+    raw.trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|n| *n > 0 && *n <= usize::MAX / 2)
+        .ok_or_else(|| "expected a positive number".to_string())
+}
+
+/// Completes the handshake, tells the client the server is full and closes with 1013 (try
+/// again later). Errors are ignored: the client is being dropped anyway.
+pub async fn reject(stream: TcpStream, layout: Layout) {
+    let Ok(mut ws) = accept_async(stream).await else {
+        return;
+    };
+    let _ = ws
+        .send(ServerMessage::error("server full").encode(Format::Json, layout))
+        .await;
+    let _ = ws
+        .send(protocol::close(CloseCode::Again, "server full"))
+        .await;
+}
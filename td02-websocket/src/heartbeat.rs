@@ -0,0 +1,133 @@
+//! Server-side keepalive. Every `interval` the server pings the client; any frame from the
+//! client counts as an answer. A client that lets `max_missed` pings in a row go unanswered
+//! (laptop lid closed, NAT entry expired) is closed instead of lingering half-open.
+//! Separately, a connection that sends nothing at all, pongs included, for `--idle-timeout`
+//! (10 minutes, `0s` to disable) is closed as idle.
+
+use std::pin::Pin;
+use std::time::Duration;
+
+use tokio::time::{interval_at, sleep, Instant, Interval, MissedTickBehavior, Sleep};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::protocol;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeartbeatConfig {
+    pub interval: Duration,
+    pub max_missed: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            max_missed: 3,
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    /// Overrides from `WS_PING_INTERVAL` (`30s`, `500ms`...) and `WS_MAX_MISSED_PONGS`.
+    pub fn with_env(mut self) -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        if let Some(raw) = var("WS_PING_INTERVAL") {
+            self.interval = humantime::parse_duration(raw.trim())
+                .ok()
+                .filter(|d| !d.is_zero())
+                .ok_or_else(|| {
+                    format!("WS_PING_INTERVAL: expected a duration such as 30s, got '{raw}'")
+                })?;
+        }
+        if let Some(raw) = var("WS_MAX_MISSED_PONGS") {
+            self.max_missed = raw
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| {
+                    format!("WS_MAX_MISSED_PONGS: expected a positive number, got '{raw}'")
+                })?;
+        }
+        Ok(self)
+    }
+}
+
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+pub struct Heartbeat {
+    ticker: Interval,
+    max_missed: u32,
+    /// Pings sent since the client was last heard from.
+    missed: u32,
+}
+
+impl Heartbeat {
+    pub fn new(cfg: HeartbeatConfig) -> Self {
+        let mut ticker = interval_at(Instant::now() + cfg.interval, cfg.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            ticker,
+            max_missed: cfg.max_missed,
+            missed: 0,
+        }
+    }
+
+    pub async fn tick(&mut self) {
+        self.ticker.tick().await;
+    }
+
+    /// The client sent something: it is still there.
+    pub fn alive(&mut self) {
+        self.missed = 0;
+    }
+
+    /// What to send on a tick: a ping, or the close frame once `max_missed` pings went
+    /// unanswered.
+    pub fn next_frame(&mut self) -> Message {
+        if self.missed >= self.max_missed {
+            return protocol::close(CloseCode::Away, "heartbeat timeout");
+        }
+        self.missed += 1;
+        Message::Ping(Vec::new())
+    }
+
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+}
+
+/// Deadline pushed back by every frame from the client.
+pub struct IdleTimer {
+    timeout: Option<Duration>,
+    deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl IdleTimer {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            deadline: timeout.map(|timeout| Box::pin(sleep(timeout))),
+        }
+    }
+
+    /// The client sent something, a pong or a ping included.
+    pub fn alive(&mut self) {
+        if let (Some(timeout), Some(deadline)) = (self.timeout, self.deadline.as_mut()) {
+            deadline.as_mut().reset(Instant::now() + timeout);
+        }
+    }
+
+    /// Resolves once the client stayed silent for the timeout; never when disabled.
+    pub async fn expired(&mut self) {
+        match self.deadline.as_mut() {
+            Some(deadline) => deadline.as_mut().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
@@ -0,0 +1,183 @@
+//! Plain HTTP on the WebSocket port, for browsers opening `http://host:port/` and clients
+//! that would rather poll JSON than hold a socket. Upgrade requests go on to the WebSocket
+//! handshake untouched.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, info};
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+
+/// How long a client has to send its request head.
+const HEAD_DEADLINE: Duration = Duration::from_secs(5);
+
+/// Longest request head read; anything longer is left to the WebSocket handshake.
+const MAX_HEAD: usize = 8192;
+
+pub struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    pub fn html(body: &str) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "text/html; charset=utf-8",
+            body: body.to_string(),
+        }
+    }
+
+    /// `status` as in the status line, e.g. `"404 Not Found"`.
+    pub fn json(status: &'static str, body: &impl Serialize) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_string(body).unwrap_or_default(),
+        }
+    }
+
+    /// Prometheus text exposition format.
+    pub fn metrics(body: String) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "text/plain; version=0.0.4; charset=utf-8",
+            body,
+        }
+    }
+
+    pub fn unauthorized() -> Self {
+        Self::json(
+            "401 Unauthorized",
+            &serde_json::json!({ "error": "unauthorized" }),
+        )
+    }
+
+    pub fn not_found() -> Self {
+        Self::text("404 Not Found", "Not found\n")
+    }
+
+    fn text(status: &'static str, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.to_string(),
+        }
+    }
+}
+
+/// Answers `GET` requests with `route(path, query)` and other methods with a 405. Returns the
+/// stream untouched for WebSocket upgrades, and for anything that doesn't parse as HTTP so
+/// the handshake reports it as before; `None` once the request was answered.
+pub async fn serve_or_upgrade(
+    mut stream: TcpStream,
+    addr: SocketAddr,
+    route: impl FnOnce(&str, Option<&str>) -> Response,
+) -> Option<TcpStream> {
+    let head = match timeout(HEAD_DEADLINE, peek_head(&stream)).await {
+        Ok(Some(head)) => head,
+        Ok(None) => return Some(stream),
+        Err(_) => {
+            debug!("No request from {addr} within {HEAD_DEADLINE:?}, closing");
+            return None;
+        }
+    };
+    let Some(request) = parse(&head) else {
+        return Some(stream);
+    };
+    if request.upgrade {
+        return Some(stream);
+    }
+
+    let response = match request.method.as_str() {
+        "GET" => route(request.path(), request.query()),
+        _ => Response::text("405 Method Not Allowed", "Method not allowed\n"),
+    };
+    info!(
+        "HTTP {} {} from {addr}: {}",
+        request.method, request.target, response.status
+    );
+
+    // Read what was peeked, so closing with unread data doesn't reset the connection
+    let mut consumed = vec![0; head.len()];
+    if stream.read_exact(&mut consumed).await.is_err() {
+        return None;
+    }
+    let Response {
+        status,
+        content_type,
+        body,
+    } = response;
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-cache\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+    None
+}
+
+/// The request head, up to and including the blank line, without consuming it. `None` when
+/// the client closed first or the head is longer than `MAX_HEAD`.
+async fn peek_head(stream: &TcpStream) -> Option<Vec<u8>> {
+    let mut buf = vec![0; MAX_HEAD];
+    loop {
+        let read = stream.peek(&mut buf).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        if let Some(end) = buf[..read].windows(4).position(|w| w == b"\r\n\r\n") {
+            buf.truncate(end + 4);
+            return Some(buf);
+        }
+        if read == buf.len() {
+            return None;
+        }
+        // Peeking doesn't wait for more than what is already there
+        sleep(Duration::from_millis(10)).await;
+    }
+}
+
+struct RequestHead {
+    method: String,
+    target: String,
+    upgrade: bool,
+}
+
+impl RequestHead {
+    fn path(&self) -> &str {
+        self.target.split(['?', '#']).next().unwrap_or(&self.target)
+    }
+
+    fn query(&self) -> Option<&str> {
+        let (_, query) = self.target.split_once('?')?;
+        query.split('#').next()
+    }
+}
+
+fn parse(head: &[u8]) -> Option<RequestHead> {
+    let head = std::str::from_utf8(head).ok()?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?.to_string();
+    if !request_line.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    let upgrade = lines
+        .filter_map(|line| line.split_once(':'))
+        .any(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("upgrade")
+                && value.trim().eq_ignore_ascii_case("websocket")
+        });
+    Some(RequestHead {
+        method,
+        target,
+        upgrade,
+    })
+}
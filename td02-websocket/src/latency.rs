@@ -0,0 +1,164 @@
+//! Feed latency as clients see it, for `ws_client --latency` and `ws_loadtest`: a `Pinger`
+//! sends `ping` commands carrying a nonce and the client's clock and matches each `pong` to
+//! its ping for the round trip time. The server's clock is taken to read the ping halfway
+//! through the fastest round trip so far, which gives its offset from the client's clock;
+//! with it, receive time minus the `sent_at` of a price is the one-way delivery delay.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use market_core::PriceUpdate;
+use tokio::time::{interval_at, Interval, MissedTickBehavior};
+
+use crate::protocol::{ClientCommand, ServerMessage};
+
+/// Pings kept waiting for their pong; past that the oldest is counted lost.
+const MAX_IN_FLIGHT: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RoundTrip {
+    pub nonce: u64,
+    pub rtt: Duration,
+    /// Server clock minus client clock, in milliseconds, as this round trip puts it.
+    pub offset_ms: f64,
+}
+
+#[derive(Debug)]
+pub struct Pinger {
+    /// `None` when pinging is left to the caller.
+    every: Option<Interval>,
+    sent: u64,
+    /// Nonce, send instant and client time of the pings without a pong, oldest first.
+    in_flight: VecDeque<(u64, Instant, DateTime<Utc>)>,
+    lost: u64,
+    fastest: Option<RoundTrip>,
+}
+
+impl Pinger {
+    /// A ping due every `every`, the first one `every` from now.
+    pub fn new(every: Option<Duration>) -> Self {
+        let every = every.map(|every| {
+            let mut ticker = interval_at(tokio::time::Instant::now() + every, every);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            ticker
+        });
+        Self {
+            every,
+            sent: 0,
+            in_flight: VecDeque::new(),
+            lost: 0,
+            fastest: None,
+        }
+    }
+
+    /// Resolves when the next ping is due; never without an interval. Cancel safe.
+    pub async fn due(&mut self) {
+        match self.every.as_mut() {
+            Some(ticker) => {
+                ticker.tick().await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    /// The next `ping`, to send right away.
+    pub fn ping(&mut self) -> ClientCommand {
+        self.sent += 1;
+        if self.in_flight.len() == MAX_IN_FLIGHT {
+            self.in_flight.pop_front();
+            self.lost += 1;
+        }
+        let now = Utc::now();
+        self.in_flight.push_back((self.sent, Instant::now(), now));
+        ClientCommand::Ping {
+            nonce: Some(self.sent.into()),
+            client_ts: Some(now.timestamp_millis().into()),
+        }
+    }
+
+    /// The round trip `message` ends, `None` unless it is the `pong` of one of our pings.
+    pub fn pong(&mut self, message: &ServerMessage) -> Option<RoundTrip> {
+        let ServerMessage::Pong {
+            nonce, server_ts, ..
+        } = message
+        else {
+            return None;
+        };
+        let nonce = nonce.as_ref()?.as_u64()?;
+        let position = self.in_flight.iter().position(|(id, ..)| *id == nonce)?;
+        // Pongs come back in order, so the pings before this one won't get theirs
+        self.lost += position as u64;
+        let (_, sent, client_ts) = self.in_flight.drain(..=position).next_back()?;
+        let rtt = sent.elapsed();
+        let halfway = client_ts + chrono::Duration::from_std(rtt / 2).unwrap_or_default();
+        let round_trip = RoundTrip {
+            nonce,
+            rtt,
+            offset_ms: millis(*server_ts - halfway),
+        };
+        if self.fastest.is_none_or(|fastest| rtt < fastest.rtt) {
+            self.fastest = Some(round_trip);
+        }
+        Some(round_trip)
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Pings whose pong never came, those still awaited aside.
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    /// Server clock minus client clock in milliseconds, from the fastest round trip.
+    pub fn offset_ms(&self) -> Option<f64> {
+        self.fastest.map(|fastest| fastest.offset_ms)
+    }
+
+    /// Milliseconds from the server sending `price` until now, the clock offset taken off
+    /// once known; `None` for prices without `sent_at`.
+    pub fn delivery_ms(&self, price: &PriceUpdate) -> Option<f64> {
+        let sent_at = price.sent_at?;
+        Some(millis(Utc::now() - sent_at) + self.offset_ms().unwrap_or_default())
+    }
+}
+
+fn millis(duration: chrono::Duration) -> f64 {
+    duration.num_microseconds().unwrap_or(i64::MAX) as f64 / 1000.0
+}
+
+/// `p`-th percentile (0-100) of sorted `values`, nearest rank.
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+pub fn sorted(mut values: Vec<f64>) -> Vec<f64> {
+    values.sort_by(f64::total_cmp);
+    values
+}
+
+/// `p50 1.2  p95 3.4  p99 5.6  max 7.8` of sorted milliseconds.
+pub fn percentiles(sorted: &[f64]) -> String {
+    let mut line = String::new();
+    for (name, value) in [
+        ("p50", percentile(sorted, 50.0)),
+        ("p95", percentile(sorted, 95.0)),
+        ("p99", percentile(sorted, 99.0)),
+        ("max", sorted.last().copied()),
+    ] {
+        let value = value.map_or_else(|| "-".to_string(), |value| format!("{value:.1}"));
+        let _ = write!(
+            line,
+            "{}{name} {value}",
+            if line.is_empty() { "" } else { "  " }
+        );
+    }
+    line
+}
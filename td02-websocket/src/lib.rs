@@ -0,0 +1,117 @@
+pub mod aggregate;
+pub mod alerts;
+pub mod args;
+pub mod auth;
+pub mod bind;
+pub mod bridge;
+pub mod candles;
+pub mod cli;
+pub mod connections;
+pub mod heartbeat;
+pub mod http;
+pub mod latency;
+pub mod metrics;
+pub mod origin;
+pub mod outbound;
+pub mod persist;
+pub mod protocol;
+pub mod rate_limit;
+pub mod reconnect;
+pub mod recording;
+pub mod replay;
+pub mod routes;
+pub mod servers;
+pub mod session;
+pub mod shutdown;
+pub mod simulator;
+pub mod staleness;
+pub mod status;
+pub mod subscription;
+pub mod throttle;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Args;
+use heartbeat::HeartbeatConfig;
+use origin::{OriginArgs, OriginPolicy};
+use outbound::OutboundConfig;
+use protocol::Layout;
+use rate_limit::RateLimitConfig;
+use routes::{Route, RouteArgs, Routes};
+
+/// The client settings given on the command line; the others come from `WS_*` variables.
+#[derive(Args, Debug, Clone, Default)]
+pub struct ClientArgs {
+    /// Close clients silent for this long (e.g. 90s), 0s to keep them [default: 10m]
+    #[arg(long, value_parser = args::duration)]
+    pub idle_timeout: Option<Duration>,
+
+    #[command(flatten)]
+    pub origins: OriginArgs,
+
+    /// Send the flat frames of before the versioned envelope
+    #[arg(long)]
+    pub legacy_format: bool,
+}
+
+/// Settings applied to every client connection.
+#[derive(Clone)]
+pub struct ClientConfig {
+    pub heartbeat: HeartbeatConfig,
+    /// Silence after which a client is closed, `None` to keep it.
+    pub idle_timeout: Option<Duration>,
+    pub rate_limit: RateLimitConfig,
+    pub outbound: OutboundConfig,
+    /// Token clients must present, `None` for an open feed.
+    pub auth_token: Option<Arc<str>>,
+    /// Token that adds the connection list to `stats`, `None` to never show it.
+    pub admin_token: Option<Arc<str>>,
+    /// Browser origins allowed to connect.
+    pub origins: Arc<OriginPolicy>,
+    /// WebSocket paths upgraded to the feed, the others get a 404.
+    pub routes: Arc<Routes>,
+    /// Frame layout, the envelope unless `--legacy-format`.
+    pub layout: Layout,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat: HeartbeatConfig::default(),
+            idle_timeout: Some(heartbeat::DEFAULT_IDLE_TIMEOUT),
+            rate_limit: RateLimitConfig::default(),
+            outbound: OutboundConfig::default(),
+            auth_token: None,
+            admin_token: None,
+            origins: Arc::default(),
+            routes: Arc::default(),
+            layout: Layout::default(),
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Defaults with the `WS_*` environment overrides, and the idle timeout, origins, routes
+    /// and layout from the arguments.
+    pub fn from_args(args: &ClientArgs, routes: &RouteArgs) -> Result<Self, String> {
+        Ok(Self {
+            heartbeat: HeartbeatConfig::default().with_env()?,
+            idle_timeout: match args.idle_timeout {
+                Some(timeout) => (!timeout.is_zero()).then_some(timeout),
+                None => Some(heartbeat::DEFAULT_IDLE_TIMEOUT),
+            },
+            rate_limit: RateLimitConfig::default().with_env()?,
+            outbound: OutboundConfig::default().with_env()?,
+            auth_token: auth::token_from_env().map(Arc::from),
+            admin_token: auth::admin_token_from_env().map(Arc::from),
+            origins: Arc::new(OriginPolicy::from_args(&args.origins)),
+            routes: Arc::new(Routes::default().with_args(routes, &[Route::Prices])?),
+            layout: if args.legacy_format {
+                Layout::Legacy
+            } else {
+                Layout::Envelope
+            },
+        })
+    }
+}
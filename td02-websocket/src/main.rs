@@ -1,3 +1,51 @@
-fn main() {
-    println!("Run one of the bins: ws_echo, ws_broadcast, ws_dashboard");
+//! `td02`: every server of the crate in one binary. `td02 serve echo|broadcast|dashboard`
+//! runs one, with the options of its own binary (`ws_echo`, `ws_broadcast`, `ws_dashboard`,
+//! which stay as shortcuts); `td02 serve all` runs them side by side, see
+//! `td02_websocket::cli`.
+
+use clap::{Parser, Subcommand};
+use td02_websocket::cli::{self, AllCommand, BroadcastCommand, DashboardCommand, EchoCommand};
+
+#[derive(Parser, Debug)]
+#[command(name = "td02", version, about = "WebSocket price servers", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Runs one server, or several in one process
+    #[command(subcommand)]
+    Serve(Serve),
+}
+
+#[derive(Subcommand, Debug)]
+enum Serve {
+    /// Echo server, or chat relay with --mode chat (port 8080)
+    Echo(EchoCommand),
+    /// Simulated or replayed prices (port 8081)
+    Broadcast(BroadcastCommand),
+    /// Prices from the database, with the dashboard page (port 8082)
+    Dashboard(DashboardCommand),
+    /// Every server side by side in one process
+    ///
+    /// Or those of --servers echo,dashboard, on --echo-port, --broadcast-port and
+    /// --dashboard-port (8080, 8081, 8082); --metrics-port and --persist-stream go to the
+    /// dashboard.
+    All(Box<AllCommand>),
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let Command::Serve(serve) = Cli::parse().command;
+    cli::init();
+
+    let configs = match serve {
+        Serve::Echo(command) => command.configs()?,
+        Serve::Broadcast(command) => command.configs()?,
+        Serve::Dashboard(command) => command.configs()?,
+        Serve::All(command) => command.configs()?,
+    };
+    cli::serve(configs).await
 }
@@ -0,0 +1,232 @@
+//! Prometheus text metrics on a listener of their own (`--metrics-port N`, else
+//! `WS_METRICS_PORT`), off by default. Everything is read from the atomics behind `stats`,
+//! so the send path takes no lock for them.
+
+use std::fmt::{Display, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{info, warn};
+use tokio::net::TcpListener;
+
+use crate::connections::Connections;
+use crate::http::{self, Response};
+
+/// Database polls of `ws_dashboard`.
+#[derive(Debug, Default)]
+pub struct PollMetrics {
+    polls: AtomicU64,
+    errors: AtomicU64,
+    rows: AtomicU64,
+    micros: AtomicU64,
+    last_micros: AtomicU64,
+    /// The last poll failed, or the price listener was lost since.
+    failing: AtomicBool,
+}
+
+impl PollMetrics {
+    /// A poll that took `took` and fetched `rows`, `None` when it failed.
+    pub fn record(&self, took: Duration, rows: Option<usize>) {
+        let micros = took.as_micros().try_into().unwrap_or(u64::MAX);
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.micros.fetch_add(micros, Ordering::Relaxed);
+        self.last_micros.store(micros, Ordering::Relaxed);
+        match rows {
+            Some(rows) => self.rows.fetch_add(rows as u64, Ordering::Relaxed),
+            None => self.errors.fetch_add(1, Ordering::Relaxed),
+        };
+        self.failing.store(rows.is_none(), Ordering::Relaxed);
+    }
+
+    /// The connection listening for new prices broke; the next poll tells whether the
+    /// database is still there.
+    pub fn listener_lost(&self) {
+        self.failing.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the last exchange with the database went through.
+    pub fn db_ok(&self) -> bool {
+        !self.failing.load(Ordering::Relaxed)
+    }
+}
+
+/// `--metrics-port N` when given, else `WS_METRICS_PORT`; `None` without either.
+pub fn metrics_port(arg: Option<u16>) -> Result<Option<u16>, String> {
+    if arg.is_some() {
+        return Ok(arg);
+    }
+    let Some(raw) = std::env::var("WS_METRICS_PORT")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+    else {
+        return Ok(None);
+    };
+    raw.trim()
+        .parse::<u16>()
+        .map(Some)
+        .map_err(|_| format!("WS_METRICS_PORT: expected a port number, got '{raw}'"))
+}
+
+/// Listens on `addr` and answers `GET /metrics` until the task is aborted.
+pub async fn serve(
+    addr: SocketAddr,
+    connections: Arc<Connections>,
+    polls: Option<Arc<PollMetrics>>,
+) -> std::io::Result<tokio::task::JoinHandle<()>> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Metrics on http://{}/metrics", listener.local_addr()?);
+    Ok(tokio::spawn(async move {
+        loop {
+            let (stream, addr) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Metrics listener: {e}");
+                    continue;
+                }
+            };
+            let connections = connections.clone();
+            let polls = polls.clone();
+            tokio::spawn(async move {
+                // Nothing to upgrade to here, so upgrade requests are just dropped
+                let _ = http::serve_or_upgrade(stream, addr, |path, _| match path {
+                    "/metrics" => Response::metrics(render(&connections, polls.as_deref())),
+                    _ => Response::not_found(),
+                })
+                .await;
+            });
+        }
+    }))
+}
+
+fn render(connections: &Connections, polls: Option<&PollMetrics>) -> String {
+    let stats = connections.stats();
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: &dyn Display| {
+        let _ = write!(
+            out,
+            "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"
+        );
+    };
+
+    metric(
+        "ws_uptime_seconds",
+        "gauge",
+        "Seconds since the server started.",
+        &stats.uptime_seconds,
+    );
+    metric(
+        "ws_active_connections",
+        "gauge",
+        "Clients currently connected.",
+        &stats.active_connections,
+    );
+    metric(
+        "ws_connections_accepted_total",
+        "counter",
+        "Clients let in since the start.",
+        &stats.connections_total,
+    );
+    metric(
+        "ws_connections_closed_total",
+        "counter",
+        "Clients gone since the start.",
+        &connections.closed_total(),
+    );
+    metric(
+        "ws_messages_sent_total",
+        "counter",
+        "Prices delivered to clients.",
+        &stats.messages_sent,
+    );
+    metric(
+        "ws_messages_dropped_total",
+        "counter",
+        "Prices lost to lagging clients, full send queues or failed sends.",
+        &stats.messages_dropped,
+    );
+    metric(
+        "ws_lagged_events_total",
+        "counter",
+        "Times a client fell behind the broadcast channel.",
+        &stats.lagged_total,
+    );
+    metric(
+        "ws_slow_disconnects_total",
+        "counter",
+        "Clients closed because their send queue stayed full.",
+        &stats.slow_disconnects_total,
+    );
+    metric(
+        "ws_rate_limited_total",
+        "counter",
+        "Inbound messages dropped by the rate limit.",
+        &stats.rate_limited_total,
+    );
+    metric(
+        "ws_updates_suppressed_total",
+        "counter",
+        "Updates held back as too close to the previous one.",
+        &stats.updates_suppressed,
+    );
+    if let Some(capacity) = stats.channel_capacity {
+        metric(
+            "ws_channel_capacity",
+            "gauge",
+            "Prices a client may fall behind before it lags.",
+            &capacity,
+        );
+    }
+    if let Some(depth) = stats.channel_depth {
+        metric(
+            "ws_channel_depth",
+            "gauge",
+            "Prices in the broadcast channel not yet read by every client.",
+            &depth,
+        );
+    }
+    if let Some(subscribers) = stats.subscribers {
+        metric(
+            "ws_channel_subscribers",
+            "gauge",
+            "Receivers of the broadcast channel.",
+            &subscribers,
+        );
+    }
+
+    if let Some(polls) = polls {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        metric(
+            "ws_db_polls_total",
+            "counter",
+            "Database polls, failed ones included.",
+            &load(&polls.polls),
+        );
+        metric(
+            "ws_db_poll_errors_total",
+            "counter",
+            "Database polls that failed.",
+            &load(&polls.errors),
+        );
+        metric(
+            "ws_db_rows_fetched_total",
+            "counter",
+            "Rows returned by database polls.",
+            &load(&polls.rows),
+        );
+        metric(
+            "ws_db_poll_seconds_total",
+            "counter",
+            "Time spent in database polls.",
+            &(load(&polls.micros) as f64 / 1e6),
+        );
+        metric(
+            "ws_db_poll_last_seconds",
+            "gauge",
+            "Duration of the last database poll.",
+            &(load(&polls.last_micros) as f64 / 1e6),
+        );
+    }
+    out
+}
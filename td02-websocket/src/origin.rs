@@ -0,0 +1,88 @@
+//! Origin check on the WebSocket handshake, against cross-site WebSocket hijacking: browsers
+//! send the `Origin` of the page opening the socket, so a page elsewhere can't read the feed
+//! through the visitor's browser. Allowed: the server's own origin (the dashboard it serves),
+//! the `--allowed-origin` ones (repeatable, `*` for any, `null` for pages opened from a file)
+//! and clients sending no `Origin`, which are not browsers, unless `--require-origin`.
+//! Refused upgrades get a 403.
+
+use clap::Args;
+use tokio_tungstenite::tungstenite::handshake::server::Request;
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct OriginArgs {
+    /// Browser origin allowed besides the server's own, repeatable (https://example.com, *
+    /// for any, null for local files)
+    #[arg(long = "allowed-origin", value_name = "ORIGIN", value_parser = origin)]
+    pub allowed: Vec<String>,
+
+    /// Refuse clients that send no Origin
+    #[arg(long = "require-origin")]
+    pub require: bool,
+}
+
+/// Lowercase without trailing slash, `*` and `null` included.
+fn origin(raw: &str) -> Result<String, String> {
+    let origin = normalize(raw);
+    if origin == "*" || origin == "null" || origin.contains("://") {
+        Ok(origin)
+    } else {
+        Err("expected an origin such as https://example.com, * or null".to_string())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OriginPolicy {
+    /// Lowercase, without trailing slash.
+    allowed: Vec<String>,
+    any: bool,
+    require: bool,
+}
+
+impl OriginPolicy {
+    /// From every `--allowed-origin` (`https://example.com`, `*`) and `--require-origin`.
+    pub fn from_args(args: &OriginArgs) -> Self {
+        Self {
+            allowed: args
+                .allowed
+                .iter()
+                .filter(|origin| *origin != "*")
+                .cloned()
+                .collect(),
+            any: args.allowed.iter().any(|origin| origin == "*"),
+            require: args.require,
+        }
+    }
+
+    /// `Err` with the origin (`none` when missing) to log when `request` is refused.
+    pub fn check(&self, request: &Request) -> Result<(), String> {
+        let header = |name: &str| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        let Some(origin) = header("origin") else {
+            return if self.require {
+                Err("none".to_string())
+            } else {
+                Ok(())
+            };
+        };
+        let normalized = normalize(origin);
+        // Same origin: the host part of the origin is the Host the request was sent to
+        let same_origin = header("host").is_some_and(|host| {
+            normalized
+                .split_once("://")
+                .is_some_and(|(_, origin_host)| origin_host == host.to_ascii_lowercase())
+        });
+        if self.any || same_origin || self.allowed.contains(&normalized) {
+            Ok(())
+        } else {
+            Err(origin.to_string())
+        }
+    }
+}
+
+fn normalize(origin: &str) -> String {
+    origin.trim().trim_end_matches('/').to_ascii_lowercase()
+}
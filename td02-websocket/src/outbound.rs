@@ -0,0 +1,158 @@
+//! Per-connection send queue. The client loop queues its frames and a writer running next to
+//! it sends them, so a slow client only holds up its own socket: its commands, pings and
+//! shutdown keep being handled meanwhile. With the queue full, the oldest queued price makes
+//! room, a newer one supersedes it anyway; other messages are never dropped. A client whose
+//! queue stays full for `slow_after` is disconnected as too slow.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures_util::{Sink, SinkExt};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::{Error, Message};
+
+use crate::connections::Tally;
+
+#[derive(Debug, Clone, Copy)]
+pub struct OutboundConfig {
+    /// Frames queued per client before prices get dropped.
+    pub capacity: usize,
+    pub slow_after: Duration,
+}
+
+impl Default for OutboundConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            slow_after: Duration::from_secs(10),
+        }
+    }
+}
+
+impl OutboundConfig {
+    /// Overrides from `WS_SEND_QUEUE` (frames) and `WS_SLOW_CLIENT_TIMEOUT` (`10s`, `500ms`...).
+    pub fn with_env(mut self) -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        if let Some(raw) = var("WS_SEND_QUEUE") {
+            self.capacity = raw
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| format!("WS_SEND_QUEUE: expected a positive number, got '{raw}'"))?;
+        }
+        if let Some(raw) = var("WS_SLOW_CLIENT_TIMEOUT") {
+            self.slow_after = humantime::parse_duration(raw.trim())
+                .ok()
+                .filter(|d| !d.is_zero())
+                .ok_or_else(|| {
+                    format!("WS_SLOW_CLIENT_TIMEOUT: expected a duration such as 10s, got '{raw}'")
+                })?;
+        }
+        Ok(self)
+    }
+}
+
+pub struct Outbound {
+    cfg: OutboundConfig,
+    state: Mutex<State>,
+    /// Wakes the writer up after a push or the close.
+    ready: Notify,
+}
+
+#[derive(Default)]
+struct State {
+    /// Frames, flagged when they carry a price.
+    queue: VecDeque<(Message, bool)>,
+    /// Since when the queue has been full without the writer catching up.
+    full_since: Option<Instant>,
+    closed: bool,
+}
+
+impl Outbound {
+    pub fn new(cfg: OutboundConfig) -> Self {
+        Self {
+            cfg,
+            state: Mutex::new(State::default()),
+            ready: Notify::new(),
+        }
+    }
+
+    /// Queues a price frame; `true` when the oldest queued price was dropped to make room.
+    pub fn push_price(&self, frame: Message) -> bool {
+        self.push_frame(frame, true)
+    }
+
+    /// Queues any other frame, never dropped.
+    pub fn push(&self, frame: Message) {
+        self.push_frame(frame, false);
+    }
+
+    fn push_frame(&self, frame: Message, price: bool) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let mut dropped = false;
+        if state.queue.len() >= self.cfg.capacity {
+            state.full_since.get_or_insert_with(Instant::now);
+            if let Some(oldest) = state.queue.iter().position(|(_, price)| *price) {
+                state.queue.remove(oldest);
+                dropped = true;
+            }
+        }
+        state.queue.push_back((frame, price));
+        drop(state);
+        self.ready.notify_one();
+        dropped
+    }
+
+    /// Whether the queue has been full for `slow_after` or longer.
+    pub fn too_slow(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .full_since
+            .is_some_and(|since| since.elapsed() >= self.cfg.slow_after)
+    }
+
+    /// Forgets what is queued, for a client about to be closed anyway.
+    pub fn clear(&self) {
+        self.state.lock().unwrap().queue.clear();
+    }
+
+    /// Lets the writer finish once the queue is sent.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.ready.notify_one();
+    }
+
+    /// Sends the queue out to `sink` until closed and empty, or the sink fails. Prices that
+    /// went out are counted on `tally`.
+    pub async fn write<S>(&self, mut sink: S, tally: Tally<'_>) -> Result<(), Error>
+    where
+        S: Sink<Message, Error = Error> + Unpin,
+    {
+        loop {
+            let next = {
+                let mut state = self.state.lock().unwrap();
+                let next = state.queue.pop_front();
+                if state.queue.len() < self.cfg.capacity {
+                    state.full_since = None;
+                }
+                if next.is_none() && state.closed {
+                    return Ok(());
+                }
+                next
+            };
+            match next {
+                Some((frame, price)) => {
+                    sink.send(frame).await?;
+                    if price {
+                        tally.sent();
+                    }
+                }
+                None => self.ready.notified().await,
+            }
+        }
+    }
+}
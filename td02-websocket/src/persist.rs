@@ -0,0 +1,309 @@
+//! `--persist-stream DIR`: every price broadcast, every announcement and, on `ws_dashboard`,
+//! every aggregate, alert, candle and status also goes to JSON lines files in `DIR`, in the
+//! format of `ws_client --record` with the server's time as `received_at`, so a file plays
+//! back with `ws_broadcast --replay`. One file per hour, `stream-2026-10-17T14.jsonl`, with
+//! `.1`, `.2`... parts past `--persist-max-size` (`100M`); files older than
+//! `--persist-retention` (`7d`) are deleted as new ones open.
+//!
+//! The writer is a task of its own with its own receivers, so a slow disk only holds up the
+//! file: what it couldn't keep up with is lost to the file and counted in the logs, never
+//! to the clients. Lines are flushed whenever it has caught up.
+
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use log::{error, info, warn};
+use market_core::PriceUpdate;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+
+use crate::args;
+use crate::protocol::{Announcement, ServerMessage};
+use crate::recording::Recorded;
+
+const DEFAULT_MAX_SIZE: u64 = 100 << 20;
+const DEFAULT_RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct PersistArgs {
+    /// Also write the stream to hourly JSON lines files in this directory
+    #[arg(long, value_name = "DIR")]
+    pub persist_stream: Option<PathBuf>,
+
+    /// Size past which a file continues in a new part (e.g. 512K, 1G) [default: 100M]
+    #[arg(long, value_parser = size, requires = "persist_stream")]
+    pub persist_max_size: Option<u64>,
+
+    /// Age past which files are deleted (e.g. 12h) [default: 7d]
+    #[arg(long, value_parser = args::nonzero_duration, requires = "persist_stream")]
+    pub persist_retention: Option<Duration>,
+}
+
+/// Where and how much of the stream to keep.
+#[derive(Debug, Clone)]
+pub struct PersistConfig {
+    pub dir: PathBuf,
+    /// Bytes per file before the next part.
+    pub max_size: u64,
+    pub retention: Duration,
+}
+
+impl PersistConfig {
+    /// `None` without `--persist-stream DIR`.
+    pub fn from_args(args: &PersistArgs) -> Option<Self> {
+        Some(Self {
+            dir: args.persist_stream.clone()?,
+            max_size: args.persist_max_size.unwrap_or(DEFAULT_MAX_SIZE),
+            retention: args.persist_retention.unwrap_or(DEFAULT_RETENTION),
+        })
+    }
+}
+
+/// `--persist-max-size`, above zero.
+fn size(raw: &str) -> Result<u64, String> {
+    parse_size(raw)
+        .filter(|size| *size > 0)
+        .ok_or_else(|| "expected a size such as 100M, 512K or bytes".to_string())
+}
+
+/// `100M`, `512K`, `1G` or plain bytes.
+fn parse_size(raw: &str) -> Option<u64> {
+    let raw = raw.trim();
+    let (digits, shift) = match raw.char_indices().last()? {
+        (i, 'K' | 'k') => (&raw[..i], 10),
+        (i, 'M' | 'm') => (&raw[..i], 20),
+        (i, 'G' | 'g') => (&raw[..i], 30),
+        _ => (raw, 0),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// What the writer records, each from a receiver of its own.
+pub struct Sources {
+    pub prices: broadcast::Receiver<PriceUpdate>,
+    pub announcements: broadcast::Receiver<Announcement>,
+    /// `ws_dashboard`'s aggregates, alerts, candles and statuses.
+    pub notices: Option<broadcast::Receiver<ServerMessage>>,
+}
+
+/// Creates `cfg.dir` if needed and starts the writer.
+pub fn start(cfg: PersistConfig, sources: Sources) -> io::Result<JoinHandle<()>> {
+    std::fs::create_dir_all(&cfg.dir).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("cannot create {}: {e}", cfg.dir.display()),
+        )
+    })?;
+    info!(
+        "Persisting the stream to {} (hourly files, {} bytes at most, kept {})",
+        cfg.dir.display(),
+        cfg.max_size,
+        humantime::format_duration(cfg.retention)
+    );
+    Ok(tokio::spawn(persist(cfg, sources)))
+}
+
+async fn persist(cfg: PersistConfig, mut sources: Sources) {
+    let mut writer = Writer {
+        cfg,
+        file: None,
+        failing: false,
+        lost: 0,
+    };
+    let mut dropped = 0;
+    loop {
+        let received = tokio::select! {
+            price = sources.prices.recv() => price.map(ServerMessage::Price),
+            announcement = sources.announcements.recv() => {
+                announcement.map(ServerMessage::Announcement)
+            }
+            notice = async {
+                match &mut sources.notices {
+                    Some(notices) => notices.recv().await,
+                    None => std::future::pending().await,
+                }
+            } => notice,
+        };
+        match received {
+            Ok(message) => writer.write(&message).await,
+            Err(RecvError::Lagged(missed)) => {
+                dropped += missed;
+                warn!(
+                    "Persisting the stream fell behind, {missed} messages not written ({dropped} since the start)"
+                );
+            }
+            Err(RecvError::Closed) => break,
+        }
+        let caught_up = sources.prices.is_empty()
+            && sources.announcements.is_empty()
+            && sources
+                .notices
+                .as_ref()
+                .is_none_or(|notices| notices.is_empty());
+        if caught_up {
+            writer.flush().await;
+        }
+    }
+    writer.flush().await;
+}
+
+struct Writer {
+    cfg: PersistConfig,
+    file: Option<Open>,
+    /// Set after a failed write, so a broken disk logs once rather than per message.
+    failing: bool,
+    /// Messages lost since it started failing.
+    lost: u64,
+}
+
+struct Open {
+    file: BufWriter<File>,
+    /// `2026-10-17T14`, the hour it is for.
+    hour: String,
+    part: u32,
+    size: u64,
+}
+
+impl Writer {
+    async fn write(&mut self, message: &ServerMessage) {
+        let now = Utc::now();
+        let mut line = match serde_json::to_vec(&Recorded {
+            received_at: now,
+            message: message.to_envelope_value(),
+        }) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Cannot encode a message to persist: {e}");
+                return;
+            }
+        };
+        line.push(b'\n');
+        let result = async {
+            let open = self.open(now, line.len() as u64).await?;
+            open.file.write_all(&line).await?;
+            open.size += line.len() as u64;
+            io::Result::Ok(())
+        }
+        .await;
+        match result {
+            Ok(()) if self.failing => {
+                info!(
+                    "Writing the stream to {} again, {} messages not written meanwhile",
+                    self.cfg.dir.display(),
+                    self.lost
+                );
+                self.failing = false;
+                self.lost = 0;
+            }
+            Ok(()) => {}
+            Err(e) => {
+                if !self.failing {
+                    error!(
+                        "Cannot write the stream to {}, dropping messages until it works: {e}",
+                        self.cfg.dir.display()
+                    );
+                    self.failing = true;
+                }
+                self.lost += 1;
+                // Reopened from scratch with the next message
+                self.file = None;
+            }
+        }
+    }
+
+    /// The file for a line of `len` bytes at `now`, the next one when the hour changed or
+    /// the current one is full.
+    async fn open(&mut self, now: DateTime<Utc>, len: u64) -> io::Result<&mut Open> {
+        let hour = now.format("%Y-%m-%dT%H").to_string();
+        let current = self.file.as_ref().and_then(|open| {
+            (open.hour == hour).then_some((open.part, open.size + len > self.cfg.max_size))
+        });
+        let mut part = match current {
+            Some((_, false)) => return Ok(self.file.as_mut().expect("checked above")),
+            Some((part, true)) => part + 1,
+            None => 0,
+        };
+        self.flush().await;
+        self.file = None;
+        self.prune().await;
+
+        // A restart within the hour appends to where the previous run stopped
+        loop {
+            let path = self.cfg.dir.join(file_name(&hour, part));
+            let size = match fs::metadata(&path).await {
+                Ok(metadata) => metadata.len(),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+                Err(e) => return Err(e),
+            };
+            if size > 0 && size + len > self.cfg.max_size {
+                part += 1;
+                continue;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await?;
+            info!("Persisting the stream to {}", path.display());
+            return Ok(self.file.insert(Open {
+                file: BufWriter::new(file),
+                hour,
+                part,
+                size,
+            }));
+        }
+    }
+
+    async fn flush(&mut self) {
+        if let Some(open) = &mut self.file {
+            if let Err(e) = open.file.flush().await {
+                error!("Cannot flush the stream file: {e}");
+                self.file = None;
+            }
+        }
+    }
+
+    /// Deletes the stream files last written before the retention.
+    async fn prune(&self) {
+        let Some(cutoff) = SystemTime::now().checked_sub(self.cfg.retention) else {
+            return;
+        };
+        let mut entries = match fs::read_dir(&self.cfg.dir).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Cannot list {}: {e}", self.cfg.dir.display());
+                return;
+            }
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !(name.starts_with("stream-") && name.ends_with(".jsonl")) {
+                continue;
+            }
+            let old = entry
+                .metadata()
+                .await
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified < cutoff);
+            if old {
+                match fs::remove_file(entry.path()).await {
+                    Ok(()) => info!("Deleted {}, past the retention", entry.path().display()),
+                    Err(e) => warn!("Cannot delete {}: {e}", entry.path().display()),
+                }
+            }
+        }
+    }
+}
+
+fn file_name(hour: &str, part: u32) -> String {
+    match part {
+        0 => format!("stream-{hour}.jsonl"),
+        part => format!("stream-{hour}.{part}.jsonl"),
+    }
+}
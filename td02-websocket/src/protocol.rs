@@ -0,0 +1,1295 @@
+//! Frames exchanged with WebSocket clients. Clients send JSON commands tagged by `action`
+//! (`{"action":"subscribe","symbols":["AAPL"]}`); every server frame is an envelope
+//! `{"v":1,"type":"price","data":{...}}`, sent as JSON text or, for clients that chose it,
+//! as MessagePack binary frames. `v` is `PROTOCOL_VERSION`, also in
+//! the welcome message. `--legacy-format` sends the flat frames of before the envelope
+//! (`{"type":"price","symbol":...}`) instead, for one release while consumers migrate.
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use market_core::PriceUpdate;
+use serde::{Deserialize, Serialize};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Version of the server frames, bumped on incompatible changes.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Prices sent back by `history` when the client gives no `limit`, and the most it may ask.
+pub const DEFAULT_HISTORY_LIMIT: u32 = 50;
+pub const MAX_HISTORY_LIMIT: u32 = 500;
+
+/// Longest `announce` message, in characters.
+pub const MAX_ANNOUNCEMENT_LEN: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum ClientCommand {
+    /// First message of a client that didn't put the token on the upgrade URL.
+    Auth {
+        token: String,
+    },
+    /// The admin token (`WS_ADMIN_TOKEN`) adds the list of connections to the reply.
+    Stats {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        admin_token: Option<String>,
+    },
+    /// Answered at once by a `pong` echoing `nonce` and `client_ts`, both optional and
+    /// any JSON value, with the server's clock: round trip time and clock offset.
+    Ping {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        nonce: Option<serde_json::Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_ts: Option<serde_json::Value>,
+    },
+    Subscribe {
+        symbols: Vec<String>,
+    },
+    Unsubscribe {
+        symbols: Vec<String>,
+    },
+    /// Encoding of the frames sent to this client from now on.
+    #[serde(rename = "set_format")]
+    SetFormat {
+        format: Format,
+    },
+    /// At most `max_per_sec` prices per second of each symbol and source, the newest; `0`
+    /// for every one.
+    #[serde(rename = "set_throttle")]
+    SetThrottle {
+        max_per_sec: f64,
+    },
+    /// Last closed one-minute candles of `symbol`, oldest first; all those kept without
+    /// a `limit`.
+    Candles {
+        symbol: String,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    /// Stored prices from `since` (Unix seconds) on, of `symbols` or else of the
+    /// subscription, sent as `price` messages flagged `replayed` before live ones resume.
+    Replay {
+        since: i64,
+        #[serde(default)]
+        symbols: Vec<String>,
+    },
+    /// Symbols and sources with a price so far, for a symbol picker.
+    Symbols,
+    /// Every connection with its subscription; needs the admin token.
+    #[serde(rename = "admin_list")]
+    AdminList {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        admin_token: Option<String>,
+    },
+    /// Closes connection `id` of `admin_list`; needs the admin token.
+    #[serde(rename = "admin_kick")]
+    AdminKick {
+        id: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        admin_token: Option<String>,
+    },
+    /// Sends `message` to every client as an `announcement`; needs the admin token.
+    Announce {
+        message: String,
+        #[serde(default)]
+        level: AnnouncementLevel,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        admin_token: Option<String>,
+    },
+    /// Last prices stored for `symbol`, from `source` only if given, oldest first.
+    History {
+        symbol: String,
+        #[serde(default)]
+        source: Option<String>,
+        #[serde(default = "default_history_limit")]
+        limit: u32,
+    },
+}
+
+/// How server frames are encoded for a client: JSON text (the default) or MessagePack
+/// binary with the same field names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Msgpack,
+}
+
+impl Format {
+    /// `format=json` or `format=msgpack` from an upgrade URL.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "json" => Some(Self::Json),
+            "msgpack" => Some(Self::Msgpack),
+            _ => None,
+        }
+    }
+}
+
+/// Layout of the server frames: the versioned envelope, or the flat legacy shape.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    #[default]
+    Envelope,
+    Legacy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Aggregate {
+    pub symbol: String,
+    /// Average across sources of each source's average over the window.
+    pub avg: f64,
+    /// Highest minus lowest of those averages; `null` with a single source.
+    pub spread: Option<f64>,
+    pub sources: usize,
+    pub window_secs: u64,
+}
+
+/// Prices of one symbol within the minute from `start`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candle {
+    pub symbol: String,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub start: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertKind {
+    Move,
+    Spread,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub rule: String,
+    pub symbol: String,
+    /// The move or spread that set the alert off, and the rule's limit, in percent.
+    pub value_pct: f64,
+    pub threshold_pct: f64,
+    pub message: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnouncementLevel {
+    #[default]
+    Info,
+    Warning,
+}
+
+/// A notice from an operator, such as planned maintenance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Announcement {
+    pub message: String,
+    pub level: AnnouncementLevel,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A symbol that got nothing from `source` for longer than its threshold.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StaleSymbol {
+    pub symbol: String,
+    pub source: String,
+    /// Timestamp of its last price.
+    pub last_update: DateTime<Utc>,
+    pub age_secs: u64,
+}
+
+/// Connected clients per frame format.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FormatCounts {
+    pub json: usize,
+    pub msgpack: usize,
+}
+
+/// Server-wide counters of the `stats` reply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerStats {
+    pub uptime_seconds: u64,
+    pub active_connections: usize,
+    /// `None` without `--max-connections`.
+    pub max_connections: Option<usize>,
+    /// Connections let in since the start.
+    pub connections_total: u64,
+    /// Prices delivered to clients, and prices they lost to lag, full send queues or failed
+    /// sends.
+    pub messages_sent: u64,
+    pub messages_dropped: u64,
+    /// Times a client fell behind the broadcast channel.
+    pub lagged_total: u64,
+    /// Clients closed because their send queue stayed full.
+    pub slow_disconnects_total: u64,
+    pub channel_capacity: Option<usize>,
+    /// Prices in the broadcast channel not yet read by every client.
+    pub channel_depth: Option<usize>,
+    /// Receivers of the broadcast channel, clients still in their handshake included.
+    pub subscribers: Option<usize>,
+    /// Inbound messages dropped by the rate limit, all connections together.
+    pub rate_limited_total: u64,
+    /// Updates `ws_dashboard --min-change` held back as too close to the previous one.
+    pub updates_suppressed: u64,
+    pub formats: FormatCounts,
+}
+
+/// A known symbol with its newest price, and when each source last quoted it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymbolInfo {
+    pub symbol: String,
+    pub timestamp: DateTime<Utc>,
+    pub age_seconds: f64,
+    pub sources: Vec<SourceQuote>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceQuote {
+    pub source: String,
+    pub timestamp: DateTime<Utc>,
+    pub age_seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub addr: String,
+    /// The path the connection upgraded on, such as `/prices`.
+    #[serde(default)]
+    pub path: String,
+    pub connected_at: DateTime<Utc>,
+    pub format: Format,
+    /// `None` while subscribed to every symbol.
+    pub subscribed_symbols: Option<usize>,
+    /// The subscribed symbols, `None` for every symbol but `excluded`.
+    #[serde(default)]
+    pub symbols: Option<Vec<String>>,
+    #[serde(default)]
+    pub excluded: Vec<String>,
+    pub messages_sent: u64,
+    /// Prices lost to lag or a full send queue.
+    pub messages_dropped: u64,
+    /// Times this connection fell behind the broadcast channel.
+    pub lagged: u64,
+    /// Its throttle, `None` without, and the prices it replaced by newer ones.
+    #[serde(default)]
+    pub throttle_per_sec: Option<f64>,
+    #[serde(default)]
+    pub messages_coalesced: u64,
+}
+
+fn default_history_limit() -> u32 {
+    DEFAULT_HISTORY_LIMIT
+}
+
+impl ClientCommand {
+    /// Parses a Text frame. The bare `/stats` of the first protocol version is still accepted.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        if text.trim() == "/stats" {
+            return Ok(Self::Stats { admin_token: None });
+        }
+        serde_json::from_str(text).map_err(|e| format!("invalid command: {e}"))
+    }
+
+    /// The command as it may be logged: its JSON with `token` and `admin_token` masked.
+    pub fn redacted(&self) -> String {
+        let mut value = serde_json::to_value(self).expect("client commands always serialize");
+        if let Some(fields) = value.as_object_mut() {
+            for key in ["token", "admin_token"] {
+                if let Some(secret) = fields.get_mut(key) {
+                    *secret = "***".into();
+                }
+            }
+        }
+        value.to_string()
+    }
+}
+
+/// Deserializing accepts an envelope of any version (`v` is ignored) but not the legacy
+/// shape; see `from_value` for that.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "lowercase")]
+pub enum ServerMessage {
+    /// `version` is the server's `PROTOCOL_VERSION`.
+    Connected {
+        message: String,
+        #[serde(default)]
+        version: u32,
+        /// The id the server logs this connection under, to quote when reporting a problem.
+        #[serde(default)]
+        connection_id: Option<u64>,
+    },
+    Price(PriceUpdate),
+    /// Rolling average and spread across sources of one symbol, sent periodically.
+    Aggregate(Aggregate),
+    /// A price move or a spread past an alert rule, sent to every client.
+    Alert(Alert),
+    /// A one-minute candle, sent once its minute is over.
+    Candle(Candle),
+    /// Sent to every client when an admin uses `announce`.
+    Announcement(Announcement),
+    /// A symbol stopped getting prices from a source, see `resumed` for when they come back.
+    Stale(StaleSymbol),
+    /// Prices of a stale symbol from that source again, `gap_secs` after the last one.
+    Resumed {
+        symbol: String,
+        source: String,
+        last_update: DateTime<Utc>,
+        gap_secs: u64,
+    },
+    /// Sent to every client on a timer; `db_ok` is false while the database fails.
+    Status {
+        uptime_secs: u64,
+        active_connections: usize,
+        /// Prices broadcast since the previous status, before subscription filters.
+        updates_last_interval: u64,
+        db_ok: bool,
+    },
+    /// Latest price per symbol and source, sent once right after `connected`, and on
+    /// `ws_dashboard` the pairs stale at the moment.
+    Snapshot {
+        prices: Vec<PriceUpdate>,
+        #[serde(default)]
+        stale: Vec<StaleSymbol>,
+    },
+    /// The client fell behind and `missed` updates were skipped; `ws_dashboard` follows
+    /// with a fresh `snapshot`.
+    Lagged {
+        missed: u64,
+    },
+    /// Reply to `subscribe` and `unsubscribe`: either every symbol but `excluded`, or only
+    /// `symbols`, of which `not_seen` have had no price yet.
+    Subscription {
+        all: bool,
+        symbols: Vec<String>,
+        excluded: Vec<String>,
+        not_seen: Vec<String>,
+    },
+    Stats {
+        #[serde(flatten)]
+        server: ServerStats,
+        /// Messages dropped by the inbound rate limit, lag events and prices coalesced by the
+        /// throttle, on this connection.
+        rate_limited: u64,
+        #[serde(default)]
+        lagged: u64,
+        #[serde(default)]
+        coalesced: u64,
+        /// Only for a `stats` with the admin token.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        connections: Option<Vec<ConnectionInfo>>,
+    },
+    History {
+        symbol: String,
+        source: Option<String>,
+        prices: Vec<PriceUpdate>,
+    },
+    /// Ends a `replay`: `sent` prices, and `truncated` when more were left out past the
+    /// maximum.
+    Replay {
+        sent: usize,
+        truncated: bool,
+    },
+    /// Reply to `candles`.
+    Candles {
+        symbol: String,
+        candles: Vec<Candle>,
+    },
+    /// Reply to `symbols`: the known symbols in order, and every source among them.
+    Symbols {
+        symbols: Vec<SymbolInfo>,
+        sources: Vec<String>,
+    },
+    /// Reply to `admin_list`.
+    Connections {
+        connections: Vec<ConnectionInfo>,
+    },
+    /// Reply to `admin_kick`, once connection `id` was told to close.
+    Kicked {
+        id: u64,
+    },
+    /// Reply to `ping`, `nonce` and `client_ts` as the client sent them; `server_ts` is when
+    /// the server read the ping.
+    Pong {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        nonce: Option<serde_json::Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_ts: Option<serde_json::Value>,
+        server_ts: DateTime<Utc>,
+    },
+    /// Reply to `set_format`, already in the new format.
+    Format {
+        format: Format,
+    },
+    /// Reply to `set_throttle`, `None` without a throttle.
+    Throttle {
+        max_per_sec: Option<f64>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::Error {
+            message: message.into(),
+        }
+    }
+
+    pub fn welcome(connection_id: u64) -> Self {
+        Self::Connected {
+            message: "Connected to stock price feed".to_string(),
+            version: PROTOCOL_VERSION,
+            connection_id: Some(connection_id),
+        }
+    }
+
+    /// Plain data with string keys, so serializing cannot fail.
+    pub fn encode(&self, format: Format, layout: Layout) -> Message {
+        match layout {
+            Layout::Envelope => encode(
+                &Envelope {
+                    v: PROTOCOL_VERSION,
+                    message: self,
+                },
+                format,
+            ),
+            Layout::Legacy => encode(&self.to_legacy(), format),
+        }
+    }
+
+    /// The envelope as a JSON value, as recorded by `ws_client --record`.
+    pub fn to_envelope_value(&self) -> serde_json::Value {
+        serde_json::to_value(Envelope {
+            v: PROTOCOL_VERSION,
+            message: self,
+        })
+        .expect("server messages always serialize")
+    }
+
+    /// A message in either layout, such as a recorded one.
+    pub fn from_value(mut value: serde_json::Value) -> Result<Self, serde_json::Error> {
+        if let Some(fields) = value.as_object_mut() {
+            if !fields.contains_key("v") {
+                // Legacy: everything but `type` moves into `data`
+                let kind = fields.remove("type");
+                let data = std::mem::take(fields);
+                fields.extend(kind.map(|kind| ("type".to_string(), kind)));
+                if !data.is_empty() {
+                    fields.insert("data".to_string(), data.into());
+                }
+            }
+        }
+        serde_json::from_value(value)
+    }
+
+    /// `type` next to the fields of `data`.
+    fn to_legacy(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).expect("server messages always serialize");
+        if let Some(fields) = value.as_object_mut() {
+            if let Some(serde_json::Value::Object(data)) = fields.remove("data") {
+                fields.extend(data);
+            }
+        }
+        value
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    v: u32,
+    #[serde(flatten)]
+    message: &'a ServerMessage,
+}
+
+fn encode(frame: &impl Serialize, format: Format) -> Message {
+    match format {
+        Format::Json => {
+            Message::Text(serde_json::to_string(frame).expect("server messages always serialize"))
+        }
+        // Maps with field names rather than arrays, so the same keys as the JSON frames
+        Format::Msgpack => Message::Binary(
+            rmp_serde::to_vec_named(frame).expect("server messages always serialize"),
+        ),
+    }
+}
+
+/// Close frame with a reason the client can show.
+pub fn close(code: CloseCode, reason: &'static str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn at() -> DateTime<Utc> {
+        "2026-10-17T14:00:00Z".parse().unwrap()
+    }
+
+    fn price() -> PriceUpdate {
+        PriceUpdate {
+            symbol: "AAPL".to_string(),
+            price: 190.5,
+            source: "finnhub".to_string(),
+            timestamp: at(),
+            open: Some(189.0),
+            high: Some(191.0),
+            low: Some(188.5),
+            prev_close: Some(188.0),
+            bid: None,
+            ask: None,
+            volume: Some(300),
+            stale: false,
+            replayed: false,
+            asset_class: None,
+            sent_at: None,
+        }
+    }
+
+    fn price_json() -> Value {
+        json!({
+            "symbol": "AAPL",
+            "price": 190.5,
+            "source": "finnhub",
+            "timestamp": "2026-10-17T14:00:00Z",
+            "open": 189.0,
+            "high": 191.0,
+            "low": 188.5,
+            "prev_close": 188.0,
+            "bid": null,
+            "ask": null,
+            "volume": 300,
+            "stale": false
+        })
+    }
+
+    fn stale() -> StaleSymbol {
+        StaleSymbol {
+            symbol: "AAPL".to_string(),
+            source: "finnhub".to_string(),
+            last_update: at(),
+            age_secs: 400,
+        }
+    }
+
+    fn stale_json() -> Value {
+        json!({
+            "symbol": "AAPL",
+            "source": "finnhub",
+            "last_update": "2026-10-17T14:00:00Z",
+            "age_secs": 400
+        })
+    }
+
+    fn candle() -> Candle {
+        Candle {
+            symbol: "AAPL".to_string(),
+            open: 189.0,
+            high: 191.0,
+            low: 188.5,
+            close: 190.5,
+            start: at(),
+        }
+    }
+
+    fn candle_json() -> Value {
+        json!({
+            "symbol": "AAPL",
+            "open": 189.0,
+            "high": 191.0,
+            "low": 188.5,
+            "close": 190.5,
+            "start": "2026-10-17T14:00:00Z"
+        })
+    }
+
+    fn connection() -> ConnectionInfo {
+        ConnectionInfo {
+            id: 12,
+            addr: "127.0.0.1:51234".to_string(),
+            path: "/prices".to_string(),
+            connected_at: at(),
+            format: Format::Msgpack,
+            subscribed_symbols: Some(1),
+            symbols: Some(vec!["AAPL".to_string()]),
+            excluded: Vec::new(),
+            messages_sent: 40,
+            messages_dropped: 2,
+            lagged: 1,
+            throttle_per_sec: Some(2.0),
+            messages_coalesced: 5,
+        }
+    }
+
+    fn connection_json() -> Value {
+        json!({
+            "id": 12,
+            "addr": "127.0.0.1:51234",
+            "path": "/prices",
+            "connected_at": "2026-10-17T14:00:00Z",
+            "format": "msgpack",
+            "subscribed_symbols": 1,
+            "symbols": ["AAPL"],
+            "excluded": [],
+            "messages_sent": 40,
+            "messages_dropped": 2,
+            "lagged": 1,
+            "throttle_per_sec": 2.0,
+            "messages_coalesced": 5
+        })
+    }
+
+    /// Every variant, with its `type` and its `data` as they must go out.
+    fn samples() -> Vec<(ServerMessage, &'static str, Value)> {
+        vec![
+            (
+                ServerMessage::welcome(7),
+                "connected",
+                json!({
+                    "message": "Connected to stock price feed",
+                    "version": PROTOCOL_VERSION,
+                    "connection_id": 7
+                }),
+            ),
+            (ServerMessage::Price(price()), "price", price_json()),
+            (
+                ServerMessage::Aggregate(Aggregate {
+                    symbol: "AAPL".to_string(),
+                    avg: 190.25,
+                    spread: Some(0.5),
+                    sources: 2,
+                    window_secs: 60,
+                }),
+                "aggregate",
+                json!({
+                    "symbol": "AAPL",
+                    "avg": 190.25,
+                    "spread": 0.5,
+                    "sources": 2,
+                    "window_secs": 60
+                }),
+            ),
+            (
+                ServerMessage::Alert(Alert {
+                    kind: AlertKind::Move,
+                    rule: "aapl-move".to_string(),
+                    symbol: "AAPL".to_string(),
+                    value_pct: 2.5,
+                    threshold_pct: 2.0,
+                    message: "AAPL up 2.5%".to_string(),
+                    timestamp: at(),
+                }),
+                "alert",
+                json!({
+                    "kind": "move",
+                    "rule": "aapl-move",
+                    "symbol": "AAPL",
+                    "value_pct": 2.5,
+                    "threshold_pct": 2.0,
+                    "message": "AAPL up 2.5%",
+                    "timestamp": "2026-10-17T14:00:00Z"
+                }),
+            ),
+            (ServerMessage::Candle(candle()), "candle", candle_json()),
+            (
+                ServerMessage::Announcement(Announcement {
+                    message: "Maintenance at 14:00".to_string(),
+                    level: AnnouncementLevel::Warning,
+                    timestamp: at(),
+                }),
+                "announcement",
+                json!({
+                    "message": "Maintenance at 14:00",
+                    "level": "warning",
+                    "timestamp": "2026-10-17T14:00:00Z"
+                }),
+            ),
+            (ServerMessage::Stale(stale()), "stale", stale_json()),
+            (
+                ServerMessage::Resumed {
+                    symbol: "AAPL".to_string(),
+                    source: "finnhub".to_string(),
+                    last_update: at(),
+                    gap_secs: 420,
+                },
+                "resumed",
+                json!({
+                    "symbol": "AAPL",
+                    "source": "finnhub",
+                    "last_update": "2026-10-17T14:00:00Z",
+                    "gap_secs": 420
+                }),
+            ),
+            (
+                ServerMessage::Status {
+                    uptime_secs: 90,
+                    active_connections: 3,
+                    updates_last_interval: 12,
+                    db_ok: true,
+                },
+                "status",
+                json!({
+                    "uptime_secs": 90,
+                    "active_connections": 3,
+                    "updates_last_interval": 12,
+                    "db_ok": true
+                }),
+            ),
+            (
+                ServerMessage::Snapshot {
+                    prices: vec![price()],
+                    stale: vec![stale()],
+                },
+                "snapshot",
+                json!({ "prices": [price_json()], "stale": [stale_json()] }),
+            ),
+            (
+                ServerMessage::Lagged { missed: 4 },
+                "lagged",
+                json!({ "missed": 4 }),
+            ),
+            (
+                ServerMessage::Subscription {
+                    all: false,
+                    symbols: vec!["AAPL".to_string(), "TSLA".to_string()],
+                    excluded: Vec::new(),
+                    not_seen: vec!["TSLA".to_string()],
+                },
+                "subscription",
+                json!({
+                    "all": false,
+                    "symbols": ["AAPL", "TSLA"],
+                    "excluded": [],
+                    "not_seen": ["TSLA"]
+                }),
+            ),
+            (
+                ServerMessage::Stats {
+                    server: ServerStats {
+                        uptime_seconds: 90,
+                        active_connections: 3,
+                        max_connections: None,
+                        connections_total: 10,
+                        messages_sent: 500,
+                        messages_dropped: 2,
+                        lagged_total: 1,
+                        slow_disconnects_total: 0,
+                        channel_capacity: Some(100),
+                        channel_depth: Some(4),
+                        subscribers: Some(3),
+                        rate_limited_total: 6,
+                        updates_suppressed: 8,
+                        formats: FormatCounts {
+                            json: 2,
+                            msgpack: 1,
+                        },
+                    },
+                    rate_limited: 1,
+                    lagged: 0,
+                    coalesced: 3,
+                    connections: Some(vec![connection()]),
+                },
+                "stats",
+                json!({
+                    "uptime_seconds": 90,
+                    "active_connections": 3,
+                    "max_connections": null,
+                    "connections_total": 10,
+                    "messages_sent": 500,
+                    "messages_dropped": 2,
+                    "lagged_total": 1,
+                    "slow_disconnects_total": 0,
+                    "channel_capacity": 100,
+                    "channel_depth": 4,
+                    "subscribers": 3,
+                    "rate_limited_total": 6,
+                    "updates_suppressed": 8,
+                    "formats": { "json": 2, "msgpack": 1 },
+                    "rate_limited": 1,
+                    "lagged": 0,
+                    "coalesced": 3,
+                    "connections": [connection_json()]
+                }),
+            ),
+            (
+                ServerMessage::History {
+                    symbol: "AAPL".to_string(),
+                    source: None,
+                    prices: vec![price()],
+                },
+                "history",
+                json!({ "symbol": "AAPL", "source": null, "prices": [price_json()] }),
+            ),
+            (
+                ServerMessage::Replay {
+                    sent: 120,
+                    truncated: true,
+                },
+                "replay",
+                json!({ "sent": 120, "truncated": true }),
+            ),
+            (
+                ServerMessage::Candles {
+                    symbol: "AAPL".to_string(),
+                    candles: vec![candle()],
+                },
+                "candles",
+                json!({ "symbol": "AAPL", "candles": [candle_json()] }),
+            ),
+            (
+                ServerMessage::Symbols {
+                    symbols: vec![SymbolInfo {
+                        symbol: "AAPL".to_string(),
+                        timestamp: at(),
+                        age_seconds: 1.5,
+                        sources: vec![SourceQuote {
+                            source: "finnhub".to_string(),
+                            timestamp: at(),
+                            age_seconds: 1.5,
+                        }],
+                    }],
+                    sources: vec!["finnhub".to_string()],
+                },
+                "symbols",
+                json!({
+                    "symbols": [{
+                        "symbol": "AAPL",
+                        "timestamp": "2026-10-17T14:00:00Z",
+                        "age_seconds": 1.5,
+                        "sources": [{
+                            "source": "finnhub",
+                            "timestamp": "2026-10-17T14:00:00Z",
+                            "age_seconds": 1.5
+                        }]
+                    }],
+                    "sources": ["finnhub"]
+                }),
+            ),
+            (
+                ServerMessage::Connections {
+                    connections: vec![connection()],
+                },
+                "connections",
+                json!({ "connections": [connection_json()] }),
+            ),
+            (
+                ServerMessage::Kicked { id: 12 },
+                "kicked",
+                json!({ "id": 12 }),
+            ),
+            (
+                ServerMessage::Pong {
+                    nonce: Some(json!(7)),
+                    client_ts: Some(json!(1760709600000_i64)),
+                    server_ts: at(),
+                },
+                "pong",
+                json!({
+                    "nonce": 7,
+                    "client_ts": 1760709600000_i64,
+                    "server_ts": "2026-10-17T14:00:00Z"
+                }),
+            ),
+            (
+                ServerMessage::Format {
+                    format: Format::Msgpack,
+                },
+                "format",
+                json!({ "format": "msgpack" }),
+            ),
+            (
+                ServerMessage::Throttle {
+                    max_per_sec: Some(2.0),
+                },
+                "throttle",
+                json!({ "max_per_sec": 2.0 }),
+            ),
+            (
+                ServerMessage::error("server full"),
+                "error",
+                json!({ "message": "server full" }),
+            ),
+        ]
+    }
+
+    fn json_frame(message: &ServerMessage, layout: Layout) -> Value {
+        match message.encode(Format::Json, layout) {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("expected a text frame, got {other:?}"),
+        }
+    }
+
+    fn legacy(kind: &str, data: &Value) -> Value {
+        let mut frame = data.as_object().unwrap().clone();
+        frame.insert("type".to_string(), kind.into());
+        frame.into()
+    }
+
+    /// No wildcard: a new variant doesn't compile until it gets a sample.
+    fn variant(message: &ServerMessage) -> &'static str {
+        match message {
+            ServerMessage::Connected { .. } => "connected",
+            ServerMessage::Price(_) => "price",
+            ServerMessage::Aggregate(_) => "aggregate",
+            ServerMessage::Alert(_) => "alert",
+            ServerMessage::Candle(_) => "candle",
+            ServerMessage::Announcement(_) => "announcement",
+            ServerMessage::Stale(_) => "stale",
+            ServerMessage::Resumed { .. } => "resumed",
+            ServerMessage::Status { .. } => "status",
+            ServerMessage::Snapshot { .. } => "snapshot",
+            ServerMessage::Lagged { .. } => "lagged",
+            ServerMessage::Subscription { .. } => "subscription",
+            ServerMessage::Stats { .. } => "stats",
+            ServerMessage::History { .. } => "history",
+            ServerMessage::Replay { .. } => "replay",
+            ServerMessage::Candles { .. } => "candles",
+            ServerMessage::Symbols { .. } => "symbols",
+            ServerMessage::Connections { .. } => "connections",
+            ServerMessage::Kicked { .. } => "kicked",
+            ServerMessage::Pong { .. } => "pong",
+            ServerMessage::Format { .. } => "format",
+            ServerMessage::Throttle { .. } => "throttle",
+            ServerMessage::Error { .. } => "error",
+        }
+    }
+
+    #[test]
+    fn every_variant_has_a_sample() {
+        let sampled: std::collections::BTreeSet<_> = samples()
+            .iter()
+            .map(|(message, kind, _)| {
+                assert_eq!(variant(message), *kind);
+                *kind
+            })
+            .collect();
+        assert_eq!(sampled.len(), 23);
+    }
+
+    #[test]
+    fn envelope_frames() {
+        for (message, kind, data) in samples() {
+            let expected = json!({ "v": PROTOCOL_VERSION, "type": kind, "data": data });
+            assert_eq!(json_frame(&message, Layout::Envelope), expected, "{kind}");
+            assert_eq!(message.to_envelope_value(), expected, "{kind}");
+        }
+    }
+
+    #[test]
+    fn legacy_frames() {
+        for (message, kind, data) in samples() {
+            assert_eq!(
+                json_frame(&message, Layout::Legacy),
+                legacy(kind, &data),
+                "{kind}"
+            );
+        }
+    }
+
+    #[test]
+    fn legacy_price_is_flat() {
+        let frame = json_frame(&ServerMessage::Price(price()), Layout::Legacy);
+        assert_eq!(frame["type"], "price");
+        assert_eq!(frame["symbol"], "AAPL");
+        assert!(frame.get("v").is_none());
+        assert!(frame.get("data").is_none());
+    }
+
+    #[test]
+    fn price_optional_fields_only_when_set() {
+        let update = PriceUpdate {
+            replayed: true,
+            asset_class: Some("crypto".to_string()),
+            sent_at: Some(at()),
+            ..price()
+        };
+        let data = &json_frame(&ServerMessage::Price(update), Layout::Envelope)["data"];
+        assert_eq!(data["replayed"], true);
+        assert_eq!(data["asset_class"], "crypto");
+        assert_eq!(data["sent_at"], "2026-10-17T14:00:00Z");
+    }
+
+    #[test]
+    fn envelope_round_trip() {
+        for (message, kind, _) in samples() {
+            let frame = json_frame(&message, Layout::Envelope);
+            let parsed = ServerMessage::from_value(frame.clone()).unwrap();
+            assert_eq!(parsed.to_envelope_value(), frame, "{kind}");
+        }
+    }
+
+    #[test]
+    fn legacy_round_trip() {
+        for (message, kind, _) in samples() {
+            let frame = json_frame(&message, Layout::Legacy);
+            let parsed = ServerMessage::from_value(frame).unwrap();
+            assert_eq!(
+                parsed.to_envelope_value(),
+                message.to_envelope_value(),
+                "{kind}"
+            );
+        }
+    }
+
+    fn parsed(text: &str) -> ClientCommand {
+        ClientCommand::parse(text).unwrap_or_else(|e| panic!("{text}: {e}"))
+    }
+
+    fn symbols(list: &[&str]) -> Vec<String> {
+        list.iter().map(|symbol| symbol.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_every_action() {
+        let cases = [
+            (
+                r#"{"action":"auth","token":"t0k"}"#,
+                ClientCommand::Auth {
+                    token: "t0k".to_string(),
+                },
+            ),
+            (
+                r#"{"action":"stats"}"#,
+                ClientCommand::Stats { admin_token: None },
+            ),
+            (
+                r#"{"action":"stats","admin_token":"adm"}"#,
+                ClientCommand::Stats {
+                    admin_token: Some("adm".to_string()),
+                },
+            ),
+            (
+                r#"{"action":"ping"}"#,
+                ClientCommand::Ping {
+                    nonce: None,
+                    client_ts: None,
+                },
+            ),
+            (
+                r#"{"action":"ping","nonce":"a1","client_ts":1760709600000}"#,
+                ClientCommand::Ping {
+                    nonce: Some(json!("a1")),
+                    client_ts: Some(json!(1760709600000_i64)),
+                },
+            ),
+            (
+                r#"{"action":"subscribe","symbols":["AAPL","TSLA"]}"#,
+                ClientCommand::Subscribe {
+                    symbols: symbols(&["AAPL", "TSLA"]),
+                },
+            ),
+            (
+                r#"{"action":"unsubscribe","symbols":["MSFT"]}"#,
+                ClientCommand::Unsubscribe {
+                    symbols: symbols(&["MSFT"]),
+                },
+            ),
+            (
+                r#"{"action":"set_format","format":"msgpack"}"#,
+                ClientCommand::SetFormat {
+                    format: Format::Msgpack,
+                },
+            ),
+            (
+                r#"{"action":"set_throttle","max_per_sec":2}"#,
+                ClientCommand::SetThrottle { max_per_sec: 2.0 },
+            ),
+            (
+                r#"{"action":"candles","symbol":"AAPL"}"#,
+                ClientCommand::Candles {
+                    symbol: "AAPL".to_string(),
+                    limit: None,
+                },
+            ),
+            (
+                r#"{"action":"candles","symbol":"AAPL","limit":10}"#,
+                ClientCommand::Candles {
+                    symbol: "AAPL".to_string(),
+                    limit: Some(10),
+                },
+            ),
+            (
+                r#"{"action":"replay","since":1760000000}"#,
+                ClientCommand::Replay {
+                    since: 1760000000,
+                    symbols: Vec::new(),
+                },
+            ),
+            (
+                r#"{"action":"replay","since":1760000000,"symbols":["AAPL"]}"#,
+                ClientCommand::Replay {
+                    since: 1760000000,
+                    symbols: symbols(&["AAPL"]),
+                },
+            ),
+            (r#"{"action":"symbols"}"#, ClientCommand::Symbols),
+            (
+                r#"{"action":"admin_list","admin_token":"adm"}"#,
+                ClientCommand::AdminList {
+                    admin_token: Some("adm".to_string()),
+                },
+            ),
+            (
+                r#"{"action":"admin_kick","id":12}"#,
+                ClientCommand::AdminKick {
+                    id: 12,
+                    admin_token: None,
+                },
+            ),
+            (
+                r#"{"action":"announce","message":"Maintenance at 14:00"}"#,
+                ClientCommand::Announce {
+                    message: "Maintenance at 14:00".to_string(),
+                    level: AnnouncementLevel::Info,
+                    admin_token: None,
+                },
+            ),
+            (
+                r#"{"action":"announce","message":"Failover","level":"warning","admin_token":"adm"}"#,
+                ClientCommand::Announce {
+                    message: "Failover".to_string(),
+                    level: AnnouncementLevel::Warning,
+                    admin_token: Some("adm".to_string()),
+                },
+            ),
+            (
+                r#"{"action":"history","symbol":"AAPL"}"#,
+                ClientCommand::History {
+                    symbol: "AAPL".to_string(),
+                    source: None,
+                    limit: DEFAULT_HISTORY_LIMIT,
+                },
+            ),
+            (
+                r#"{"action":"history","symbol":"AAPL","source":"finnhub","limit":200}"#,
+                ClientCommand::History {
+                    symbol: "AAPL".to_string(),
+                    source: Some("finnhub".to_string()),
+                    limit: 200,
+                },
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parsed(text), expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn bare_stats_of_the_first_version() {
+        assert_eq!(parsed("/stats"), ClientCommand::Stats { admin_token: None });
+        assert_eq!(
+            parsed("  /stats\n"),
+            ClientCommand::Stats { admin_token: None }
+        );
+    }
+
+    #[test]
+    fn commands_survive_serializing() {
+        let command = ClientCommand::AdminKick {
+            id: 3,
+            admin_token: Some("adm".to_string()),
+        };
+        let text = serde_json::to_string(&command).unwrap();
+        assert_eq!(parsed(&text), command);
+    }
+
+    #[test]
+    fn rejects_malformed_and_unknown_commands() {
+        for text in [
+            "",
+            "hello",
+            "/help",
+            "{",
+            "[]",
+            r#"{"symbols":["AAPL"]}"#,
+            r#"{"action":"dance"}"#,
+            r#"{"action":"SUBSCRIBE","symbols":["AAPL"]}"#,
+            r#"{"action":"subscribe"}"#,
+            r#"{"action":"subscribe","symbols":"AAPL"}"#,
+            r#"{"action":"set_format","format":"xml"}"#,
+            r#"{"action":"set_throttle","max_per_sec":"fast"}"#,
+            r#"{"action":"admin_kick","id":-1}"#,
+            r#"{"action":"history"}"#,
+        ] {
+            let error = ClientCommand::parse(text).expect_err(text);
+            assert!(error.starts_with("invalid command: "), "{text}: {error}");
+        }
+    }
+
+    #[test]
+    fn redacts_tokens() {
+        let command = parsed(r#"{"action":"announce","message":"hi","admin_token":"s3cret"}"#);
+        let logged = command.redacted();
+        assert!(!logged.contains("s3cret"), "{logged}");
+        assert!(logged.contains(r#""admin_token":"***""#), "{logged}");
+        assert!(!parsed(r#"{"action":"auth","token":"s3cret"}"#)
+            .redacted()
+            .contains("s3cret"));
+    }
+
+    fn msgpack_frame(message: &ServerMessage, layout: Layout) -> Vec<u8> {
+        match message.encode(Format::Msgpack, layout) {
+            Message::Binary(bytes) => bytes,
+            other => panic!("expected a binary frame, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn msgpack_has_the_json_field_names() {
+        for (message, kind, _) in samples() {
+            for layout in [Layout::Envelope, Layout::Legacy] {
+                let decoded: Value =
+                    rmp_serde::from_slice(&msgpack_frame(&message, layout)).unwrap();
+                assert_eq!(decoded, json_frame(&message, layout), "{kind} {layout:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn msgpack_round_trip() {
+        for (message, kind, _) in samples() {
+            let bytes = msgpack_frame(&message, Layout::Envelope);
+            let parsed: ServerMessage = rmp_serde::from_slice(&bytes).unwrap();
+            assert_eq!(
+                parsed.to_envelope_value(),
+                message.to_envelope_value(),
+                "{kind}"
+            );
+
+            let legacy: Value =
+                rmp_serde::from_slice(&msgpack_frame(&message, Layout::Legacy)).unwrap();
+            let parsed = ServerMessage::from_value(legacy).unwrap();
+            assert_eq!(
+                parsed.to_envelope_value(),
+                message.to_envelope_value(),
+                "{kind}"
+            );
+        }
+    }
+
+    #[test]
+    fn msgpack_is_a_map_not_an_array() {
+        let bytes = msgpack_frame(&ServerMessage::Lagged { missed: 4 }, Layout::Envelope);
+        // fixmap of 3 entries: v, type, data
+        assert_eq!(bytes[0], 0x83);
+    }
+
+    #[test]
+    fn envelope_of_another_version_parses() {
+        let frame = json!({ "v": 2, "type": "lagged", "data": { "missed": 3 } });
+        let parsed = ServerMessage::from_value(frame).unwrap();
+        assert!(matches!(parsed, ServerMessage::Lagged { missed: 3 }));
+    }
+}
@@ -0,0 +1,119 @@
+//! Per-connection token bucket on inbound Text frames, so a flooding client can't keep the
+//! server busy logging and answering it at the expense of the price feed.
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// A limited streak ends once the client has gone this long without a dropped message.
+const STREAK_GAP: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitConfig {
+    /// Sustained messages per second, also the burst allowed after a quiet period.
+    pub per_second: u32,
+    /// A client still over the limit after this long is disconnected.
+    pub disconnect_after: Duration,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_second: 10,
+            disconnect_after: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Overrides from `WS_CLIENT_RATE` (messages per second) and `WS_CLIENT_RATE_DISCONNECT`
+    /// (`10s`, `500ms`...).
+    pub fn with_env(mut self) -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        if let Some(raw) = var("WS_CLIENT_RATE") {
+            self.per_second = raw
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| {
+                    format!("WS_CLIENT_RATE: expected a positive number, got '{raw}'")
+                })?;
+        }
+        if let Some(raw) = var("WS_CLIENT_RATE_DISCONNECT") {
+            self.disconnect_after = humantime::parse_duration(raw.trim())
+                .ok()
+                .filter(|d| !d.is_zero())
+                .ok_or_else(|| {
+                    format!(
+                        "WS_CLIENT_RATE_DISCONNECT: expected a duration such as 10s, got '{raw}'"
+                    )
+                })?;
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Inbound {
+    Allowed,
+    /// Over the limit: drop the message. `notify` is set for the first one of a streak, so
+    /// the client gets a single error reply.
+    Dropped {
+        notify: bool,
+    },
+    /// Over the limit for `disconnect_after` without a break.
+    Disconnect,
+}
+
+pub struct InboundLimiter {
+    cfg: RateLimitConfig,
+    tokens: f64,
+    refilled: Instant,
+    /// Start of the current limited streak, and the last message dropped in it.
+    streak: Option<(Instant, Instant)>,
+}
+
+impl InboundLimiter {
+    pub fn new(cfg: RateLimitConfig) -> Self {
+        Self {
+            cfg,
+            tokens: f64::from(cfg.per_second),
+            refilled: Instant::now(),
+            streak: None,
+        }
+    }
+
+    pub fn check(&mut self) -> Inbound {
+        let now = Instant::now();
+        let rate = f64::from(self.cfg.per_second);
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.refilled = now;
+
+        if self
+            .streak
+            .is_some_and(|(_, last)| now.duration_since(last) > STREAK_GAP)
+        {
+            self.streak = None;
+        }
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Inbound::Allowed;
+        }
+        match &mut self.streak {
+            None => {
+                self.streak = Some((now, now));
+                Inbound::Dropped { notify: true }
+            }
+            Some((started, last)) => {
+                *last = now;
+                if now.duration_since(*started) >= self.cfg.disconnect_after {
+                    Inbound::Disconnect
+                } else {
+                    Inbound::Dropped { notify: false }
+                }
+            }
+        }
+    }
+}
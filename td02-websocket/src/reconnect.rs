@@ -0,0 +1,276 @@
+//! Client side of the price feeds that outlives its connection: `ResilientClient` connects,
+//! subscribes, and when the connection fails or drops (server restart, network loss) tries
+//! again after a backoff that doubles up to `max_backoff`, with jitter so a crowd of clients
+//! doesn't come back all at once. The subscription is sent again on every new connection.
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use log::debug;
+use rand::Rng;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use crate::protocol::{self, ClientCommand, ServerMessage};
+use crate::shutdown::ShutdownRx;
+use crate::subscription::ALL;
+
+type Ws = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Events not yet taken by the caller before the connection task waits.
+const EVENT_BUFFER: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectConfig {
+    /// Wait before the first retry, doubled after each failed one.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// `attempt` counts from 1 since the last successful connection.
+    Connecting {
+        attempt: u32,
+    },
+    Connected,
+    /// Connecting failed or the connection was lost; the next attempt is in `retry_in`.
+    Disconnected {
+        reason: String,
+        retry_in: Duration,
+    },
+}
+
+#[derive(Debug)]
+pub enum FeedEvent {
+    State(ConnectionState),
+    Message(ServerMessage),
+}
+
+pub struct ResilientClient {
+    events: mpsc::Receiver<FeedEvent>,
+    symbols: watch::Sender<BTreeSet<String>>,
+    task: JoinHandle<()>,
+}
+
+impl ResilientClient {
+    /// Starts connecting to `url`, subscribed to `symbols` (every symbol when empty). Runs
+    /// until `shutdown` turns true or its sender is dropped, closing the connection normally.
+    pub fn connect(
+        url: impl Into<String>,
+        symbols: Vec<String>,
+        cfg: ReconnectConfig,
+        shutdown: ShutdownRx,
+    ) -> Self {
+        let (events, events_rx) = mpsc::channel(EVENT_BUFFER);
+        let (symbols, symbols_rx) = watch::channel(normalize(symbols));
+        let task = tokio::spawn(run(url.into(), cfg, symbols_rx, events, shutdown));
+        Self {
+            events: events_rx,
+            symbols,
+            task,
+        }
+    }
+
+    /// The next message or state change; `None` once shut down.
+    pub async fn next(&mut self) -> Option<FeedEvent> {
+        self.events.recv().await
+    }
+
+    /// Adds to the subscription, now and on every reconnection.
+    pub fn subscribe(&self, symbols: Vec<String>) {
+        self.symbols
+            .send_modify(|current| current.extend(normalize(symbols)));
+    }
+
+    /// Removes from the subscription; removing the last symbol goes back to every symbol.
+    pub fn unsubscribe(&self, symbols: Vec<String>) {
+        let symbols = normalize(symbols);
+        self.symbols
+            .send_modify(|current| current.retain(|symbol| !symbols.contains(symbol)));
+    }
+}
+
+impl Drop for ResilientClient {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+enum Ended {
+    Shutdown,
+    Lost(String),
+}
+
+async fn run(
+    url: String,
+    cfg: ReconnectConfig,
+    mut symbols: watch::Receiver<BTreeSet<String>>,
+    events: mpsc::Sender<FeedEvent>,
+    mut shutdown: ShutdownRx,
+) {
+    let state = |state| events.send(FeedEvent::State(state));
+    let mut attempt = 0;
+    let mut backoff = cfg.initial_backoff;
+    while !*shutdown.borrow() {
+        attempt += 1;
+        if state(ConnectionState::Connecting { attempt })
+            .await
+            .is_err()
+        {
+            return;
+        }
+        let connected = tokio::select! {
+            connected = connect_async(url.as_str()) => connected,
+            _ = shutdown.changed() => return,
+        };
+        let reason = match connected {
+            Ok((ws, _)) => {
+                attempt = 0;
+                backoff = cfg.initial_backoff;
+                if state(ConnectionState::Connected).await.is_err() {
+                    return;
+                }
+                match session(ws, &mut symbols, &events, &mut shutdown).await {
+                    Ended::Shutdown => return,
+                    Ended::Lost(reason) => reason,
+                }
+            }
+            Err(e) => e.to_string(),
+        };
+
+        let retry_in = jitter(backoff);
+        if state(ConnectionState::Disconnected { reason, retry_in })
+            .await
+            .is_err()
+        {
+            return;
+        }
+        tokio::select! {
+            _ = sleep(retry_in) => {}
+            _ = shutdown.changed() => return,
+        }
+        backoff = (backoff * 2).min(cfg.max_backoff);
+    }
+}
+
+/// One connection, from the subscription to its loss or the shutdown.
+async fn session(
+    ws: Ws,
+    symbols: &mut watch::Receiver<BTreeSet<String>>,
+    events: &mpsc::Sender<FeedEvent>,
+    shutdown: &mut ShutdownRx,
+) -> Ended {
+    let (mut write, mut read) = ws.split();
+
+    let mut subscribed = symbols.borrow_and_update().clone();
+    if !subscribed.is_empty() {
+        let symbols = subscribed.iter().cloned().collect();
+        if let Err(e) = write
+            .send(command(&ClientCommand::Subscribe { symbols }))
+            .await
+        {
+            return Ended::Lost(e.to_string());
+        }
+    }
+
+    loop {
+        tokio::select! {
+            message = read.next() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<ServerMessage>(&text) {
+                    Ok(message) => {
+                        if events.send(FeedEvent::Message(message)).await.is_err() {
+                            return Ended::Shutdown;
+                        }
+                    }
+                    Err(e) => debug!("Skipping a message that isn't part of the protocol: {e}"),
+                },
+                Some(Ok(Message::Close(frame))) => {
+                    let reason = frame.map_or("closed".to_string(), |frame| {
+                        format!("closed: {} {}", frame.code, frame.reason)
+                    });
+                    return Ended::Lost(reason);
+                }
+                // Pings are answered by tungstenite
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Ended::Lost(e.to_string()),
+                None => return Ended::Lost("connection dropped".to_string()),
+            },
+
+            changed = symbols.changed() => {
+                if changed.is_err() {
+                    return Ended::Shutdown;
+                }
+                let wanted = symbols.borrow_and_update().clone();
+                for change in changes(&subscribed, &wanted) {
+                    if let Err(e) = write.send(command(&change)).await {
+                        return Ended::Lost(e.to_string());
+                    }
+                }
+                subscribed = wanted;
+            }
+
+            _ = shutdown.changed() => {
+                let _ = write.send(protocol::close(CloseCode::Normal, "client exiting")).await;
+                return Ended::Shutdown;
+            }
+        }
+    }
+}
+
+/// Commands taking the server from `from` to `to`, empty meaning every symbol.
+fn changes(from: &BTreeSet<String>, to: &BTreeSet<String>) -> Vec<ClientCommand> {
+    match (from.is_empty(), to.is_empty()) {
+        (true, true) => Vec::new(),
+        (false, true) => vec![ClientCommand::Subscribe {
+            symbols: vec![ALL.to_string()],
+        }],
+        // From every symbol, a first subscribe narrows down to the set
+        (true, false) => vec![ClientCommand::Subscribe {
+            symbols: to.iter().cloned().collect(),
+        }],
+        (false, false) => {
+            let added: Vec<String> = to.difference(from).cloned().collect();
+            let removed: Vec<String> = from.difference(to).cloned().collect();
+            let mut commands = Vec::new();
+            if !added.is_empty() {
+                commands.push(ClientCommand::Subscribe { symbols: added });
+            }
+            if !removed.is_empty() {
+                commands.push(ClientCommand::Unsubscribe { symbols: removed });
+            }
+            commands
+        }
+    }
+}
+
+fn command(command: &ClientCommand) -> Message {
+    Message::Text(serde_json::to_string(command).expect("client commands always serialize"))
+}
+
+/// Somewhere between half of `backoff` and all of it.
+pub(crate) fn jitter(backoff: Duration) -> Duration {
+    backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+fn normalize(symbols: Vec<String>) -> BTreeSet<String> {
+    symbols
+        .into_iter()
+        .map(|symbol| symbol.trim().to_uppercase())
+        .filter(|symbol| !symbol.is_empty())
+        .collect()
+}
@@ -0,0 +1,183 @@
+//! Captured feeds: `ws_client --record feed.jsonl` writes one line per received message,
+//! `{"received_at":"...","message":{...}}`, and `ws_broadcast --replay feed.jsonl` plays the
+//! prices back with the recorded spacing, without a database. Recordings from before the
+//! versioned envelope replay too.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use log::{debug, info, warn};
+use market_core::PriceUpdate;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::broadcast;
+use tokio::time::{sleep, Duration};
+
+use crate::protocol::ServerMessage;
+use crate::subscription::Seen;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Recorded {
+    pub received_at: DateTime<Utc>,
+    /// The frame as received: JSON when it parses as such, else the raw text.
+    pub message: serde_json::Value,
+}
+
+/// Appends received frames to a JSON lines file, flushed line by line so an interrupted
+/// client leaves a complete file.
+pub struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn record(&mut self, text: &str) -> std::io::Result<()> {
+        let line = Recorded {
+            received_at: Utc::now(),
+            message: serde_json::from_str(text)
+                .unwrap_or_else(|_| serde_json::Value::String(text.to_string())),
+        };
+        serde_json::to_writer(&mut self.file, &line)?;
+        self.file.write_all(b"\n")?;
+        self.file.flush()
+    }
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ReplayArgs {
+    /// Play back a ws_client --record file instead of simulating prices
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+
+    /// Playback speed, 2 for twice as fast as recorded [default: 1]
+    #[arg(long, value_parser = speed, requires = "replay")]
+    pub speed: Option<f64>,
+
+    /// Start the recording over once it ends
+    #[arg(long = "loop", requires = "replay")]
+    pub looping: bool,
+}
+
+fn speed(raw: &str) -> Result<f64, String> {
+    raw.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|speed| speed.is_finite() && *speed > 0.0)
+        .ok_or_else(|| "expected a positive number".to_string())
+}
+
+/// `--replay FILE [--speed X] [--loop]`.
+#[derive(Debug, Clone)]
+pub struct ReplayConfig {
+    pub path: PathBuf,
+    /// 2.0 plays twice as fast as recorded.
+    pub speed: f64,
+    pub looping: bool,
+}
+
+impl ReplayConfig {
+    /// `None` without `--replay`.
+    pub fn from_args(args: &ReplayArgs) -> Result<Option<Self>, String> {
+        let Some(path) = &args.replay else {
+            return Ok(None);
+        };
+        if !path.is_file() {
+            return Err(format!("--replay: no such file '{}'", path.display()));
+        }
+        Ok(Some(Self {
+            path: path.clone(),
+            speed: args.speed.unwrap_or(1.0),
+            looping: args.looping,
+        }))
+    }
+}
+
+/// Broadcasts the prices of a recording (`price` and `snapshot` frames; the rest is
+/// skipped) with the recorded gaps divided by `speed`. Prices keep their recorded
+/// timestamps. Malformed lines are logged and skipped.
+pub async fn replay(cfg: ReplayConfig, tx: broadcast::Sender<PriceUpdate>, seen: Arc<Seen>) {
+    loop {
+        let sent = match replay_once(&cfg, &tx, &seen).await {
+            Ok(sent) => sent,
+            Err(e) => {
+                warn!("Cannot read recording {}: {e}", cfg.path.display());
+                return;
+            }
+        };
+        info!("Replayed {sent} prices from {}", cfg.path.display());
+        if !cfg.looping {
+            return;
+        }
+        if sent == 0 {
+            warn!("No prices in {}, not looping", cfg.path.display());
+            return;
+        }
+    }
+}
+
+async fn replay_once(
+    cfg: &ReplayConfig,
+    tx: &broadcast::Sender<PriceUpdate>,
+    seen: &Seen,
+) -> std::io::Result<usize> {
+    let mut lines = BufReader::new(tokio::fs::File::open(&cfg.path).await?).lines();
+    let mut previous: Option<DateTime<Utc>> = None;
+    let mut number = 0;
+    let mut sent = 0;
+
+    while let Some(line) = lines.next_line().await? {
+        number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let recorded: Recorded = match serde_json::from_str(&line) {
+            Ok(recorded) => recorded,
+            Err(e) => {
+                warn!("Skipping line {number} of {}: {e}", cfg.path.display());
+                continue;
+            }
+        };
+        let kind = recorded.message.get("type").and_then(|kind| kind.as_str());
+        if !matches!(kind, Some("price" | "snapshot")) {
+            continue;
+        }
+        let prices = match ServerMessage::from_value(recorded.message) {
+            Ok(ServerMessage::Price(price)) => vec![price],
+            Ok(ServerMessage::Snapshot { prices, .. }) => prices,
+            Ok(_) => continue,
+            Err(e) => {
+                warn!("Skipping line {number} of {}: {e}", cfg.path.display());
+                continue;
+            }
+        };
+
+        // Only gaps between prices count, so skipped frames don't add pauses of their own
+        if let Some(previous) = previous {
+            let gap = (recorded.received_at - previous)
+                .to_std()
+                .unwrap_or_default();
+            sleep(Duration::from_secs_f64(gap.as_secs_f64() / cfg.speed)).await;
+        }
+        previous = Some(recorded.received_at);
+
+        for price in prices {
+            debug!(
+                "Replaying {} @ ${:.2} from {}",
+                price.symbol, price.price, price.source
+            );
+            seen.insert(&price.symbol);
+            let _ = tx.send(price);
+            sent += 1;
+        }
+    }
+    Ok(sent)
+}
@@ -0,0 +1,156 @@
+//! `replay` for `ws_dashboard`: the stored prices since a time, so a client that reconnects
+//! can fill the gap. A task reads them a page at a time and hands each page over once the
+//! previous one went out; live prices arriving meanwhile are held and sent after the
+//! replay, less those it already sent.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use log::error;
+use market_core::store::{PageKey, PriceStore};
+use market_core::PriceUpdate;
+use tokio::sync::mpsc;
+
+use crate::subscription::Subscription;
+
+/// Rows read per query.
+const PAGE_ROWS: usize = 200;
+
+/// Live prices held during one replay; later ones are dropped.
+const MAX_HELD: usize = 10_000;
+
+/// Most rows one replay sends without `--max-replay`.
+pub const DEFAULT_MAX_REPLAY: usize = 5000;
+
+#[derive(Debug)]
+pub enum Page {
+    /// Oldest first, flagged `replayed`.
+    Prices(Vec<PriceUpdate>),
+    /// `truncated` when rows were left out past the maximum.
+    End {
+        truncated: bool,
+    },
+    Failed,
+}
+
+/// One replay in progress on a connection.
+pub struct Replay {
+    pages: mpsc::Receiver<Page>,
+    /// Asked for; empty for those of the subscription.
+    symbols: Vec<String>,
+    held: Vec<PriceUpdate>,
+    /// (symbol, source, timestamp) of every price replayed.
+    replayed: HashSet<(String, String, DateTime<Utc>)>,
+}
+
+impl Replay {
+    /// Starts reading the prices of `symbols` (every symbol when empty) from `since` on,
+    /// `max` at most.
+    pub fn start(
+        store: Arc<dyn PriceStore>,
+        symbols: Vec<String>,
+        since: DateTime<Utc>,
+        max: usize,
+    ) -> Self {
+        // One page waiting at most, so a slow client holds the reading back
+        let (tx, pages) = mpsc::channel(1);
+        tokio::spawn(read_pages(store, symbols.clone(), since, max, tx));
+        Self {
+            pages,
+            symbols,
+            held: Vec::new(),
+            replayed: HashSet::new(),
+        }
+    }
+
+    /// The next page; cancel safe.
+    pub async fn next(&mut self) -> Page {
+        self.pages.recv().await.unwrap_or(Page::Failed)
+    }
+
+    /// Whether a stored price of `symbol` goes out: all those asked for, else those of the
+    /// subscription.
+    pub fn wants(&self, symbol: &str, subscription: &Subscription) -> bool {
+        !self.symbols.is_empty() || subscription.wants(symbol)
+    }
+
+    /// Prices replayed so far.
+    pub fn count(&self) -> usize {
+        self.replayed.len()
+    }
+
+    /// Remembers `price` went out, so the live copy isn't sent again.
+    pub fn sent(&mut self, price: &PriceUpdate) {
+        self.replayed.insert(key(price));
+    }
+
+    /// Keeps a live price for after the replay; `false` when too many are held already.
+    pub fn hold(&mut self, price: PriceUpdate) -> bool {
+        if self.held.len() >= MAX_HELD {
+            return false;
+        }
+        self.held.push(price);
+        true
+    }
+
+    /// The live prices held, in arrival order, without those the replay sent.
+    pub fn held(self) -> Vec<PriceUpdate> {
+        let replayed = self.replayed;
+        self.held
+            .into_iter()
+            .filter(|price| !replayed.contains(&key(price)))
+            .collect()
+    }
+}
+
+fn key(price: &PriceUpdate) -> (String, String, DateTime<Utc>) {
+    (price.symbol.clone(), price.source.clone(), price.timestamp)
+}
+
+async fn read_pages(
+    store: Arc<dyn PriceStore>,
+    symbols: Vec<String>,
+    since: DateTime<Utc>,
+    max: usize,
+    tx: mpsc::Sender<Page>,
+) {
+    let mut after = PageKey::since(since);
+    let mut left = max;
+    loop {
+        // One row more than sent tells whether there is anything after this page
+        let limit = left.min(PAGE_ROWS) + 1;
+        let mut rows = match store.page_after(&symbols, &after, limit as u32).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                error!("Replay query failed: {e}");
+                let _ = tx.send(Page::Failed).await;
+                return;
+            }
+        };
+        let more = rows.len() == limit;
+        if more {
+            rows.pop();
+        }
+        left -= rows.len();
+        if let Some(last) = rows.last() {
+            after = PageKey::after(last);
+        }
+
+        let prices = rows
+            .into_iter()
+            .map(|row| PriceUpdate {
+                replayed: true,
+                ..PriceUpdate::from(row)
+            })
+            .collect();
+        // Send fails once the client is gone
+        if tx.send(Page::Prices(prices)).await.is_err() {
+            return;
+        }
+        if !more || left == 0 {
+            let _ = tx.send(Page::End { truncated: more }).await;
+            return;
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Which WebSocket paths a server upgrades, and to what. The feeds answer `/` and `/prices`
+//! with the price feed, the echo server `/` (its `--mode`), `/echo` and `/chat`; upgrades
+//! on any other path get a 404. `--route /path=target` (repeatable, or `WS_ROUTES` with
+//! comma separated pairs) replaces the defaults, e.g. `--route /feed=prices`. The query
+//! string is not part of the path.
+
+use std::fmt;
+
+use clap::Args;
+
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    Prices,
+    Echo,
+    Chat,
+}
+
+impl Route {
+    fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "prices" => Some(Self::Prices),
+            "echo" => Some(Self::Echo),
+            "chat" => Some(Self::Chat),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Prices => "prices",
+            Self::Echo => "echo",
+            Self::Chat => "chat",
+        })
+    }
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct RouteArgs {
+    /// WebSocket path and what it serves, repeatable (/feed=prices, /talk=chat) [default:
+    /// WS_ROUTES, else the server's own]
+    #[arg(long = "route", value_name = "PATH=TARGET")]
+    pub routes: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Routes {
+    /// Paths without trailing slash, but `/` itself.
+    paths: Vec<(String, Route)>,
+}
+
+impl Default for Routes {
+    /// Those of the price feeds.
+    fn default() -> Self {
+        Self::new([("/", Route::Prices), ("/prices", Route::Prices)])
+    }
+}
+
+impl Routes {
+    pub fn new<'a>(paths: impl IntoIterator<Item = (&'a str, Route)>) -> Self {
+        Self {
+            paths: paths
+                .into_iter()
+                .map(|(path, route)| (normalize(path), route))
+                .collect(),
+        }
+    }
+
+    /// The echo server's, `/` going to `default`.
+    pub fn echo(default: Route) -> Self {
+        Self::new([
+            ("/", default),
+            ("/echo", Route::Echo),
+            ("/chat", Route::Chat),
+        ])
+    }
+
+    /// Replaced by every `--route /path=target`, else by `WS_ROUTES`, if given. Targets
+    /// outside `allowed` are refused, as is a path given twice.
+    pub fn with_args(self, args: &RouteArgs, allowed: &[Route]) -> Result<Self, String> {
+        let mut given: Vec<(String, String)> = args
+            .routes
+            .iter()
+            .map(|raw| ("--route".to_string(), raw.clone()))
+            .collect();
+        if given.is_empty() {
+            if let Some(raw) = std::env::var("WS_ROUTES")
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+            {
+                given.extend(
+                    raw.split(',')
+                        .map(|pair| ("WS_ROUTES".to_string(), pair.to_string())),
+                );
+            }
+        }
+        if given.is_empty() {
+            return Ok(self);
+        }
+
+        let names = allowed
+            .iter()
+            .map(Route::to_string)
+            .collect::<Vec<_>>()
+            .join(" or ");
+        let mut paths: Vec<(String, Route)> = Vec::new();
+        for (source, raw) in given {
+            let pair = raw.trim();
+            let (path, route) = pair
+                .split_once('=')
+                .filter(|(path, _)| path.starts_with('/'))
+                .and_then(|(path, target)| Some((path, Route::parse(target.trim())?)))
+                .filter(|(_, route)| allowed.contains(route))
+                .ok_or_else(|| {
+                    format!(
+                        "{source}: expected /path={names} such as /feed={}, got '{raw}'",
+                        allowed[0]
+                    )
+                })?;
+            let path = normalize(path);
+            if paths.iter().any(|(known, _)| *known == path) {
+                return Err(format!("{source}: {path} routed twice"));
+            }
+            paths.push((path, route));
+        }
+        Ok(Self { paths })
+    }
+
+    /// Where `path` goes, `None` when nowhere.
+    pub fn get(&self, path: &str) -> Option<Route> {
+        let path = normalize(path);
+        self.paths
+            .iter()
+            .find(|(known, _)| *known == path)
+            .map(|(_, route)| *route)
+    }
+
+    /// The route of an upgrade `request`, or the 404 to answer it with.
+    // The error type is tungstenite's, not ours to shrink
+    #[allow(clippy::result_large_err)]
+    pub fn check(&self, request: &Request) -> Result<Route, ErrorResponse> {
+        match self.get(request.uri().path()) {
+            Some(route) => Ok(route),
+            None => {
+                let mut not_found =
+                    ErrorResponse::new(Some("No WebSocket on this path\n".to_string()));
+                *not_found.status_mut() = StatusCode::NOT_FOUND;
+                Err(not_found)
+            }
+        }
+    }
+
+    /// `/path=target` pairs, for the startup log.
+    pub fn describe(&self) -> String {
+        self.paths
+            .iter()
+            .map(|(path, route)| format!("{path}={route}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn normalize(path: &str) -> String {
+    match path.trim().trim_end_matches('/') {
+        "" => "/".to_string(),
+        path => path.to_string(),
+    }
+}
@@ -0,0 +1,246 @@
+//! The simulated feed: random-walk prices, or a recording played back with `--replay`,
+//! broadcast to every client.
+
+use std::convert::Infallible;
+use std::future::{pending, Future};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Args;
+use log::{error, info};
+use market_core::PriceUpdate;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+
+use crate::bind::{self, bind_addrs};
+use crate::connections::{Connections, DEFAULT_CHANNEL_CAPACITY};
+use crate::metrics;
+use crate::persist::{self, PersistConfig, Sources};
+use crate::protocol::{ClientCommand, ServerMessage};
+use crate::recording::{self, ReplayArgs, ReplayConfig};
+use crate::servers::{FeedArgs, ServerArgs};
+use crate::session::{self, Feed, Handler};
+use crate::shutdown::{grace_from_env, serve, ServerHandle, ShutdownRx, DEFAULT_GRACE};
+use crate::simulator::{self, SimulatorArgs, SimulatorConfig};
+use crate::subscription::{Seen, Subscription};
+use crate::ClientConfig;
+
+/// Port without `--port` or one in `--bind`.
+pub const DEFAULT_PORT: u16 = 8081;
+
+/// Where the prices come from.
+#[derive(Debug, Clone)]
+pub enum Source {
+    Simulator(SimulatorConfig),
+    Replay(ReplayConfig),
+}
+
+/// The broadcast server's own options; those of every feed are in `FeedArgs`.
+#[derive(Args, Debug, Clone, Default)]
+pub struct BroadcastArgs {
+    #[command(flatten)]
+    pub simulator: SimulatorArgs,
+
+    #[command(flatten)]
+    pub replay: ReplayArgs,
+}
+
+/// Settings of the broadcast server.
+#[derive(Clone)]
+pub struct BroadcastConfig {
+    /// Every address to listen on.
+    pub addrs: Vec<SocketAddr>,
+    pub source: Source,
+    pub channel_capacity: usize,
+    pub max_connections: Option<usize>,
+    pub client: ClientConfig,
+    /// Port of `/metrics`, on the same address, `None` without.
+    pub metrics_port: Option<u16>,
+    /// `--persist-stream`, `None` to keep nothing.
+    pub persist: Option<PersistConfig>,
+    pub grace: Duration,
+}
+
+impl Default for BroadcastConfig {
+    /// The default simulator on port 8081 of loopback.
+    fn default() -> Self {
+        Self {
+            addrs: vec![SocketAddr::new(Ipv4Addr::LOCALHOST.into(), DEFAULT_PORT)],
+            source: Source::Simulator(SimulatorConfig::default()),
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+            max_connections: None,
+            client: ClientConfig::default(),
+            metrics_port: None,
+            persist: None,
+            grace: DEFAULT_GRACE,
+        }
+    }
+}
+
+impl BroadcastConfig {
+    /// `--bind`/`--port` (port 8081), the simulator or `--replay` options and the settings
+    /// every feed takes.
+    pub fn from_args(
+        server: &ServerArgs,
+        feed: &FeedArgs,
+        args: &BroadcastArgs,
+    ) -> Result<Self, String> {
+        let source = match ReplayConfig::from_args(&args.replay)? {
+            Some(replay) => Source::Replay(replay),
+            None => Source::Simulator(SimulatorConfig::from_args(&args.simulator)?),
+        };
+        Ok(Self {
+            addrs: bind_addrs(&server.bind, DEFAULT_PORT)?,
+            source,
+            channel_capacity: feed.channel_capacity,
+            max_connections: feed.max_connections,
+            client: ClientConfig::from_args(&feed.client, &server.routes)?,
+            metrics_port: metrics::metrics_port(feed.metrics_port)?,
+            persist: PersistConfig::from_args(&feed.persist),
+            grace: grace_from_env()?,
+        })
+    }
+}
+
+/// The simulator has nothing of its own to push, and keeps no prices to query.
+struct Simulated;
+
+impl Handler for Simulated {
+    type Event = Infallible;
+
+    fn next_event(&mut self) -> impl Future<Output = Infallible> + Send {
+        pending()
+    }
+
+    fn event(&mut self, event: Infallible, _subscription: &Subscription) -> Vec<ServerMessage> {
+        match event {}
+    }
+
+    fn command(
+        &mut self,
+        command: ClientCommand,
+        _subscription: &Subscription,
+    ) -> Option<ServerMessage> {
+        let refusal = match command {
+            ClientCommand::Candles { .. } => "candles are only built by ws_dashboard",
+            ClientCommand::Replay { .. } => "replay is not available on the simulator",
+            ClientCommand::Symbols => "symbols is only answered by ws_dashboard",
+            _ => "history is not available on the simulator",
+        };
+        Some(ServerMessage::error(refusal))
+    }
+}
+
+async fn handle_client(
+    stream: TcpStream,
+    rx: broadcast::Receiver<PriceUpdate>,
+    feed: Feed,
+    shutdown: ShutdownRx,
+) {
+    let addr = match bind::peer_addr(&stream) {
+        Ok(addr) => addr,
+        Err(e) => {
+            error!("Failed to read peer addr: {e}");
+            return;
+        }
+    };
+    session::run(stream, addr, rx, &feed, Simulated, shutdown).await;
+}
+
+/// Starts the feed, binds `cfg.addrs` and serves in the background until shut down through
+/// the handle.
+pub async fn run(cfg: BroadcastConfig) -> std::io::Result<ServerHandle> {
+    // No receiver kept here: the channel depth in `stats` only counts what clients have yet to read
+    let capacity = cfg.channel_capacity;
+    let (tx, _) = broadcast::channel::<PriceUpdate>(capacity);
+    let connections = Arc::new(Connections::new(cfg.max_connections).with_channel(&tx, capacity));
+    let seen = Arc::new(Seen::default());
+
+    let listeners = bind::listen(&cfg.addrs)?;
+    let addrs = bind::local_addrs(&listeners)?;
+    for addr in &addrs {
+        info!("Broadcast server listening on ws://{addr}");
+    }
+    info!("Routes: {}", cfg.client.routes.describe());
+    // On the first address only
+    let metrics = match cfg.metrics_port {
+        Some(port) => Some(
+            metrics::serve(
+                SocketAddr::new(addrs[0].ip(), port),
+                connections.clone(),
+                None,
+            )
+            .await?,
+        ),
+        None => None,
+    };
+
+    // Simulated prices, or a recording played back with --replay
+    let feed = match cfg.source {
+        Source::Replay(replay) => {
+            info!(
+                "Replaying {} at {}x{}",
+                replay.path.display(),
+                replay.speed,
+                if replay.looping { ", looping" } else { "" }
+            );
+            tokio::spawn(recording::replay(replay, tx.clone(), seen.clone()))
+        }
+        Source::Simulator(cfg) => {
+            info!(
+                "Simulating {} from {} every {:?}, volatility {}, jump chance {}, spread {}, seed {}",
+                cfg.symbols.join(","),
+                cfg.sources.join(","),
+                cfg.tick,
+                cfg.volatility,
+                cfg.jump_chance,
+                cfg.spread,
+                cfg.seed.map_or("random".to_string(), |seed| seed.to_string())
+            );
+            if let Some(hours) = &cfg.market_hours {
+                info!(
+                    "Market hours {}-{} {}{}",
+                    hours.open.format("%H:%M"),
+                    hours.close.format("%H:%M"),
+                    hours.timezone,
+                    hours
+                        .day_minutes
+                        .map(|minutes| format!(", a day every {minutes} minutes"))
+                        .unwrap_or_default()
+                );
+            }
+            tokio::spawn(simulator::simulate(cfg, tx.clone(), seen.clone()))
+        }
+    };
+
+    let persist = match cfg.persist {
+        Some(persist) => Some(persist::start(
+            persist,
+            Sources {
+                prices: tx.subscribe(),
+                announcements: connections.announcements(),
+                notices: None,
+            },
+        )?),
+        None => None,
+    };
+
+    let shared = Feed {
+        connections,
+        seen,
+        client: cfg.client,
+    };
+    let grace = cfg.grace;
+    Ok(ServerHandle::spawn(addrs, move |stop| async move {
+        serve(listeners, grace, stop, |stream, shutdown| {
+            handle_client(stream, tx.subscribe(), shared.clone(), shutdown)
+        })
+        .await?;
+        feed.abort();
+        for task in metrics.into_iter().chain(persist) {
+            task.abort();
+        }
+        Ok(())
+    }))
+}
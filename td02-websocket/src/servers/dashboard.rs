@@ -0,0 +1,1017 @@
+//! The database feed: prices polled (or, for `stock_prices` on Postgres, listened to) from
+//! the price tables, with the dashboard page, the JSON prices, history, replay, candles,
+//! aggregates, alerts and status on top.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use futures_util::future::BoxFuture;
+use futures_util::stream::FuturesUnordered;
+use futures_util::{FutureExt, StreamExt};
+use log::{debug, error, info, warn};
+use market_core::store::notify::{self, Body, Event, PriceListener};
+use market_core::store::{self, PoolOptions, PriceStore, PriceTable};
+use market_core::{PriceUpdate, StockPrice};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{sleep, Duration, Instant};
+
+use crate::aggregate::{self, AggregateArgs, AggregateConfig};
+use crate::alerts::{Alerts, AlertsArgs, AlertsConfig};
+use crate::args;
+use crate::auth;
+use crate::bind::{self, bind_addrs};
+use crate::candles::{self, CandleHistory, KEPT_CANDLES};
+use crate::cli::DatabaseArgs;
+use crate::connections::{ClientSlot, Connections, DEFAULT_CHANNEL_CAPACITY};
+use crate::http;
+use crate::metrics::{self, PollMetrics};
+use crate::persist::{self, PersistConfig, Sources};
+use crate::protocol::{ClientCommand, ServerMessage, SourceQuote, SymbolInfo, MAX_HISTORY_LIMIT};
+use crate::replay::{Page, Replay, DEFAULT_MAX_REPLAY};
+use crate::servers::{FeedArgs, ServerArgs};
+use crate::session::{self, Feed, Handler};
+use crate::shutdown::{grace_from_env, serve, ServerHandle, ShutdownRx, DEFAULT_GRACE};
+use crate::staleness::{self, Stale, StalenessArgs, StalenessConfig};
+use crate::status::{self, DEFAULT_STATUS_EVERY};
+use crate::subscription::{Seen, Subscription};
+use crate::ClientConfig;
+
+/// Port without `--port` or one in `--bind`.
+pub const DEFAULT_PORT: u16 = 8082;
+
+/// Served on `GET /`, so the dashboard needs no separate file server.
+const DASHBOARD_PAGE: &str = include_str!("../../dashboard.html");
+
+/// Polling period while no price listener is connected, unless `--poll-interval` says.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Incremental polls between two full reads of the latest prices.
+const FULL_POLL_EVERY: u32 = 12;
+
+/// Longest wait between polls while the database keeps failing, unless `--poll-max-backoff`
+/// says.
+const DEFAULT_MAX_POLL_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Longest a (symbol, source) stays without an update while `--min-change` holds them
+/// back, unless `--max-quiet` says.
+const DEFAULT_MAX_QUIET: Duration = Duration::from_secs(30);
+
+/// History queries a single connection may have running at once.
+const MAX_PENDING_HISTORY: usize = 4;
+
+/// Latest price per (symbol, source), kept by the poller and sent to clients as they connect.
+type Latest = RwLock<BTreeMap<(String, String), PriceUpdate>>;
+
+/// What every client task gets a handle to.
+#[derive(Clone)]
+struct Shared {
+    feed: Feed,
+    store: Arc<dyn PriceStore>,
+    latest: Arc<Latest>,
+    /// Aggregates and alerts. Kept even with both off, so client receivers wait instead of
+    /// seeing it closed.
+    notices: broadcast::Sender<ServerMessage>,
+    candle_history: Arc<CandleHistory>,
+    stale: Arc<Stale>,
+    /// `--max-replay`.
+    max_replay: usize,
+}
+
+/// A dashboard client's part of the session: notices, history queries and replays.
+struct Dashboard {
+    store: Arc<dyn PriceStore>,
+    latest: Arc<Latest>,
+    notices: broadcast::Receiver<ServerMessage>,
+    candle_history: Arc<CandleHistory>,
+    stale: Arc<Stale>,
+    max_replay: usize,
+    /// History queries run next to the loop, so price updates keep flowing while they do
+    pending: FuturesUnordered<BoxFuture<'static, ServerMessage>>,
+    /// Live prices wait in there while it runs
+    replay: Option<Replay>,
+}
+
+enum ClientEvent {
+    Notice(Result<ServerMessage, RecvError>),
+    History(ServerMessage),
+    Page(Page),
+}
+
+impl Handler for Dashboard {
+    type Event = ClientEvent;
+
+    async fn next_event(&mut self) -> ClientEvent {
+        let Self {
+            notices,
+            pending,
+            replay,
+            ..
+        } = self;
+        tokio::select! {
+            notice = notices.recv() => ClientEvent::Notice(notice),
+            page = async { replay.as_mut().expect("checked by the guard").next().await }, if replay.is_some() => ClientEvent::Page(page),
+            Some(reply) = pending.next(), if !pending.is_empty() => ClientEvent::History(reply),
+        }
+    }
+
+    fn event(&mut self, event: ClientEvent, subscription: &Subscription) -> Vec<ServerMessage> {
+        match event {
+            ClientEvent::Notice(notice) => {
+                // Lagged: aggregates and statuses come again next round, alerts and candles
+                // are best effort (candles can still be asked for)
+                let Ok(notice) = notice else {
+                    return Vec::new();
+                };
+                // Aggregates, candles and data gaps follow the subscription, alerts and
+                // statuses go to everyone
+                let symbol = match &notice {
+                    ServerMessage::Aggregate(aggregate) => Some(&aggregate.symbol),
+                    ServerMessage::Candle(candle) => Some(&candle.symbol),
+                    ServerMessage::Stale(stale) => Some(&stale.symbol),
+                    ServerMessage::Resumed { symbol, .. } => Some(symbol),
+                    _ => None,
+                };
+                if symbol.is_some_and(|symbol| !subscription.wants(symbol)) {
+                    return Vec::new();
+                }
+                vec![notice]
+            }
+            ClientEvent::History(reply) => vec![reply],
+            ClientEvent::Page(page) => {
+                let Some(current) = &mut self.replay else {
+                    return Vec::new();
+                };
+                let end = match page {
+                    Page::Prices(prices) => {
+                        let mut sent = Vec::new();
+                        for price in prices {
+                            if current.wants(&price.symbol, subscription) {
+                                current.sent(&price);
+                                sent.push(ServerMessage::Price(price));
+                            }
+                        }
+                        return sent;
+                    }
+                    Page::End { truncated } => ServerMessage::Replay {
+                        sent: current.count(),
+                        truncated,
+                    },
+                    Page::Failed => ServerMessage::error(format!(
+                        "replay failed after {} prices",
+                        current.count()
+                    )),
+                };
+                // Over: the live prices that came in meanwhile, then back to normal
+                let held = self.replay.take().map(Replay::held).unwrap_or_default();
+                std::iter::once(end)
+                    .chain(held.into_iter().map(ServerMessage::Price))
+                    .collect()
+            }
+        }
+    }
+
+    fn command(
+        &mut self,
+        command: ClientCommand,
+        _subscription: &Subscription,
+    ) -> Option<ServerMessage> {
+        Some(match command {
+            ClientCommand::Candles { symbol, limit } => {
+                let symbol = symbol.trim().to_uppercase();
+                let candles = self
+                    .candle_history
+                    .last(&symbol, limit.unwrap_or(KEPT_CANDLES));
+                ServerMessage::Candles { symbol, candles }
+            }
+            ClientCommand::Replay { .. } if self.replay.is_some() => {
+                ServerMessage::error("a replay is already in progress")
+            }
+            ClientCommand::Replay { since, symbols } => match DateTime::from_timestamp(since, 0) {
+                Some(since) => {
+                    let symbols = symbols
+                        .iter()
+                        .map(|symbol| symbol.trim().to_uppercase())
+                        .filter(|symbol| !symbol.is_empty())
+                        .collect();
+                    self.replay = Some(Replay::start(
+                        self.store.clone(),
+                        symbols,
+                        since,
+                        self.max_replay,
+                    ));
+                    return None;
+                }
+                None => ServerMessage::error(format!("replay: {since} is not a valid Unix time")),
+            },
+            ClientCommand::Symbols => symbols(&self.latest),
+            ClientCommand::History { .. } if self.pending.len() >= MAX_PENDING_HISTORY => {
+                ServerMessage::error("too many history requests in progress")
+            }
+            ClientCommand::History {
+                symbol,
+                source,
+                limit,
+            } => {
+                self.pending
+                    .push(history(self.store.clone(), symbol, source, limit).boxed());
+                return None;
+            }
+            // Answered by the session itself
+            _ => return None,
+        })
+    }
+
+    fn snapshot(&self, subscription: &Subscription) -> Option<ServerMessage> {
+        Some(snapshot(&self.latest, &self.stale, subscription))
+    }
+
+    fn price(&mut self, price: PriceUpdate, slot: &ClientSlot) -> Option<PriceUpdate> {
+        let Some(replay) = &mut self.replay else {
+            return Some(price);
+        };
+        if !replay.hold(price) {
+            slot.dropped();
+        }
+        None
+    }
+}
+
+async fn handle_client(
+    stream: TcpStream,
+    rx: broadcast::Receiver<PriceUpdate>,
+    shared: Shared,
+    shutdown: ShutdownRx,
+) {
+    let Shared {
+        feed,
+        store,
+        latest,
+        notices,
+        candle_history,
+        stale,
+        max_replay,
+    } = shared;
+    let addr = match bind::peer_addr(&stream) {
+        Ok(addr) => addr,
+        Err(e) => {
+            error!("Failed to read peer addr: {e}");
+            return;
+        }
+    };
+
+    // Plain HTTP requests get the page or the JSON prices instead of a failed handshake
+    let route = |path: &str, query: Option<&str>| {
+        http_route(path, query, feed.client.auth_token.as_deref(), &latest)
+    };
+    let Some(stream) = http::serve_or_upgrade(stream, addr, route).await else {
+        return;
+    };
+
+    let dashboard = Dashboard {
+        store,
+        latest,
+        notices: notices.subscribe(),
+        candle_history,
+        stale,
+        max_replay,
+        pending: FuturesUnordered::new(),
+        replay: None,
+    };
+    session::run(stream, addr, rx, &feed, dashboard, shutdown).await;
+}
+
+/// Latest prices of the subscribed symbols.
+/// A cached price as served on `/prices`.
+#[derive(Serialize)]
+struct PriceView<'a> {
+    #[serde(flatten)]
+    price: &'a PriceUpdate,
+    age_seconds: f64,
+}
+
+/// `GET /` the dashboard page, `GET /prices` the latest price per (symbol, source) and
+/// `GET /prices/{symbol}` those of one symbol, all from `latest`, never from the database.
+/// With a token set, prices need `?token=` like the WebSocket; the page asks for it itself.
+fn http_route(
+    path: &str,
+    query: Option<&str>,
+    token: Option<&str>,
+    latest: &Latest,
+) -> http::Response {
+    let symbol = match path.trim_end_matches('/') {
+        "" | "/index.html" => return http::Response::html(DASHBOARD_PAGE),
+        "/prices" => None,
+        path => match path.strip_prefix("/prices/") {
+            Some(symbol) if !symbol.contains('/') => Some(symbol.trim().to_uppercase()),
+            _ => return http::Response::not_found(),
+        },
+    };
+    if token.is_some_and(|token| !auth::query_authorized(query, token)) {
+        return http::Response::unauthorized();
+    }
+
+    let now = Utc::now();
+    let latest = latest.read().unwrap();
+    let prices: Vec<PriceView> = latest
+        .values()
+        .filter(|price| symbol.as_ref().is_none_or(|symbol| price.symbol == *symbol))
+        .map(|price| PriceView {
+            price,
+            age_seconds: (now - price.timestamp).num_milliseconds() as f64 / 1000.0,
+        })
+        .collect();
+    match symbol {
+        Some(symbol) if prices.is_empty() => http::Response::json(
+            "404 Not Found",
+            &json!({ "error": format!("unknown symbol {symbol}") }),
+        ),
+        Some(symbol) => {
+            http::Response::json("200 OK", &json!({ "symbol": symbol, "prices": prices }))
+        }
+        None => http::Response::json("200 OK", &json!({ "prices": prices })),
+    }
+}
+
+fn snapshot(latest: &Latest, stale: &Stale, subscription: &Subscription) -> ServerMessage {
+    let prices = latest
+        .read()
+        .unwrap()
+        .values()
+        .filter(|price| subscription.wants(&price.symbol))
+        .cloned()
+        .collect();
+    ServerMessage::Snapshot {
+        prices,
+        stale: stale.list(|symbol| subscription.wants(symbol)),
+    }
+}
+
+/// The symbols and sources in `latest`, which the poller extends as new ones show up.
+fn symbols(latest: &Latest) -> ServerMessage {
+    let now = Utc::now();
+    let age = |timestamp: DateTime<Utc>| (now - timestamp).num_milliseconds() as f64 / 1000.0;
+    let mut symbols: Vec<SymbolInfo> = Vec::new();
+    let mut sources = BTreeSet::new();
+    // Keyed by (symbol, source), so each symbol's prices come one after the other
+    for ((symbol, source), price) in latest.read().unwrap().iter() {
+        sources.insert(source.clone());
+        let quote = SourceQuote {
+            source: source.clone(),
+            timestamp: price.timestamp,
+            age_seconds: age(price.timestamp),
+        };
+        match symbols.last_mut() {
+            Some(info) if info.symbol == *symbol => {
+                if price.timestamp > info.timestamp {
+                    info.timestamp = price.timestamp;
+                    info.age_seconds = quote.age_seconds;
+                }
+                info.sources.push(quote);
+            }
+            _ => symbols.push(SymbolInfo {
+                symbol: symbol.clone(),
+                timestamp: price.timestamp,
+                age_seconds: quote.age_seconds,
+                sources: vec![quote],
+            }),
+        }
+    }
+    ServerMessage::Symbols {
+        symbols,
+        sources: sources.into_iter().collect(),
+    }
+}
+
+/// Last `limit` prices of `symbol` (from `source` only, if given), served by the
+/// `(symbol, timestamp)` and `(symbol, source, timestamp)` indexes.
+async fn history(
+    store: Arc<dyn PriceStore>,
+    symbol: String,
+    source: Option<String>,
+    limit: u32,
+) -> ServerMessage {
+    let symbol = symbol.trim().to_uppercase();
+    let limit = limit.min(MAX_HISTORY_LIMIT);
+    match store
+        .recent(&symbol, source.as_deref(), Utc::now(), limit)
+        .await
+    {
+        Ok(prices) => ServerMessage::History {
+            symbol,
+            source,
+            prices: prices.into_iter().map(PriceUpdate::from).collect(),
+        },
+        Err(e) => {
+            error!("History query failed for {symbol}: {e}");
+            ServerMessage::error(format!("cannot load history for {symbol}"))
+        }
+    }
+}
+
+/// Smallest price move worth a broadcast.
+#[derive(Debug, Clone, Copy)]
+enum MinChange {
+    Absolute(f64),
+    Percent(f64),
+}
+
+impl MinChange {
+    fn is_zero(self) -> bool {
+        match self {
+            Self::Absolute(min) | Self::Percent(min) => min == 0.0,
+        }
+    }
+}
+
+/// `0.01` or `0.05%`.
+fn min_change(raw: &str) -> Result<MinChange, String> {
+    let (number, percent) = match raw.trim().strip_suffix('%') {
+        Some(number) => (number, true),
+        None => (raw, false),
+    };
+    let value = number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite() && *value >= 0.0)
+        .ok_or_else(|| "expected an amount such as 0.01 or 0.05%".to_string())?;
+    Ok(if percent {
+        MinChange::Percent(value)
+    } else {
+        MinChange::Absolute(value)
+    })
+}
+
+/// Holds back updates that barely move the price from the last one sent for their symbol
+/// and source, so clients don't flicker when two writers report the same quote.
+#[derive(Debug, Clone)]
+pub struct ChangeFilter {
+    /// `None` sends every newer quote.
+    min_change: Option<MinChange>,
+    /// An update goes out anyway once the last one sent is this old.
+    max_quiet: Duration,
+}
+
+impl Default for ChangeFilter {
+    fn default() -> Self {
+        Self {
+            min_change: None,
+            max_quiet: DEFAULT_MAX_QUIET,
+        }
+    }
+}
+
+impl ChangeFilter {
+    /// `--min-change 0.01` (absolute) or `--min-change 0.05%`, off by default, and
+    /// `--max-quiet 30s`.
+    pub fn from_args(args: &DashboardArgs) -> Self {
+        Self {
+            min_change: args.min_change.filter(|min_change| !min_change.is_zero()),
+            max_quiet: args.max_quiet.unwrap_or(DEFAULT_MAX_QUIET),
+        }
+    }
+
+    /// Whether `row` moved far enough from `last`, the update last sent, or `last` is old.
+    fn worth_sending(&self, last: &PriceUpdate, row: &StockPrice) -> bool {
+        let moved = (row.price - last.price).abs();
+        let far_enough = match self.min_change {
+            None => return true,
+            Some(MinChange::Absolute(min)) => moved >= min,
+            Some(MinChange::Percent(min)) => moved >= last.price.abs() * min / 100.0,
+        };
+        far_enough
+            || row.stale != last.stale
+            || (row.timestamp - last.timestamp)
+                .to_std()
+                .is_ok_and(|quiet| quiet >= self.max_quiet)
+    }
+}
+
+/// Reads new prices from one price table and publishes them, tagged with its asset class.
+/// After a first full read of the latest price per (symbol, source), polls only read prices
+/// stamped since the newest one seen; every `FULL_POLL_EVERY` polls the full read is done
+/// again, for quotes a lagging provider stamps earlier than that. Failed polls back off up to
+/// `max_backoff`, and the first poll that works again is a full read.
+struct Poller {
+    store: Arc<dyn PriceStore>,
+    table: PriceTable,
+    tx: broadcast::Sender<PriceUpdate>,
+    seen: Arc<Seen>,
+    latest: Arc<Latest>,
+    newest: Option<DateTime<Utc>>,
+    filter: ChangeFilter,
+    alerts: Alerts,
+    notices: broadcast::Sender<ServerMessage>,
+    connections: Arc<Connections>,
+    metrics: Arc<PollMetrics>,
+    polls: u32,
+    /// Consecutive failed polls, since `down_since`.
+    failures: u32,
+    down_since: Option<Instant>,
+    /// Between polls without a listener, and the most failed polls back off to.
+    interval: Duration,
+    max_backoff: Duration,
+}
+
+impl Poller {
+    /// Sends `row` to the clients unless it is not newer than the price already sent for
+    /// its symbol and source, or too close to it for the change filter. Returns whether it
+    /// was sent.
+    fn publish(&mut self, row: StockPrice) -> bool {
+        self.newest = self.newest.max(Some(row.timestamp));
+        let key = (row.symbol.clone(), row.source.clone());
+        let (newer, worth_sending) = match self.latest.read().unwrap().get(&key) {
+            Some(last) => (
+                last.timestamp < row.timestamp,
+                self.filter.worth_sending(last, &row),
+            ),
+            None => (true, true),
+        };
+        if !newer {
+            return false;
+        }
+        // `latest` keeps the update last sent, so small moves can't add up unnoticed
+        if !worth_sending {
+            self.connections.count_suppressed();
+            return false;
+        }
+
+        self.seen.insert(&row.symbol);
+        let update = PriceUpdate {
+            asset_class: Some(self.table.asset_class.clone()),
+            ..PriceUpdate::from(row)
+        };
+        let alerts = self.alerts.check(&update);
+        self.latest.write().unwrap().insert(key, update.clone());
+        let _ = self.tx.send(update);
+        for alert in alerts {
+            let _ = self.notices.send(ServerMessage::Alert(alert));
+        }
+        true
+    }
+
+    async fn poll(&mut self, full: bool) {
+        // After an outage, rows written meanwhile may be stamped before `newest`
+        let full = full
+            || self.failures > 0
+            || self.newest.is_none()
+            || self.polls.is_multiple_of(FULL_POLL_EVERY);
+        self.polls = self.polls.wrapping_add(1);
+        let started = Instant::now();
+        let rows = match self.newest {
+            Some(newest) if !full => self.store.since_in(&self.table, newest).await,
+            _ => self.store.latest_in(&self.table).await,
+        };
+        self.metrics.record(
+            started.elapsed(),
+            rows.as_ref().ok().map(|rows: &Vec<StockPrice>| rows.len()),
+        );
+        let rows: Vec<StockPrice> = match rows {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.failures += 1;
+                self.down_since.get_or_insert(started);
+                error!(
+                    "Database poll error on {} ({} in a row, retrying in {}): {e}",
+                    self.table.name,
+                    self.failures,
+                    humantime::format_duration(self.delay())
+                );
+                return;
+            }
+        };
+        if let Some(down_since) = self.down_since.take() {
+            info!(
+                "Database poll on {} working again after {} failures, down for {}, prices resynchronized",
+                self.table.name,
+                self.failures,
+                humantime::format_duration(Duration::from_secs(down_since.elapsed().as_secs()))
+            );
+            self.failures = 0;
+        }
+
+        let fetched = rows.len();
+        let mut broadcast = 0;
+        for row in rows {
+            if self.publish(row) {
+                broadcast += 1;
+            }
+        }
+        if broadcast > 0 {
+            info!(
+                "Polled {fetched} rows from {}, broadcast {broadcast}",
+                self.table.name
+            );
+        }
+    }
+
+    /// `interval`, doubled for each failed poll in a row up to `max_backoff`.
+    fn delay(&self) -> Duration {
+        if self.failures == 0 {
+            return self.interval;
+        }
+        self.interval
+            .saturating_mul(2u32.saturating_pow(self.failures))
+            .min(self.max_backoff.max(self.interval))
+    }
+}
+
+/// Feeds the prices of one table. For `stock_prices` on Postgres, follows `NOTIFY
+/// stock_prices` and only queries the database after (re)connecting the listener or when a
+/// notification lists symbols instead of prices. While the listener is down, for other
+/// tables and on SQLite, polls every `interval` of the poller instead.
+async fn database_feed(mut poller: Poller, listen_url: Option<String>) {
+    if listen_url.is_none() {
+        poller.poll(true).await;
+    }
+    // Warn once per outage, not on every retry
+    let mut listener_down = false;
+
+    loop {
+        if let Some(url) = &listen_url {
+            match PriceListener::connect(url).await {
+                Ok(mut listener) => {
+                    info!("Listening for new prices on channel {}", notify::CHANNEL);
+                    // Listening already, so nothing written from here on is missed
+                    poller.poll(true).await;
+                    loop {
+                        match listener.recv().await {
+                            Ok(Event::Notification(notification)) => match notification.body {
+                                Body::Prices { prices } => {
+                                    for note in prices {
+                                        poller.publish(StockPrice::from(note));
+                                    }
+                                }
+                                Body::Changed { .. } => poller.poll(false).await,
+                            },
+                            Ok(Event::Invalid(e)) => warn!("Ignoring price notification: {e}"),
+                            Ok(Event::Lost) => {
+                                warn!("Price listener connection lost, polling until it is back");
+                                poller.metrics.listener_lost();
+                                break;
+                            }
+                            Err(e) => {
+                                warn!("Price listener failed, polling until it is back: {e}");
+                                poller.metrics.listener_lost();
+                                break;
+                            }
+                        }
+                    }
+                    // Already reported above
+                    listener_down = true;
+                }
+                Err(e) if !listener_down => {
+                    warn!("Cannot listen for new prices, polling until it works: {e}");
+                    listener_down = true;
+                }
+                Err(e) => debug!("Price listener still unavailable: {e}"),
+            }
+        }
+
+        sleep(poller.delay()).await;
+        poller.poll(false).await;
+    }
+}
+
+/// Price tables from `--tables FILE`, else `WS_TABLES`, else `stock_prices` alone:
+///
+/// ```toml
+/// [[table]]
+/// name = "stock_prices"
+/// asset_class = "stock"
+///
+/// [[table]]
+/// name = "crypto_prices"
+/// asset_class = "crypto"
+/// columns = { symbol = "pair", price = "last_price", timestamp = "traded_at" }
+/// ```
+///
+/// Columns default to `symbol`, `price`, `source` and `timestamp`; `open`, `high`, `low`,
+/// `prev_close` and `stale` are read when named (all of them for `stock_prices`).
+fn tables(path: Option<&Path>) -> Result<Vec<PriceTable>, String> {
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Tables {
+        table: Vec<PriceTable>,
+    }
+
+    let path = path.map(Path::to_path_buf).or_else(|| {
+        std::env::var("WS_TABLES")
+            .ok()
+            .filter(|path| !path.trim().is_empty())
+            .map(PathBuf::from)
+    });
+    let Some(path) = path else {
+        return Ok(vec![PriceTable::stock_prices()]);
+    };
+    let name = path.display();
+    let content = std::fs::read_to_string(&path).map_err(|e| format!("{name}: {e}"))?;
+    let Tables { table: mut tables } =
+        toml::from_str(&content).map_err(|e| format!("{name}: {e}"))?;
+    if tables.is_empty() {
+        return Err(format!("{name}: no [[table]]"));
+    }
+    let mut names = BTreeSet::new();
+    for table in &mut tables {
+        // Without a mapping, stock_prices keeps the columns it is known to have
+        if table.name == "stock_prices" && table.columns == Default::default() {
+            table.columns = PriceTable::stock_prices().columns;
+        }
+        table.validate().map_err(|e| format!("{name}: {e}"))?;
+        if !names.insert(table.name.clone()) {
+            return Err(format!("{name}: table {} listed twice", table.name));
+        }
+    }
+    Ok(tables)
+}
+
+/// The dashboard's own options; those of every feed are in `FeedArgs`.
+#[derive(Args, Debug, Clone, Default)]
+pub struct DashboardArgs {
+    #[command(flatten)]
+    pub database: DatabaseArgs,
+
+    /// TOML file of the price tables to feed from [default: WS_TABLES, else stock_prices]
+    #[arg(long, value_name = "FILE")]
+    pub tables: Option<PathBuf>,
+
+    /// Time between polls while no price listener is connected [default: 5s]
+    #[arg(long, value_parser = args::nonzero_duration)]
+    pub poll_interval: Option<Duration>,
+
+    /// Longest wait between polls while the database keeps failing [default: 60s]
+    #[arg(long, value_parser = args::nonzero_duration)]
+    pub poll_max_backoff: Option<Duration>,
+
+    /// Smallest move worth sending, absolute (0.01) or relative (0.05%) [default: any]
+    #[arg(long, value_parser = min_change)]
+    min_change: Option<MinChange>,
+
+    /// Longest a symbol and source go without an update while --min-change holds them back
+    /// [default: 30s]
+    #[arg(long, value_parser = args::nonzero_duration)]
+    pub max_quiet: Option<Duration>,
+
+    #[command(flatten)]
+    pub alerts: AlertsArgs,
+
+    #[command(flatten)]
+    pub aggregate: AggregateArgs,
+
+    /// Time between two status messages, 0s for none [default: 15s]
+    #[arg(long, value_parser = args::duration)]
+    pub status_every: Option<Duration>,
+
+    #[command(flatten)]
+    pub staleness: StalenessArgs,
+
+    /// Most prices one replay sends [default: 5000]
+    #[arg(long, value_parser = args::positive)]
+    pub max_replay: Option<usize>,
+}
+
+/// Settings of the dashboard server.
+#[derive(Clone)]
+pub struct DashboardConfig {
+    /// Every address to listen on.
+    pub addrs: Vec<SocketAddr>,
+    /// `postgres://...` or `sqlite://...`.
+    pub database_url: String,
+    /// Applies the migrations of the workspace on connect.
+    pub migrate: bool,
+    pub pool: PoolOptions,
+    pub tables: Vec<PriceTable>,
+    /// Between polls while no price listener is connected.
+    pub poll_interval: Duration,
+    pub max_backoff: Duration,
+    pub filter: ChangeFilter,
+    pub alerts: AlertsConfig,
+    pub aggregate: AggregateConfig,
+    /// `None` sends no `status`.
+    pub status_every: Option<Duration>,
+    pub staleness: StalenessConfig,
+    pub max_replay: usize,
+    pub channel_capacity: usize,
+    pub max_connections: Option<usize>,
+    pub client: ClientConfig,
+    /// Port of `/metrics`, on the same address, `None` without.
+    pub metrics_port: Option<u16>,
+    /// `--persist-stream`, `None` to keep nothing.
+    pub persist: Option<PersistConfig>,
+    pub grace: Duration,
+}
+
+impl Default for DashboardConfig {
+    /// `stock_prices` of the local SQLite file, on port 8082 of loopback.
+    fn default() -> Self {
+        Self {
+            addrs: vec![SocketAddr::new(Ipv4Addr::LOCALHOST.into(), DEFAULT_PORT)],
+            database_url: store::DEFAULT_SQLITE_URL.to_string(),
+            migrate: true,
+            pool: PoolOptions::default(),
+            tables: vec![PriceTable::stock_prices()],
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            max_backoff: DEFAULT_MAX_POLL_BACKOFF,
+            filter: ChangeFilter::default(),
+            alerts: AlertsConfig::default(),
+            aggregate: AggregateConfig::default(),
+            status_every: Some(DEFAULT_STATUS_EVERY),
+            staleness: StalenessConfig::default(),
+            max_replay: DEFAULT_MAX_REPLAY,
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+            max_connections: None,
+            client: ClientConfig::default(),
+            metrics_port: None,
+            persist: None,
+            grace: DEFAULT_GRACE,
+        }
+    }
+}
+
+impl DashboardConfig {
+    /// `--bind`/`--port` (port 8082), `--db` or `DATABASE_URL` (else a local SQLite file),
+    /// `--skip-migrations` and the other options listed in the README.
+    pub fn from_args(
+        server: &ServerArgs,
+        feed: &FeedArgs,
+        args: &DashboardArgs,
+    ) -> Result<Self, String> {
+        let status_every = args.status_every.unwrap_or(DEFAULT_STATUS_EVERY);
+        Ok(Self {
+            addrs: bind_addrs(&server.bind, DEFAULT_PORT)?,
+            database_url: args.database.url(),
+            migrate: args.database.migrate(),
+            pool: PoolOptions::default().with_env()?,
+            tables: tables(args.tables.as_deref())?,
+            poll_interval: args.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL),
+            max_backoff: args.poll_max_backoff.unwrap_or(DEFAULT_MAX_POLL_BACKOFF),
+            filter: ChangeFilter::from_args(args),
+            alerts: AlertsConfig::from_args(&args.alerts)?,
+            aggregate: AggregateConfig::from_args(&args.aggregate),
+            status_every: (!status_every.is_zero()).then_some(status_every),
+            staleness: StalenessConfig::from_args(&args.staleness),
+            max_replay: args.max_replay.unwrap_or(DEFAULT_MAX_REPLAY),
+            channel_capacity: feed.channel_capacity,
+            max_connections: feed.max_connections,
+            client: ClientConfig::from_args(&feed.client, &server.routes)?,
+            metrics_port: metrics::metrics_port(feed.metrics_port)?,
+            persist: PersistConfig::from_args(&feed.persist),
+            grace: grace_from_env()?,
+        })
+    }
+}
+
+/// Connects to the database, starts the pollers, binds `cfg.addrs` and serves in the
+/// background until shut down through the handle.
+pub async fn run(cfg: DashboardConfig) -> Result<ServerHandle, Box<dyn std::error::Error>> {
+    // Schema lives in migrations/ at the workspace root; no migrations when the database
+    // user has no DDL rights
+    let store = store::connect(&cfg.database_url, &cfg.pool, cfg.migrate).await?;
+
+    info!("Connected to {} database", store.backend());
+
+    let listeners = bind::listen(&cfg.addrs)?;
+    let addrs = bind::local_addrs(&listeners)?;
+
+    // No receiver kept here: the channel depth in `stats` only counts what clients have yet to read
+    let capacity = cfg.channel_capacity;
+    let (tx, _) = broadcast::channel::<PriceUpdate>(capacity);
+    let connections = Arc::new(Connections::new(cfg.max_connections).with_channel(&tx, capacity));
+    let seen = Arc::new(Seen::default());
+    let latest = Arc::new(Latest::default());
+
+    // Aggregates, alerts, candles, statuses and data gaps, as message types of their own
+    let (notices, _) = broadcast::channel::<ServerMessage>(capacity);
+    if !cfg.alerts.rules.is_empty() {
+        info!("Loaded {} alert rule(s)", cfg.alerts.rules.len());
+    }
+
+    // One DB poller per table, so a table failing doesn't hold the others up
+    let poll_metrics = Arc::new(PollMetrics::default());
+    let mut pollers = Vec::new();
+    for table in cfg.tables {
+        info!("Feeding {} prices from {}", table.asset_class, table.name);
+        // NOTIFY stock_prices only announces rows of that table
+        let listen_url = (store.backend() == "postgres" && table.name == "stock_prices")
+            .then(|| cfg.database_url.clone());
+        let poller = Poller {
+            store: store.clone(),
+            table,
+            tx: tx.clone(),
+            seen: seen.clone(),
+            latest: latest.clone(),
+            newest: None,
+            filter: cfg.filter.clone(),
+            alerts: Alerts::new(cfg.alerts.clone()),
+            notices: notices.clone(),
+            connections: connections.clone(),
+            metrics: poll_metrics.clone(),
+            polls: 0,
+            failures: 0,
+            down_since: None,
+            interval: cfg.poll_interval,
+            max_backoff: cfg.max_backoff,
+        };
+        pollers.push(tokio::spawn(database_feed(poller, listen_url)));
+    }
+
+    // One-minute candles, from what is broadcast
+    let candle_history = Arc::new(CandleHistory::default());
+    let mut tasks = vec![tokio::spawn(candles::candles(
+        tx.subscribe(),
+        notices.clone(),
+        candle_history.clone(),
+    ))];
+    tasks.extend(pollers);
+
+    let aggregate_cfg = cfg.aggregate;
+    if let Some(every) = aggregate_cfg.every {
+        info!("Aggregates every {every:?} over {:?}", aggregate_cfg.window);
+        tasks.push(tokio::spawn(aggregate::aggregate(
+            aggregate_cfg.window,
+            every,
+            tx.subscribe(),
+            notices.clone(),
+        )));
+    }
+
+    if let Some(every) = cfg.status_every {
+        tasks.push(tokio::spawn(status::status(
+            every,
+            connections.clone(),
+            poll_metrics.clone(),
+            tx.subscribe(),
+            notices.clone(),
+        )));
+    }
+
+    // Stays empty with the check off, for the snapshot
+    let stale = Arc::new(Stale::default());
+    if cfg.staleness.enabled() {
+        tasks.push(tokio::spawn(staleness::watch(
+            cfg.staleness,
+            tx.subscribe(),
+            notices.clone(),
+            stale.clone(),
+        )));
+    }
+
+    if let Some(persist) = cfg.persist {
+        tasks.push(persist::start(
+            persist,
+            Sources {
+                prices: tx.subscribe(),
+                announcements: connections.announcements(),
+                notices: Some(notices.subscribe()),
+            },
+        )?);
+    }
+
+    for addr in &addrs {
+        info!("Dashboard WebSocket server on ws://{addr}");
+    }
+    info!("Routes: {}", cfg.client.routes.describe());
+    // On the first address only
+    if let Some(port) = cfg.metrics_port {
+        tasks.push(
+            metrics::serve(
+                SocketAddr::new(addrs[0].ip(), port),
+                connections.clone(),
+                Some(poll_metrics),
+            )
+            .await?,
+        );
+    }
+
+    let shared = Shared {
+        feed: Feed {
+            connections,
+            seen,
+            client: cfg.client,
+        },
+        store,
+        latest,
+        notices,
+        candle_history,
+        stale,
+        max_replay: cfg.max_replay,
+    };
+    let grace = cfg.grace;
+    Ok(ServerHandle::spawn(addrs, move |stop| async move {
+        serve(listeners, grace, stop, |stream, shutdown| {
+            handle_client(stream, tx.subscribe(), shared.clone(), shutdown)
+        })
+        .await?;
+        for task in tasks {
+            task.abort();
+        }
+        Ok(())
+    }))
+}
@@ -0,0 +1,553 @@
+//! Echo server for trying the protocol by hand: text comes back as text and binary as
+//! binary, fragmented messages whole (tungstenite puts them back together), pings get their
+//! pong. A few text commands change its behavior, see `HELP`.
+//!
+//! With `--mode chat` it relays instead: each client picks a nick with `/nick`, then its text
+//! goes to every other client, see `CHAT_HELP`.
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use clap::{Args, ValueEnum};
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, error, info, warn};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::{accept_hdr_async, tungstenite::Message, WebSocketStream};
+
+use crate::bind::{self, bind_addrs};
+use crate::connections::Peer;
+use crate::protocol;
+use crate::routes::{Route, Routes};
+use crate::servers::ServerArgs;
+use crate::shutdown::{grace_from_env, serve, ServerHandle, ShutdownRx, DEFAULT_GRACE};
+
+/// Port without `--port` or one in `--bind`.
+pub const DEFAULT_PORT: u16 = 8080;
+
+const HELP: &str = "Commands:
+  /help              this list
+  /delay MS          echo what follows after MS milliseconds (0 to stop)
+  /big N             a text payload of N bytes, up to 16 MiB
+  /close [CODE] [REASON]  close the connection from the server side (1000 by default)
+Anything else comes back as sent, text as text and binary as binary.";
+
+const CHAT_HELP: &str = "Commands:
+  /help              this list
+  /nick NAME         pick your nick, needed before talking (letters, digits, - and _)
+  /who               who is here
+Anything else goes to everyone else as 'NAME: text'.";
+
+/// Longest nick, in characters.
+const MAX_NICK: usize = 24;
+
+/// Messages queued for a chat client before the ones relayed to it are dropped.
+const CHAT_BUFFER: usize = 64;
+
+/// Largest `/big`, tungstenite's default frame limit.
+const MAX_BIG: usize = 16 << 20;
+
+/// How long `/close` waits for the client's Close.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+enum Command {
+    Help,
+    Delay(Duration),
+    Big(usize),
+    Close(CloseFrame<'static>),
+}
+
+/// `None` for text that isn't a command, echoed as is.
+fn command(text: &str) -> Option<Result<Command, String>> {
+    let mut words = text.split_whitespace();
+    let name = words.next()?.strip_prefix('/')?;
+    let arg = words.next();
+    Some(match name {
+        "help" => Ok(Command::Help),
+        "delay" => arg
+            .and_then(|ms| ms.parse::<u64>().ok())
+            .map(|ms| Command::Delay(Duration::from_millis(ms)))
+            .ok_or_else(|| "usage: /delay MS".to_string()),
+        "big" => arg
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|n| *n <= MAX_BIG)
+            .map(Command::Big)
+            .ok_or_else(|| format!("usage: /big N, with N up to {MAX_BIG}")),
+        "close" => {
+            let code = match arg {
+                Some(raw) => match close_code(raw) {
+                    Ok(code) => code,
+                    Err(e) => return Some(Err(e)),
+                },
+                None => CloseCode::Normal,
+            };
+            let reason = words.collect::<Vec<_>>().join(" ");
+            // The reason shares the 125 bytes of a control frame with the code
+            if reason.len() > 123 {
+                return Some(Err("/close: reason longer than 123 bytes".to_string()));
+            }
+            Ok(Command::Close(CloseFrame {
+                code,
+                reason: reason.into(),
+            }))
+        }
+        _ => Err(format!("unknown command /{name}, see /help")),
+    })
+}
+
+/// Codes an endpoint may send: the defined ones but those reserved for local use, and the
+/// 3000–4999 range left to libraries and applications.
+fn close_code(raw: &str) -> Result<CloseCode, String> {
+    match raw.parse::<u16>() {
+        Ok(code @ (1000..=1003 | 1007..=1011 | 3000..=4999)) => Ok(CloseCode::from(code)),
+        _ => Err(format!(
+            "/close: expected a code such as 1000 or 4000, got '{raw}'"
+        )),
+    }
+}
+
+/// Peer address and handshake, with the route of the path; `None` (logged) if either fails
+/// or the path has no route, answered with a 404.
+async fn accept(
+    stream: TcpStream,
+    routes: &Routes,
+    id: u64,
+) -> Option<(Peer, WebSocketStream<TcpStream>, Route)> {
+    let peer = match bind::peer_addr(&stream) {
+        Ok(addr) => Peer { id, addr },
+        Err(e) => {
+            error!("Failed to read peer addr: {e}");
+            return None;
+        }
+    };
+
+    info!("New connection from {peer}");
+
+    let mut path = String::new();
+    let mut route = None;
+    // The error type is tungstenite's, not ours to shrink
+    #[allow(clippy::result_large_err)]
+    let callback = |request: &Request, response: Response| {
+        path = request.uri().path().to_string();
+        route = Some(routes.check(request)?);
+        Ok(response)
+    };
+    let ws_stream = match accept_hdr_async(stream, callback).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            match route {
+                None if !path.is_empty() => warn!("Refused {peer}: no WebSocket on {path}"),
+                _ => error!("WebSocket handshake failed for {peer}: {e}"),
+            }
+            return None;
+        }
+    };
+
+    info!("WebSocket connection established: {peer} on {path}");
+    Some((peer, ws_stream, route?))
+}
+
+/// Serves one client as echo or chat, depending on the path it upgraded on.
+async fn handle(
+    stream: TcpStream,
+    shutdown: ShutdownRx,
+    routes: Arc<Routes>,
+    room: Arc<Room>,
+    id: u64,
+) {
+    let Some((peer, ws_stream, route)) = accept(stream, &routes, id).await else {
+        return;
+    };
+    match route {
+        Route::Chat => chat(ws_stream, peer, shutdown, room).await,
+        Route::Echo | Route::Prices => echo(ws_stream, peer, shutdown).await,
+    }
+}
+
+/// The last log line of a connection.
+fn closed(peer: Peer, started: Instant, sent: u64) {
+    info!(
+        "Connection closed: {peer} after {}, {sent} messages sent",
+        humantime::format_duration(Duration::from_secs(started.elapsed().as_secs()))
+    );
+}
+
+async fn echo(ws_stream: WebSocketStream<TcpStream>, peer: Peer, mut shutdown: ShutdownRx) {
+    let started = Instant::now();
+    let (mut write, mut read) = ws_stream.split();
+
+    // Send welcome message once connected
+    if let Err(e) = write
+        .send(Message::Text(format!(
+            "Welcome to the echo server (connection #{}), /help for commands",
+            peer.id
+        )))
+        .await
+    {
+        error!("Failed to send welcome to {peer}: {e}");
+        return;
+    }
+
+    let mut delay = Duration::ZERO;
+    let mut sent = 0;
+
+    // Echo whatever we receive
+    loop {
+        let msg = tokio::select! {
+            msg = read.next() => msg,
+            _ = shutdown.changed() => {
+                let _ = write.send(protocol::close(CloseCode::Away, "server shutting down")).await;
+                break;
+            }
+        };
+        // Echoes wait for `/delay`, answers to commands don't
+        let (reply, echo) = match msg {
+            Some(Ok(Message::Text(text))) => {
+                info!("{peer} says: {text}");
+                match command(&text) {
+                    None => (Message::Text(text), true),
+                    Some(Ok(Command::Help)) => (Message::Text(HELP.into()), false),
+                    Some(Ok(Command::Delay(chosen))) => {
+                        delay = chosen;
+                        let ack = format!("echo delay set to {}ms", delay.as_millis());
+                        (Message::Text(ack), false)
+                    }
+                    Some(Ok(Command::Big(n))) => (Message::Text("x".repeat(n)), false),
+                    Some(Ok(Command::Close(frame))) => {
+                        info!("Closing {peer} with {} as asked", frame.code);
+                        if write.send(Message::Close(Some(frame))).await.is_ok() {
+                            // The client answers with its own Close, then the stream ends
+                            let _ = timeout(CLOSE_TIMEOUT, async {
+                                while let Some(Ok(message)) = read.next().await {
+                                    if matches!(message, Message::Close(_)) {
+                                        break;
+                                    }
+                                }
+                            })
+                            .await;
+                        }
+                        break;
+                    }
+                    Some(Err(e)) => (Message::Text(e), false),
+                }
+            }
+            Some(Ok(Message::Binary(data))) => {
+                info!("{peer} sent {} bytes", data.len());
+                (Message::Binary(data), true)
+            }
+            // tungstenite answers pings itself
+            Some(Ok(Message::Ping(_))) => continue,
+            Some(Ok(Message::Pong(_))) => {
+                debug!("Pong from {peer}");
+                continue;
+            }
+            Some(Ok(Message::Close(_))) | None => {
+                info!("Client closed connection: {peer}");
+                break;
+            }
+            Some(Err(e)) => {
+                error!("WebSocket error for {peer}: {e}");
+                break;
+            }
+            // Raw frames only come up when writing them, never when reading
+            Some(Ok(Message::Frame(_))) => continue,
+        };
+        if echo && !delay.is_zero() {
+            sleep(delay).await;
+        }
+        if write.send(reply).await.is_err() {
+            break;
+        }
+        sent += 1;
+    }
+
+    closed(peer, started, sent);
+}
+
+/// Chat clients by nick, each with the queue its connection task writes out.
+#[derive(Default)]
+struct Room {
+    members: Mutex<HashMap<String, mpsc::Sender<Message>>>,
+}
+
+/// A nick held in the room, given back when dropped, which also tells the others. Dropping
+/// covers every way out of a connection, aborts on shutdown included.
+struct Member {
+    room: Arc<Room>,
+    nick: String,
+}
+
+impl Room {
+    /// Takes `nick` for the client behind `queue` and announces it, or says why not.
+    fn join(self: &Arc<Self>, nick: &str, queue: mpsc::Sender<Message>) -> Result<Member, String> {
+        let mut members = self.members.lock().unwrap();
+        if members.contains_key(nick) {
+            return Err(format!("nick {nick} is taken"));
+        }
+        members.insert(nick.to_string(), queue);
+        Self::relay(&mut members, nick, &format!("* {nick} joined"));
+        Ok(Member {
+            room: self.clone(),
+            nick: nick.to_string(),
+        })
+    }
+
+    /// Moves `member` to `nick` and announces it, or says why not.
+    fn rename(&self, member: &mut Member, nick: &str) -> Result<(), String> {
+        let mut members = self.members.lock().unwrap();
+        if members.contains_key(nick) {
+            return Err(format!("nick {nick} is taken"));
+        }
+        let Some(queue) = members.remove(&member.nick) else {
+            return Err("you are no longer in the room".to_string());
+        };
+        members.insert(nick.to_string(), queue);
+        Self::relay(
+            &mut members,
+            nick,
+            &format!("* {} is now {nick}", member.nick),
+        );
+        member.nick = nick.to_string();
+        Ok(())
+    }
+
+    fn say(&self, from: &str, text: &str) {
+        let mut members = self.members.lock().unwrap();
+        Self::relay(&mut members, from, &format!("{from}: {text}"));
+    }
+
+    fn nicks(&self) -> Vec<String> {
+        let mut nicks: Vec<String> = self.members.lock().unwrap().keys().cloned().collect();
+        nicks.sort();
+        nicks
+    }
+
+    /// Queues `text` for everyone but `from`. Clients whose task is gone are dropped from the
+    /// room; a client too far behind misses the message rather than holding up the others.
+    fn relay(members: &mut HashMap<String, mpsc::Sender<Message>>, from: &str, text: &str) {
+        members.retain(|nick, queue| {
+            if nick == from {
+                return true;
+            }
+            match queue.try_send(Message::Text(text.to_string())) {
+                Ok(()) => true,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    warn!("{nick} is {CHAT_BUFFER} messages behind, dropping one");
+                    true
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => false,
+            }
+        });
+    }
+}
+
+impl Drop for Member {
+    fn drop(&mut self) {
+        let mut members = self.room.members.lock().unwrap();
+        members.remove(&self.nick);
+        Room::relay(&mut members, &self.nick, &format!("* {} left", self.nick));
+    }
+}
+
+/// Letters, digits, `-` and `_`, up to `MAX_NICK`.
+fn valid_nick(nick: &str) -> Result<&str, String> {
+    let ok = !nick.is_empty()
+        && nick.chars().count() <= MAX_NICK
+        && nick
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    if ok {
+        Ok(nick)
+    } else {
+        Err(format!(
+            "/nick: up to {MAX_NICK} letters, digits, - or _, got '{nick}'"
+        ))
+    }
+}
+
+/// What a chat client's text asks of the room: the reply to send it, if any.
+fn chat_text(
+    room: &Arc<Room>,
+    member: &mut Option<Member>,
+    queue: &mpsc::Sender<Message>,
+    text: &str,
+) -> Option<String> {
+    let mut words = text.split_whitespace();
+    match words.next() {
+        Some("/help") => Some(CHAT_HELP.to_string()),
+        Some("/who") => Some(format!("here: {}", room.nicks().join(", "))),
+        Some("/nick") => {
+            let nick = match words.next().map(valid_nick) {
+                Some(Ok(nick)) => nick,
+                Some(Err(e)) => return Some(e),
+                None => return Some("usage: /nick NAME".to_string()),
+            };
+            let joined = match member {
+                Some(member) if member.nick == nick => return None,
+                Some(member) => room.rename(member, nick),
+                None => room
+                    .join(nick, queue.clone())
+                    .map(|joined| *member = Some(joined)),
+            };
+            Some(joined.map_or_else(|e| e, |()| format!("you are {nick}")))
+        }
+        Some(other) if other.starts_with('/') => {
+            Some(format!("unknown command {other}, see /help"))
+        }
+        _ => match member {
+            Some(member) => {
+                room.say(&member.nick, text);
+                None
+            }
+            None => Some("pick a nick first with /nick NAME".to_string()),
+        },
+    }
+}
+
+async fn chat(
+    ws_stream: WebSocketStream<TcpStream>,
+    peer: Peer,
+    mut shutdown: ShutdownRx,
+    room: Arc<Room>,
+) {
+    let started = Instant::now();
+    let (mut write, mut read) = ws_stream.split();
+
+    if let Err(e) = write
+        .send(Message::Text(format!(
+            "Welcome to the chat (connection #{}), pick a nick with /nick NAME, /help for commands",
+            peer.id
+        )))
+        .await
+    {
+        error!("Failed to send welcome to {peer}: {e}");
+        return;
+    }
+
+    let (queue, mut relayed) = mpsc::channel(CHAT_BUFFER);
+    let mut member: Option<Member> = None;
+    let mut sent = 0;
+
+    loop {
+        let reply = tokio::select! {
+            msg = read.next() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    match chat_text(&room, &mut member, &queue, &text) {
+                        Some(reply) => Message::Text(reply),
+                        None => continue,
+                    }
+                }
+                Some(Ok(Message::Binary(_))) => {
+                    Message::Text("binary messages are not relayed in chat mode".into())
+                }
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => continue,
+                Some(Ok(Message::Close(_))) | None => {
+                    info!("Client closed connection: {peer}");
+                    break;
+                }
+                Some(Err(e)) => {
+                    error!("WebSocket error for {peer}: {e}");
+                    break;
+                }
+            },
+            // Never ends, `queue` is held here
+            Some(message) = relayed.recv() => message,
+            _ = shutdown.changed() => {
+                let _ = write.send(protocol::close(CloseCode::Away, "server shutting down")).await;
+                break;
+            }
+        };
+        if write.send(reply).await.is_err() {
+            break;
+        }
+        sent += 1;
+    }
+
+    if let Some(member) = &member {
+        info!("{} ({peer}) left the chat", member.nick);
+    }
+    closed(peer, started, sent);
+}
+
+/// Settings of the echo server.
+#[derive(Debug, Clone)]
+pub struct EchoConfig {
+    /// Every address to listen on.
+    pub addrs: Vec<SocketAddr>,
+    /// Relays between clients instead of echoing on `/`, `--mode chat`.
+    pub chat: bool,
+    /// Echo or chat by path.
+    pub routes: Routes,
+    pub grace: Duration,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct EchoArgs {
+    /// What the server does on /
+    #[arg(long, value_enum, default_value_t = Mode::Echo)]
+    pub mode: Mode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Mode {
+    /// Send every message back
+    #[default]
+    Echo,
+    /// Relay messages between clients
+    Chat,
+}
+
+impl Default for EchoConfig {
+    /// Echo on port 8080 of loopback.
+    fn default() -> Self {
+        Self {
+            addrs: vec![SocketAddr::new(Ipv4Addr::LOCALHOST.into(), DEFAULT_PORT)],
+            chat: false,
+            routes: Routes::echo(Route::Echo),
+            grace: DEFAULT_GRACE,
+        }
+    }
+}
+
+impl EchoConfig {
+    /// `--bind`/`--port` (port 8080), `--mode echo|chat`, `--route`/`WS_ROUTES` and
+    /// `WS_SHUTDOWN_GRACE`.
+    pub fn from_args(server: &ServerArgs, args: &EchoArgs) -> Result<Self, String> {
+        let chat = args.mode == Mode::Chat;
+        let default = if chat { Route::Chat } else { Route::Echo };
+        Ok(Self {
+            addrs: bind_addrs(&server.bind, DEFAULT_PORT)?,
+            chat,
+            routes: Routes::echo(default).with_args(&server.routes, &[Route::Echo, Route::Chat])?,
+            grace: grace_from_env()?,
+        })
+    }
+}
+
+/// Binds `cfg.addrs` and serves in the background until shut down through the handle.
+pub async fn run(cfg: EchoConfig) -> std::io::Result<ServerHandle> {
+    let listeners = bind::listen(&cfg.addrs)?;
+    let addrs = bind::local_addrs(&listeners)?;
+    let grace = cfg.grace;
+    let name = if cfg.chat { "Chat" } else { "Echo" };
+    for addr in &addrs {
+        info!("{name} server listening on ws://{addr}");
+    }
+    info!("Routes: {}", cfg.routes.describe());
+    let routes = Arc::new(cfg.routes);
+    let room = Arc::new(Room::default());
+    let next_id = AtomicU64::new(1);
+    Ok(ServerHandle::spawn(addrs, move |stop| {
+        serve(listeners, grace, stop, move |stream, shutdown| {
+            let id = next_id.fetch_add(1, Ordering::Relaxed);
+            handle(stream, shutdown, routes.clone(), room.clone(), id)
+        })
+    }))
+}
@@ -0,0 +1,48 @@
+//! The WebSocket servers behind `ws_echo`, `ws_broadcast` and `ws_dashboard`, as functions
+//! taking their settings and returning a `ServerHandle` with the address actually bound,
+//! so they can also run inside another program.
+
+pub mod broadcast;
+pub mod dashboard;
+pub mod echo;
+
+use clap::Args;
+
+use crate::args;
+use crate::bind::BindArgs;
+use crate::connections::{self, DEFAULT_CHANNEL_CAPACITY};
+use crate::persist::PersistArgs;
+use crate::routes::RouteArgs;
+use crate::ClientArgs;
+
+/// What every server takes: where it listens and what its paths serve.
+#[derive(Args, Debug, Clone, Default)]
+pub struct ServerArgs {
+    #[command(flatten)]
+    pub bind: BindArgs,
+
+    #[command(flatten)]
+    pub routes: RouteArgs,
+}
+
+/// What the price feeds, broadcast and dashboard, take besides.
+#[derive(Args, Debug, Clone)]
+pub struct FeedArgs {
+    #[command(flatten)]
+    pub client: ClientArgs,
+
+    /// Prices a client may fall behind before it lags
+    #[arg(long, default_value_t = DEFAULT_CHANNEL_CAPACITY, value_parser = connections::channel_capacity)]
+    pub channel_capacity: usize,
+
+    /// Clients served at once, the next ones are told to come back later [default: no limit]
+    #[arg(long, value_parser = args::positive)]
+    pub max_connections: Option<usize>,
+
+    /// Serve Prometheus metrics on this port at /metrics [default: WS_METRICS_PORT, else none]
+    #[arg(long)]
+    pub metrics_port: Option<u16>,
+
+    #[command(flatten)]
+    pub persist: PersistArgs,
+}
@@ -0,0 +1,403 @@
+//! The client loop shared by `ws_broadcast` and `ws_dashboard`: connection cap, auth
+//! handshake, welcome, then prices from the broadcast channel filtered by the subscription,
+//! heartbeat, idle timeout, rate limit, shutdown and the commands every feed answers the
+//! same way (`stats`, `ping`, `format`, `subscribe`, `unsubscribe`, `set_throttle`, the
+//! admin commands), and admin announcements. What differs goes through a `Handler`. Frames
+//! go out through the connection's `Outbound` queue, prices first through its `Throttle`.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use futures_util::StreamExt;
+use log::{debug, info, log, warn, Level};
+use market_core::PriceUpdate;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::auth;
+use crate::connections::{reject, ClientSlot, Connections, Peer};
+use crate::heartbeat::{Heartbeat, IdleTimer};
+use crate::outbound::Outbound;
+use crate::protocol::{
+    self, Announcement, AnnouncementLevel, ClientCommand, ServerMessage, MAX_ANNOUNCEMENT_LEN,
+};
+use crate::rate_limit::{Inbound, InboundLimiter};
+use crate::shutdown::ShutdownRx;
+use crate::subscription::{Seen, Subscription};
+use crate::throttle::{self, Throttle};
+use crate::ClientConfig;
+
+/// How long a closing client gets to take what is left in its send queue.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What every client task of a feed gets a handle to.
+#[derive(Clone)]
+pub struct Feed {
+    pub connections: Arc<Connections>,
+    pub seen: Arc<Seen>,
+    pub client: ClientConfig,
+}
+
+/// A feed's own part of the client loop, one per connection.
+pub trait Handler {
+    /// What the feed pushes by itself, next to the prices.
+    type Event: Send;
+
+    /// The next event; never resolving when there is none. Must be cancel safe.
+    fn next_event(&mut self) -> impl Future<Output = Self::Event> + Send;
+
+    /// The messages an event turns into, in order.
+    fn event(&mut self, event: Self::Event, subscription: &Subscription) -> Vec<ServerMessage>;
+
+    /// Answer to the commands the loop leaves to the feed (`history`, `replay`, `candles`),
+    /// `None` when it comes later as an event.
+    fn command(
+        &mut self,
+        command: ClientCommand,
+        subscription: &Subscription,
+    ) -> Option<ServerMessage>;
+
+    /// Sent after the welcome and again after lagging behind, to resynchronize.
+    fn snapshot(&self, _subscription: &Subscription) -> Option<ServerMessage> {
+        None
+    }
+
+    /// A live price the client wants: `Some` to send it now, `None` when the feed keeps it
+    /// (and counts it on `slot` if it can't).
+    fn price(&mut self, price: PriceUpdate, _slot: &ClientSlot) -> Option<PriceUpdate> {
+        Some(price)
+    }
+}
+
+/// Serves one client from accept to disconnect.
+pub async fn run<H: Handler>(
+    stream: TcpStream,
+    addr: SocketAddr,
+    mut rx: broadcast::Receiver<PriceUpdate>,
+    feed: &Feed,
+    mut handler: H,
+    mut shutdown: ShutdownRx,
+) {
+    let Feed {
+        connections,
+        seen,
+        client,
+    } = feed;
+
+    let peer = connections.peer(addr);
+    let Some(current) = connections.open() else {
+        let max = connections.max().unwrap_or_default();
+        warn!("Connection limit of {max} reached, rejecting {peer}");
+        reject(stream, client.layout).await;
+        return;
+    };
+    info!("Client connected: {peer} (active: {current})");
+    let started = Instant::now();
+
+    let Some(upgraded) = auth::handshake(stream, peer, client).await else {
+        connections.close();
+        return;
+    };
+    let path = upgraded.path;
+    info!("WebSocket connection established: {peer} on {path}");
+    let mut slot = connections.register(peer, &path, upgraded.format, client.layout);
+    let mut throttle = Throttle::new(upgraded.throttle);
+    slot.set_throttle(upgraded.throttle);
+    let mut announcements = connections.announcements();
+
+    let (write, mut read) = upgraded.ws.split();
+    let outbound = Outbound::new(client.outbound);
+    // Polled by the select below, so it sends while the loop waits for its next event
+    let writer = outbound.write(write, slot.tally());
+    tokio::pin!(writer);
+    let mut writer_done = false;
+
+    let mut subscription = Subscription::default();
+
+    queue(&outbound, &slot, ServerMessage::welcome(peer.id));
+    // The receiver was subscribed before the snapshot is read, so no update falls in between
+    if let Some(snapshot) = handler.snapshot(&subscription) {
+        queue(&outbound, &slot, snapshot);
+    }
+
+    let mut heartbeat = Heartbeat::new(client.heartbeat);
+    let mut idle = IdleTimer::new(client.idle_timeout);
+    let mut limiter = InboundLimiter::new(client.rate_limit);
+    let mut rate_limited = 0;
+
+    loop {
+        if outbound.too_slow() {
+            warn!(
+                "{peer} kept its send queue full for {:?}, closing as too slow",
+                client.outbound.slow_after
+            );
+            connections.count_slow();
+            outbound.clear();
+            outbound.push(protocol::close(CloseCode::Policy, "too slow"));
+            break;
+        }
+
+        let outgoing = tokio::select! {
+            update = rx.recv() => match update {
+                Ok(price_update) => {
+                    if !subscription.wants(&price_update.symbol) {
+                        continue;
+                    }
+                    match handler
+                        .price(price_update, &slot)
+                        .and_then(|price_update| throttle.offer(price_update, &slot))
+                    {
+                        Some(price_update) => vec![ServerMessage::Price(price_update)],
+                        None => continue,
+                    }
+                }
+                // Too slow to keep up: the oldest updates were overwritten in the channel, so
+                // the client gets the snapshot again, if the feed has one
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Client {peer} lagged behind, {missed} updates skipped");
+                    slot.lagged(missed);
+                    let mut notices = vec![ServerMessage::Lagged { missed }];
+                    notices.extend(handler.snapshot(&subscription));
+                    notices
+                }
+                Err(RecvError::Closed) => break,
+            },
+
+            event = handler.next_event() => handler.event(event, &subscription),
+
+            // Prices the throttle held, once their window is over
+            _ = throttle.due() => throttle
+                .take_due()
+                .into_iter()
+                .filter(|price_update| subscription.wants(&price_update.symbol))
+                .map(ServerMessage::Price)
+                .collect(),
+
+            // Sent whatever the subscription; a client too far behind skips those it missed
+            announcement = announcements.recv() => match announcement {
+                Ok(announcement) => vec![ServerMessage::Announcement(announcement)],
+                Err(_) => continue,
+            },
+
+            msg = read.next() => {
+                if let Some(Ok(_)) = &msg {
+                    heartbeat.alive();
+                    idle.alive();
+                }
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        match limiter.check() {
+                            Inbound::Allowed => {}
+                            Inbound::Dropped { notify } => {
+                                rate_limited += 1;
+                                connections.count_rate_limited();
+                                if notify {
+                                    queue(&outbound, &slot, ServerMessage::error("rate limited"));
+                                }
+                                continue;
+                            }
+                            Inbound::Disconnect => {
+                                warn!("{peer} kept exceeding the message rate limit, closing");
+                                outbound.push(protocol::close(CloseCode::Policy, "rate limited"));
+                                break;
+                            }
+                        }
+                        // Commands may carry the admin token, never logged as sent
+                        let command = ClientCommand::parse(&text);
+                        match &command {
+                            Ok(command) => debug!("Received from {peer}: {}", command.redacted()),
+                            Err(e) => debug!("Received from {peer}: {e}"),
+                        }
+                        let reply = match command {
+                            Ok(ClientCommand::Stats { admin_token }) => Some(ServerMessage::Stats {
+                                server: connections.stats(),
+                                rate_limited,
+                                lagged: slot.lagged_events(),
+                                coalesced: slot.coalesced_events(),
+                                connections: auth::is_admin(admin_token.as_deref(), client.admin_token.as_deref())
+                                    .then(|| connections.list()),
+                            }),
+                            Ok(ClientCommand::Ping { nonce, client_ts }) => Some(ServerMessage::Pong {
+                                nonce,
+                                client_ts,
+                                server_ts: Utc::now(),
+                            }),
+                            Ok(ClientCommand::SetFormat { format: chosen }) => {
+                                slot.set_format(chosen);
+                                Some(ServerMessage::Format { format: chosen })
+                            }
+                            Ok(ClientCommand::SetThrottle { max_per_sec }) => match throttle::parse_rate(max_per_sec) {
+                                Ok(rate) => {
+                                    // Removing the throttle lets out what it held
+                                    for price_update in throttle.set(rate) {
+                                        if subscription.wants(&price_update.symbol) {
+                                            queue(&outbound, &slot, ServerMessage::Price(price_update));
+                                        }
+                                    }
+                                    slot.set_throttle(rate);
+                                    Some(ServerMessage::Throttle { max_per_sec: rate })
+                                }
+                                Err(e) => Some(ServerMessage::error(e)),
+                            },
+                            Ok(ClientCommand::Auth { .. }) => {
+                                Some(ServerMessage::error("auth is only accepted as the first message"))
+                            }
+                            Ok(ClientCommand::Subscribe { symbols }) => {
+                                subscription.subscribe(symbols);
+                                slot.set_subscribed(&subscription);
+                                Some(subscription.ack(seen))
+                            }
+                            Ok(ClientCommand::Unsubscribe { symbols }) => {
+                                subscription.unsubscribe(symbols);
+                                slot.set_subscribed(&subscription);
+                                Some(subscription.ack(seen))
+                            }
+                            Ok(ClientCommand::AdminList { admin_token }
+                                | ClientCommand::AdminKick { admin_token, .. }
+                                | ClientCommand::Announce { admin_token, .. })
+                                if !auth::is_admin(admin_token.as_deref(), client.admin_token.as_deref()) =>
+                            {
+                                warn!("{peer} sent an admin command without the admin token");
+                                Some(ServerMessage::error("admin token required"))
+                            }
+                            Ok(ClientCommand::AdminList { .. }) => Some(ServerMessage::Connections {
+                                connections: connections.list(),
+                            }),
+                            Ok(ClientCommand::AdminKick { id, .. }) => Some(if connections.kick(id) {
+                                warn!("{peer} kicked connection {id}");
+                                ServerMessage::Kicked { id }
+                            } else {
+                                ServerMessage::error(format!("no connection {id}"))
+                            }),
+                            Ok(ClientCommand::Announce { message, level, .. }) => {
+                                announce(connections, peer, message, level)
+                            }
+                            Ok(command) => handler.command(command, &subscription),
+                            Err(e) => Some(ServerMessage::error(e)),
+                        };
+                        reply.into_iter().collect()
+                    }
+                    Some(Ok(Message::Close(_))) | None => {
+                        info!("Client closed connection: {peer}");
+                        break;
+                    }
+                    Some(Err(e)) => {
+                        warn!("WebSocket error for {peer}: {e}");
+                        break;
+                    }
+                    _ => continue,
+                }
+            }
+
+            sent = &mut writer, if !writer_done => {
+                writer_done = true;
+                if let Err(e) = sent {
+                    info!("Client disconnected while sending: {peer} ({e})");
+                }
+                break;
+            }
+
+            _ = slot.kicked() => {
+                info!("Closing {peer}, kicked by an admin");
+                outbound.push(protocol::close(CloseCode::Policy, "kicked"));
+                break;
+            }
+
+            _ = idle.expired() => {
+                info!("Closing {peer}, nothing received for {:?}", idle.timeout().unwrap_or_default());
+                outbound.push(protocol::close(CloseCode::Away, "idle timeout"));
+                break;
+            }
+
+            _ = shutdown.changed() => {
+                outbound.push(protocol::close(CloseCode::Away, "server shutting down"));
+                break;
+            }
+
+            _ = heartbeat.tick() => {
+                let frame = heartbeat.next_frame();
+                let closing = matches!(frame, Message::Close(_));
+                outbound.push(frame);
+                if closing {
+                    warn!("No answer from {peer} to {} pings, closing", heartbeat.missed());
+                    break;
+                }
+                continue;
+            }
+        };
+
+        for message in outgoing {
+            queue(&outbound, &slot, message);
+        }
+    }
+
+    // What is left, a Close frame among it, goes out before the connection is dropped
+    outbound.close();
+    if !writer_done {
+        let _ = timeout(FLUSH_TIMEOUT, &mut writer).await;
+    }
+
+    let remaining = connections.close();
+    info!(
+        "Client disconnected: {peer} on {path} after {}, {} messages sent (active: {remaining})",
+        humantime::format_duration(Duration::from_secs(started.elapsed().as_secs())),
+        slot.messages_sent()
+    );
+}
+
+/// Sends an admin's announcement to every client, this one included; an error reply when
+/// the message is empty or too long.
+fn announce(
+    connections: &Connections,
+    peer: Peer,
+    message: String,
+    level: AnnouncementLevel,
+) -> Option<ServerMessage> {
+    let message = message.trim().to_string();
+    if message.is_empty() {
+        return Some(ServerMessage::error("announcement is empty"));
+    }
+    let len = message.chars().count();
+    if len > MAX_ANNOUNCEMENT_LEN {
+        return Some(ServerMessage::error(format!(
+            "announcement of {len} characters, at most {MAX_ANNOUNCEMENT_LEN}"
+        )));
+    }
+    let log_level = match level {
+        AnnouncementLevel::Info => Level::Info,
+        AnnouncementLevel::Warning => Level::Warn,
+    };
+    let clients = connections.announce(Announcement {
+        message: message.clone(),
+        level,
+        timestamp: Utc::now(),
+    });
+    log!(
+        log_level,
+        "Announcement from {peer} to {clients} clients: {message}"
+    );
+    // The announcement itself comes back as the reply
+    None
+}
+
+/// Queues `message` in the client's format, counting the price it displaced, if any. Prices
+/// get their `sent_at` here.
+fn queue(outbound: &Outbound, slot: &ClientSlot, mut message: ServerMessage) {
+    if let ServerMessage::Price(price_update) = &mut message {
+        price_update.sent_at = Some(Utc::now());
+    }
+    let frame = slot.frame(&message);
+    if matches!(message, ServerMessage::Price(_)) {
+        if outbound.push_price(frame) {
+            slot.dropped();
+        }
+    } else {
+        outbound.push(frame);
+    }
+}
@@ -0,0 +1,239 @@
+//! Graceful shutdown of the WebSocket servers: on `ServerHandle::shutdown` (Ctrl+C or
+//! SIGTERM for the binaries) the accept loop stops, every client is told to close (`Close`
+//! 1001 "server shutting down") and gets up to `WS_SHUTDOWN_GRACE` to do so before its task
+//! is aborted.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{error, info, warn};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio::task::{JoinHandle, JoinSet};
+
+/// Time the clients get to close once shutdown starts.
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(5);
+
+/// Tells client tasks the server is shutting down: `changed()` resolves once it does.
+pub type ShutdownRx = watch::Receiver<bool>;
+
+/// Shutdown requests made so far: the first starts the shutdown, the next skips the grace
+/// period.
+pub type StopRx = watch::Receiver<u32>;
+
+/// `WS_SHUTDOWN_GRACE` (`5s`, `500ms`...), else `DEFAULT_GRACE`.
+pub fn grace_from_env() -> Result<Duration, String> {
+    match std::env::var("WS_SHUTDOWN_GRACE")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+    {
+        Some(raw) => humantime::parse_duration(raw.trim())
+            .map_err(|_| format!("WS_SHUTDOWN_GRACE: expected a duration such as 5s, got '{raw}'")),
+        None => Ok(DEFAULT_GRACE),
+    }
+}
+
+/// SIGINT or SIGTERM on unix, Ctrl+C elsewhere. Signals are buffered from creation on, so
+/// one arriving while nothing is waiting for it is not lost.
+pub struct ShutdownSignal {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+}
+
+impl ShutdownSignal {
+    pub fn new() -> std::io::Result<Self> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            Ok(Self {
+                interrupt: signal(SignalKind::interrupt())?,
+                terminate: signal(SignalKind::terminate())?,
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            Ok(Self {})
+        }
+    }
+
+    /// Waits for the next signal and returns its name, for logs.
+    pub async fn recv(&mut self) -> &'static str {
+        #[cfg(unix)]
+        {
+            tokio::select! {
+                _ = self.interrupt.recv() => "SIGINT",
+                _ = self.terminate.recv() => "SIGTERM",
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = tokio::signal::ctrl_c().await;
+            "Ctrl+C"
+        }
+    }
+}
+
+/// A server running in its own task, from `ServerHandle::spawn`.
+pub struct ServerHandle {
+    addrs: Vec<SocketAddr>,
+    stop: watch::Sender<u32>,
+    task: JoinHandle<std::io::Result<()>>,
+}
+
+impl ServerHandle {
+    /// Runs `server`, given the shutdown requests, for listeners bound to `addrs`.
+    pub fn spawn<F, Fut>(addrs: Vec<SocketAddr>, server: F) -> Self
+    where
+        F: FnOnce(StopRx) -> Fut,
+        Fut: Future<Output = std::io::Result<()>> + Send + 'static,
+    {
+        let (stop, stop_rx) = watch::channel(0);
+        Self {
+            addrs,
+            stop,
+            task: tokio::spawn(server(stop_rx)),
+        }
+    }
+
+    /// The first address actually bound, with the port picked for port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addrs[0]
+    }
+
+    /// Every address bound, in the order asked.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+
+    /// Starts the shutdown; called again, stops waiting for the clients.
+    pub fn shutdown(&self) {
+        self.stop.send_modify(|requests| *requests += 1);
+    }
+
+    /// Shuts down on SIGINT or SIGTERM, and a second one skips the grace period.
+    pub fn shutdown_on_signals(&self) -> std::io::Result<()> {
+        Self::shutdown_all_on_signals(std::slice::from_ref(self))
+    }
+
+    /// As `shutdown_on_signals`, for servers run side by side in one process.
+    pub fn shutdown_all_on_signals(handles: &[Self]) -> std::io::Result<()> {
+        let mut signal = ShutdownSignal::new()?;
+        let stops: Vec<_> = handles.iter().map(|handle| handle.stop.clone()).collect();
+        let stop = move || {
+            for stop in &stops {
+                stop.send_modify(|requests| *requests += 1);
+            }
+        };
+        tokio::spawn(async move {
+            let name = signal.recv().await;
+            info!("{name} received, shutting down");
+            stop();
+            let name = signal.recv().await;
+            warn!("{name} received again, not waiting for clients");
+            stop();
+        });
+        Ok(())
+    }
+
+    /// Waits until the server stopped. Dropping the handle instead stops it.
+    pub async fn wait(self) -> std::io::Result<()> {
+        let Self { stop, task, .. } = self;
+        let stopped = task.await.map_err(std::io::Error::other)?;
+        drop(stop);
+        stopped
+    }
+}
+
+/// Accepts clients on every listener until the first shutdown request and runs `handle` for
+/// each, then shuts down as described above. A second request skips the grace period.
+pub async fn serve<F, Fut>(
+    listeners: Vec<TcpListener>,
+    grace: Duration,
+    mut stop: StopRx,
+    mut handle: F,
+) -> std::io::Result<()>
+where
+    F: FnMut(TcpStream, ShutdownRx) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let (shutdown, shutdown_rx) = watch::channel(false);
+    let mut clients = JoinSet::new();
+
+    // One accept loop per listener, all handing their clients over here
+    let (accepted_tx, mut accepted) = mpsc::channel(listeners.len());
+    let mut accepting = JoinSet::new();
+    for listener in listeners {
+        let accepted_tx = accepted_tx.clone();
+        accepting.spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        if accepted_tx.send(stream).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let addr = listener.local_addr().map(|addr| addr.to_string());
+                        error!(
+                            "Accept failed on {}, no longer taking connections there: {e}",
+                            addr.unwrap_or_default()
+                        );
+                        break;
+                    }
+                }
+            }
+        });
+    }
+    drop(accepted_tx);
+
+    loop {
+        tokio::select! {
+            stream = accepted.recv() => match stream {
+                Some(stream) => {
+                    clients.spawn(handle(stream, shutdown_rx.clone()));
+                }
+                None => {
+                    error!("No listener left taking connections");
+                    break;
+                }
+            },
+            // Reap finished clients so the set doesn't grow
+            Some(_) = clients.join_next(), if !clients.is_empty() => {}
+            // Also when the handle is gone, with nothing left to stop the server
+            _ = stop.changed() => break,
+        }
+    }
+    // Dropping the accept loops closes the listeners
+    drop(accepting);
+
+    let _ = shutdown.send(true);
+    if !clients.is_empty() {
+        info!("Closing {} client connection(s)", clients.len());
+    }
+    let drained = async { while clients.join_next().await.is_some() {} };
+    let skip_grace = async {
+        if *stop.borrow_and_update() >= 2 {
+            return;
+        }
+        if stop.changed().await.is_err() {
+            // Dropped handle: nobody left to ask, so the grace period runs out
+            std::future::pending::<()>().await;
+        }
+    };
+    tokio::select! {
+        _ = tokio::time::timeout(grace, drained) => {}
+        _ = skip_grace => {}
+    }
+    if !clients.is_empty() {
+        warn!(
+            "{} client(s) still open after the grace period, dropping them",
+            clients.len()
+        );
+        clients.abort_all();
+    }
+    info!("Server stopped");
+    Ok(())
+}
@@ -0,0 +1,485 @@
+//! Simulated prices for `ws_broadcast`: every symbol follows its own random walk from a
+//! realistic starting price and is quoted by every source each tick, the sources a small
+//! spread apart, each quote with a bid, an ask and a tick volume. `--symbols AAPL,TSLA`,
+//! `--sources a,b`, `--tick-ms N` (2000), `--volatility X` (standard deviation of a step,
+//! 0.002), `--jump-chance P` (chance per tick of a 2–5% "news" jump, 0 by default),
+//! `--spread X` (0.001) and `--seed N` for the same prices on every run.
+//!
+//! `--market-hours` keeps quiet outside a weekday session (`--timezone America/New_York`,
+//! `--open 09:30`, `--close 16:00`) but for an unchanged stale quote now and then, and opens
+//! with a small gap. `--time-scale N` makes a simulated day last N minutes.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeDelta, Utc, Weekday};
+use chrono_tz::Tz;
+use clap::Args;
+use log::{debug, info};
+use market_core::PriceUpdate;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::sync::broadcast;
+use tokio::time::{interval, Duration, Instant};
+
+use crate::subscription::Seen;
+
+/// Shortest `--tick-ms`.
+const MIN_TICK: Duration = Duration::from_millis(10);
+
+/// Starting prices of the default symbols; others start anywhere from 20 to 500.
+const START_PRICES: [(&str, f64); 3] = [("AAPL", 190.0), ("GOOGL", 140.0), ("MSFT", 410.0)];
+
+const DEFAULT_SOURCES: [&str; 2] = ["alpha_vantage", "finnhub"];
+
+/// Half the gap between bid and ask, relative to the price.
+const HALF_BID_ASK: f64 = 0.0002;
+
+/// How often a closed market repeats its last quotes.
+const CLOSED_HEARTBEAT: Duration = Duration::from_secs(60);
+
+/// A uniform step in [-√3, √3] has a standard deviation of 1.
+const UNIT_STEP: f64 = 1.732_050_807_568_877_2;
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct SimulatorArgs {
+    /// Symbols to simulate, comma separated [default: AAPL,GOOGL,MSFT]
+    #[arg(long, value_delimiter = ',')]
+    pub symbols: Option<Vec<String>>,
+
+    /// Sources quoting every symbol, comma separated [default: alpha_vantage,finnhub]
+    #[arg(long, value_delimiter = ',')]
+    pub sources: Option<Vec<String>>,
+
+    /// Milliseconds between two ticks, 10 or more [default: 2000]
+    #[arg(long, value_name = "MS", value_parser = tick)]
+    pub tick_ms: Option<Duration>,
+
+    /// Standard deviation of a step, relative to the price, up to 0.5 [default: 0.002]
+    #[arg(long, value_parser = |raw: &str| fraction(raw, 0.5))]
+    pub volatility: Option<f64>,
+
+    /// Chance per tick of a 2-5% jump, up to 1 [default: 0]
+    #[arg(long, value_parser = |raw: &str| fraction(raw, 1.0))]
+    pub jump_chance: Option<f64>,
+
+    /// Widest relative gap between two sources, up to 0.5 [default: 0.001]
+    #[arg(long, value_parser = |raw: &str| fraction(raw, 0.5))]
+    pub spread: Option<f64>,
+
+    /// Seed of the random walks, for the same prices on every run
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Only trade during weekday sessions, with stale quotes now and then outside
+    #[arg(long)]
+    pub market_hours: bool,
+
+    /// Timezone of the exchange [default: America/New_York]
+    #[arg(long, value_parser = timezone, requires = "market_hours")]
+    pub timezone: Option<Tz>,
+
+    /// Local time the session opens [default: 09:30]
+    #[arg(long, value_name = "HH:MM", value_parser = time, requires = "market_hours")]
+    pub open: Option<NaiveTime>,
+
+    /// Local time the session closes [default: 16:00]
+    #[arg(long, value_name = "HH:MM", value_parser = time, requires = "market_hours")]
+    pub close: Option<NaiveTime>,
+
+    /// Real minutes a simulated day lasts [default: real time]
+    #[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u32).range(1..), requires = "market_hours")]
+    pub time_scale: Option<u32>,
+}
+
+fn tick(raw: &str) -> Result<Duration, String> {
+    raw.trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_millis)
+        .filter(|tick| *tick >= MIN_TICK)
+        .ok_or_else(|| format!("expected {} or more", MIN_TICK.as_millis()))
+}
+
+fn fraction(raw: &str, max: f64) -> Result<f64, String> {
+    raw.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|value| (0.0..=max).contains(value))
+        .ok_or_else(|| format!("expected a number from 0 to {max}"))
+}
+
+fn timezone(raw: &str) -> Result<Tz, String> {
+    raw.trim()
+        .parse()
+        .map_err(|_| "expected a zone such as Europe/Paris".to_string())
+}
+
+fn time(raw: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(raw.trim(), "%H:%M")
+        .map_err(|_| "expected a time such as 09:30".to_string())
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulatorConfig {
+    pub symbols: Vec<String>,
+    pub sources: Vec<String>,
+    pub tick: Duration,
+    pub volatility: f64,
+    pub jump_chance: f64,
+    /// Widest relative gap between two sources quoting the same symbol.
+    pub spread: f64,
+    /// Random without one.
+    pub seed: Option<u64>,
+    /// Always open without one.
+    pub market_hours: Option<MarketHours>,
+}
+
+/// A weekday trading session in the exchange's local time.
+#[derive(Debug, Clone)]
+pub struct MarketHours {
+    pub timezone: Tz,
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+    /// Real minutes a simulated day lasts; `None` follows the real clock.
+    pub day_minutes: Option<u32>,
+}
+
+impl Default for MarketHours {
+    fn default() -> Self {
+        Self {
+            timezone: chrono_tz::America::New_York,
+            open: NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+            close: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+            day_minutes: None,
+        }
+    }
+}
+
+impl MarketHours {
+    fn is_open(&self, now: DateTime<Utc>) -> bool {
+        let local = now.with_timezone(&self.timezone);
+        !matches!(local.weekday(), Weekday::Sat | Weekday::Sun)
+            && self.open <= local.time()
+            && local.time() < self.close
+    }
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        Self {
+            symbols: START_PRICES
+                .iter()
+                .map(|(symbol, _)| symbol.to_string())
+                .collect(),
+            sources: DEFAULT_SOURCES
+                .iter()
+                .map(|source| source.to_string())
+                .collect(),
+            tick: Duration::from_secs(2),
+            volatility: 0.002,
+            jump_chance: 0.0,
+            spread: 0.001,
+            seed: None,
+            market_hours: None,
+        }
+    }
+}
+
+impl SimulatorConfig {
+    /// Defaults with the command-line overrides.
+    pub fn from_args(args: &SimulatorArgs) -> Result<Self, String> {
+        let list = |name: &str, items: &[String]| {
+            let items: Vec<String> = items
+                .iter()
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect();
+            if items.is_empty() {
+                return Err(format!("{name}: expected a comma-separated list"));
+            }
+            Ok(items)
+        };
+
+        let mut cfg = Self::default();
+        if let Some(symbols) = &args.symbols {
+            cfg.symbols = list("--symbols", symbols)?
+                .iter()
+                .map(|symbol| symbol.to_uppercase())
+                .collect();
+        }
+        if let Some(sources) = &args.sources {
+            cfg.sources = list("--sources", sources)?;
+        }
+        cfg.tick = args.tick_ms.unwrap_or(cfg.tick);
+        cfg.volatility = args.volatility.unwrap_or(cfg.volatility);
+        cfg.jump_chance = args.jump_chance.unwrap_or(cfg.jump_chance);
+        cfg.spread = args.spread.unwrap_or(cfg.spread);
+        cfg.seed = args.seed;
+        cfg.market_hours = market_hours(args)?;
+        Ok(cfg)
+    }
+}
+
+fn market_hours(args: &SimulatorArgs) -> Result<Option<MarketHours>, String> {
+    if !args.market_hours {
+        return Ok(None);
+    }
+    let mut hours = MarketHours::default();
+    hours.timezone = args.timezone.unwrap_or(hours.timezone);
+    hours.open = args.open.unwrap_or(hours.open);
+    hours.close = args.close.unwrap_or(hours.close);
+    if hours.open >= hours.close {
+        return Err(format!(
+            "--open {} must come before --close {}",
+            hours.open.format("%H:%M"),
+            hours.close.format("%H:%M")
+        ));
+    }
+    hours.day_minutes = args.time_scale;
+    Ok(Some(hours))
+}
+
+/// Simulated time: the real clock, or with `--time-scale` a faster one starting now.
+struct Clock {
+    start: DateTime<Utc>,
+    started: Instant,
+    scale: f64,
+}
+
+impl Clock {
+    fn new(hours: Option<&MarketHours>) -> Self {
+        Self {
+            start: Utc::now(),
+            started: Instant::now(),
+            scale: hours
+                .and_then(|hours| hours.day_minutes)
+                .map_or(1.0, |minutes| 24.0 * 60.0 / f64::from(minutes)),
+        }
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        let elapsed = self.started.elapsed().mul_f64(self.scale);
+        self.start + TimeDelta::from_std(elapsed).unwrap_or(TimeDelta::MAX)
+    }
+}
+
+struct Walk {
+    symbol: String,
+    price: f64,
+    /// Day range of the quotes sent, reset when the (simulated) UTC date changes or the
+    /// market opens.
+    day: NaiveDate,
+    open: f64,
+    high: f64,
+    low: f64,
+    /// Last price before the market closed.
+    prev_close: Option<f64>,
+    /// Last quote of each source, repeated while the market is closed.
+    last: Vec<Option<PriceUpdate>>,
+}
+
+impl Walk {
+    fn new(symbol: String, price: f64, now: DateTime<Utc>, sources: usize) -> Self {
+        Self {
+            symbol,
+            price,
+            day: now.date_naive(),
+            open: price,
+            high: price,
+            low: price,
+            prev_close: None,
+            last: vec![None; sources],
+        }
+    }
+
+    /// Opens the session with a gap of 0.5–2% from the close.
+    fn open_session(&mut self, rng: &mut StdRng, now: DateTime<Utc>) {
+        self.prev_close = Some(self.price);
+        let gap = rng.gen_range(0.005..0.02);
+        self.price *= if rng.gen_bool(0.5) {
+            1.0 + gap
+        } else {
+            1.0 - gap
+        };
+        self.day = now.date_naive();
+        self.open = self.price;
+        self.high = self.price;
+        self.low = self.price;
+    }
+
+    fn step(&mut self, cfg: &SimulatorConfig, rng: &mut StdRng) {
+        let mut step = normal_ish(rng) * cfg.volatility;
+        if rng.gen_bool(cfg.jump_chance) {
+            let jump = rng.gen_range(0.02..0.05);
+            step += if rng.gen_bool(0.5) { jump } else { -jump };
+            debug!("News jump of {:+.1}% on {}", step * 100.0, self.symbol);
+        }
+        // Never reaches zero, whatever the volatility
+        self.price = (self.price * (1.0 + step)).max(self.price * 0.5);
+    }
+
+    /// A fresh quote from `source`, a spread away from the walk.
+    fn quote(
+        &mut self,
+        cfg: &SimulatorConfig,
+        source: &str,
+        rng: &mut StdRng,
+        now: DateTime<Utc>,
+    ) -> PriceUpdate {
+        let offset = rng.gen_range(-0.5..=0.5) * cfg.spread;
+        let price = self.price * (1.0 + offset);
+        // Each side at least a tenth of the usual gap away, so bid < price < ask
+        let bid = price * (1.0 - HALF_BID_ASK * rng.gen_range(0.1..=1.0));
+        let ask = price * (1.0 + HALF_BID_ASK * rng.gen_range(0.1..=1.0));
+        self.quoted(price, now);
+        PriceUpdate {
+            symbol: self.symbol.clone(),
+            price,
+            source: source.to_string(),
+            timestamp: Utc::now(),
+            open: Some(self.open),
+            high: Some(self.high),
+            low: Some(self.low),
+            prev_close: self.prev_close,
+            bid: Some(bid),
+            ask: Some(ask),
+            // Round lots of 100 shares
+            volume: Some(rng.gen_range(1..=50) * 100),
+            stale: false,
+            replayed: false,
+            asset_class: None,
+            sent_at: None,
+        }
+    }
+
+    /// Counts `price` in the day range.
+    fn quoted(&mut self, price: f64, now: DateTime<Utc>) {
+        if now.date_naive() != self.day {
+            self.day = now.date_naive();
+            self.open = price;
+            self.high = price;
+            self.low = price;
+        }
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+    }
+}
+
+/// Roughly normal with a standard deviation of 1: the mean of four uniform steps, doubled.
+fn normal_ish(rng: &mut StdRng) -> f64 {
+    (0..4)
+        .map(|_| rng.gen_range(-UNIT_STEP..=UNIT_STEP))
+        .sum::<f64>()
+        / 2.0
+}
+
+/// The random walks of the configured symbols, every one quoted by every source on each
+/// tick; market hours are left to the caller.
+pub struct Market {
+    cfg: SimulatorConfig,
+    // Kept across ticks, unlike thread_rng, so it can live across awaits and be seeded
+    rng: StdRng,
+    walks: Vec<Walk>,
+}
+
+impl Market {
+    pub fn new(cfg: SimulatorConfig, now: DateTime<Utc>) -> Self {
+        let mut rng = match cfg.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let walks = cfg
+            .symbols
+            .iter()
+            .map(|symbol| {
+                let price = START_PRICES
+                    .iter()
+                    .find(|(known, _)| known == symbol)
+                    .map(|(_, price)| *price)
+                    .unwrap_or_else(|| rng.gen_range(20.0..500.0));
+                Walk::new(symbol.clone(), price, now, cfg.sources.len())
+            })
+            .collect();
+        Self { cfg, rng, walks }
+    }
+
+    /// Steps every walk, then quotes it from every source.
+    pub fn tick(&mut self, now: DateTime<Utc>) -> Vec<PriceUpdate> {
+        let Self { cfg, rng, walks } = self;
+        let mut quotes = Vec::with_capacity(walks.len() * cfg.sources.len());
+        for walk in walks {
+            walk.step(cfg, rng);
+            for (i, source) in cfg.sources.iter().enumerate() {
+                let quote = walk.quote(cfg, source, rng, now);
+                walk.last[i] = Some(quote.clone());
+                quotes.push(quote);
+            }
+        }
+        quotes
+    }
+}
+
+pub async fn simulate(cfg: SimulatorConfig, tx: broadcast::Sender<PriceUpdate>, seen: Arc<Seen>) {
+    let clock = Clock::new(cfg.market_hours.as_ref());
+    let mut market = Market::new(cfg.clone(), clock.now());
+    let mut ticker = interval(cfg.tick);
+    let mut open = true;
+    let mut heartbeat: Option<Instant> = None;
+
+    loop {
+        ticker.tick().await;
+        let now = clock.now();
+
+        if let Some(hours) = &cfg.market_hours {
+            let local = now.with_timezone(&hours.timezone).format("%a %H:%M %Z");
+            match (open, hours.is_open(now)) {
+                (false, true) => {
+                    info!("Market open ({local})");
+                    for walk in &mut market.walks {
+                        walk.open_session(&mut market.rng, now);
+                    }
+                }
+                (true, false) => {
+                    info!("Market closed ({local}), repeating the last quotes every {CLOSED_HEARTBEAT:?}");
+                    heartbeat = None;
+                }
+                _ => {}
+            }
+            open = hours.is_open(now);
+        }
+
+        if !open {
+            if heartbeat.is_some_and(|last| last.elapsed() < CLOSED_HEARTBEAT) {
+                continue;
+            }
+            heartbeat = Some(Instant::now());
+            for walk in &mut market.walks {
+                seen.insert(&walk.symbol);
+                for (i, source) in cfg.sources.iter().enumerate() {
+                    let quote = match walk.last[i].clone() {
+                        Some(quote) => quote,
+                        // Closed since the start: a first quote, then that one again
+                        None => walk.quote(&cfg, source, &mut market.rng, now),
+                    };
+                    walk.last[i] = Some(quote.clone());
+                    let _ = tx.send(PriceUpdate {
+                        timestamp: Utc::now(),
+                        volume: Some(0),
+                        stale: true,
+                        ..quote
+                    });
+                }
+            }
+            continue;
+        }
+
+        for quote in market.tick(now) {
+            debug!(
+                "Broadcasting {} @ ${:.2} from {}",
+                quote.symbol, quote.price, quote.source
+            );
+            seen.insert(&quote.symbol);
+            let _ = tx.send(quote);
+        }
+    }
+}
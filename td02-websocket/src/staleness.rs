@@ -0,0 +1,206 @@
+//! Data gaps for `ws_dashboard`: when a symbol gets nothing from one of its sources for
+//! longer than its threshold, judged on the timestamp of the last price, every client gets a
+//! `stale` message and a warning is logged; the next price of that pair sends `resumed`.
+//! `--stale-after 5m` sets the threshold of every symbol (`0s` turns the check off), and
+//! `--stale-after BTC-USD=30s`, repeatable, that of one symbol. The pairs stale at the moment
+//! are in the snapshot new clients get.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use log::{info, warn};
+use market_core::PriceUpdate;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{interval, Duration, MissedTickBehavior};
+
+use crate::protocol::{ServerMessage, StaleSymbol};
+
+pub const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(300);
+
+/// How often the last prices are looked at.
+const CHECK_EVERY: Duration = Duration::from_secs(1);
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct StalenessArgs {
+    /// Warn about a symbol and source without a price for this long, 0s never; SYMBOL=30s for
+    /// one symbol, repeatable [default: 5m]
+    #[arg(long, value_name = "[SYMBOL=]DURATION", value_parser = threshold)]
+    pub stale_after: Vec<(Option<String>, Duration)>,
+}
+
+/// `5m` or `BTC-USD=30s`, the symbol uppercase.
+fn threshold(raw: &str) -> Result<(Option<String>, Duration), String> {
+    let (symbol, duration) = match raw.split_once('=') {
+        Some((symbol, duration)) => (Some(symbol.trim().to_uppercase()), duration),
+        None => (None, raw),
+    };
+    humantime::parse_duration(duration.trim())
+        .ok()
+        .filter(|_| symbol.as_ref().is_none_or(|symbol| !symbol.is_empty()))
+        .map(|threshold| (symbol, threshold))
+        .ok_or_else(|| "expected a duration such as 5m or SYMBOL=30s".to_string())
+}
+
+#[derive(Debug, Clone)]
+pub struct StalenessConfig {
+    /// `None` for symbols without a threshold of their own to never be stale.
+    pub default: Option<Duration>,
+    /// By symbol, uppercase; `None` turns the check off for that symbol.
+    pub per_symbol: BTreeMap<String, Option<Duration>>,
+}
+
+impl Default for StalenessConfig {
+    fn default() -> Self {
+        Self {
+            default: Some(DEFAULT_STALE_AFTER),
+            per_symbol: BTreeMap::new(),
+        }
+    }
+}
+
+impl StalenessConfig {
+    /// Every `--stale-after DURATION` or `--stale-after SYMBOL=DURATION`.
+    pub fn from_args(args: &StalenessArgs) -> Self {
+        let mut cfg = Self::default();
+        for (symbol, threshold) in &args.stale_after {
+            let threshold = (!threshold.is_zero()).then_some(*threshold);
+            match symbol {
+                Some(symbol) => {
+                    cfg.per_symbol.insert(symbol.clone(), threshold);
+                }
+                None => cfg.default = threshold,
+            }
+        }
+        cfg
+    }
+
+    /// Whether any symbol can go stale.
+    pub fn enabled(&self) -> bool {
+        self.default.is_some() || self.per_symbol.values().any(Option::is_some)
+    }
+
+    fn threshold(&self, symbol: &str) -> Option<Duration> {
+        match self.per_symbol.get(symbol) {
+            Some(threshold) => *threshold,
+            None => self.default,
+        }
+    }
+}
+
+/// The (symbol, source) pairs stale at the moment, for the snapshot.
+#[derive(Debug, Default)]
+pub struct Stale(Mutex<BTreeMap<(String, String), DateTime<Utc>>>);
+
+impl Stale {
+    /// Those of the symbols `wants` keeps, ages as of now.
+    pub fn list(&self, wants: impl Fn(&str) -> bool) -> Vec<StaleSymbol> {
+        let now = Utc::now();
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((symbol, _), _)| wants(symbol))
+            .map(|((symbol, source), last_update)| StaleSymbol {
+                symbol: symbol.clone(),
+                source: source.clone(),
+                last_update: *last_update,
+                age_secs: age_secs(now, *last_update),
+            })
+            .collect()
+    }
+}
+
+/// Follows the prices broadcast on `rx` and sends `stale` and `resumed` messages to `tx`,
+/// until the price channel closes.
+pub async fn watch(
+    cfg: StalenessConfig,
+    mut rx: broadcast::Receiver<PriceUpdate>,
+    tx: broadcast::Sender<ServerMessage>,
+    stale: Arc<Stale>,
+) {
+    info!(
+        "Symbols stale after {} without a price{}",
+        cfg.default
+            .map_or("never".to_string(), |d| humantime::format_duration(d)
+                .to_string()),
+        cfg.per_symbol
+            .iter()
+            .map(|(symbol, threshold)| match threshold {
+                Some(threshold) => format!(
+                    ", {symbol} after {}",
+                    humantime::format_duration(*threshold)
+                ),
+                None => format!(", {symbol} never"),
+            })
+            .collect::<String>()
+    );
+    let mut last: BTreeMap<(String, String), DateTime<Utc>> = BTreeMap::new();
+    let mut ticker = interval(CHECK_EVERY);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            update = rx.recv() => match update {
+                Ok(price) => {
+                    let key = (price.symbol, price.source);
+                    let newest = last.entry(key.clone()).or_insert(price.timestamp);
+                    // Prices read again after a full poll are no news
+                    if price.timestamp <= *newest {
+                        continue;
+                    }
+                    *newest = price.timestamp;
+                    let Some(since) = stale.0.lock().unwrap().remove(&key) else {
+                        continue;
+                    };
+                    let (symbol, source) = key;
+                    let gap_secs = age_secs(price.timestamp, since);
+                    info!(
+                        "{symbol} from {source} resumed after a gap of {}",
+                        humantime::format_duration(Duration::from_secs(gap_secs))
+                    );
+                    let _ = tx.send(ServerMessage::Resumed {
+                        symbol,
+                        source,
+                        last_update: price.timestamp,
+                        gap_secs,
+                    });
+                }
+                // Missed prices only delay noticing that a stale pair resumed
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            },
+            _ = ticker.tick() => {
+                let now = Utc::now();
+                let mut stale = stale.0.lock().unwrap();
+                for ((symbol, source), last_update) in &last {
+                    let Some(threshold) = cfg.threshold(symbol) else {
+                        continue;
+                    };
+                    let age = (now - *last_update).to_std().unwrap_or_default();
+                    let key = (symbol.clone(), source.clone());
+                    if age <= threshold || stale.contains_key(&key) {
+                        continue;
+                    }
+                    stale.insert(key, *last_update);
+                    let age_secs = age.as_secs();
+                    warn!(
+                        "No price of {symbol} from {source} for {}, last at {last_update}",
+                        humantime::format_duration(Duration::from_secs(age_secs))
+                    );
+                    let _ = tx.send(ServerMessage::Stale(StaleSymbol {
+                        symbol: symbol.clone(),
+                        source: source.clone(),
+                        last_update: *last_update,
+                        age_secs,
+                    }));
+                }
+            }
+        }
+    }
+}
+
+fn age_secs(now: DateTime<Utc>, then: DateTime<Utc>) -> u64 {
+    (now - then).num_seconds().max(0) as u64
+}
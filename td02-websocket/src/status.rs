@@ -0,0 +1,50 @@
+//! A `status` message to every client of `ws_dashboard` every `--status-every` (`15s`,
+//! `0s` to turn it off), so a quiet feed can be told from a wedged server or a database
+//! that stopped answering.
+
+use std::sync::Arc;
+
+use market_core::PriceUpdate;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{interval, Duration, MissedTickBehavior};
+
+use crate::connections::Connections;
+use crate::metrics::PollMetrics;
+use crate::protocol::ServerMessage;
+
+pub const DEFAULT_STATUS_EVERY: Duration = Duration::from_secs(15);
+
+/// Counts the prices broadcast on `rx` and sends a status to `tx` every `every`, until
+/// the price channel closes.
+pub async fn status(
+    every: Duration,
+    connections: Arc<Connections>,
+    polls: Arc<PollMetrics>,
+    mut rx: broadcast::Receiver<PriceUpdate>,
+    tx: broadcast::Sender<ServerMessage>,
+) {
+    let mut ticker = interval(every);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick is immediate
+    ticker.tick().await;
+    let mut updates = 0;
+
+    loop {
+        tokio::select! {
+            update = rx.recv() => match update {
+                Ok(_) => updates += 1,
+                Err(RecvError::Lagged(missed)) => updates += missed,
+                Err(RecvError::Closed) => return,
+            },
+            _ = ticker.tick() => {
+                let _ = tx.send(ServerMessage::Status {
+                    uptime_secs: connections.uptime().as_secs(),
+                    active_connections: connections.active(),
+                    updates_last_interval: updates,
+                    db_ok: polls.db_ok(),
+                });
+                updates = 0;
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Per-connection symbol filter driven by the `subscribe` and `unsubscribe` commands. A fresh
+//! connection, with no symbol subscribed, receives every symbol. The session checks it before
+//! a price is encoded, so a price the client didn't ask for costs a hash lookup and no serde
+//! work.
+
+use std::collections::{BTreeSet, HashSet};
+use std::sync::RwLock;
+
+use crate::protocol::ServerMessage;
+
+/// Subscribing to `*` goes back to every symbol.
+pub const ALL: &str = "*";
+
+#[derive(Debug)]
+pub enum Subscription {
+    /// Every symbol but the unsubscribed ones.
+    All {
+        except: HashSet<String>,
+    },
+    Only(HashSet<String>),
+}
+
+impl Default for Subscription {
+    fn default() -> Self {
+        Self::All {
+            except: HashSet::new(),
+        }
+    }
+}
+
+impl Subscription {
+    pub fn wants(&self, symbol: &str) -> bool {
+        match self {
+            Self::All { except } => !except.contains(symbol),
+            Self::Only(symbols) => symbols.contains(symbol),
+        }
+    }
+
+    /// A first `subscribe` narrows the default "all symbols" down to the listed ones; later
+    /// ones add to the set.
+    pub fn subscribe(&mut self, symbols: Vec<String>) {
+        let symbols = normalize(symbols);
+        if symbols.contains(ALL) {
+            *self = Self::default();
+            return;
+        }
+        match self {
+            Self::All { .. } => *self = Self::Only(symbols),
+            Self::Only(current) => current.extend(symbols),
+        }
+    }
+
+    pub fn unsubscribe(&mut self, symbols: Vec<String>) {
+        let symbols = normalize(symbols);
+        match self {
+            Self::All { except } => except.extend(symbols),
+            Self::Only(current) => current.retain(|s| !symbols.contains(s)),
+        }
+    }
+
+    /// How many symbols are subscribed, `None` for every symbol.
+    pub fn symbol_count(&self) -> Option<usize> {
+        match self {
+            Self::All { .. } => None,
+            Self::Only(symbols) => Some(symbols.len()),
+        }
+    }
+
+    /// The subscribed symbols, sorted, `None` for every symbol.
+    pub fn symbols(&self) -> Option<Vec<String>> {
+        match self {
+            Self::All { .. } => None,
+            Self::Only(symbols) => Some(sorted(symbols)),
+        }
+    }
+
+    /// Symbols left out of every symbol, sorted.
+    pub fn excluded(&self) -> Vec<String> {
+        match self {
+            Self::All { except } => sorted(except),
+            Self::Only(_) => Vec::new(),
+        }
+    }
+
+    /// Current subscription, with the subscribed symbols no price was seen for yet.
+    pub fn ack(&self, seen: &Seen) -> ServerMessage {
+        let seen = seen.0.read().unwrap();
+        match self {
+            Self::All { except } => ServerMessage::Subscription {
+                all: true,
+                symbols: Vec::new(),
+                excluded: sorted(except),
+                not_seen: Vec::new(),
+            },
+            Self::Only(symbols) => {
+                let symbols = sorted(symbols);
+                let not_seen = symbols
+                    .iter()
+                    .filter(|symbol| !seen.contains(*symbol))
+                    .cloned()
+                    .collect();
+                ServerMessage::Subscription {
+                    all: false,
+                    symbols,
+                    excluded: Vec::new(),
+                    not_seen,
+                }
+            }
+        }
+    }
+}
+
+/// Symbols a price went out for since the server started, shared by all connections.
+#[derive(Debug, Default)]
+pub struct Seen(RwLock<BTreeSet<String>>);
+
+impl Seen {
+    pub fn insert(&self, symbol: &str) {
+        if !self.0.read().unwrap().contains(symbol) {
+            self.0.write().unwrap().insert(symbol.to_string());
+        }
+    }
+}
+
+fn sorted(symbols: &HashSet<String>) -> Vec<String> {
+    let mut symbols: Vec<String> = symbols.iter().cloned().collect();
+    symbols.sort();
+    symbols
+}
+
+fn normalize(symbols: Vec<String>) -> HashSet<String> {
+    symbols
+        .into_iter()
+        .map(|s| s.trim().to_uppercase())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
@@ -0,0 +1,127 @@
+//! Per-client cap on price updates, for clients such as phones that only want the latest
+//! value now and then: with `max_per_sec` set (`set_throttle`, or `?throttle=2` on the
+//! upgrade URL), each (symbol, source) goes out at most that often. A price coming sooner
+//! waits for the end of its window, replaced by any newer one of the same pair meanwhile;
+//! those replaced are counted as coalesced. Every other message goes out as usual.
+
+use std::collections::HashMap;
+use std::future;
+use std::time::Duration;
+
+use market_core::PriceUpdate;
+use tokio::time::{sleep_until, Instant};
+
+use crate::connections::ClientSlot;
+
+/// Highest `max_per_sec`; a client wanting more can go without a throttle.
+pub const MAX_PER_SEC: f64 = 1000.0;
+
+/// `Some(rate)` for a throttle, `None` for `0`, which removes it.
+pub fn parse_rate(max_per_sec: f64) -> Result<Option<f64>, String> {
+    if max_per_sec == 0.0 {
+        Ok(None)
+    } else if max_per_sec > 0.0 && max_per_sec <= MAX_PER_SEC {
+        Ok(Some(max_per_sec))
+    } else {
+        Err(format!(
+            "max_per_sec: expected 0 (no throttle) up to {MAX_PER_SEC}, got {max_per_sec}"
+        ))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Throttle {
+    max_per_sec: Option<f64>,
+    /// When each (symbol, source) last went out.
+    sent: HashMap<(String, String), Instant>,
+    /// The newest price of each pair waiting for its window.
+    pending: HashMap<(String, String), PriceUpdate>,
+}
+
+impl Throttle {
+    pub fn new(max_per_sec: Option<f64>) -> Self {
+        Self {
+            max_per_sec,
+            ..Self::default()
+        }
+    }
+
+    /// Changes the rate; the prices held so far are returned when the throttle is removed.
+    pub fn set(&mut self, max_per_sec: Option<f64>) -> Vec<PriceUpdate> {
+        self.max_per_sec = max_per_sec;
+        if max_per_sec.is_some() {
+            return Vec::new();
+        }
+        self.sent.clear();
+        sorted(self.pending.drain().map(|(_, price)| price).collect())
+    }
+
+    /// `Some` to send `price` now, `None` when held; the held price it replaces, if any, is
+    /// counted on `slot`.
+    pub fn offer(&mut self, price: PriceUpdate, slot: &ClientSlot) -> Option<PriceUpdate> {
+        let Some(window) = self.window() else {
+            return Some(price);
+        };
+        let key = (price.symbol.clone(), price.source.clone());
+        let now = Instant::now();
+        match self.sent.get(&key) {
+            Some(sent) if now < *sent + window => {
+                if self.pending.insert(key, price).is_some() {
+                    slot.coalesced();
+                }
+                None
+            }
+            _ => {
+                self.sent.insert(key, now);
+                Some(price)
+            }
+        }
+    }
+
+    /// Resolves once a held price is due; never while none is held. Cancel safe.
+    pub async fn due(&self) {
+        let next = self.window().and_then(|window| {
+            self.pending
+                .keys()
+                .filter_map(|key| self.sent.get(key))
+                .min()
+                .map(|sent| *sent + window)
+        });
+        match next {
+            Some(at) => sleep_until(at).await,
+            None => future::pending().await,
+        }
+    }
+
+    /// The held prices whose window is over, by symbol then source.
+    pub fn take_due(&mut self) -> Vec<PriceUpdate> {
+        let Some(window) = self.window() else {
+            return Vec::new();
+        };
+        let now = Instant::now();
+        let due: Vec<_> = self
+            .pending
+            .keys()
+            .filter(|key| self.sent.get(*key).is_none_or(|sent| *sent + window <= now))
+            .cloned()
+            .collect();
+        let mut prices = Vec::with_capacity(due.len());
+        for key in due {
+            if let Some(price) = self.pending.remove(&key) {
+                self.sent.insert(key, now);
+                prices.push(price);
+            }
+        }
+        sorted(prices)
+    }
+
+    fn window(&self) -> Option<Duration> {
+        self.max_per_sec
+            .map(|max_per_sec| Duration::from_secs_f64(1.0 / max_per_sec))
+    }
+}
+
+fn sorted(mut prices: Vec<PriceUpdate>) -> Vec<PriceUpdate> {
+    prices.sort_by(|a, b| (&a.symbol, &a.source).cmp(&(&b.symbol, &b.source)));
+    prices
+}
@@ -0,0 +1,148 @@
+//! What the integration tests share: the servers' settings on port 0, and a client reading
+//! protocol messages with a timeout.
+
+// Each test file uses its own part of this
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use td02_websocket::protocol::{ClientCommand, ServerMessage};
+use td02_websocket::servers::broadcast::{BroadcastConfig, Source};
+use td02_websocket::servers::dashboard::DashboardConfig;
+use td02_websocket::servers::echo::EchoConfig;
+use td02_websocket::simulator::SimulatorConfig;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+pub type Ws = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Longest wait for anything the server should send.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A port picked by the system, on loopback.
+pub fn any_port() -> Vec<SocketAddr> {
+    vec!["127.0.0.1:0".parse().unwrap()]
+}
+
+pub fn echo_config() -> EchoConfig {
+    EchoConfig {
+        addrs: any_port(),
+        grace: Duration::from_secs(1),
+        ..EchoConfig::default()
+    }
+}
+
+/// The simulator ticking every 20ms.
+pub fn broadcast_config() -> BroadcastConfig {
+    BroadcastConfig {
+        addrs: any_port(),
+        source: Source::Simulator(SimulatorConfig {
+            tick: Duration::from_millis(20),
+            ..SimulatorConfig::default()
+        }),
+        grace: Duration::from_secs(1),
+        ..BroadcastConfig::default()
+    }
+}
+
+/// Polling a fresh SQLite file every 50ms.
+pub fn dashboard_config(db: &TempDb) -> DashboardConfig {
+    DashboardConfig {
+        addrs: any_port(),
+        database_url: db.url(),
+        poll_interval: Duration::from_millis(50),
+        grace: Duration::from_secs(1),
+        ..DashboardConfig::default()
+    }
+}
+
+/// A SQLite file in the temporary directory, removed when dropped.
+pub struct TempDb {
+    path: PathBuf,
+}
+
+impl TempDb {
+    pub fn new() -> Self {
+        static NEXT: AtomicU32 = AtomicU32::new(0);
+        let name = format!(
+            "td02-test-{}-{}.db",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        Self {
+            path: std::env::temp_dir().join(name),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("sqlite://{}", self.path.display())
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", self.path.display()));
+        }
+    }
+}
+
+pub async fn connect(addr: SocketAddr, path: &str) -> Ws {
+    let (ws, _) = timeout(TIMEOUT, connect_async(format!("ws://{addr}{path}")))
+        .await
+        .expect("connecting timed out")
+        .expect("connecting failed");
+    ws
+}
+
+/// The next frame but pings and pongs.
+pub async fn next(ws: &mut Ws) -> Message {
+    loop {
+        let message = timeout(TIMEOUT, ws.next())
+            .await
+            .expect("nothing received in time")
+            .expect("connection ended")
+            .expect("connection failed");
+        if !matches!(message, Message::Ping(_) | Message::Pong(_)) {
+            return message;
+        }
+    }
+}
+
+pub async fn next_text(ws: &mut Ws) -> String {
+    match next(ws).await {
+        Message::Text(text) => text,
+        other => panic!("expected text, got {other:?}"),
+    }
+}
+
+/// The next JSON frame, as sent.
+pub async fn next_json(ws: &mut Ws) -> Value {
+    serde_json::from_str(&next_text(ws).await).expect("a JSON frame")
+}
+
+pub async fn next_message(ws: &mut Ws) -> ServerMessage {
+    ServerMessage::from_value(next_json(ws).await).expect("a server message")
+}
+
+/// Skips messages until one `wanted`.
+pub async fn wait_for(ws: &mut Ws, wanted: impl Fn(&ServerMessage) -> bool) -> ServerMessage {
+    loop {
+        let message = next_message(ws).await;
+        if wanted(&message) {
+            return message;
+        }
+    }
+}
+
+pub async fn send(ws: &mut Ws, command: &ClientCommand) {
+    let text = serde_json::to_string(command).unwrap();
+    ws.send(Message::Text(text)).await.expect("sending failed");
+}
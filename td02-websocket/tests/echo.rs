@@ -0,0 +1,132 @@
+//! The echo server on `/echo`: frames back as they came and the text commands of `HELP`.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::*;
+use futures_util::{SinkExt, StreamExt};
+use td02_websocket::servers::echo;
+use td02_websocket::shutdown::ServerHandle;
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::{CloseCode, Data, OpCode};
+use tokio_tungstenite::tungstenite::protocol::frame::Frame;
+use tokio_tungstenite::tungstenite::Message;
+
+/// A server and a client past the welcome.
+async fn start() -> (ServerHandle, Ws) {
+    let handle = echo::run(echo_config()).await.unwrap();
+    let mut ws = connect(handle.local_addr(), "/echo").await;
+    let welcome = next_text(&mut ws).await;
+    assert!(welcome.contains("/help"), "{welcome}");
+    (handle, ws)
+}
+
+async fn stop(handle: ServerHandle) {
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
+
+async fn say(ws: &mut Ws, text: &str) -> String {
+    ws.send(Message::Text(text.to_string())).await.unwrap();
+    next_text(ws).await
+}
+
+#[tokio::test]
+async fn ping_gets_one_pong() {
+    let (handle, mut ws) = start().await;
+
+    ws.send(Message::Ping(b"are you there".to_vec()))
+        .await
+        .unwrap();
+    let pong = timeout(TIMEOUT, ws.next()).await.unwrap().unwrap().unwrap();
+    assert_eq!(pong, Message::Pong(b"are you there".to_vec()));
+    ws.send(Message::Text("still here".to_string()))
+        .await
+        .unwrap();
+    let echo = timeout(TIMEOUT, ws.next()).await.unwrap().unwrap().unwrap();
+    assert_eq!(echo, Message::Text("still here".to_string()));
+
+    stop(handle).await;
+}
+
+#[tokio::test]
+async fn text_and_binary_come_back_as_sent() {
+    let (handle, mut ws) = start().await;
+
+    assert_eq!(say(&mut ws, "héllo").await, "héllo");
+    let bytes = vec![0, 1, 2, 254, 255];
+    ws.send(Message::Binary(bytes.clone())).await.unwrap();
+    assert_eq!(next(&mut ws).await, Message::Binary(bytes));
+
+    stop(handle).await;
+}
+
+#[tokio::test]
+async fn fragments_come_back_whole() {
+    let (handle, mut ws) = start().await;
+
+    for frame in [
+        Frame::message(b"frag".to_vec(), OpCode::Data(Data::Text), false),
+        Frame::message(b"men".to_vec(), OpCode::Data(Data::Continue), false),
+        Frame::message(b"ted".to_vec(), OpCode::Data(Data::Continue), true),
+    ] {
+        ws.send(Message::Frame(frame)).await.unwrap();
+    }
+    assert_eq!(next_text(&mut ws).await, "fragmented");
+
+    stop(handle).await;
+}
+
+#[tokio::test]
+async fn delay_holds_echoes_back() {
+    let (handle, mut ws) = start().await;
+
+    assert_eq!(say(&mut ws, "/delay 300").await, "echo delay set to 300ms");
+    let sent = Instant::now();
+    assert_eq!(say(&mut ws, "late").await, "late");
+    assert!(sent.elapsed() >= Duration::from_millis(300));
+    // Answers to commands are not held
+    let sent = Instant::now();
+    assert_eq!(say(&mut ws, "/delay 0").await, "echo delay set to 0ms");
+    assert!(sent.elapsed() < Duration::from_millis(300));
+    assert_eq!(say(&mut ws, "/delay soon").await, "usage: /delay MS");
+
+    stop(handle).await;
+}
+
+#[tokio::test]
+async fn big_sends_that_many_bytes() {
+    let (handle, mut ws) = start().await;
+
+    let big = say(&mut ws, "/big 100000").await;
+    assert_eq!(big.len(), 100_000);
+    assert!(big.bytes().all(|byte| byte == b'x'));
+    assert!(say(&mut ws, "/big 99999999")
+        .await
+        .starts_with("usage: /big N"));
+
+    stop(handle).await;
+}
+
+#[tokio::test]
+async fn close_uses_the_code_asked() {
+    let (handle, mut ws) = start().await;
+
+    assert!(say(&mut ws, "/close 1005")
+        .await
+        .starts_with("/close: expected a code"));
+    ws.send(Message::Text("/close 4000 see you".into()))
+        .await
+        .unwrap();
+    let Message::Close(Some(frame)) = next(&mut ws).await else {
+        panic!("expected a close frame");
+    };
+    assert_eq!(frame.code, CloseCode::from(4000));
+    assert_eq!(frame.reason, "see you");
+    // Our Close answered, the server ends the connection
+    let end = timeout(TIMEOUT, ws.next()).await.unwrap();
+    assert!(!matches!(end, Some(Ok(_))), "{end:?}");
+
+    stop(handle).await;
+}
@@ -0,0 +1,58 @@
+//! A client falling behind the broadcast channel is told so, then gets prices again.
+
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+use td02_websocket::protocol::{ClientCommand, ServerMessage};
+use td02_websocket::servers::broadcast::{self, BroadcastConfig};
+use tokio::time::sleep;
+
+#[tokio::test]
+async fn lagged_then_prices_again() {
+    // Each tick sends a price per symbol and source at once, more than the channel holds
+    let handle = broadcast::run(BroadcastConfig {
+        channel_capacity: 1,
+        ..broadcast_config()
+    })
+    .await
+    .unwrap();
+    let mut ws = connect(handle.local_addr(), "/prices").await;
+    assert!(matches!(
+        next_message(&mut ws).await,
+        ServerMessage::Connected { .. }
+    ));
+
+    // Not reading for a few ticks
+    sleep(Duration::from_millis(300)).await;
+
+    let lagged = wait_for(&mut ws, |message| {
+        matches!(message, ServerMessage::Lagged { .. })
+    })
+    .await;
+    let ServerMessage::Lagged { missed } = lagged else {
+        unreachable!()
+    };
+    assert!(missed > 0);
+    // The feed goes on, whatever more lag comes in between
+    wait_for(&mut ws, |message| {
+        matches!(message, ServerMessage::Price(_))
+    })
+    .await;
+
+    send(&mut ws, &ClientCommand::Stats { admin_token: None }).await;
+    let stats = wait_for(&mut ws, |message| {
+        matches!(message, ServerMessage::Stats { .. })
+    })
+    .await;
+    let ServerMessage::Stats { server, lagged, .. } = stats else {
+        unreachable!()
+    };
+    assert!(lagged > 0);
+    assert!(server.lagged_total >= lagged);
+    assert_eq!(server.channel_capacity, Some(1));
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
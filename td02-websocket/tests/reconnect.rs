@@ -0,0 +1,116 @@
+//! `ResilientClient` against a broadcast server that goes away and comes back on the same
+//! port.
+
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+use td02_websocket::protocol::ServerMessage;
+use td02_websocket::reconnect::{ConnectionState, FeedEvent, ReconnectConfig, ResilientClient};
+use td02_websocket::servers::broadcast::{self, BroadcastConfig};
+use tokio::sync::watch;
+use tokio::time::timeout;
+
+const BACKOFF: ReconnectConfig = ReconnectConfig {
+    initial_backoff: Duration::from_millis(20),
+    max_backoff: Duration::from_millis(100),
+};
+
+async fn next_event(client: &mut ResilientClient) -> FeedEvent {
+    timeout(TIMEOUT, client.next())
+        .await
+        .expect("no event in time")
+        .expect("client stopped")
+}
+
+async fn next_state(client: &mut ResilientClient) -> ConnectionState {
+    loop {
+        if let FeedEvent::State(state) = next_event(client).await {
+            return state;
+        }
+    }
+}
+
+/// Skips events until the server acknowledges a subscription, returning its symbols.
+async fn subscribed(client: &mut ResilientClient) -> Vec<String> {
+    loop {
+        match next_event(client).await {
+            FeedEvent::Message(ServerMessage::Subscription { all, symbols, .. }) => {
+                assert!(!all);
+                return symbols;
+            }
+            FeedEvent::State(state) => panic!("expected a subscription, got {state:?}"),
+            FeedEvent::Message(_) => {}
+        }
+    }
+}
+
+#[tokio::test]
+async fn reconnects_and_subscribes_again() {
+    let handle = broadcast::run(broadcast_config()).await.unwrap();
+    let addr = handle.local_addr();
+    let (stop, shutdown) = watch::channel(false);
+    let mut client = ResilientClient::connect(
+        format!("ws://{addr}/prices"),
+        vec!["aapl".to_string()],
+        BACKOFF,
+        shutdown,
+    );
+
+    assert_eq!(
+        next_state(&mut client).await,
+        ConnectionState::Connecting { attempt: 1 }
+    );
+    assert_eq!(next_state(&mut client).await, ConnectionState::Connected);
+    assert_eq!(subscribed(&mut client).await, ["AAPL"]);
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+
+    // Lost, then refused while the port is closed, never waiting past the maximum
+    let ConnectionState::Disconnected { retry_in, .. } = next_state(&mut client).await else {
+        panic!("expected a disconnection");
+    };
+    assert!(retry_in <= BACKOFF.initial_backoff);
+    // Changed while disconnected, for the next connection
+    client.subscribe(vec!["MSFT".to_string()]);
+    let mut waits = Vec::new();
+    for attempt in 1..=5 {
+        assert_eq!(
+            next_state(&mut client).await,
+            ConnectionState::Connecting { attempt }
+        );
+        let ConnectionState::Disconnected { retry_in, .. } = next_state(&mut client).await else {
+            panic!("expected the connection to be refused");
+        };
+        assert!(retry_in <= BACKOFF.max_backoff, "{retry_in:?}");
+        waits.push(retry_in);
+    }
+    // Doubled from 20ms to the 100ms cap, jitter taking off up to half
+    assert!(waits[4] >= BACKOFF.max_backoff / 2, "{waits:?}");
+
+    let handle = broadcast::run(BroadcastConfig {
+        addrs: vec![addr],
+        ..broadcast_config()
+    })
+    .await
+    .unwrap();
+    assert_eq!(
+        next_state(&mut client).await,
+        ConnectionState::Connecting { attempt: 6 }
+    );
+    assert_eq!(next_state(&mut client).await, ConnectionState::Connected);
+    assert_eq!(subscribed(&mut client).await, ["AAPL", "MSFT"]);
+    loop {
+        if let FeedEvent::Message(ServerMessage::Price(price)) = next_event(&mut client).await {
+            assert!(["AAPL", "MSFT"].contains(&price.symbol.as_str()));
+            break;
+        }
+    }
+
+    stop.send(true).unwrap();
+    while timeout(TIMEOUT, client.next()).await.unwrap().is_some() {}
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
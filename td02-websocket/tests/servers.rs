@@ -0,0 +1,103 @@
+//! Each server started on port 0, talked to over a real connection, then shut down.
+
+mod common;
+
+use chrono::Utc;
+use common::*;
+use futures_util::SinkExt;
+use market_core::store::{self, PoolOptions};
+use market_core::StockPrice;
+use td02_websocket::protocol::{ClientCommand, ServerMessage, PROTOCOL_VERSION};
+use td02_websocket::servers::{broadcast, dashboard, echo};
+use tokio_tungstenite::tungstenite::Message;
+
+/// What every price feed sends: `connected` in its envelope first, then prices, and the
+/// `stats` of one connection when asked.
+async fn check_feed(ws: &mut Ws) {
+    let connected = next_json(ws).await;
+    assert_eq!(connected["v"], 1);
+    assert_eq!(connected["type"], "connected");
+    assert_eq!(connected["data"]["version"], PROTOCOL_VERSION);
+    assert!(connected["data"]["connection_id"].is_u64(), "{connected}");
+
+    let price = wait_for(ws, |message| matches!(message, ServerMessage::Price(_))).await;
+    let ServerMessage::Price(price) = price else {
+        unreachable!()
+    };
+    assert!(price.price > 0.0);
+
+    send(ws, &ClientCommand::Stats { admin_token: None }).await;
+    let stats = wait_for(ws, |message| matches!(message, ServerMessage::Stats { .. })).await;
+    let ServerMessage::Stats {
+        server,
+        rate_limited,
+        connections,
+        ..
+    } = stats
+    else {
+        unreachable!()
+    };
+    assert_eq!(server.active_connections, 1);
+    assert_eq!(server.connections_total, 1);
+    assert!(server.messages_sent > 0);
+    assert_eq!(rate_limited, 0);
+    // Only shown with the admin token
+    assert!(connections.is_none());
+}
+
+#[tokio::test]
+async fn echo_server() {
+    let handle = echo::run(echo_config()).await.unwrap();
+    let mut ws = connect(handle.local_addr(), "/").await;
+
+    let welcome = next_text(&mut ws).await;
+    assert!(
+        welcome.starts_with("Welcome to the echo server"),
+        "{welcome}"
+    );
+    ws.send(Message::Text("hello".into())).await.unwrap();
+    assert_eq!(next_text(&mut ws).await, "hello");
+
+    handle.shutdown();
+    assert!(matches!(next(&mut ws).await, Message::Close(_)));
+    handle.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn broadcast_server() {
+    let handle = broadcast::run(broadcast_config()).await.unwrap();
+    let mut ws = connect(handle.local_addr(), "/prices").await;
+
+    check_feed(&mut ws).await;
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn dashboard_server() {
+    let db = TempDb::new();
+    let handle = dashboard::run(dashboard_config(&db)).await.unwrap();
+    let mut ws = connect(handle.local_addr(), "/prices").await;
+
+    // Written once the client is in, so it comes as a price rather than in the snapshot
+    let writer = store::connect(&db.url(), &PoolOptions::default(), false)
+        .await
+        .unwrap();
+    writer
+        .save(&StockPrice {
+            symbol: "AAPL".to_string(),
+            price: 189.5,
+            source: "test".to_string(),
+            timestamp: Utc::now(),
+            ..StockPrice::default()
+        })
+        .await
+        .unwrap();
+
+    check_feed(&mut ws).await;
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+    writer.close().await;
+}
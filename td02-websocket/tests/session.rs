@@ -0,0 +1,221 @@
+//! The client session both feeds share, driven the same way against each: subscriptions,
+//! ping, formats, throttle and bad commands, then what only the handler of each answers.
+
+mod common;
+
+use std::time::Duration;
+
+use chrono::Utc;
+use common::*;
+use futures_util::{SinkExt, StreamExt};
+use market_core::store::{self, PoolOptions};
+use market_core::StockPrice;
+use td02_websocket::protocol::{ClientCommand, Format, ServerMessage};
+use td02_websocket::servers::{broadcast, dashboard};
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::tungstenite::Message;
+
+async fn next_price(ws: &mut Ws) -> String {
+    match wait_for(ws, |message| matches!(message, ServerMessage::Price(_))).await {
+        ServerMessage::Price(price) => price.symbol,
+        _ => unreachable!(),
+    }
+}
+
+async fn error(ws: &mut Ws) -> String {
+    match wait_for(ws, |message| matches!(message, ServerMessage::Error { .. })).await {
+        ServerMessage::Error { message } => message,
+        _ => unreachable!(),
+    }
+}
+
+/// Sends a WebSocket ping then a `ping` command, and counts the pong frames that come
+/// before the command's reply.
+async fn pongs_before_reply(ws: &mut Ws) -> usize {
+    ws.send(Message::Ping(b"hi".to_vec())).await.unwrap();
+    send(
+        ws,
+        &ClientCommand::Ping {
+            nonce: None,
+            client_ts: None,
+        },
+    )
+    .await;
+    let mut pongs = 0;
+    loop {
+        match timeout(TIMEOUT, ws.next()).await.unwrap().unwrap().unwrap() {
+            Message::Pong(payload) => {
+                assert_eq!(payload, b"hi");
+                pongs += 1;
+            }
+            Message::Text(text) => {
+                let message = ServerMessage::from_value(serde_json::from_str(&text).unwrap());
+                if matches!(message, Ok(ServerMessage::Pong { .. })) {
+                    return pongs;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn check_session(ws: &mut Ws) {
+    assert!(matches!(
+        next_message(ws).await,
+        ServerMessage::Connected { .. }
+    ));
+
+    send(
+        ws,
+        &ClientCommand::Subscribe {
+            symbols: vec!["msft".to_string()],
+        },
+    )
+    .await;
+    let ack = wait_for(ws, |message| {
+        matches!(message, ServerMessage::Subscription { .. })
+    })
+    .await;
+    let ServerMessage::Subscription { all, symbols, .. } = ack else {
+        unreachable!()
+    };
+    assert!(!all);
+    assert_eq!(symbols, ["MSFT"]);
+    for _ in 0..5 {
+        assert_eq!(next_price(ws).await, "MSFT");
+    }
+
+    send(
+        ws,
+        &ClientCommand::Ping {
+            nonce: Some(7.into()),
+            client_ts: None,
+        },
+    )
+    .await;
+    let pong = wait_for(ws, |message| matches!(message, ServerMessage::Pong { .. })).await;
+    let ServerMessage::Pong {
+        nonce, client_ts, ..
+    } = pong
+    else {
+        unreachable!()
+    };
+    assert_eq!(nonce, Some(7.into()));
+    assert_eq!(client_ts, None);
+    assert_eq!(pongs_before_reply(ws).await, 1);
+
+    send(ws, &ClientCommand::SetThrottle { max_per_sec: 2.0 }).await;
+    let throttle = wait_for(ws, |message| {
+        matches!(message, ServerMessage::Throttle { .. })
+    })
+    .await;
+    assert!(matches!(
+        throttle,
+        ServerMessage::Throttle {
+            max_per_sec: Some(2.0)
+        }
+    ));
+    send(ws, &ClientCommand::SetThrottle { max_per_sec: -1.0 }).await;
+    assert!(error(ws).await.starts_with("max_per_sec"));
+    ws.send(Message::Text(r#"{"action":"dance"}"#.to_string()))
+        .await
+        .unwrap();
+    let message = error(ws).await;
+    assert!(message.contains("dance"), "{message}");
+
+    // The reply itself already comes as MessagePack
+    send(
+        ws,
+        &ClientCommand::SetFormat {
+            format: Format::Msgpack,
+        },
+    )
+    .await;
+    loop {
+        match next(ws).await {
+            Message::Text(_) => continue,
+            Message::Binary(bytes) => {
+                let message: ServerMessage = rmp_serde::from_slice(&bytes).unwrap();
+                assert!(
+                    matches!(
+                        message,
+                        ServerMessage::Format {
+                            format: Format::Msgpack
+                        }
+                    ),
+                    "{message:?}"
+                );
+                break;
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+    let Message::Binary(bytes) = next(ws).await else {
+        panic!("expected MessagePack");
+    };
+    let _: ServerMessage = rmp_serde::from_slice(&bytes).unwrap();
+}
+
+#[tokio::test]
+async fn broadcast_session() {
+    let handle = broadcast::run(broadcast_config()).await.unwrap();
+    let mut ws = connect(handle.local_addr(), "/prices").await;
+
+    check_session(&mut ws).await;
+    let mut ws = connect(handle.local_addr(), "/prices").await;
+    send(&mut ws, &ClientCommand::Symbols).await;
+    assert_eq!(
+        error(&mut ws).await,
+        "symbols is only answered by ws_dashboard"
+    );
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn dashboard_session() {
+    let db = TempDb::new();
+    let handle = dashboard::run(dashboard_config(&db)).await.unwrap();
+    let writer = store::connect(&db.url(), &PoolOptions::default(), false)
+        .await
+        .unwrap();
+    let prices = tokio::spawn({
+        let writer = writer.clone();
+        async move {
+            for tick in 0.. {
+                for symbol in ["AAPL", "MSFT"] {
+                    let price = StockPrice {
+                        symbol: symbol.to_string(),
+                        price: 100.0 + f64::from(tick),
+                        source: "test".to_string(),
+                        timestamp: Utc::now(),
+                        ..StockPrice::default()
+                    };
+                    writer.save(&price).await.unwrap();
+                }
+                sleep(Duration::from_millis(30)).await;
+            }
+        }
+    });
+    let mut ws = connect(handle.local_addr(), "/prices").await;
+
+    check_session(&mut ws).await;
+    let mut ws = connect(handle.local_addr(), "/prices").await;
+    send(&mut ws, &ClientCommand::Symbols).await;
+    let ServerMessage::Symbols { symbols, sources } = wait_for(&mut ws, |message| {
+        matches!(message, ServerMessage::Symbols { .. })
+    })
+    .await
+    else {
+        unreachable!()
+    };
+    let symbols: Vec<_> = symbols.iter().map(|info| info.symbol.as_str()).collect();
+    assert_eq!(symbols, ["AAPL", "MSFT"]);
+    assert_eq!(sources, ["test"]);
+
+    prices.abort();
+    handle.shutdown();
+    handle.wait().await.unwrap();
+    writer.close().await;
+}